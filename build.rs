@@ -0,0 +1,90 @@
+use std::env;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Embeds what is needed to tell builds apart in field reports: the git commit, when it was built and with which
+// features. See `src/build_info.rs`.
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/index");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    println!("cargo:rustc-env=ROESTBAK_GIT_COMMIT={}", git_commit());
+    println!("cargo:rustc-env=ROESTBAK_BUILD_TIME={}", build_time());
+    println!("cargo:rustc-env=ROESTBAK_FEATURES={}", features());
+}
+
+// The abbreviated commit hash, marked `-dirty` when there are uncommitted changes, or `unknown` when not building
+// from a git checkout (e.g. from a source tarball).
+fn git_commit() -> String {
+    let git = |arguments: &[&str]| {
+        Command::new("git")
+            .args(arguments)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+
+    match git(&["rev-parse", "--short=10", "HEAD"]) {
+        Some(commit) => match git(&["status", "--porcelain", "--untracked-files=no"]) {
+            Some(changes) if !changes.is_empty() => format!("{}-dirty", commit),
+            _ => commit,
+        },
+        None => "unknown".to_string(),
+    }
+}
+
+// In UTC, e.g. `2024-05-17T09:41:00Z`. Reproducible builds can fix it with `SOURCE_DATE_EPOCH`.
+fn build_time() -> String {
+    let seconds = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<u64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_secs())
+        });
+
+    // Days to civil date, after Howard Hinnant's `civil_from_days`.
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let second_of_day = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    )
+}
+
+// The enabled Cargo features, comma separated.
+fn features() -> String {
+    let mut features = env::vars()
+        .filter_map(|(name, _)| {
+            name.strip_prefix("CARGO_FEATURE_")
+                .map(|feature| feature.to_lowercase().replace('_', "-"))
+        })
+        .filter(|feature| feature != "default")
+        .collect::<Vec<_>>();
+    features.sort();
+
+    features.join(",")
+}
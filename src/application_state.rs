@@ -0,0 +1,119 @@
+use crate::control_values::Throttle;
+use crate::event_bus::{Event, EventBus};
+use crate::runloop;
+use std::time::Duration;
+
+// What the service as a whole is up to. The state is determined once per runloop iteration from the various bits of
+// state the runloop keeps (whether the vehicle is armed, whether input is connected, ...), so that everything that
+// only cares about the overall picture can go by this instead of piecing it together again.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ApplicationState {
+    // No input source is connected.
+    WaitingForInput,
+    // Input is connected, but commands are not executed.
+    Disarmed,
+    // Disarmed after being idle for a while, with the PCA9685 asleep. Input arms the vehicle again.
+    Asleep,
+    // Ready to drive, but standing still.
+    Armed,
+    // Armed with a non-neutral throttle.
+    Driving,
+    // The failsafe brought the vehicle to a stop, until input is restored.
+    Failsafe,
+    // The service is stopping because of an error.
+    Fault,
+}
+
+impl std::fmt::Display for ApplicationState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ApplicationState::WaitingForInput => "waiting_for_input",
+            ApplicationState::Disarmed => "disarmed",
+            ApplicationState::Asleep => "asleep",
+            ApplicationState::Armed => "armed",
+            ApplicationState::Driving => "driving",
+            ApplicationState::Failsafe => "failsafe",
+            ApplicationState::Fault => "fault",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+// The conditions the state is determined from while running, by order of precedence.
+pub struct Conditions {
+    pub failsafe_engaged: bool,
+    pub asleep: bool,
+    pub input_connected: bool,
+    pub armed: bool,
+    pub throttle: Throttle,
+}
+
+impl ApplicationState {
+    pub fn determine(conditions: Conditions) -> ApplicationState {
+        if conditions.failsafe_engaged {
+            ApplicationState::Failsafe
+        } else if conditions.asleep {
+            ApplicationState::Asleep
+        } else if !conditions.input_connected {
+            ApplicationState::WaitingForInput
+        } else if !conditions.armed {
+            ApplicationState::Disarmed
+        } else if !conditions.throttle.is_neutral() {
+            ApplicationState::Driving
+        } else {
+            ApplicationState::Armed
+        }
+    }
+}
+
+// Changes of state are published as `Event::StateChanged`.
+pub struct ApplicationStateMachine {
+    state: ApplicationState,
+    entered_at: Duration,
+    event_bus: EventBus,
+}
+
+impl ApplicationStateMachine {
+    pub fn new(event_bus: EventBus) -> ApplicationStateMachine {
+        ApplicationStateMachine {
+            state: ApplicationState::WaitingForInput,
+            entered_at: runloop::now(),
+            event_bus,
+        }
+    }
+
+    pub fn state(&self) -> ApplicationState {
+        self.state
+    }
+
+    // Moves to `state`, publishing an event if that is a change. Entering and leaving `Driving` happens all the time
+    // and is therefore only logged at debug level.
+    pub fn update(&mut self, state: ApplicationState) {
+        if state == self.state {
+            return;
+        }
+
+        let now = runloop::now();
+        let previous_state = self.state;
+        let message = format!(
+            "State changed from {} to {} after {:.1}s.",
+            previous_state,
+            state,
+            (now - self.entered_at).as_secs_f64()
+        );
+        if previous_state == ApplicationState::Driving || state == ApplicationState::Driving {
+            log::debug!("{}", message);
+        } else {
+            log::info!("{}", message);
+        }
+
+        self.state = state;
+        self.entered_at = now;
+
+        self.event_bus.publish(Event::StateChanged {
+            previous_state,
+            state,
+        });
+    }
+}
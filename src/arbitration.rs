@@ -0,0 +1,242 @@
+use crate::locomotion::LocomotionCommand;
+use std::time::Duration;
+
+// Decides which of the commands proposed in an iteration gets executed, so that it is clear and the same every time
+// whatever the combination of sources:
+//
+// - Every source proposes commands on its own schedule. A proposal only counts for the source's validity window
+//   after it was made, so a source that stops proposing (e.g. because it got stuck) drops out by itself, rather than
+//   having its last command executed indefinitely.
+// - The valid proposal of the highest priority source wins, unless it is neutral: a source that is not asking for
+//   anything lets those below it have their way. That is how the driver takes over from a choreography simply by
+//   moving a stick, and hands back control only once the choreography is started again.
+// - When control passes from one source to another, the command is blended from what the previous source asked for
+//   last to what the new one asks for over the takeover ramp, so that the vehicle does not jump. Control passing to
+//   nobody (every source is neutral) is not blended: stopping is never delayed.
+// - Safety sources (e.g. the geofence, thermal derating) do not propose commands, they limit whichever command won.
+//   A limit can only ever scale the throttle down, never up, whatever value it is given.
+//
+// 💁‍♂️ Training mode mixes the driver's and the instructor's steering before anything gets here, see `copilot.rs`,
+// so the two count as the one driving source.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CommandSource {
+    // Whatever is configured as the input source.
+    Driver,
+    // See `choreography.rs`.
+    Choreography,
+}
+
+impl CommandSource {
+    const ALL: [CommandSource; 2] = [CommandSource::Driver, CommandSource::Choreography];
+
+    // Higher wins.
+    fn priority(self) -> u8 {
+        match self {
+            CommandSource::Driver => 2,
+            CommandSource::Choreography => 1,
+        }
+    }
+
+    // Both propose every iteration. The driver's window covers a few iterations that the runloop may be held up for
+    // without the input source timing out itself.
+    fn validity(self) -> Duration {
+        match self {
+            CommandSource::Driver => Duration::from_millis(500),
+            CommandSource::Choreography => Duration::from_millis(100),
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SafetyLimit {
+    ThermalDerating,
+    Geofence,
+    // Holding the throttle at neutral after the failsafe responded to leaving the geofence.
+    GeofenceHold,
+    // See `guest_lock.rs`.
+    GuestLock,
+}
+
+impl SafetyLimit {
+    const ALL: [SafetyLimit; 4] = [
+        SafetyLimit::ThermalDerating,
+        SafetyLimit::Geofence,
+        SafetyLimit::GeofenceHold,
+        SafetyLimit::GuestLock,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Decision {
+    pub command: LocomotionCommand,
+    // `None` when no source has a valid proposal that is not neutral.
+    pub source: Option<CommandSource>,
+}
+
+pub struct Arbiter {
+    // The latest proposal of every source, and when it was made.
+    proposals: [Option<(LocomotionCommand, Duration)>; CommandSource::ALL.len()],
+    // The share of the throttle every safety source allows, from 0.0 to 1.0.
+    limits: [f64; SafetyLimit::ALL.len()],
+    takeover_ramp: Duration,
+    // The source that was in control last, and what it asked for.
+    last_decision: Option<(CommandSource, LocomotionCommand)>,
+    // The command control was taken over from, and when that happened, while blending.
+    takeover: Option<(LocomotionCommand, Duration)>,
+}
+
+impl Arbiter {
+    pub fn new(takeover_ramp: Duration) -> Arbiter {
+        Arbiter {
+            proposals: [None; CommandSource::ALL.len()],
+            limits: [1.0; SafetyLimit::ALL.len()],
+            takeover_ramp,
+            last_decision: None,
+            takeover: None,
+        }
+    }
+
+    pub fn propose(&mut self, source: CommandSource, command: LocomotionCommand, now: Duration) {
+        self.proposals[source.index()] = Some((command, now));
+    }
+
+    // For a source that has nothing to propose any longer, e.g. a choreography that has ended.
+    pub fn withdraw(&mut self, source: CommandSource) {
+        self.proposals[source.index()] = None;
+    }
+
+    // Sets how far the safety source allows the throttle to go, replacing what it allowed before.
+    pub fn limit(&mut self, safety_limit: SafetyLimit, throttle_cap: f64) {
+        self.limits[safety_limit.index()] = throttle_cap.clamp(0.0, 1.0);
+    }
+
+    pub fn arbitrate(&mut self, now: Duration) -> Decision {
+        let mut candidates: Vec<(CommandSource, LocomotionCommand)> = CommandSource::ALL
+            .into_iter()
+            .filter_map(|source| {
+                self.proposals[source.index()]
+                    .filter(|(_, proposed_at)| {
+                        now.saturating_sub(*proposed_at) <= source.validity()
+                    })
+                    .map(|(command, _)| (source, command))
+            })
+            .collect();
+        candidates.sort_by_key(|(source, _)| std::cmp::Reverse(source.priority()));
+
+        let Some((source, command)) = candidates
+            .iter()
+            .copied()
+            .find(|(_, command)| !command.is_neutral())
+        else {
+            self.last_decision = None;
+            self.takeover = None;
+
+            // Still the neutral command of the highest priority source, as it tells when the input was received.
+            return Decision {
+                command: candidates
+                    .first()
+                    .map_or(LocomotionCommand::neutral(), |(_, command)| *command),
+                source: None,
+            };
+        };
+
+        if let Some((last_source, last_command)) = self.last_decision {
+            if last_source != source && !self.takeover_ramp.is_zero() {
+                self.takeover = Some((last_command, now));
+            }
+        }
+        let command = match self.takeover {
+            Some((from, since)) if now.saturating_sub(since) < self.takeover_ramp => {
+                let progress =
+                    now.saturating_sub(since).as_secs_f64() / self.takeover_ramp.as_secs_f64();
+                command.with_values(
+                    from.get_throttle().blend(command.get_throttle(), progress),
+                    from.get_direction()
+                        .blend(command.get_direction(), progress),
+                )
+            }
+            _ => {
+                self.takeover = None;
+                command
+            }
+        };
+        self.last_decision = Some((source, command));
+
+        Decision {
+            command,
+            source: Some(source),
+        }
+    }
+
+    // Applies every safety limit to the command, on top of whatever limit it already has.
+    pub fn clamp(&self, command: LocomotionCommand) -> LocomotionCommand {
+        command.with_speed_limit(self.limits.iter().product())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control_values::{Steering, Throttle};
+
+    #[test]
+    fn priorities_validity_and_limits() {
+        let mut arbiter = Arbiter::new(Duration::ZERO);
+        let at = Duration::from_millis;
+        let forward = LocomotionCommand::new(Throttle::new(0.8), Steering::CENTER);
+        let left = LocomotionCommand::new(Throttle::new(0.4), Steering::new(-1.0));
+
+        // A neutral driver lets the choreography drive, until moving a stick.
+        arbiter.propose(CommandSource::Choreography, left, at(0));
+        arbiter.propose(CommandSource::Driver, LocomotionCommand::neutral(), at(0));
+        assert_eq!(
+            arbiter.arbitrate(at(0)).source,
+            Some(CommandSource::Choreography)
+        );
+        arbiter.propose(CommandSource::Driver, forward, at(50));
+        let decision = arbiter.arbitrate(at(50));
+        assert_eq!(decision.source, Some(CommandSource::Driver));
+        assert_eq!(decision.command.get_throttle(), forward.get_throttle());
+
+        // Proposals that are not renewed expire.
+        assert_eq!(arbiter.arbitrate(at(600)).source, None);
+        arbiter.withdraw(CommandSource::Driver);
+        assert_eq!(
+            arbiter.arbitrate(at(50)).source,
+            Some(CommandSource::Choreography)
+        );
+
+        // Limits scale the throttle down, but never up.
+        arbiter.limit(SafetyLimit::Geofence, 0.5);
+        arbiter.limit(SafetyLimit::ThermalDerating, 3.0);
+        assert_eq!(arbiter.clamp(forward).get_throttle(), Throttle::new(0.4));
+        arbiter.limit(SafetyLimit::GeofenceHold, 0.0);
+        assert!(arbiter.clamp(forward).get_throttle().is_neutral());
+
+        // With a takeover ramp, the driver's command is blended in.
+        let mut arbiter = Arbiter::new(Duration::from_millis(100));
+        arbiter.propose(CommandSource::Choreography, left, at(0));
+        arbiter.arbitrate(at(0));
+        arbiter.propose(CommandSource::Driver, forward, at(20));
+        let decision = arbiter.arbitrate(at(20));
+        assert_eq!(decision.source, Some(CommandSource::Driver));
+        assert_eq!(decision.command.get_throttle(), Throttle::new(0.4));
+        arbiter.propose(CommandSource::Driver, forward, at(70));
+        let halfway = arbiter.arbitrate(at(70)).command.get_throttle().value();
+        assert!((halfway - 0.6).abs() < 1e-9);
+        arbiter.propose(CommandSource::Driver, forward, at(120));
+        assert_eq!(
+            arbiter.arbitrate(at(120)).command.get_throttle(),
+            forward.get_throttle()
+        );
+    }
+}
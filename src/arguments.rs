@@ -0,0 +1,282 @@
+use crate::control::Request;
+use crate::exit_codes::FatalErrorKind;
+use crate::telemetry;
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+// When invoked through a link with this name, the arguments are interpreted as a control request.
+const CONTROL_CLIENT_NAME: &str = "roestbakctl";
+
+// Selects the vehicle when `--vehicle` is not given, e.g. from a systemd drop-in.
+const VEHICLE_ENVIRONMENT_VARIABLE: &str = "ROESTBAK_VEHICLE";
+
+pub enum Mode {
+    // Drive the vehicle, optionally detached from the terminal as a classic daemon (`--daemon`).
+    Service {
+        daemonize: bool,
+    },
+    ControlClient(Request),
+    // Validate the configuration and report any problems, without starting the service.
+    CheckConfiguration,
+    // Find the steering servo's endpoints and center with the gamepad.
+    CalibrateSteering,
+    // Print the telemetry packets arriving on a port, e.g. on the ground station.
+    ListenTelemetry {
+        port: u16,
+    },
+    // Print the version, commit and features of this build.
+    PrintVersion,
+    // Print how to invoke the program and what it exits with.
+    PrintHelp,
+    // List the devices responding on an I2C bus, or on the configured one.
+    ScanI2C {
+        bus: Option<PathBuf>,
+    },
+    // Write what an input device sends to a file, until interrupted. See `gamepads/capture.rs`.
+    RecordRaw {
+        device_file: PathBuf,
+        capture_file: PathBuf,
+    },
+}
+
+pub struct Arguments {
+    pub configuration_file: Option<PathBuf>,
+    pub vehicle: Option<String>,
+    pub mode: Mode,
+}
+
+impl Arguments {
+    pub fn parse() -> Result<Arguments, ParseError> {
+        let mut arguments = std::env::args_os();
+
+        let invoked_as_control_client = arguments
+            .next()
+            .as_deref()
+            .and_then(|program| Path::new(program).file_name())
+            .is_some_and(|name| name == CONTROL_CLIENT_NAME);
+
+        let mut parsed_arguments = Self::parse_from(arguments, invoked_as_control_client)?;
+        if parsed_arguments.vehicle.is_none() {
+            parsed_arguments.vehicle = std::env::var(VEHICLE_ENVIRONMENT_VARIABLE)
+                .ok()
+                .filter(|vehicle| !vehicle.is_empty());
+        }
+
+        Ok(parsed_arguments)
+    }
+
+    fn parse_from(
+        mut arguments: impl Iterator<Item = OsString>,
+        invoked_as_control_client: bool,
+    ) -> Result<Arguments, ParseError> {
+        let mut configuration_file = None;
+        let mut vehicle = None;
+        let mut request_words = Vec::new();
+        let mut control_client = invoked_as_control_client;
+        let mut check_configuration = false;
+        let mut calibrate_steering = false;
+        let mut telemetry_port = None;
+        let mut daemonize = false;
+        let mut print_version = false;
+        let mut print_help = false;
+        let mut scan_i2c = false;
+        let mut i2c_bus = None;
+        let mut record_raw = false;
+        let mut record_raw_files = Vec::new();
+
+        while let Some(argument) = arguments.next() {
+            match argument.to_str() {
+                Some("--config") => {
+                    let value = arguments
+                        .next()
+                        .ok_or(ParseError::MissingValue { option: "--config" })?;
+                    configuration_file = Some(PathBuf::from(value));
+                }
+                Some("--vehicle") => {
+                    let value = arguments.next().ok_or(ParseError::MissingValue {
+                        option: "--vehicle",
+                    })?;
+                    vehicle = Some(
+                        value
+                            .into_string()
+                            .map_err(|argument| ParseError::UnknownArgument { argument })?,
+                    );
+                }
+                Some("--version") => {
+                    print_version = true;
+                }
+                Some("--help" | "-h") => {
+                    print_help = true;
+                }
+                Some("--daemon") if !control_client => {
+                    daemonize = true;
+                }
+                Some("--check-config") if !control_client => {
+                    check_configuration = true;
+                }
+                Some("calibrate-steering") if !control_client && !check_configuration => {
+                    calibrate_steering = true;
+                }
+                Some("listen-telemetry")
+                    if !control_client && !check_configuration && !calibrate_steering =>
+                {
+                    telemetry_port = Some(telemetry::DEFAULT_PORT);
+                }
+                Some(word) if telemetry_port.is_some() && !word.starts_with("--") => {
+                    telemetry_port =
+                        Some(word.parse().map_err(|_| ParseError::UnknownArgument {
+                            argument: argument.clone(),
+                        })?);
+                }
+                Some("i2c-scan")
+                    if !control_client
+                        && !check_configuration
+                        && !calibrate_steering
+                        && telemetry_port.is_none() =>
+                {
+                    scan_i2c = true;
+                }
+                // Either the bus number, as in `/dev/i2c-<number>`, or the path of its device file.
+                Some(word) if scan_i2c && i2c_bus.is_none() && !word.starts_with("--") => {
+                    i2c_bus = Some(match word.parse::<u32>() {
+                        Ok(number) => PathBuf::from(format!("/dev/i2c-{}", number)),
+                        Err(_) => PathBuf::from(word),
+                    });
+                }
+                Some("record-raw")
+                    if !control_client
+                        && !check_configuration
+                        && !calibrate_steering
+                        && telemetry_port.is_none()
+                        && !scan_i2c =>
+                {
+                    record_raw = true;
+                }
+                // The device file, then the capture file.
+                Some(word)
+                    if record_raw && record_raw_files.len() < 2 && !word.starts_with("--") =>
+                {
+                    record_raw_files.push(PathBuf::from(word));
+                }
+                Some("ctl")
+                    if !control_client
+                        && !check_configuration
+                        && !calibrate_steering
+                        && telemetry_port.is_none()
+                        && !scan_i2c
+                        && !record_raw =>
+                {
+                    control_client = true;
+                }
+                Some(word) if control_client && !word.starts_with("--") => {
+                    request_words.push(word.to_string());
+                }
+                _ => return Err(ParseError::UnknownArgument { argument }),
+            }
+        }
+
+        let mode = if print_help {
+            Mode::PrintHelp
+        } else if print_version {
+            Mode::PrintVersion
+        } else if control_client {
+            let request_text = request_words.join(" ");
+            let request = Request::parse(&request_text)
+                .ok_or(ParseError::InvalidControlRequest { request_text })?;
+            Mode::ControlClient(request)
+        } else if check_configuration {
+            Mode::CheckConfiguration
+        } else if calibrate_steering {
+            Mode::CalibrateSteering
+        } else if let Some(port) = telemetry_port {
+            Mode::ListenTelemetry { port }
+        } else if scan_i2c {
+            Mode::ScanI2C { bus: i2c_bus }
+        } else if record_raw {
+            let mut files = record_raw_files.into_iter();
+            match (files.next(), files.next()) {
+                (Some(device_file), Some(capture_file)) => Mode::RecordRaw {
+                    device_file,
+                    capture_file,
+                },
+                _ => {
+                    return Err(ParseError::MissingValue {
+                        option: "record-raw",
+                    })
+                }
+            }
+        } else {
+            Mode::Service { daemonize }
+        };
+        if daemonize && !matches!(mode, Mode::Service { .. }) {
+            return Err(ParseError::UnknownArgument {
+                argument: OsString::from("--daemon"),
+            });
+        }
+
+        Ok(Arguments {
+            configuration_file,
+            vehicle,
+            mode,
+        })
+    }
+}
+
+// What `--help` prints.
+pub fn usage() -> String {
+    let mut usage = String::from(
+        "Usage: roestbak [--config <file>] [--vehicle <name>] [--daemon]
+       roestbak [--config <file>] [--vehicle <name>] --check-config
+       roestbak [--config <file>] [--vehicle <name>] calibrate-steering
+       roestbak [--config <file>] [--vehicle <name>] i2c-scan [<bus number or device file>]
+       roestbak listen-telemetry [<port>]
+       roestbak record-raw <input device file> <capture file>
+       roestbak [--config <file>] ctl <request>
+       roestbak --version | --help
+
+Control requests:
+  status | arm | disarm | set-limit <0-100> | enable <subsystem> | disable <subsystem> | guest-lock | guest-unlock
+  where <subsystem> is one of: telemetry, network-input, lighting
+
+Exit codes:
+  0  success
+  1  the command did not succeed, e.g. a control request was refused or calibration was cancelled
+",
+    );
+    for kind in FatalErrorKind::ALL {
+        usage.push_str(&format!("  {}  {}\n", kind.code(), kind));
+    }
+
+    usage
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    UnknownArgument { argument: OsString },
+    MissingValue { option: &'static str },
+    InvalidControlRequest { request_text: String },
+}
+
+impl Error for ParseError {}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            ParseError::UnknownArgument { argument } => {
+                format!("Unknown argument {}.", argument.to_string_lossy())
+            }
+            ParseError::MissingValue { option } => {
+                format!("Missing value for {}.", option)
+            }
+            ParseError::InvalidControlRequest { request_text } => {
+                format!(
+                    "Invalid control request '{}'. Expected one of: status, arm, disarm, set-limit <0-100>, enable <subsystem>, disable <subsystem>, guest-lock, guest-unlock.",
+                    request_text
+                )
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
@@ -0,0 +1,224 @@
+use crate::input_source::AuxiliaryAxis;
+use crate::locomotion::{self, PCA9685Driver, PulseWidths, SetPWMError};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Duration;
+
+// An extra output on a spare PCA9685 channel, controlled by an axis the driving controls leave unused (e.g. the right
+// stick's vertical axis): the brightness of LED headlights, or the position of a servo tilting a camera.
+//
+// The axis either sets the level directly, or moves it for as long as it is pushed, which suits a spring-centered
+// stick, as the level stays where it was left. Either way, the output follows the level smoothly rather than jumping,
+// so that lights fade and servos do not jerk.
+//
+// 💁‍♂️ Levels go from -1.0 to 1.0 for a servo, with 0.0 centering it, and from 0.0 to 1.0 for brightness. Pushing the
+// axis up (or right) raises the level.
+
+// Servos center at 1.5ms and take the standard range to either side.
+const SERVO_PULSE_WIDTHS: PulseWidths = PulseWidths {
+    negative_us: 1000,
+    center_us: 1500,
+    positive_us: 2000,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AuxiliaryOutput {
+    // A duty cycle, for dimming LEDs through a MOSFET.
+    Brightness,
+    // Servo pulses.
+    Servo,
+}
+
+impl FromStr for AuxiliaryOutput {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "brightness" => Ok(AuxiliaryOutput::Brightness),
+            "servo" => Ok(AuxiliaryOutput::Servo),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AuxiliaryMode {
+    // The axis position is the level.
+    Absolute,
+    // Pushing the axis all the way moves the level across its whole range in `full_range_time`, slower when pushed
+    // less.
+    Incremental,
+}
+
+impl FromStr for AuxiliaryMode {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "absolute" => Ok(AuxiliaryMode::Absolute),
+            "incremental" => Ok(AuxiliaryMode::Incremental),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AuxiliarySettings {
+    pub axis: AuxiliaryAxis,
+    pub output: AuxiliaryOutput,
+    pub mode: AuxiliaryMode,
+    pub full_range_time: Duration,
+    // The time constant the output follows the level with. Zero follows it right away.
+    pub smoothing: Duration,
+}
+
+// The level and the smoothed value following it, apart from the output so that it can be tested without one.
+struct Level {
+    settings: AuxiliarySettings,
+    level: f64,
+    smoothed: f64,
+}
+
+impl Level {
+    fn new(settings: AuxiliarySettings) -> Level {
+        Level {
+            settings,
+            level: 0.0,
+            smoothed: 0.0,
+        }
+    }
+
+    fn minimum(&self) -> f64 {
+        match self.settings.output {
+            AuxiliaryOutput::Brightness => 0.0,
+            AuxiliaryOutput::Servo => -1.0,
+        }
+    }
+
+    // Moves on by `elapsed`, with the axis at `input`, and returns the smoothed value.
+    fn advance(&mut self, input: f64, elapsed: Duration) -> f64 {
+        let level = match self.settings.mode {
+            AuxiliaryMode::Absolute => input,
+            AuxiliaryMode::Incremental => {
+                let range = 1.0 - self.minimum();
+                self.level
+                    + input * range * elapsed.as_secs_f64()
+                        / self.settings.full_range_time.as_secs_f64()
+            }
+        };
+        self.level = level.clamp(self.minimum(), 1.0);
+
+        self.smoothed = if self.settings.smoothing.is_zero() {
+            self.level
+        } else {
+            let factor =
+                1.0 - (-elapsed.as_secs_f64() / self.settings.smoothing.as_secs_f64()).exp();
+            self.smoothed + (self.level - self.smoothed) * factor
+        };
+
+        self.smoothed
+    }
+}
+
+pub struct AuxiliaryChannel {
+    pca9685_driver: Rc<PCA9685Driver>,
+    channel: u8,
+    level: Level,
+    last_update: Option<Duration>,
+    // As a duty cycle from 0.0 to 1.0, `None` while it is not known what the output was last set to.
+    written_duty_cycle: Option<f64>,
+}
+
+impl AuxiliaryChannel {
+    pub fn new(
+        pca9685_driver: Rc<PCA9685Driver>,
+        channel: u8,
+        settings: AuxiliarySettings,
+    ) -> AuxiliaryChannel {
+        AuxiliaryChannel {
+            pca9685_driver,
+            channel,
+            level: Level::new(settings),
+            last_update: None,
+            written_duty_cycle: None,
+        }
+    }
+
+    pub fn axis(&self) -> AuxiliaryAxis {
+        self.level.settings.axis
+    }
+
+    // Expected to be called every runloop iteration, with the position of the axis if the input source has it.
+    // Without one, the level stays where it is.
+    pub fn update(&mut self, input: Option<f64>, now: Duration) -> Result<(), SetPWMError> {
+        let elapsed = self.last_update.map_or(Duration::ZERO, |last_update| {
+            now.saturating_sub(last_update)
+        });
+        self.last_update = Some(now);
+        let input = input.unwrap_or(match self.level.settings.mode {
+            AuxiliaryMode::Absolute => self.level.level,
+            AuxiliaryMode::Incremental => 0.0,
+        });
+        let value = self.level.advance(input, elapsed);
+
+        // Rounded to what the PCA9685 can tell apart, so that the output is not rewritten over and over while the
+        // smoothed value closes in on the level.
+        let duty_cycle = match self.level.settings.output {
+            AuxiliaryOutput::Brightness => value,
+            AuxiliaryOutput::Servo => locomotion::locomotion_value_to_pwm_on_percentage(
+                value,
+                &SERVO_PULSE_WIDTHS,
+                self.pca9685_driver.pwm_frequency(),
+            ),
+        };
+        let duty_cycle = (duty_cycle.clamp(0.0, 1.0) * 4095.0).round() / 4095.0;
+        if self.written_duty_cycle != Some(duty_cycle) {
+            self.written_duty_cycle = None;
+            self.pca9685_driver
+                .set_pwm_on_percentage(self.channel, duty_cycle)?;
+            self.written_duty_cycle = Some(duty_cycle);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn incremental_level_holds_and_smooths() {
+        let mut level = Level::new(AuxiliarySettings {
+            axis: AuxiliaryAxis::RightStickVertical,
+            output: AuxiliaryOutput::Brightness,
+            mode: AuxiliaryMode::Incremental,
+            full_range_time: Duration::from_secs(2),
+            smoothing: Duration::ZERO,
+        });
+        let step = Duration::from_millis(100);
+
+        // Pushing all the way for a second goes halfway up, and letting go keeps it there.
+        for _ in 0..10 {
+            level.advance(1.0, step);
+        }
+        assert!((level.advance(0.0, step) - 0.5).abs() < 1e-9);
+        // Pulling down goes no lower than off.
+        for _ in 0..30 {
+            level.advance(-1.0, step);
+        }
+        assert_eq!(level.advance(0.0, step), 0.0);
+
+        // With smoothing, the output gets about two thirds of the way in one time constant.
+        let mut level = Level::new(AuxiliarySettings {
+            mode: AuxiliaryMode::Absolute,
+            smoothing: Duration::from_millis(200),
+            ..level.settings
+        });
+        let mut value = 0.0;
+        for _ in 0..2 {
+            value = level.advance(1.0, step);
+        }
+        assert!((value - 0.632).abs() < 0.001);
+    }
+}
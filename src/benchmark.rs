@@ -0,0 +1,55 @@
+// A minimal harness for timing the hot control path, as the standard library's benchmark harness is only available
+// on nightly. Benchmarks are ignored tests named `benchmark_...`, next to the code they measure, and are meant to be
+// run with optimizations:
+//
+//     cargo test --release benchmark_ -- --ignored --nocapture --test-threads 1
+//
+// Every benchmark has a budget: the share of a runloop iteration the measured step may take up. A benchmark fails
+// when an iteration takes longer than that, so that a regression shows before it makes iterations overrun on the
+// vehicle.
+//
+// 💁‍♂️ The budgets are meant for the slowest supported board (a Pi Zero). A development machine is an order of
+// magnitude faster, which a regression has to eat up before a benchmark fails there.
+
+use std::time::{Duration, Instant};
+
+const WARM_UP_DURATION: Duration = Duration::from_millis(200);
+const MEASUREMENT_DURATION: Duration = Duration::from_secs(1);
+
+// Iterations between looking at the clock, so that doing so does not dominate quick iterations.
+const BATCH_SIZE: u32 = 100;
+
+// Runs `iteration` over and over (passing the number of the iteration) and returns how long it took on average.
+pub fn measure(name: &str, budget: Duration, mut iteration: impl FnMut(u32)) -> Duration {
+    let mut iterations = 0;
+    let mut run_for = |duration: Duration| {
+        let start = Instant::now();
+        let mut count = 0;
+        while start.elapsed() < duration {
+            for _ in 0..BATCH_SIZE {
+                iteration(iterations);
+                iterations = iterations.wrapping_add(1);
+            }
+            count += BATCH_SIZE;
+        }
+
+        start.elapsed() / count
+    };
+
+    run_for(WARM_UP_DURATION);
+    let per_iteration = run_for(MEASUREMENT_DURATION);
+
+    println!(
+        "{}: {:?} per iteration (budget {:?}).",
+        name, per_iteration, budget
+    );
+    assert!(
+        per_iteration <= budget,
+        "{} takes {:?} per iteration, which is over its budget of {:?}.",
+        name,
+        per_iteration,
+        budget
+    );
+
+    per_iteration
+}
@@ -0,0 +1,22 @@
+// Identifies the build, so that field reports can be matched to the exact source. Filled in by `build.rs`.
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_COMMIT: &str = env!("ROESTBAK_GIT_COMMIT");
+pub const BUILD_TIME: &str = env!("ROESTBAK_BUILD_TIME");
+// Comma separated, empty if none.
+pub const FEATURES: &str = env!("ROESTBAK_FEATURES");
+
+// E.g. `roestbak 0.1.0 (3f2c4e1a9b, built 2024-05-17T09:41:00Z, features: none)`.
+pub fn describe() -> String {
+    format!(
+        "roestbak {} ({}, built {}, features: {})",
+        VERSION,
+        GIT_COMMIT,
+        BUILD_TIME,
+        if FEATURES.is_empty() {
+            "none"
+        } else {
+            FEATURES
+        }
+    )
+}
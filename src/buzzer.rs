@@ -0,0 +1,126 @@
+use crate::event_bus::{Event, EventSubscriber};
+use crate::locomotion::{PCA9685Driver, SetPWMError};
+use crate::runloop;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+// An active buzzer on a spare PCA9685 channel, for feedback when nobody is looking at the logs. Every alert has its
+// own beep pattern.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Alert {
+    Armed,
+    Disarmed,
+    // 💁‍♂️ There is no battery monitoring yet, so nothing raises this alert for now.
+    #[allow(dead_code)]
+    LowBattery,
+    InputLost,
+    FatalError,
+}
+
+const SHORT: Duration = Duration::from_millis(80);
+const MEDIUM: Duration = Duration::from_millis(250);
+const LONG: Duration = Duration::from_millis(600);
+const GAP: Duration = Duration::from_millis(100);
+
+// Alternating beep and silence durations, starting with a beep.
+fn pattern(alert: Alert) -> &'static [Duration] {
+    match alert {
+        Alert::Armed => &[SHORT, GAP, SHORT],
+        Alert::Disarmed => &[LONG],
+        Alert::LowBattery => &[SHORT, GAP, SHORT, GAP, SHORT, GAP, SHORT],
+        Alert::InputLost => &[MEDIUM, GAP, MEDIUM, GAP, MEDIUM],
+        Alert::FatalError => &[LONG, GAP, LONG, GAP, LONG],
+    }
+}
+
+pub struct Buzzer {
+    pca9685_driver: Rc<PCA9685Driver>,
+    channel: u8,
+    playing: Option<(Alert, Duration)>,
+    sounding: Option<bool>,
+}
+
+impl Buzzer {
+    pub fn new(pca9685_driver: Rc<PCA9685Driver>, channel: u8) -> Buzzer {
+        Buzzer {
+            pca9685_driver,
+            channel,
+            playing: None,
+            sounding: None,
+        }
+    }
+
+    // Starts playing the pattern for `alert`, cutting short whatever was playing before.
+    pub fn alert(&mut self, alert: Alert) {
+        self.playing = Some((alert, runloop::now()));
+    }
+
+    // Advances the pattern being played. Expected to be called every runloop iteration.
+    pub fn update(&mut self) -> Result<(), SetPWMError> {
+        let sounding = match self.playing {
+            Some((alert, started_at)) => match sounding_at(alert, runloop::now() - started_at) {
+                Some(sounding) => sounding,
+                None => {
+                    self.playing = None;
+                    false
+                }
+            },
+            None => false,
+        };
+
+        self.set_sounding(sounding)
+    }
+
+    // Plays the pattern for `alert` to completion, for when there is no runloop (anymore) to drive it.
+    pub fn play_to_completion(&mut self, alert: Alert) -> Result<(), SetPWMError> {
+        self.playing = None;
+
+        for (index, duration) in pattern(alert).iter().enumerate() {
+            self.set_sounding(index % 2 == 0)?;
+            thread::sleep(*duration);
+        }
+
+        self.set_sounding(false)
+    }
+
+    fn set_sounding(&mut self, sounding: bool) -> Result<(), SetPWMError> {
+        if self.sounding != Some(sounding) {
+            self.sounding = None;
+            if sounding {
+                self.pca9685_driver.set_full_on(self.channel)?;
+            } else {
+                self.pca9685_driver.set_full_off(self.channel)?;
+            }
+            self.sounding = Some(sounding);
+        }
+
+        Ok(())
+    }
+}
+
+// Whether the buzzer should sound at `elapsed` into the pattern for `alert`, or `None` once the pattern is over.
+fn sounding_at(alert: Alert, elapsed: Duration) -> Option<bool> {
+    let mut step_end = Duration::ZERO;
+
+    for (index, duration) in pattern(alert).iter().enumerate() {
+        step_end += *duration;
+        if elapsed < step_end {
+            return Some(index % 2 == 0);
+        }
+    }
+
+    None
+}
+
+impl EventSubscriber for Buzzer {
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Armed => self.alert(Alert::Armed),
+            Event::Disarmed => self.alert(Alert::Disarmed),
+            Event::InputDisconnected => self.alert(Alert::InputLost),
+            _ => (),
+        }
+    }
+}
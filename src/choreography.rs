@@ -0,0 +1,183 @@
+use crate::control_values::{Steering, Throttle};
+use crate::locomotion::LocomotionCommand;
+use std::error::Error;
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// A choreography is a fixed sequence of locomotion commands, each held for a given duration. It is described in a
+// text file with one step per line, consisting of the duration in seconds, the throttle and the steering direction
+// (both from -1.0 to 1.0), separated by whitespace. Lines starting with `#` are comments. For example:
+//
+//     # Drive forward, turn right and reverse back out.
+//     2.0   0.3   0.0
+//     1.5   0.3   0.8
+//     2.0  -0.3   0.0
+//
+// 💁‍♂️ The vehicle is brought to a stop once the last step has finished, so there is no need to end with a neutral
+// step.
+
+#[derive(Debug, Copy, Clone)]
+struct Step {
+    duration: Duration,
+    command: LocomotionCommand,
+}
+
+pub struct Choreography {
+    steps: Vec<Step>,
+}
+
+impl Choreography {
+    pub fn load(path: &Path) -> Result<Choreography, LoadError> {
+        let text = fs::read_to_string(path).map_err(|source| LoadError::CouldNotReadFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let choreography = Self::parse(&text)?;
+        log::info!(
+            "Loaded choreography with {} steps lasting {:.1}s from {}.",
+            choreography.steps.len(),
+            choreography.duration().as_secs_f64(),
+            path.display()
+        );
+
+        Ok(choreography)
+    }
+
+    pub fn parse(text: &str) -> Result<Choreography, LoadError> {
+        let mut steps = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [duration, throttle, direction] = fields[..] else {
+                return Err(LoadError::InvalidStep { line: line_number });
+            };
+
+            let duration = duration
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| seconds.is_finite() && *seconds > 0.0)
+                .ok_or(LoadError::InvalidStep { line: line_number })?;
+            let throttle = parse_locomotion_value(throttle)
+                .ok_or(LoadError::InvalidStep { line: line_number })?;
+            let direction = parse_locomotion_value(direction)
+                .ok_or(LoadError::InvalidStep { line: line_number })?;
+
+            steps.push(Step {
+                duration: Duration::from_secs_f64(duration),
+                command: LocomotionCommand::new(Throttle::new(throttle), Steering::new(direction)),
+            });
+        }
+
+        if steps.is_empty() {
+            return Err(LoadError::NoSteps);
+        }
+
+        Ok(Choreography { steps })
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.steps.iter().map(|step| step.duration).sum()
+    }
+
+    // The command to execute at `elapsed` into the choreography, or `None` once it has finished.
+    fn command_at(&self, elapsed: Duration) -> Option<LocomotionCommand> {
+        let mut step_end = Duration::ZERO;
+
+        for step in &self.steps {
+            step_end += step.duration;
+            if elapsed < step_end {
+                return Some(step.command);
+            }
+        }
+
+        None
+    }
+}
+
+fn parse_locomotion_value(text: &str) -> Option<f64> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|value| (-1.0..=1.0).contains(value))
+}
+
+// Plays a choreography from start to finish, driven by the runloop asking for the current command every iteration.
+pub struct ChoreographyPlayer {
+    started_at: Option<Instant>,
+}
+
+impl ChoreographyPlayer {
+    pub fn new() -> ChoreographyPlayer {
+        ChoreographyPlayer { started_at: None }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    pub fn start(&mut self) {
+        self.started_at = Some(Instant::now());
+    }
+
+    pub fn stop(&mut self) {
+        self.started_at = None;
+    }
+
+    // Returns the command to execute right now, or `None` if nothing is playing. Playback stops by itself once the
+    // choreography has finished.
+    pub fn current_command(&mut self, choreography: &Choreography) -> Option<LocomotionCommand> {
+        let started_at = self.started_at?;
+        let command = choreography.command_at(started_at.elapsed());
+
+        if command.is_none() {
+            log::info!("Choreography finished.");
+            self.stop();
+        }
+
+        command
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    CouldNotReadFile { path: PathBuf, source: IoError },
+    InvalidStep { line: usize },
+    NoSteps,
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::CouldNotReadFile { path: _, source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            LoadError::CouldNotReadFile { path, source: _ } => {
+                format!("Could not read choreography file at {}.", path.display())
+            }
+            LoadError::InvalidStep { line } => {
+                format!(
+                    "Invalid step in choreography file on line {}. Expected: <seconds> <throttle> <steering>.",
+                    line
+                )
+            }
+            LoadError::NoSteps => "Choreography file does not contain any steps.".to_string(),
+        };
+
+        write!(f, "{}", description)
+    }
+}
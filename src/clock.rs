@@ -0,0 +1,142 @@
+use std::io::Error as IoError;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::time::Duration;
+
+#[cfg(test)]
+pub use manual::ManualClock;
+
+/// Where the runloop and anything timing-dependent get the time from. Points in time are durations since some
+/// unspecified moment in the past, and only meaningful when compared to other points in time of the same clock.
+pub trait Clock {
+    fn now(&self) -> Duration;
+
+    // How long the system has been suspended in total. The clock stands still meanwhile, so this is the only way to
+    // tell that time has passed.
+    fn suspended(&self) -> Duration;
+
+    // Blocks until `deadline`, or returns right away if it has passed already.
+    fn sleep_until(&self, deadline: Duration);
+}
+
+// The clock of `runloop::now()`, which kernel timestamps (of input events, GPIO edges and such) are taken on as well.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MonotonicClock;
+
+// Rust internally represents `libc::timespec` values using a private `Timespec` type, which includes operations for arithmetic, comparing
+// and so on. As a point in time is—in present context—defined as a duration since some agreed upon past moment, the publicly available
+// `Duration` type is used(/abused?) for this purpose here. This avoids needlessly duplicating the logic for some needed operations.
+
+// ⚠️ Contrary to the `Duration` type, `libc::timespec`'s fields are signed. A negative value for `tv_sec` could be used to represent a
+// point in time before epoch. We therefore need to ensure that the clock we use won't emit negative values. This should not be a problem
+// for the monotonic clock. From clock_gettime(3):
+//
+// > A nonsettable system-wide clock that represents monotonic time since—as described by POSIX—"some unspecified point in the past".  On
+// > Linux, that point corresponds to the number of seconds that the system has been running since it was booted.
+const CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Duration {
+        clock_gettime(CLOCK)
+    }
+
+    // The boot time clock is the monotonic clock plus the time spent suspended. The two are read one after the other,
+    // so the result is off by a few microseconds either way.
+    fn suspended(&self) -> Duration {
+        let now = self.now();
+        clock_gettime(libc::CLOCK_BOOTTIME).saturating_sub(now)
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        // `libc::c_long` is only 32 bits wide on some of the targeted platforms, so the conversion is not infallible
+        // everywhere.
+        #[allow(clippy::unnecessary_fallible_conversions)]
+        let deadline = libc::timespec {
+            tv_sec: libc::time_t::try_from(deadline.as_secs())
+                .expect("deadline.as_secs() out of bounds."),
+            tv_nsec: libc::c_long::try_from(deadline.subsec_nanos())
+                .expect("deadline.subsec_nanos() out of bounds."),
+        };
+
+        let result = unsafe {
+            libc::clock_nanosleep(CLOCK, libc::TIMER_ABSTIME, &deadline, ptr::null_mut())
+        };
+
+        // This implementation assumes that signals are blocked so that this call will never be interrupted.
+        if result != 0 {
+            panic!(
+                "Sleep is expected to succeed (are signals blocked?). Error code: {}",
+                result
+            );
+        }
+    }
+}
+
+fn clock_gettime(clock: libc::clockid_t) -> Duration {
+    let mut timespec: MaybeUninit<libc::timespec> = MaybeUninit::uninit();
+
+    let result = unsafe { libc::clock_gettime(clock, timespec.as_mut_ptr()) };
+    if result != 0 {
+        let error = IoError::last_os_error();
+        panic!(
+            "Retrieving time from clock is expected to succeed. Error: {}",
+            error
+        );
+    }
+
+    let timespec = unsafe { timespec.assume_init() };
+
+    Duration::new(
+        u64::try_from(timespec.tv_sec).expect("timespec.tv_sec out of bounds."),
+        u32::try_from(timespec.tv_nsec).expect("timespec.tv_nsec out of bounds."),
+    )
+}
+
+#[cfg(test)]
+mod manual {
+    use super::Clock;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// A clock that only moves when told to, so that timing-dependent behaviour can be stepped through
+    /// deterministically. Clones share the time, so that a test can keep one while the code under test owns another.
+    /// Sleeping moves the time to the deadline right away.
+    #[derive(Debug, Clone)]
+    pub struct ManualClock {
+        now: Rc<Cell<Duration>>,
+        suspended: Rc<Cell<Duration>>,
+    }
+
+    impl ManualClock {
+        pub fn new(now: Duration) -> Self {
+            Self {
+                now: Rc::new(Cell::new(now)),
+                suspended: Rc::new(Cell::new(Duration::ZERO)),
+            }
+        }
+
+        pub fn advance(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
+        }
+
+        // Like the system being suspended for `duration`, which leaves the time as it was.
+        pub fn suspend(&self, duration: Duration) {
+            self.suspended.set(self.suspended.get() + duration);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Duration {
+            self.now.get()
+        }
+
+        fn suspended(&self) -> Duration {
+            self.suspended.get()
+        }
+
+        fn sleep_until(&self, deadline: Duration) {
+            self.now.set(self.now.get().max(deadline));
+        }
+    }
+}
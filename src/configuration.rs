@@ -0,0 +1,1151 @@
+use crate::auxiliary_channel::{AuxiliaryMode, AuxiliaryOutput};
+use crate::driving::DrivingSettings;
+use crate::failsafe_policy::FailsafePolicy;
+use crate::gamepads::{Button, GamepadDeviceRules, GamepadSettings, RumblePatterns};
+use crate::gps::Position;
+use crate::input_source::{AuxiliaryAxis, ChannelMapping, InputSourceKind};
+use crate::locomotion::{
+    ArmingStep, Output, OutputChannels, PCA9685Settings, PulseWidths,
+    DEFAULT_WRITE_LATENCY_THRESHOLD, I2C_DEVICE_FILE,
+};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+// The configuration file is a list of `key = value` lines, optionally grouped under `[section]` headers. Settings
+// are identified by their section and key joined with a dot, e.g. `summary_file` under `[session]` is
+// `session.summary_file`. Lines starting with `#` are comments.
+//
+// 💁‍♂️ Every setting is optional, so leaving out the configuration file entirely is fine.
+//
+// Settings for a specific vehicle go into sections prefixed with `vehicle.<name>.`, e.g. `[vehicle.crawler.driving]`.
+// When a vehicle is selected (with `--vehicle` or the ROESTBAK_VEHICLE environment variable), its settings override
+// the ones outside of vehicle sections. Settings of the other vehicles are validated, but otherwise ignored.
+//
+// The file is reloaded when it changes or on SIGHUP, but only the `driving.*` settings take effect without a restart.
+
+const DEFAULT_CONFIGURATION_FILE: &str = "/etc/roestbak.conf";
+const VEHICLE_PREFIX: &str = "vehicle.";
+
+// Device paths can be given in the environment, e.g. when running in a container with the devices bind-mounted
+// elsewhere. These take precedence over the configuration file.
+const I2C_DEVICE_ENVIRONMENT_VARIABLE: &str = "ROESTBAK_I2C_DEVICE";
+const INPUT_FOLDER_ENVIRONMENT_VARIABLE: &str = "ROESTBAK_INPUT_FOLDER";
+const GPIO_CHIP_ENVIRONMENT_VARIABLE: &str = "ROESTBAK_GPIO_CHIP";
+const SBUS_DEVICE_ENVIRONMENT_VARIABLE: &str = "ROESTBAK_SBUS_DEVICE";
+const KEYBOARD_DEVICE_ENVIRONMENT_VARIABLE: &str = "ROESTBAK_KEYBOARD_DEVICE";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Configuration {
+    // How often input is processed and commands are sent to the hardware.
+    pub runloop_interval: Duration,
+
+    // How often PWM values are rewritten even if they did not change. Changes are always written immediately.
+    pub locomotion_refresh_interval: Duration,
+
+    // Whether to look PWM values up in precomputed tables rather than work them out for every command, for boards
+    // where every bit of latency counts. See `locomotion/pwm_table.rs`.
+    pub locomotion_pwm_tables_enabled: bool,
+
+    // How long it takes to bring the throttle from full to neutral when input is lost. Zero stops right away.
+    pub failsafe_brake_ramp: Duration,
+    // How to respond to input being lost, the MAVLink link being lost and the vehicle leaving the geofence, e.g.
+    // `brake:300:disarm`. Losing input or the link coasts down over `failsafe_brake_ramp` unless configured otherwise.
+    // See `failsafe_policy.rs`.
+    pub failsafe_policy: FailsafePolicy,
+
+    // After being armed without any input for this long, the vehicle is disarmed and the PCA9685 put to sleep to save
+    // power. See `idle.rs`.
+    pub idle_timeout: Option<Duration>,
+    // After how long without the driver touching the gamepad an armed vehicle standing still is disarmed, so that a
+    // bumped controller left lying around cannot set it off. Only the START button arms it again. See `idle.rs`.
+    pub inactivity_lock_timeout: Option<Duration>,
+
+    // Whether to start with the guest lock engaged, how fast it lets the vehicle go, and which buttons unlock it. See
+    // `guest_lock.rs`.
+    pub guest_lock_engaged: bool,
+    pub guest_lock_max_speed_percent: u8,
+    pub guest_lock_unlock_sequence: Vec<Button>,
+
+    // The I2C bus peripherals are on, unless configured otherwise for the peripheral, as `pca9685_i2c_device_file` does
+    // for the PCA9685.
+    pub i2c_device_file: PathBuf,
+    pub pca9685_i2c_device_file: Option<PathBuf>,
+    // Above this p99 of writing a PWM channel, the I2C bus is considered degraded and a warning is raised.
+    pub i2c_latency_warning_threshold: Duration,
+    // Resetting the PCA9685 after this many I2C errors in a row that look like a locked-up bus, rather than giving up
+    // right away. See `locomotion/bus_recovery.rs`.
+    pub i2c_recovery_after_errors: Option<u32>,
+
+    // Output staggering, clock source and frequency correction of the PCA9685.
+    pub pca9685: PCA9685Settings,
+
+    // Which PCA9685 channel each output is connected to. See `locomotion/channels.rs`.
+    pub channels: OutputChannels,
+
+    // Where the pulse widths found by `calibrate-steering` are kept.
+    pub steering_calibration_file: PathBuf,
+
+    // What the ESC is sent before neutral at startup, for ESCs that do not arm on neutral alone. See
+    // `locomotion/arming.rs`.
+    pub esc_arming_sequence: Vec<ArmingStep>,
+
+    // A TMP102 temperature sensor on the ESC or motor, by its address on the I2C bus, and how the throttle is derated
+    // as it heats up: from full throttle at `thermal_derate_from` down to `thermal_max_speed_at_limit_percent` at
+    // `thermal_limit` (both in °C). See `thermal.rs`.
+    pub thermal_sensor_address: Option<u8>,
+    pub thermal_derate_from: f64,
+    pub thermal_limit: f64,
+    pub thermal_max_speed_at_limit_percent: u8,
+
+    // A fan cooling the ESC or motor, on either a PCA9685 channel (`channels.fan`) or a line of a GPIO chip, and the
+    // temperatures (in °C, as read by the thermal sensor) it is switched on above and off below. See `fan.rs`.
+    pub fan_gpio_chip: PathBuf,
+    pub fan_gpio_line: Option<u32>,
+    pub fan_on_above: f64,
+    pub fan_off_below: f64,
+
+    // The UART a GPS module is connected to, if any, and the baud rate it sends at. See `gps.rs`.
+    pub gps_device_file: Option<PathBuf>,
+    pub gps_baud_rate: u32,
+
+    // A boundary the vehicle is to stay within, as a circle (`geofence_center` and `geofence_radius_m`) or a polygon,
+    // and how the throttle is capped beyond it: down to `geofence_speed_outside_percent` at `geofence_margin_m` out.
+    // Requires a GPS module. See `geofence.rs`.
+    pub geofence_center: Option<Position>,
+    pub geofence_radius_m: f64,
+    pub geofence_polygon: Vec<Position>,
+    pub geofence_margin_m: f64,
+    pub geofence_speed_outside_percent: u8,
+
+    // Deadzone, curves and speed cap applied to the driver's input.
+    pub driving: DrivingSettings,
+
+    // Whether to show the state on the gamepad's lightbar and player indicators. See `gamepads/lights.rs`.
+    pub gamepad_lights_enabled: bool,
+    // What the gamepad's rumble patterns are, for when the vehicle is armed and so on. See `gamepads/haptics.rs`.
+    pub gamepad_rumble_patterns: RumblePatterns,
+
+    // How far and how long the steering has to be turned to set off a turn signal, for turn signals on
+    // `channels.left_turn_signal` and `channels.right_turn_signal`. See `lighting.rs`.
+    pub lighting_turn_signal_threshold_percent: u8,
+    pub lighting_turn_signal_delay: Duration,
+
+    // How the gamepad axis the driving controls leave unused controls `channels.aux`, for dimming headlights or
+    // tilting a camera. See `auxiliary_channel.rs`.
+    pub auxiliary_axis: AuxiliaryAxis,
+    pub auxiliary_output: AuxiliaryOutput,
+    pub auxiliary_mode: AuxiliaryMode,
+    pub auxiliary_full_range_time: Duration,
+    pub auxiliary_smoothing: Duration,
+
+    // The endpoints of a pan/tilt camera gimbal's servos, on `channels.gimbal_pan` and `channels.gimbal_tilt`. The
+    // right stick aims it once `gamepad.gimbal_button` is pressed. See `gimbal.rs`.
+    pub gimbal_pan_pulse_widths: PulseWidths,
+    pub gimbal_tilt_pulse_widths: PulseWidths,
+    pub gimbal_full_deflection_time: Duration,
+    pub gimbal_center_on_release: bool,
+
+    // The endpoints of a winch's ESC on `channels.winch`, and how fast it runs. The dpad runs it while
+    // `gamepad.winch_modifier` is held. See `winch.rs`.
+    pub winch_pulse_widths: PulseWidths,
+    pub winch_speed_percent: u8,
+
+    // The ALSA PCM device to play a simulated engine sound on, if any (e.g. `/dev/snd/pcmC0D0p`), how loud, and how
+    // many times a second the imaginary engine fires at idle and at full throttle. See `engine_sound.rs`.
+    pub engine_sound_device_file: Option<PathBuf>,
+    pub engine_sound_volume_percent: u8,
+    pub engine_sound_idle_frequency: f64,
+    pub engine_sound_max_frequency: f64,
+
+    // Whether to restrict the system calls the service can make once it is up and running.
+    pub seccomp_enabled: bool,
+
+    // Whether to exercise the motors at startup. ⚠️ This moves the wheels.
+    pub self_test_enabled: bool,
+
+    // Where to write a JSON summary of the session when the service stops.
+    pub session_summary_file: Option<PathBuf>,
+
+    // Where the odometer counters are persisted across restarts.
+    pub odometer_state_file: PathBuf,
+
+    // The socket through which `roestbakctl` talks to the service.
+    pub control_socket_file: PathBuf,
+
+    // Whether to keep a JSON file describing the service's health up to date, and where. See `status_file.rs`.
+    pub status_file_enabled: bool,
+    pub status_file: PathBuf,
+
+    // Where a service started with `--daemon` writes its output and its PID. See `daemon.rs`.
+    pub daemon_log_file: PathBuf,
+    pub daemon_pid_file: PathBuf,
+
+    // Whether to expose the service on the system bus. This requires a bus policy allowing us to own our name.
+    pub dbus_enabled: bool,
+
+    // Whether to advertise the MAVLink and telemetry endpoints via mDNS, and under which name (the host name if not
+    // given). See `mdns.rs`.
+    pub mdns_enabled: bool,
+    pub mdns_instance_name: Option<String>,
+
+    // A choreography to play when the Y button is pressed. See `choreography.rs` for the file format.
+    pub choreography_file: Option<PathBuf>,
+
+    // What the vehicle is driven with: `gamepad`, `sbus`, `mavlink` or `keyboard`.
+    pub input_source: InputSourceKind,
+
+    // Training mode: a second input source for an instructor, whose steering is mixed into the driver's with the
+    // given authority (0% only advises, 100% overrides). See `copilot.rs`.
+    pub copilot_source: Option<InputSourceKind>,
+    pub copilot_authority_percent: u8,
+
+    // How long it takes to blend from one source's command to another's when control passes between them, e.g. from
+    // a choreography to the driver. See `arbitration.rs`.
+    pub arbitration_takeover_ramp: Duration,
+
+    // How the gamepad's controls are interpreted, when driving with a gamepad.
+    pub gamepad: GamepadSettings,
+    // Where gamepads are looked for and which devices count as one. See `gamepads/detection.rs`.
+    pub gamepad_devices: GamepadDeviceRules,
+    // Which evdev codes controllers other than an Xbox controller report their buttons and axes with. See
+    // `gamepads/mapping.rs` for the file format.
+    pub gamepad_mapping_file: Option<PathBuf>,
+
+    // The UART an SBUS receiver is connected to, and which of its channels control the vehicle.
+    pub sbus_device_file: PathBuf,
+    pub sbus_channel_mapping: ChannelMapping,
+
+    // Where to listen for MAVLink messages, where to find the ground control station (if it is not to be discovered
+    // by its heartbeats) and which RC_CHANNELS_OVERRIDE channels control the vehicle.
+    pub mavlink_listen_address: SocketAddr,
+    pub mavlink_ground_control_station_address: Option<SocketAddr>,
+    pub mavlink_channel_mapping: ChannelMapping,
+    // The passphrase MAVLink messages are signed with, and whether unsigned messages are ignored. See
+    // `mavlink/signing.rs`.
+    pub mavlink_signing_passphrase: Option<String>,
+    pub mavlink_require_signing: bool,
+
+    // A relay cutting the ESC's power unless the service is healthy, on a line of a GPIO chip. See `kill_relay.rs`.
+    pub kill_relay_gpio_chip: PathBuf,
+    pub kill_relay_line: Option<u32>,
+    pub kill_relay_active_low: bool,
+    pub kill_relay_max_gap: Duration,
+    pub kill_relay_heartbeat: bool,
+
+    // Where to log telemetry for every runloop iteration, and how large the file may grow before it is rotated. See
+    // `telemetry/csv_log.rs`.
+    pub telemetry_csv_file: Option<PathBuf>,
+    pub telemetry_csv_max_size_mb: u64,
+    // Where to send live telemetry packets, e.g. the broadcast address of the network, and how many per second. See
+    // `telemetry/udp_stream.rs`.
+    pub telemetry_udp_destination: Option<SocketAddr>,
+    pub telemetry_udp_rate_hz: u32,
+
+    // The evdev device of the keyboard to drive with, preferably one of the stable links in /dev/input/by-id.
+    pub keyboard_device_file: PathBuf,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Self {
+            runloop_interval: Duration::from_millis(10),
+            locomotion_refresh_interval: Duration::from_millis(100),
+            locomotion_pwm_tables_enabled: false,
+            failsafe_brake_ramp: Duration::from_millis(500),
+            failsafe_policy: FailsafePolicy::default(),
+            idle_timeout: None,
+            inactivity_lock_timeout: None,
+            guest_lock_engaged: false,
+            guest_lock_max_speed_percent: 30,
+            guest_lock_unlock_sequence: Vec::new(),
+            i2c_device_file: PathBuf::from(I2C_DEVICE_FILE),
+            pca9685_i2c_device_file: None,
+            i2c_latency_warning_threshold: DEFAULT_WRITE_LATENCY_THRESHOLD,
+            i2c_recovery_after_errors: None,
+            pca9685: PCA9685Settings::default(),
+            channels: OutputChannels::default(),
+            steering_calibration_file: PathBuf::from("/var/lib/roestbak/steering_calibration"),
+            esc_arming_sequence: Vec::new(),
+            thermal_sensor_address: None,
+            thermal_derate_from: 70.0,
+            thermal_limit: 90.0,
+            thermal_max_speed_at_limit_percent: 25,
+            fan_gpio_chip: PathBuf::from("/dev/gpiochip0"),
+            fan_gpio_line: None,
+            fan_on_above: 50.0,
+            fan_off_below: 40.0,
+            gps_device_file: None,
+            gps_baud_rate: 9600,
+            geofence_center: None,
+            geofence_radius_m: 100.0,
+            geofence_polygon: Vec::new(),
+            geofence_margin_m: 20.0,
+            geofence_speed_outside_percent: 20,
+            driving: DrivingSettings::default(),
+            gamepad_lights_enabled: false,
+            gamepad_rumble_patterns: RumblePatterns::default(),
+            lighting_turn_signal_threshold_percent: 50,
+            lighting_turn_signal_delay: Duration::from_millis(500),
+            auxiliary_axis: AuxiliaryAxis::RightStickVertical,
+            auxiliary_output: AuxiliaryOutput::Brightness,
+            auxiliary_mode: AuxiliaryMode::Incremental,
+            auxiliary_full_range_time: Duration::from_secs(2),
+            auxiliary_smoothing: Duration::from_millis(150),
+            gimbal_pan_pulse_widths: PulseWidths {
+                negative_us: 1000,
+                center_us: 1500,
+                positive_us: 2000,
+            },
+            gimbal_tilt_pulse_widths: PulseWidths {
+                negative_us: 1000,
+                center_us: 1500,
+                positive_us: 2000,
+            },
+            gimbal_full_deflection_time: Duration::from_millis(500),
+            gimbal_center_on_release: true,
+            winch_pulse_widths: PulseWidths {
+                negative_us: 1000,
+                center_us: 1500,
+                positive_us: 2000,
+            },
+            winch_speed_percent: 100,
+            engine_sound_device_file: None,
+            engine_sound_volume_percent: 50,
+            engine_sound_idle_frequency: 25.0,
+            engine_sound_max_frequency: 120.0,
+            seccomp_enabled: false,
+            self_test_enabled: false,
+            session_summary_file: None,
+            odometer_state_file: PathBuf::from("/var/lib/roestbak/odometer"),
+            control_socket_file: PathBuf::from("/run/roestbak/control.sock"),
+            status_file_enabled: true,
+            status_file: PathBuf::from("/run/roestbak/status.json"),
+            daemon_log_file: PathBuf::from("/var/log/roestbak.log"),
+            daemon_pid_file: PathBuf::from("/run/roestbak.pid"),
+            dbus_enabled: false,
+            mdns_enabled: false,
+            mdns_instance_name: None,
+            choreography_file: None,
+            input_source: InputSourceKind::Gamepad,
+            copilot_source: None,
+            copilot_authority_percent: 50,
+            arbitration_takeover_ramp: Duration::from_millis(200),
+            gamepad: GamepadSettings::default(),
+            gamepad_devices: GamepadDeviceRules::default(),
+            gamepad_mapping_file: None,
+            sbus_device_file: PathBuf::from("/dev/serial0"),
+            // Surface radios conventionally put steering on channel 1 and throttle on channel 2.
+            sbus_channel_mapping: ChannelMapping {
+                throttle_channel: 2,
+                steering_channel: 1,
+                throttle_reversed: false,
+                steering_reversed: false,
+            },
+            mavlink_listen_address: SocketAddr::from(([0, 0, 0, 0], 14555)),
+            mavlink_ground_control_station_address: None,
+            // This matches ArduPilot's Rover defaults.
+            mavlink_channel_mapping: ChannelMapping {
+                throttle_channel: 3,
+                steering_channel: 1,
+                throttle_reversed: false,
+                steering_reversed: false,
+            },
+            mavlink_signing_passphrase: None,
+            mavlink_require_signing: false,
+            kill_relay_gpio_chip: PathBuf::from("/dev/gpiochip0"),
+            kill_relay_line: None,
+            kill_relay_active_low: false,
+            kill_relay_max_gap: Duration::from_millis(100),
+            kill_relay_heartbeat: false,
+            telemetry_csv_file: None,
+            telemetry_csv_max_size_mb: 16,
+            telemetry_udp_destination: None,
+            telemetry_udp_rate_hz: 10,
+            keyboard_device_file: PathBuf::from("/dev/input/event0"),
+        }
+    }
+}
+
+impl Configuration {
+    /// Load the configuration from `path`, or from the default location if no path is given. The default file is
+    /// allowed to be missing, an explicitly given one is not.
+    pub fn load(path: Option<&Path>, vehicle: Option<&str>) -> Result<Configuration, LoadError> {
+        let required = path.is_some();
+        let path = Self::file_path(path);
+
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == ErrorKind::NotFound && !required => {
+                log::info!(
+                    "No configuration file at {}, using defaults.",
+                    path.display()
+                );
+                // Still fails when a vehicle was selected, as it cannot be found.
+                let mut configuration = Self::parse("", vehicle)?;
+                configuration.apply_environment(|name| std::env::var_os(name));
+                return Ok(configuration);
+            }
+            Err(source) => {
+                return Err(LoadError::CouldNotReadFile {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        };
+
+        let mut configuration = Self::parse(&text, vehicle)?;
+        configuration.apply_environment(|name| std::env::var_os(name));
+        match vehicle {
+            Some(vehicle) => log::info!(
+                "Loaded configuration for vehicle {} from {}.",
+                vehicle,
+                path.display()
+            ),
+            None => log::info!("Loaded configuration from {}.", path.display()),
+        }
+
+        Ok(configuration)
+    }
+
+    // Replaces the device paths given by the environment variables `variable` finds, see above.
+    fn apply_environment(&mut self, variable: impl Fn(&str) -> Option<OsString>) {
+        let device_paths = [
+            (I2C_DEVICE_ENVIRONMENT_VARIABLE, &mut self.i2c_device_file),
+            (
+                INPUT_FOLDER_ENVIRONMENT_VARIABLE,
+                &mut self.gamepad_devices.folder,
+            ),
+            (
+                GPIO_CHIP_ENVIRONMENT_VARIABLE,
+                &mut self.kill_relay_gpio_chip,
+            ),
+            (SBUS_DEVICE_ENVIRONMENT_VARIABLE, &mut self.sbus_device_file),
+            (
+                KEYBOARD_DEVICE_ENVIRONMENT_VARIABLE,
+                &mut self.keyboard_device_file,
+            ),
+        ];
+
+        for (name, path) in device_paths {
+            if let Some(value) = variable(name).filter(|value| !value.is_empty()) {
+                *path = PathBuf::from(value);
+                log::info!("Using {} from {}.", path.display(), name);
+            }
+        }
+    }
+
+    // The I2C bus the PCA9685 is on.
+    pub fn pca9685_bus(&self) -> &Path {
+        self.pca9685_i2c_device_file
+            .as_deref()
+            .unwrap_or(&self.i2c_device_file)
+    }
+
+    // The file `load` reads from.
+    pub fn file_path(path: Option<&Path>) -> &Path {
+        path.unwrap_or(Path::new(DEFAULT_CONFIGURATION_FILE))
+    }
+
+    pub fn parse(text: &str, vehicle: Option<&str>) -> Result<Configuration, LoadError> {
+        let mut configuration = Configuration::default();
+        let mut vehicle_entries: BTreeMap<String, Vec<Entry>> = BTreeMap::new();
+
+        for entry in parse_entries(text)? {
+            match entry.key.strip_prefix(VEHICLE_PREFIX) {
+                Some(vehicle_key) => {
+                    let (name, key) = vehicle_key
+                        .split_once('.')
+                        .filter(|(name, key)| !name.is_empty() && !key.is_empty())
+                        .ok_or_else(|| LoadError::UnknownSetting {
+                            line: entry.line,
+                            key: entry.key.clone(),
+                        })?;
+                    let (name, key) = (name.to_string(), key.to_string());
+
+                    vehicle_entries
+                        .entry(name)
+                        .or_default()
+                        .push(Entry { key, ..entry });
+                }
+                None => configuration.apply(entry)?,
+            }
+        }
+
+        // Every vehicle's settings are applied on top of the common ones, so that mistakes are caught regardless of
+        // which vehicle is selected.
+        let mut selected_configuration = None;
+        for (name, entries) in vehicle_entries {
+            let mut vehicle_configuration = configuration.clone();
+            for entry in entries {
+                vehicle_configuration.apply(entry)?;
+            }
+
+            if vehicle == Some(name.as_str()) {
+                selected_configuration = Some(vehicle_configuration);
+            }
+        }
+
+        match (vehicle, selected_configuration) {
+            (None, _) => Ok(configuration),
+            (Some(_), Some(selected_configuration)) => Ok(selected_configuration),
+            (Some(vehicle), None) => Err(LoadError::UnknownVehicle {
+                name: vehicle.to_string(),
+            }),
+        }
+    }
+
+    fn apply(&mut self, entry: Entry) -> Result<(), LoadError> {
+        match entry.key.as_str() {
+            "runloop.interval_ms" => {
+                self.runloop_interval = entry.parse_milliseconds(1..=1000)?;
+            }
+            "locomotion.refresh_interval_ms" => {
+                self.locomotion_refresh_interval = entry.parse_milliseconds(1..=10_000)?;
+            }
+            "locomotion.low_latency" => {
+                self.locomotion_pwm_tables_enabled = entry.parse()?;
+            }
+            "failsafe.brake_ramp_ms" => {
+                self.failsafe_brake_ramp = entry.parse_milliseconds(0..=5000)?;
+            }
+            "failsafe.on_input_lost" => {
+                self.failsafe_policy.input_lost = Some(entry.parse()?);
+            }
+            "failsafe.on_link_lost" => {
+                self.failsafe_policy.link_lost = Some(entry.parse()?);
+            }
+            "failsafe.on_geofence_breach" => {
+                self.failsafe_policy.geofence_breach = Some(entry.parse()?);
+            }
+            "idle.disarm_after_minutes" => {
+                let minutes: u64 = entry.parse_in_range(1..=24 * 60)?;
+                self.idle_timeout = Some(Duration::from_secs(minutes * 60));
+            }
+            "inactivity_lock.after_seconds" => {
+                let seconds: u64 = entry.parse_in_range(5..=60 * 60)?;
+                self.inactivity_lock_timeout = Some(Duration::from_secs(seconds));
+            }
+            "guest_lock.engaged" => {
+                self.guest_lock_engaged = entry.parse()?;
+            }
+            "guest_lock.max_speed_percent" => {
+                self.guest_lock_max_speed_percent = entry.parse_in_range(1..=100)?;
+            }
+            "guest_lock.unlock_sequence" => {
+                self.guest_lock_unlock_sequence = entry.parse_list()?;
+            }
+            "i2c.device_file" => {
+                self.i2c_device_file = entry.parse()?;
+            }
+            "i2c.latency_warning_ms" => {
+                self.i2c_latency_warning_threshold = entry.parse_milliseconds(1..=100)?;
+            }
+            "i2c.recovery_after_errors" => {
+                self.i2c_recovery_after_errors = Some(entry.parse_in_range(1..=100)?);
+            }
+            "pca9685.device_file" => {
+                self.pca9685_i2c_device_file = Some(entry.parse()?);
+            }
+            "pca9685.force_address" => {
+                self.pca9685.force_address = entry.parse()?;
+            }
+            "pca9685.stagger_outputs" => {
+                self.pca9685.stagger_outputs = entry.parse()?;
+            }
+            "pca9685.frequency_correction" => {
+                self.pca9685.frequency_correction = entry.parse_in_range(0.9..=1.1)?;
+            }
+            "pca9685.external_clock_hz" => {
+                // The datasheet allows for up to 50 MHz.
+                self.pca9685.external_clock_frequency = Some(entry.parse_in_range(1..=50_000_000)?);
+            }
+            "steering.calibration_file" => {
+                self.steering_calibration_file = entry.parse()?;
+            }
+            "esc.arming_sequence" => {
+                self.esc_arming_sequence = entry.parse_list()?;
+            }
+            "thermal.sensor_address" => {
+                // Written the way `i2cdetect` shows it, e.g. `0x48`. The TMP102 can only be at 0x48 to 0x4B.
+                let address = entry
+                    .value
+                    .strip_prefix("0x")
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .filter(|address| (0x48..=0x4B).contains(address))
+                    .ok_or_else(|| entry.invalid_value())?;
+                self.thermal_sensor_address = Some(address);
+            }
+            "thermal.derate_from_celsius" => {
+                self.thermal_derate_from = entry.parse_in_range(0.0..=150.0)?;
+            }
+            "thermal.limit_celsius" => {
+                self.thermal_limit = entry.parse_in_range(0.0..=150.0)?;
+            }
+            "thermal.max_speed_at_limit_percent" => {
+                self.thermal_max_speed_at_limit_percent = entry.parse_in_range(0..=100)?;
+            }
+            "fan.channel" => {
+                self.channels
+                    .assign(Output::Fan, entry.parse_pca9685_channel()?);
+            }
+            "fan.gpio_chip" => {
+                self.fan_gpio_chip = entry.parse()?;
+            }
+            "fan.gpio_line" => {
+                self.fan_gpio_line = Some(entry.parse()?);
+            }
+            "fan.on_above_celsius" => {
+                self.fan_on_above = entry.parse_in_range(0.0..=150.0)?;
+            }
+            "fan.off_below_celsius" => {
+                self.fan_off_below = entry.parse_in_range(0.0..=150.0)?;
+            }
+            "gps.device_file" => {
+                self.gps_device_file = Some(entry.parse()?);
+            }
+            "gps.baud_rate" => {
+                self.gps_baud_rate = entry.parse_in_range(4800..=921_600)?;
+            }
+            "geofence.center" => {
+                self.geofence_center = Some(entry.parse()?);
+            }
+            "geofence.radius_m" => {
+                self.geofence_radius_m = entry.parse_in_range(1.0..=100_000.0)?;
+            }
+            "geofence.polygon" => {
+                self.geofence_polygon = entry.parse_list()?;
+                if self.geofence_polygon.len() < 3 {
+                    return Err(entry.invalid_value());
+                }
+            }
+            "geofence.margin_m" => {
+                self.geofence_margin_m = entry.parse_in_range(1.0..=1000.0)?;
+            }
+            "geofence.speed_outside_percent" => {
+                self.geofence_speed_outside_percent = entry.parse_in_range(0..=100)?;
+            }
+            "driving.deadzone" => {
+                self.driving.deadzone = entry.parse_in_range(0.0..=0.5)?;
+            }
+            "driving.throttle_expo" => {
+                self.driving.throttle_expo = entry.parse_in_range(0.0..=1.0)?;
+            }
+            "driving.steering_expo" => {
+                self.driving.steering_expo = entry.parse_in_range(0.0..=1.0)?;
+            }
+            "driving.max_speed_percent" => {
+                self.driving.max_speed_percent = entry.parse_in_range(1..=100)?;
+            }
+            "driving.min_throttle" => {
+                self.driving.min_throttle = entry.parse_in_range(0.0..=0.5)?;
+            }
+            "driving.creep_below" => {
+                self.driving.creep_below = entry.parse_in_range(0.0..=0.5)?;
+            }
+            "status_led.channel" => {
+                self.channels
+                    .assign(Output::StatusLed, entry.parse_pca9685_channel()?);
+            }
+            "buzzer.channel" => {
+                self.channels
+                    .assign(Output::Buzzer, entry.parse_pca9685_channel()?);
+            }
+            "lighting.brake_channel" => {
+                self.channels
+                    .assign(Output::BrakeLight, entry.parse_pca9685_channel()?);
+            }
+            "lighting.left_turn_channel" => {
+                self.channels
+                    .assign(Output::LeftTurnSignal, entry.parse_pca9685_channel()?);
+            }
+            "lighting.right_turn_channel" => {
+                self.channels
+                    .assign(Output::RightTurnSignal, entry.parse_pca9685_channel()?);
+            }
+            "lighting.turn_signal_threshold_percent" => {
+                self.lighting_turn_signal_threshold_percent = entry.parse_in_range(1..=100)?;
+            }
+            "lighting.turn_signal_delay_ms" => {
+                self.lighting_turn_signal_delay = entry.parse_milliseconds(0..=10_000)?;
+            }
+            "aux.channel" => {
+                self.channels
+                    .assign(Output::Auxiliary, entry.parse_pca9685_channel()?);
+            }
+            "aux.axis" => {
+                self.auxiliary_axis = entry.parse()?;
+            }
+            "aux.output" => {
+                self.auxiliary_output = entry.parse()?;
+            }
+            "aux.mode" => {
+                self.auxiliary_mode = entry.parse()?;
+            }
+            "aux.full_range_ms" => {
+                self.auxiliary_full_range_time = entry.parse_milliseconds(100..=60_000)?;
+            }
+            "aux.smoothing_ms" => {
+                self.auxiliary_smoothing = entry.parse_milliseconds(0..=5000)?;
+            }
+            "gimbal.pan_channel" => {
+                self.channels
+                    .assign(Output::GimbalPan, entry.parse_pca9685_channel()?);
+            }
+            "gimbal.tilt_channel" => {
+                self.channels
+                    .assign(Output::GimbalTilt, entry.parse_pca9685_channel()?);
+            }
+            "gimbal.pan_pulse_widths" => {
+                self.gimbal_pan_pulse_widths = entry.parse()?;
+            }
+            "gimbal.tilt_pulse_widths" => {
+                self.gimbal_tilt_pulse_widths = entry.parse()?;
+            }
+            "gimbal.full_deflection_ms" => {
+                self.gimbal_full_deflection_time = entry.parse_milliseconds(50..=10_000)?;
+            }
+            "gimbal.center_on_release" => {
+                self.gimbal_center_on_release = entry.parse()?;
+            }
+            "winch.channel" => {
+                self.channels
+                    .assign(Output::Winch, entry.parse_pca9685_channel()?);
+            }
+            "winch.pulse_widths" => {
+                self.winch_pulse_widths = entry.parse()?;
+            }
+            "winch.speed_percent" => {
+                self.winch_speed_percent = entry.parse_in_range(1..=100)?;
+            }
+            "engine_sound.device_file" => {
+                self.engine_sound_device_file = Some(entry.parse()?);
+            }
+            "engine_sound.volume_percent" => {
+                self.engine_sound_volume_percent = entry.parse_in_range(1..=100)?;
+            }
+            "engine_sound.idle_frequency_hz" => {
+                self.engine_sound_idle_frequency = entry.parse_in_range(5.0..=500.0)?;
+            }
+            "engine_sound.max_frequency_hz" => {
+                self.engine_sound_max_frequency = entry.parse_in_range(5.0..=500.0)?;
+            }
+            "seccomp.enabled" => {
+                self.seccomp_enabled = entry.parse()?;
+            }
+            "self_test.enabled" => {
+                self.self_test_enabled = entry.parse()?;
+            }
+            "session.summary_file" => {
+                self.session_summary_file = Some(entry.parse()?);
+            }
+            "odometer.state_file" => {
+                self.odometer_state_file = entry.parse()?;
+            }
+            "control.socket_file" => {
+                self.control_socket_file = entry.parse()?;
+            }
+            "status.enabled" => {
+                self.status_file_enabled = entry.parse()?;
+            }
+            "status.file" => {
+                self.status_file = entry.parse()?;
+            }
+            "daemon.log_file" => {
+                self.daemon_log_file = entry.parse()?;
+            }
+            "daemon.pid_file" => {
+                self.daemon_pid_file = entry.parse()?;
+            }
+            "dbus.enabled" => {
+                self.dbus_enabled = entry.parse()?;
+                if self.dbus_enabled {
+                    entry.require_feature("dbus", cfg!(feature = "dbus"))?;
+                }
+            }
+            "mdns.enabled" => {
+                self.mdns_enabled = entry.parse()?;
+                if self.mdns_enabled {
+                    entry.require_feature("mdns", cfg!(feature = "mdns"))?;
+                }
+            }
+            "mdns.instance_name" => {
+                self.mdns_instance_name = Some(entry.parse()?);
+            }
+            "choreography.file" => {
+                self.choreography_file = Some(entry.parse()?);
+            }
+            "input.source" => {
+                self.input_source = entry.parse()?;
+                if self.input_source == InputSourceKind::Mavlink {
+                    entry.require_feature("mavlink", cfg!(feature = "mavlink"))?;
+                }
+            }
+            "copilot.source" => {
+                self.copilot_source = Some(entry.parse()?);
+                if self.copilot_source == Some(InputSourceKind::Mavlink) {
+                    entry.require_feature("mavlink", cfg!(feature = "mavlink"))?;
+                }
+            }
+            "copilot.authority_percent" => {
+                self.copilot_authority_percent = entry.parse_in_range(0..=100)?;
+            }
+            "arbitration.takeover_ramp_ms" => {
+                self.arbitration_takeover_ramp = entry.parse_milliseconds(0..=5_000)?;
+            }
+            "gamepad.lights" => {
+                self.gamepad_lights_enabled = entry.parse()?;
+            }
+            "gamepad.rumble_armed" => {
+                self.gamepad_rumble_patterns.armed = entry.parse()?;
+            }
+            "gamepad.rumble_disarmed" => {
+                self.gamepad_rumble_patterns.disarmed = entry.parse()?;
+            }
+            "gamepad.rumble_low_battery" => {
+                self.gamepad_rumble_patterns.low_battery = entry.parse()?;
+            }
+            "gamepad.rumble_collision_warning" => {
+                self.gamepad_rumble_patterns.collision_warning = entry.parse()?;
+            }
+            "gamepad.device_folder" => {
+                self.gamepad_devices.folder = entry.parse()?;
+            }
+            "gamepad.device_pattern" => {
+                self.gamepad_devices.file_name_pattern = entry.parse()?;
+            }
+            "gamepad.allowed_devices" => {
+                self.gamepad_devices.allowed_ids = entry.parse_list()?;
+            }
+            "gamepad.mapping_file" => {
+                self.gamepad_mapping_file = Some(entry.parse()?);
+            }
+            "gamepad.profile" => {
+                self.gamepad.profile = entry.parse()?;
+            }
+            "gamepad.trigger_brake" => {
+                self.gamepad.trigger_brake = entry.parse()?;
+            }
+            "gamepad.boost_button" => {
+                self.gamepad.boost_button = Some(entry.parse()?);
+            }
+            "gamepad.boost_duration_ms" => {
+                self.gamepad.boost_duration = entry.parse_milliseconds(100..=60_000)?;
+            }
+            "gamepad.winch_modifier" => {
+                self.gamepad.winch_modifier = Some(entry.parse()?);
+            }
+            "gamepad.gimbal_button" => {
+                self.gamepad.gimbal_button = Some(entry.parse()?);
+            }
+            "gamepad.tilt_steering_button" => {
+                self.gamepad.tilt_steering_button = Some(entry.parse()?);
+            }
+            "gamepad.tilt_steering_angle" => {
+                self.gamepad.tilt_steering_angle = entry.parse_in_range(5.0..=90.0)?;
+            }
+            "gamepad.touchpad_swipe_up" => {
+                self.gamepad.touchpad.swipe_up = Some(entry.parse()?);
+            }
+            "gamepad.touchpad_swipe_down" => {
+                self.gamepad.touchpad.swipe_down = Some(entry.parse()?);
+            }
+            "gamepad.touchpad_swipe_left" => {
+                self.gamepad.touchpad.swipe_left = Some(entry.parse()?);
+            }
+            "gamepad.touchpad_swipe_right" => {
+                self.gamepad.touchpad.swipe_right = Some(entry.parse()?);
+            }
+            "gamepad.touchpad_tap_left" => {
+                self.gamepad.touchpad.tap_left = Some(entry.parse()?);
+            }
+            "gamepad.touchpad_tap_right" => {
+                self.gamepad.touchpad.tap_right = Some(entry.parse()?);
+            }
+            "gamepad.touchpad_speed_limit_step" => {
+                self.gamepad.touchpad_speed_limit_step = entry.parse_in_range(1..=50)?;
+            }
+            "gamepad.boost_cooldown_ms" => {
+                self.gamepad.boost_cooldown = entry.parse_milliseconds(0..=600_000)?;
+            }
+            "sbus.device_file" => {
+                self.sbus_device_file = entry.parse()?;
+            }
+            "sbus.throttle_channel" => {
+                self.sbus_channel_mapping.throttle_channel = entry.parse_channel(16)?;
+            }
+            "sbus.steering_channel" => {
+                self.sbus_channel_mapping.steering_channel = entry.parse_channel(16)?;
+            }
+            "sbus.throttle_reversed" => {
+                self.sbus_channel_mapping.throttle_reversed = entry.parse()?;
+            }
+            "sbus.steering_reversed" => {
+                self.sbus_channel_mapping.steering_reversed = entry.parse()?;
+            }
+            "kill_relay.gpio_chip" => {
+                self.kill_relay_gpio_chip = entry.parse()?;
+            }
+            "kill_relay.line" => {
+                self.kill_relay_line = Some(entry.parse()?);
+            }
+            "kill_relay.active_low" => {
+                self.kill_relay_active_low = entry.parse()?;
+            }
+            "kill_relay.max_gap_ms" => {
+                self.kill_relay_max_gap = entry.parse_milliseconds(10..=10_000)?;
+            }
+            "kill_relay.heartbeat" => {
+                self.kill_relay_heartbeat = entry.parse()?;
+            }
+            "telemetry.csv_file" => {
+                self.telemetry_csv_file = Some(entry.parse()?);
+            }
+            "telemetry.csv_max_size_mb" => {
+                self.telemetry_csv_max_size_mb = entry.parse_in_range(1..=1024)?;
+            }
+            "telemetry.udp_destination" => {
+                self.telemetry_udp_destination = Some(entry.parse()?);
+            }
+            "telemetry.udp_rate_hz" => {
+                self.telemetry_udp_rate_hz = entry.parse_in_range(1..=100)?;
+            }
+            "keyboard.device_file" => {
+                self.keyboard_device_file = entry.parse()?;
+            }
+            "mavlink.listen_address" => {
+                self.mavlink_listen_address = entry.parse()?;
+            }
+            "mavlink.ground_control_station_address" => {
+                self.mavlink_ground_control_station_address = Some(entry.parse()?);
+            }
+            "mavlink.throttle_channel" => {
+                self.mavlink_channel_mapping.throttle_channel = entry.parse_channel(18)?;
+            }
+            "mavlink.steering_channel" => {
+                self.mavlink_channel_mapping.steering_channel = entry.parse_channel(18)?;
+            }
+            "mavlink.throttle_reversed" => {
+                self.mavlink_channel_mapping.throttle_reversed = entry.parse()?;
+            }
+            "mavlink.steering_reversed" => {
+                self.mavlink_channel_mapping.steering_reversed = entry.parse()?;
+            }
+            "mavlink.signing_passphrase" => {
+                self.mavlink_signing_passphrase = Some(entry.parse()?);
+            }
+            "mavlink.require_signing" => {
+                self.mavlink_require_signing = entry.parse()?;
+            }
+            key => match key.strip_prefix("channels.").map(str::parse::<Output>) {
+                Some(Ok(output)) => {
+                    self.channels.assign(output, entry.parse_pca9685_channel()?);
+                }
+                _ => {
+                    return Err(LoadError::UnknownSetting {
+                        line: entry.line,
+                        key: entry.key,
+                    })
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    CouldNotReadFile {
+        path: PathBuf,
+        source: IoError,
+    },
+    InvalidSyntax {
+        line: usize,
+    },
+    UnknownSetting {
+        line: usize,
+        key: String,
+    },
+    InvalidValue {
+        line: usize,
+        key: String,
+        value: String,
+    },
+    UnknownVehicle {
+        name: String,
+    },
+    FeatureNotBuilt {
+        line: usize,
+        key: String,
+        feature: &'static str,
+    },
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::CouldNotReadFile { path: _, source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            LoadError::CouldNotReadFile { path, source: _ } => {
+                format!("Could not read configuration file at {}.", path.display())
+            }
+            LoadError::InvalidSyntax { line } => {
+                format!("Invalid syntax in configuration file on line {}.", line)
+            }
+            LoadError::UnknownSetting { line, key } => {
+                format!(
+                    "Unknown setting {} in configuration file on line {}.",
+                    key, line
+                )
+            }
+            LoadError::InvalidValue { line, key, value } => {
+                format!(
+                    "Invalid value '{}' for setting {} in configuration file on line {}.",
+                    value, key, line
+                )
+            }
+            LoadError::UnknownVehicle { name } => {
+                format!("No settings for vehicle {} in configuration file.", name)
+            }
+            LoadError::FeatureNotBuilt { line, key, feature } => {
+                format!(
+                    "Setting {} in configuration file on line {} needs the {} feature, which this build lacks.",
+                    key, line, feature
+                )
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+struct Entry {
+    line: usize,
+    key: String,
+    value: String,
+}
+
+impl Entry {
+    fn parse<T: FromStr>(&self) -> Result<T, LoadError> {
+        self.value.parse().map_err(|_| self.invalid_value())
+    }
+
+    // A comma separated list, e.g. `045e:02fd, 054c`.
+    fn parse_list<T: FromStr>(&self) -> Result<Vec<T>, LoadError> {
+        self.value
+            .split(',')
+            .map(|item| item.trim().parse().map_err(|_| self.invalid_value()))
+            .collect()
+    }
+
+    fn parse_channel(&self, number_of_channels: u8) -> Result<u8, LoadError> {
+        self.parse()
+            .ok()
+            .filter(|channel| (1..=number_of_channels).contains(channel))
+            .ok_or_else(|| self.invalid_value())
+    }
+
+    // Whether another output is on the same channel is left to `configuration_check`.
+    fn parse_pca9685_channel(&self) -> Result<u8, LoadError> {
+        self.parse()
+            .ok()
+            .filter(|channel| (0..=15).contains(channel))
+            .ok_or_else(|| self.invalid_value())
+    }
+
+    fn parse_milliseconds(&self, range: RangeInclusive<u64>) -> Result<Duration, LoadError> {
+        self.parse_in_range(range).map(Duration::from_millis)
+    }
+
+    fn parse_in_range<T: FromStr + PartialOrd>(
+        &self,
+        range: RangeInclusive<T>,
+    ) -> Result<T, LoadError> {
+        self.parse()
+            .ok()
+            .filter(|value| range.contains(value))
+            .ok_or_else(|| self.invalid_value())
+    }
+
+    // Turning on a subsystem that was left out of the build is an error rather than silently doing nothing.
+    fn require_feature(&self, feature: &'static str, enabled: bool) -> Result<(), LoadError> {
+        if enabled {
+            Ok(())
+        } else {
+            Err(LoadError::FeatureNotBuilt {
+                line: self.line,
+                key: self.key.clone(),
+                feature,
+            })
+        }
+    }
+
+    fn invalid_value(&self) -> LoadError {
+        LoadError::InvalidValue {
+            line: self.line,
+            key: self.key.clone(),
+            value: self.value.clone(),
+        }
+    }
+}
+
+fn parse_entries(text: &str) -> Result<Vec<Entry>, LoadError> {
+    let mut entries = Vec::new();
+    let mut section: Option<&str> = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(LoadError::InvalidSyntax { line: line_number });
+            }
+            section = Some(name);
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or(LoadError::InvalidSyntax { line: line_number })?;
+
+        let key = match section {
+            Some(section) => format!("{}.{}", section, key),
+            None => key.to_string(),
+        };
+
+        entries.push(Entry {
+            line: line_number,
+            key,
+            value: value.to_string(),
+        });
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,413 @@
+use crate::choreography::Choreography;
+use crate::configuration::Configuration;
+use crate::gamepads::{Button, MappingDatabase};
+use crate::input_source::{ChannelMapping, InputSourceKind};
+use crate::locomotion::{self, Output, PulseWidths, PWM_FREQUENCY};
+use std::path::Path;
+
+// Checks that go beyond what parsing the configuration file already verifies: settings that are fine on their own
+// but conflict with each other, and files and devices that the service will need once it starts. Running these
+// before restarting the service on a new configuration avoids finding out about a mistake with the rover stuck.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Severity {
+    // The service will not start, or will not be able to drive the vehicle.
+    Error,
+    // The service will run, but something will not work as intended.
+    Warning,
+}
+
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = match self.severity {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARNING",
+        };
+
+        write!(f, "{}: {}", label, self.message)
+    }
+}
+
+pub fn check(configuration: &Configuration) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut error = |message: String| {
+        findings.push(Finding {
+            severity: Severity::Error,
+            message,
+        })
+    };
+
+    if !locomotion::is_supported_pwm_frequency(PWM_FREQUENCY, &configuration.pca9685) {
+        error(format!(
+            "A PWM frequency of {} Hz is outside of what the PCA9685 prescaler supports with this oscillator.",
+            PWM_FREQUENCY
+        ));
+    }
+    if !configuration.pca9685_bus().exists() {
+        error(format!(
+            "The I2C bus {} of the PCA9685 does not exist. Is I2C enabled?",
+            configuration.pca9685_bus().display()
+        ));
+    }
+
+    for (first, second, channel) in configuration.channels.clashes() {
+        error(format!(
+            "The {} and the {} are both configured on PCA9685 channel {}.",
+            first, second, channel
+        ));
+    }
+
+    match configuration.input_source {
+        InputSourceKind::Gamepad => {
+            if !configuration.gamepad_devices.folder.is_dir() {
+                error(format!(
+                    "The gamepad device folder {} does not exist.",
+                    configuration.gamepad_devices.folder.display()
+                ));
+            }
+        }
+        InputSourceKind::Sbus => {
+            check_channel_mapping("sbus", &configuration.sbus_channel_mapping, &mut error);
+            if !configuration.sbus_device_file.exists() {
+                error(format!(
+                    "The SBUS device {} does not exist.",
+                    configuration.sbus_device_file.display()
+                ));
+            }
+        }
+        InputSourceKind::Mavlink => {
+            check_channel_mapping(
+                "mavlink",
+                &configuration.mavlink_channel_mapping,
+                &mut error,
+            );
+            if configuration.mavlink_require_signing
+                && configuration.mavlink_signing_passphrase.is_none()
+            {
+                error(
+                    "MAVLink signing is required, but there is no signing passphrase.".to_string(),
+                );
+            }
+        }
+        InputSourceKind::Keyboard => {
+            if !configuration.keyboard_device_file.exists() {
+                error(format!(
+                    "The keyboard {} does not exist.",
+                    configuration.keyboard_device_file.display()
+                ));
+            }
+        }
+    }
+
+    if configuration.kill_relay_line.is_some() && !configuration.kill_relay_gpio_chip.exists() {
+        error(format!(
+            "The GPIO chip for the kill relay {} does not exist.",
+            configuration.kill_relay_gpio_chip.display()
+        ));
+    }
+
+    if configuration.copilot_source == Some(configuration.input_source) {
+        error(
+            "copilot.source is the same as input.source, but the instructor needs a different input source than the \
+             driver."
+                .to_string(),
+        );
+    }
+
+    if configuration.thermal_sensor_address.is_some()
+        && configuration.thermal_limit <= configuration.thermal_derate_from
+    {
+        error(
+            "thermal.limit_celsius has to be above thermal.derate_from_celsius for the throttle to be derated \
+             gradually."
+                .to_string(),
+        );
+    }
+
+    let fan_configured =
+        configuration.channels.get(Output::Fan).is_some() || configuration.fan_gpio_line.is_some();
+    if configuration.channels.get(Output::Fan).is_some() && configuration.fan_gpio_line.is_some() {
+        error(
+            "channels.fan and fan.gpio_line are both set, but the fan can only be on one of them."
+                .to_string(),
+        );
+    }
+    if fan_configured && configuration.thermal_sensor_address.is_none() {
+        error(
+            "A fan is configured, but it needs thermal.sensor_address to know when to run."
+                .to_string(),
+        );
+    }
+    if fan_configured && configuration.fan_off_below >= configuration.fan_on_above {
+        error("fan.off_below_celsius has to be below fan.on_above_celsius.".to_string());
+    }
+    if configuration.fan_gpio_line.is_some() && !configuration.fan_gpio_chip.exists() {
+        error(format!(
+            "The GPIO chip for the fan {} does not exist.",
+            configuration.fan_gpio_chip.display()
+        ));
+    }
+
+    if let Some(path) = &configuration.engine_sound_device_file {
+        if !path.exists() {
+            error(format!(
+                "The engine sound device {} does not exist.",
+                path.display()
+            ));
+        }
+    }
+    if configuration.engine_sound_idle_frequency >= configuration.engine_sound_max_frequency {
+        error(
+            "engine_sound.idle_frequency_hz has to be below engine_sound.max_frequency_hz."
+                .to_string(),
+        );
+    }
+
+    if let Some(path) = &configuration.gps_device_file {
+        if !path.exists() {
+            error(format!("The GPS device {} does not exist.", path.display()));
+        }
+        if configuration.input_source == InputSourceKind::Sbus
+            && *path == configuration.sbus_device_file
+        {
+            error(format!(
+                "The GPS module and the SBUS receiver are both configured on {}.",
+                path.display()
+            ));
+        }
+    }
+
+    let geofence_configured =
+        configuration.geofence_center.is_some() || !configuration.geofence_polygon.is_empty();
+    if configuration.geofence_center.is_some() && !configuration.geofence_polygon.is_empty() {
+        error("geofence.center and geofence.polygon are both set, but the geofence can only be one of them.".to_string());
+    }
+    if geofence_configured && configuration.gps_device_file.is_none() {
+        error(
+            "A geofence is configured, but it needs gps.device_file to know where the vehicle is."
+                .to_string(),
+        );
+    }
+    if configuration.channels.get(Output::GimbalPan).is_some()
+        != configuration.channels.get(Output::GimbalTilt).is_some()
+    {
+        error("A gimbal needs both channels.gimbal_pan and channels.gimbal_tilt.".to_string());
+    }
+
+    if let Some(path) = &configuration.choreography_file {
+        if let Err(load_error) = Choreography::load(path) {
+            error(format!("{}", load_error));
+        }
+    }
+
+    if let Some(path) = &configuration.gamepad_mapping_file {
+        if let Err(load_error) = MappingDatabase::load(path) {
+            error(format!("{}", load_error));
+        }
+    }
+
+    if !has_existing_parent(&configuration.control_socket_file) {
+        error(format!(
+            "The folder for the control socket {} does not exist.",
+            configuration.control_socket_file.display()
+        ));
+    }
+
+    let mut warning = |message: String| {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            message,
+        })
+    };
+
+    if !has_existing_parent(&configuration.odometer_state_file) {
+        warning(format!(
+            "The folder for the odometer state {} does not exist, so the odometer will not be saved.",
+            configuration.odometer_state_file.display()
+        ));
+    }
+    if configuration.failsafe_policy.geofence_breach.is_some() && !geofence_configured {
+        warning(
+            "failsafe.on_geofence_breach is set, but there is no geofence to breach.".to_string(),
+        );
+    }
+    if configuration.failsafe_policy.link_lost.is_some()
+        && configuration.input_source != InputSourceKind::Mavlink
+    {
+        warning(
+            "failsafe.on_link_lost is set, but only applies when input comes from MAVLink (input.source = mavlink)."
+                .to_string(),
+        );
+    }
+
+    if configuration.guest_lock_engaged && configuration.guest_lock_unlock_sequence.is_empty() {
+        warning(
+            "guest_lock.engaged is set without guest_lock.unlock_sequence, so only the control socket can unlock it."
+                .to_string(),
+        );
+    }
+
+    if configuration.channels.get(Output::Auxiliary).is_some()
+        && configuration.input_source != InputSourceKind::Gamepad
+    {
+        warning(
+            "channels.aux is set, but only a gamepad has axes to control it with (input.source = gamepad).".to_string(),
+        );
+    }
+    if configuration.driving.creep_below > 0.0 && configuration.driving.min_throttle == 0.0 {
+        warning(
+            "driving.creep_below has no effect without driving.min_throttle, which is what is pulsed.".to_string(),
+        );
+    }
+    if configuration.status_file_enabled && !has_existing_parent(&configuration.status_file) {
+        warning(format!(
+            "The folder for the status file {} does not exist, so no status will be written.",
+            configuration.status_file.display()
+        ));
+    }
+    if let Some(path) = &configuration.session_summary_file {
+        if !has_existing_parent(path) {
+            warning(format!(
+                "The folder for the session summary {} does not exist, so no summary will be written.",
+                path.display()
+            ));
+        }
+    }
+    if let Some(path) = &configuration.telemetry_csv_file {
+        if !has_existing_parent(path) {
+            warning(format!(
+                "The folder for the telemetry log {} does not exist, so no telemetry will be logged.",
+                path.display()
+            ));
+        }
+    }
+    if let Err(load_error) = PulseWidths::load(&configuration.steering_calibration_file) {
+        warning(format!(
+            "The steering calibration {} could not be loaded, so standard pulse widths will be used. - Cause: {}",
+            configuration.steering_calibration_file.display(),
+            load_error
+        ));
+    }
+    if configuration.input_source == InputSourceKind::Gamepad
+        && configuration.gamepad.boost_button == Some(Button::Y)
+    {
+        warning(
+            "The boost button is Y, which is also the choreography button. It will only boost."
+                .to_string(),
+        );
+    }
+    if configuration.input_source == InputSourceKind::Gamepad
+        && configuration.gamepad.tilt_steering_button.is_some()
+        && configuration.gamepad.tilt_steering_button == configuration.gamepad.boost_button
+    {
+        warning(
+            "The tilt steering button is also the boost button. It will only boost.".to_string(),
+        );
+    }
+    let gimbal_configured = configuration.channels.get(Output::GimbalPan).is_some()
+        || configuration.channels.get(Output::GimbalTilt).is_some();
+    if gimbal_configured && configuration.gamepad.gimbal_button.is_none() {
+        warning(
+            "A gimbal is configured, but without gamepad.gimbal_button it stays centered."
+                .to_string(),
+        );
+    }
+    if configuration.gamepad.gimbal_button.is_some()
+        && configuration.input_source == InputSourceKind::Gamepad
+        && [
+            configuration.gamepad.boost_button,
+            configuration.gamepad.tilt_steering_button,
+        ]
+        .contains(&configuration.gamepad.gimbal_button)
+    {
+        warning(
+            "The gimbal button is also the boost or tilt steering button. It will not switch the gimbal."
+                .to_string(),
+        );
+    }
+    if configuration.channels.get(Output::Winch).is_some()
+        && configuration.gamepad.winch_modifier.is_none()
+    {
+        warning(
+            "A winch is configured, but without gamepad.winch_modifier it cannot be run."
+                .to_string(),
+        );
+    }
+    if configuration.gamepad.winch_modifier.is_some()
+        && configuration.input_source == InputSourceKind::Gamepad
+        && [
+            configuration.gamepad.boost_button,
+            configuration.gamepad.tilt_steering_button,
+            configuration.gamepad.gimbal_button,
+        ]
+        .contains(&configuration.gamepad.winch_modifier)
+    {
+        warning(
+            "The winch modifier is also the boost, tilt steering or gimbal button. It will not run the winch."
+                .to_string(),
+        );
+    }
+    if configuration.kill_relay_line.is_some()
+        && configuration.kill_relay_max_gap <= configuration.runloop_interval
+    {
+        warning(format!(
+            "The kill relay's maximum gap ({} ms) is not longer than the runloop interval ({} ms), so it will open \
+             on the slightest delay.",
+            configuration.kill_relay_max_gap.as_millis(),
+            configuration.runloop_interval.as_millis()
+        ));
+    }
+    if configuration.input_source == InputSourceKind::Mavlink
+        && !configuration.mavlink_require_signing
+    {
+        warning(
+            "MAVLink signing is not required, so anyone on the network can drive the vehicle."
+                .to_string(),
+        );
+    }
+    if configuration.mdns_enabled
+        && configuration.telemetry_udp_destination.is_none()
+        && configuration.input_source != InputSourceKind::Mavlink
+        && configuration.copilot_source != Some(InputSourceKind::Mavlink)
+    {
+        warning(
+            "mDNS is enabled, but there is nothing to advertise: neither MAVLink input nor UDP telemetry is used."
+                .to_string(),
+        );
+    }
+    if configuration.locomotion_refresh_interval < configuration.runloop_interval {
+        warning(format!(
+            "The locomotion refresh interval ({} ms) is shorter than the runloop interval ({} ms), so it is \
+             effectively the runloop interval.",
+            configuration.locomotion_refresh_interval.as_millis(),
+            configuration.runloop_interval.as_millis()
+        ));
+    }
+
+    findings
+}
+
+fn check_channel_mapping(
+    section: &str,
+    channel_mapping: &ChannelMapping,
+    error: &mut impl FnMut(String),
+) {
+    if channel_mapping.throttle_channel == channel_mapping.steering_channel {
+        error(format!(
+            "{}.throttle_channel and {}.steering_channel both refer to channel {}.",
+            section, section, channel_mapping.throttle_channel
+        ));
+    }
+}
+
+fn has_existing_parent(path: &Path) -> bool {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.is_dir(),
+        _ => true,
+    }
+}
@@ -0,0 +1,141 @@
+use crate::configuration::Configuration;
+use crate::configuration_check::{self, Severity};
+use crate::folder_monitor::{FolderEvent, FolderMonitor, ProcessingError};
+use std::path::{Path, PathBuf};
+
+// Picks up changes to the configuration file while the service is running, either when the file is rewritten or when
+// asked to through SIGHUP. Only the driving settings are applied on the fly: changing anything else (devices,
+// channels, sockets) safely would mean tearing down and setting up most of the service again, so those changes are
+// reported as needing a restart instead.
+//
+// A changed file is only applied once it has been loaded and passes the same checks as `--check-config`, as a whole or
+// not at all. Should it fail, the service keeps running on the configuration it has, which is the last one known to
+// be good, and what is wrong is logged and reported by the status API until a reload succeeds.
+//
+// 💁‍♂️ The folder containing the file is watched, rather than the file itself, because editors typically save by
+// writing a new file and renaming it over the old one.
+
+pub struct ConfigurationReloader {
+    configuration_file: Option<PathBuf>,
+    vehicle: Option<String>,
+    watched_file: PathBuf,
+    folder_monitor: Option<FolderMonitor>,
+    // Why the last reload was refused, if it was: the first error, and how many more there were. All of them are
+    // logged, but a status response has no room for them.
+    last_error: Option<String>,
+}
+
+impl ConfigurationReloader {
+    pub fn new(configuration_file: Option<&Path>, vehicle: Option<&str>) -> ConfigurationReloader {
+        let watched_file = Configuration::file_path(configuration_file).to_path_buf();
+        let folder = match watched_file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        // Not being able to watch the file is no reason not to drive.
+        let folder_monitor = match FolderMonitor::new(folder) {
+            Ok(folder_monitor) => Some(folder_monitor),
+            Err(error) => {
+                log::warn!(
+                    "Not watching {} for changes, the configuration will only be reloaded on SIGHUP. - Cause: {}",
+                    watched_file.display(),
+                    error
+                );
+                None
+            }
+        };
+
+        ConfigurationReloader {
+            configuration_file: configuration_file.map(Path::to_path_buf),
+            vehicle: vehicle.map(str::to_string),
+            watched_file,
+            folder_monitor,
+            last_error: None,
+        }
+    }
+
+    // Returns whether the configuration file was written since the last call.
+    pub fn file_changed(&self) -> Result<bool, ProcessingError> {
+        let Some(folder_monitor) = &self.folder_monitor else {
+            return Ok(false);
+        };
+
+        let mut changed = false;
+        folder_monitor.process_filesystem_events(|event| match event {
+            FolderEvent::Added(path) | FolderEvent::Modified(path) => {
+                changed |= path.file_name() == self.watched_file.file_name();
+            }
+            FolderEvent::EventQueueOverflowed => changed = true,
+            _ => (),
+        })?;
+
+        Ok(changed)
+    }
+
+    // After the file could not be watched any more, it is only reloaded on SIGHUP.
+    pub fn stop_watching(&mut self) {
+        self.folder_monitor = None;
+    }
+
+    pub fn watched_file(&self) -> &Path {
+        &self.watched_file
+    }
+
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    // Reloads the file and applies what can be applied to `configuration`. A file that fails to load or to pass the
+    // checks leaves `configuration` untouched.
+    pub fn reload(&mut self, configuration: &mut Configuration) {
+        let new_configuration = match self.load_checked() {
+            Ok(new_configuration) => new_configuration,
+            Err(errors) => {
+                log::error!(
+                    "Keeping the current configuration, as the changed one is not usable. - Cause: {}",
+                    errors.join(" ")
+                );
+                self.last_error = Some(match errors.as_slice() {
+                    [first, others @ ..] if !others.is_empty() => {
+                        format!("{} ({} more errors, see the log)", first, others.len())
+                    }
+                    _ => errors.join(" "),
+                });
+                return;
+            }
+        };
+        self.last_error = None;
+
+        let driving = new_configuration.driving;
+        configuration.driving.log_changes(&driving);
+
+        let unapplied_configuration = Configuration {
+            driving: configuration.driving,
+            ..new_configuration
+        };
+        if unapplied_configuration != *configuration {
+            log::warn!("Some changed settings will only take effect after restarting the service.");
+        }
+
+        configuration.driving = driving;
+    }
+
+    // Returns what is wrong with the file, if anything.
+    fn load_checked(&self) -> Result<Configuration, Vec<String>> {
+        let new_configuration =
+            Configuration::load(self.configuration_file.as_deref(), self.vehicle.as_deref())
+                .map_err(|error| vec![error.to_string()])?;
+
+        let errors: Vec<String> = configuration_check::check(&new_configuration)
+            .into_iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .map(|finding| finding.message)
+            .collect();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(new_configuration)
+    }
+}
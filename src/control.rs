@@ -0,0 +1,7 @@
+mod client;
+mod protocol;
+mod server;
+
+pub use client::run_client;
+pub use protocol::{Request, Response};
+pub use server::ControlSocket;
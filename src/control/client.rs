@@ -0,0 +1,167 @@
+use super::protocol::{Request, Response, MAX_MESSAGE_SIZE};
+use crate::logging::format_duration;
+use std::error::Error;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Send a single request to a running service and print the response in a human friendly format.
+///
+/// Returns whether the service reported success.
+pub fn run_client(socket_file_path: &Path, request: Request) -> Result<bool, ClientError> {
+    let response = send_request(socket_file_path, request)?;
+
+    match response {
+        Response::Ok(fields) => {
+            if fields.is_empty() {
+                println!("OK.");
+            }
+
+            let label_width = fields
+                .iter()
+                .map(|(key, _)| key_to_label(key).len())
+                .max()
+                .unwrap_or(0);
+
+            for (key, value) in &fields {
+                println!(
+                    "{:<width$}  {}",
+                    format!("{}:", key_to_label(key)),
+                    format_value(key, value),
+                    width = label_width + 1
+                );
+            }
+
+            Ok(true)
+        }
+        Response::Error(message) => {
+            eprintln!("Error: {}", message);
+            Ok(false)
+        }
+    }
+}
+
+fn send_request(socket_file_path: &Path, request: Request) -> Result<Response, ClientError> {
+    // The service replies to the address a request was sent from, so the client socket needs an address too. An
+    // abstract address avoids having to clean up a socket file afterwards.
+    let client_address = SocketAddr::from_abstract_name(format!("roestbakctl-{}", process::id()))
+        .map_err(|source| ClientError::CouldNotCreateSocket { source })?;
+    let socket = UnixDatagram::bind_addr(&client_address)
+        .map_err(|source| ClientError::CouldNotCreateSocket { source })?;
+    socket
+        .set_read_timeout(Some(RESPONSE_TIMEOUT))
+        .map_err(|source| ClientError::CouldNotCreateSocket { source })?;
+
+    socket
+        .send_to(request.to_text().as_bytes(), socket_file_path)
+        .map_err(|source| ClientError::CouldNotSendRequest {
+            path: socket_file_path.to_path_buf(),
+            source,
+        })?;
+
+    let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+    let size = socket.recv(&mut buffer).map_err(|source| {
+        if source.kind() == ErrorKind::WouldBlock || source.kind() == ErrorKind::TimedOut {
+            ClientError::NoResponse
+        } else {
+            ClientError::CouldNotReceiveResponse { source }
+        }
+    })?;
+
+    std::str::from_utf8(&buffer[..size])
+        .ok()
+        .and_then(Response::parse)
+        .ok_or(ClientError::InvalidResponse)
+}
+
+// `speed_limit_percent` becomes "Speed limit".
+fn key_to_label(key: &str) -> String {
+    let key = key
+        .strip_suffix("_percent")
+        .or_else(|| key.strip_suffix("_seconds"))
+        .or_else(|| key.strip_suffix("_ms"))
+        .unwrap_or(key);
+
+    let mut label = key.replace('_', " ");
+    if let Some(first) = label.get_mut(0..1) {
+        first.make_ascii_uppercase();
+    }
+
+    label
+}
+
+fn format_value(key: &str, value: &str) -> String {
+    if key.ends_with("_percent") {
+        return format!("{}%", value);
+    }
+
+    if key.ends_with("_ms") {
+        return format!("{} ms", value);
+    }
+
+    if key.ends_with("_seconds") {
+        if let Some(duration) = value
+            .parse::<f64>()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        {
+            return format_duration(duration);
+        }
+    }
+
+    match value {
+        "true" => "yes".to_string(),
+        "false" => "no".to_string(),
+        _ => value.to_string(),
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    CouldNotCreateSocket { source: IoError },
+    CouldNotSendRequest { path: PathBuf, source: IoError },
+    CouldNotReceiveResponse { source: IoError },
+    NoResponse,
+    InvalidResponse,
+}
+
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClientError::CouldNotCreateSocket { source } => Some(source),
+            ClientError::CouldNotSendRequest { path: _, source } => Some(source),
+            ClientError::CouldNotReceiveResponse { source } => Some(source),
+            ClientError::NoResponse => None,
+            ClientError::InvalidResponse => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            ClientError::CouldNotCreateSocket { source: _ } => {
+                "Could not create client socket.".to_string()
+            }
+            ClientError::CouldNotSendRequest { path, source: _ } => format!(
+                "Could not send request to control socket at {} (is the service running?).",
+                path.display()
+            ),
+            ClientError::CouldNotReceiveResponse { source: _ } => {
+                "Could not receive response from service.".to_string()
+            }
+            ClientError::NoResponse => "No response from service.".to_string(),
+            ClientError::InvalidResponse => {
+                "Received an invalid response from service.".to_string()
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
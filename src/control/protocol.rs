@@ -0,0 +1,158 @@
+// Requests and responses are exchanged as single datagrams of UTF-8 text.
+//
+// A request is a command, optionally followed by an argument, e.g. `set-limit 50`.
+//
+// A response starts with a line reading either `ok` or `error`. For `ok`, the remaining lines are `key=value`
+// fields describing the outcome. For `error`, the remaining text is a human readable explanation.
+//
+// ⚠️ A datagram longer than the receiver's buffer is cut short without notice, losing whatever fields come last. Values
+// are therefore kept to a single line of at most `MAX_VALUE_LENGTH` characters, which leaves room for the status
+// response to grow by quite a few fields before reaching `MAX_MESSAGE_SIZE`.
+
+use crate::subsystems::SubsystemKind;
+
+pub const MAX_MESSAGE_SIZE: usize = 4096;
+const MAX_VALUE_LENGTH: usize = 200;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Request {
+    Status,
+    Arm,
+    Disarm,
+    // The maximum throttle as a percentage of full throttle.
+    SetSpeedLimit(u8),
+    // See `subsystems.rs`.
+    EnableSubsystem(SubsystemKind),
+    DisableSubsystem(SubsystemKind),
+    // See `guest_lock.rs`.
+    EngageGuestLock,
+    DisengageGuestLock,
+}
+
+impl Request {
+    pub fn parse(text: &str) -> Option<Request> {
+        let mut words = text.split_whitespace();
+        let command = words.next()?;
+        let argument = words.next();
+
+        if words.next().is_some() {
+            return None;
+        }
+
+        match (command, argument) {
+            ("status", None) => Some(Request::Status),
+            ("arm", None) => Some(Request::Arm),
+            ("disarm", None) => Some(Request::Disarm),
+            ("guest-lock", None) => Some(Request::EngageGuestLock),
+            ("guest-unlock", None) => Some(Request::DisengageGuestLock),
+            ("set-limit", Some(percentage)) => percentage
+                .parse::<u8>()
+                .ok()
+                .filter(|percentage| *percentage <= 100)
+                .map(Request::SetSpeedLimit),
+            ("enable", Some(name)) => name.parse().ok().map(Request::EnableSubsystem),
+            ("disable", Some(name)) => name.parse().ok().map(Request::DisableSubsystem),
+            _ => None,
+        }
+    }
+
+    pub fn to_text(self) -> String {
+        match self {
+            Request::Status => "status".to_string(),
+            Request::Arm => "arm".to_string(),
+            Request::Disarm => "disarm".to_string(),
+            Request::SetSpeedLimit(percentage) => format!("set-limit {}", percentage),
+            Request::EnableSubsystem(kind) => format!("enable {}", kind.name()),
+            Request::DisableSubsystem(kind) => format!("disable {}", kind.name()),
+            Request::EngageGuestLock => "guest-lock".to_string(),
+            Request::DisengageGuestLock => "guest-unlock".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    Ok(Vec<(String, String)>),
+    Error(String),
+}
+
+impl Response {
+    pub fn ok() -> Response {
+        Response::Ok(Vec::new())
+    }
+
+    pub fn with_field(self, key: &str, value: impl ToString) -> Response {
+        match self {
+            Response::Ok(mut fields) => {
+                let value = value.to_string().replace('\n', " ");
+                let value = match value.char_indices().nth(MAX_VALUE_LENGTH) {
+                    Some((cut_at, _)) => format!("{}…", &value[..cut_at]),
+                    None => value,
+                };
+                fields.push((key.to_string(), value));
+                Response::Ok(fields)
+            }
+            Response::Error(_) => self,
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Response> {
+        let (status, rest) = text.split_once('\n').unwrap_or((text, ""));
+
+        match status {
+            "ok" => rest
+                .lines()
+                .map(|line| {
+                    line.split_once('=')
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(Response::Ok),
+            "error" => Some(Response::Error(rest.to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        match self {
+            Response::Ok(fields) => {
+                let mut text = "ok".to_string();
+                for (key, value) in fields {
+                    text.push_str(&format!("\n{}={}", key, value));
+                }
+                text
+            }
+            Response::Error(message) => format!("error\n{}", message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_values_do_not_push_out_other_fields() {
+        let configuration_error = format!(
+            "The choreography {} does not exist.\nThe steering calibration file does not exist either.",
+            "/very/long/path".repeat(100)
+        );
+        let response = (0..40)
+            .fold(Response::ok(), |response, index| {
+                response.with_field(&format!("field_{}", index), "some value")
+            })
+            .with_field("configuration_error", configuration_error)
+            .with_field("last", true);
+
+        let text = response.to_text();
+        assert!(text.len() <= MAX_MESSAGE_SIZE);
+
+        let Some(Response::Ok(fields)) = Response::parse(&text) else {
+            panic!("Could not parse {:?}.", text);
+        };
+        assert_eq!(fields.len(), 42);
+        assert!(fields[40].1.starts_with("The choreography /very/long/path"));
+        assert!(fields[40].1.ends_with('…'));
+        assert_eq!(fields[41], ("last".to_string(), "true".to_string()));
+    }
+}
@@ -0,0 +1,149 @@
+use super::protocol::{Request, Response, MAX_MESSAGE_SIZE};
+use std::error::Error;
+use std::fs;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+
+// Handling requests happens on the runloop, so a flood of requests should not be able to stall it. Any requests
+// beyond this number are left for the next iteration.
+const MAX_REQUESTS_PER_ITERATION: usize = 4;
+
+pub struct ControlSocket {
+    socket: UnixDatagram,
+    socket_file_path: PathBuf,
+}
+
+impl ControlSocket {
+    pub fn bind(socket_file_path: &Path) -> Result<ControlSocket, SetupError> {
+        // A socket file left behind by a previous instance would cause binding to fail.
+        match fs::remove_file(socket_file_path) {
+            Ok(_) => (),
+            Err(error) if error.kind() == ErrorKind::NotFound => (),
+            Err(source) => {
+                return Err(SetupError::CouldNotRemoveStaleSocketFile {
+                    path: socket_file_path.to_path_buf(),
+                    source,
+                })
+            }
+        }
+
+        let socket =
+            UnixDatagram::bind(socket_file_path).map_err(|source| SetupError::CouldNotBind {
+                path: socket_file_path.to_path_buf(),
+                source,
+            })?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|source| SetupError::CouldNotSetNonBlocking { source })?;
+
+        Ok(ControlSocket {
+            socket,
+            socket_file_path: socket_file_path.to_path_buf(),
+        })
+    }
+
+    pub fn process_requests(
+        &self,
+        mut handler: impl FnMut(Request) -> Response,
+    ) -> Result<(), ProcessingError> {
+        let mut buffer = [0u8; MAX_MESSAGE_SIZE];
+
+        for _ in 0..MAX_REQUESTS_PER_ITERATION {
+            let (size, address) = match self.socket.recv_from(&mut buffer) {
+                Ok(result) => result,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(source) => return Err(ProcessingError::CouldNotReceive { source }),
+            };
+
+            let request = std::str::from_utf8(&buffer[..size])
+                .ok()
+                .and_then(Request::parse);
+
+            let response = match request {
+                Some(request) => handler(request),
+                None => Response::Error("Unknown or malformed request.".to_string()),
+            };
+
+            // A client that went away before receiving its response is not our problem.
+            if let Err(error) = self
+                .socket
+                .send_to_addr(response.to_text().as_bytes(), &address)
+            {
+                log::warn!("Could not send control response. - Cause: {}", error);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.socket_file_path);
+    }
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    CouldNotRemoveStaleSocketFile { path: PathBuf, source: IoError },
+    CouldNotBind { path: PathBuf, source: IoError },
+    CouldNotSetNonBlocking { source: IoError },
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            SetupError::CouldNotRemoveStaleSocketFile { path: _, source } => source,
+            SetupError::CouldNotBind { path: _, source } => source,
+            SetupError::CouldNotSetNonBlocking { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::CouldNotRemoveStaleSocketFile { path, source: _ } => {
+                format!(
+                    "Could not remove stale control socket at {}.",
+                    path.display()
+                )
+            }
+            SetupError::CouldNotBind { path, source: _ } => {
+                format!("Could not bind control socket at {}.", path.display())
+            }
+            SetupError::CouldNotSetNonBlocking { source: _ } => {
+                "Could not make control socket non-blocking.".to_string()
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug)]
+pub enum ProcessingError {
+    CouldNotReceive { source: IoError },
+}
+
+impl Error for ProcessingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            ProcessingError::CouldNotReceive { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for ProcessingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            ProcessingError::CouldNotReceive { source: _ } => {
+                "Could not receive from control socket."
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
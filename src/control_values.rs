@@ -0,0 +1,108 @@
+use std::ops::Neg;
+
+// The values that driving input passes through on its way to the hardware, each in a type of its own that is
+// guaranteed to be in range. Mixing them up with each other, or with percentages and pulse widths, is a compile error
+// rather than a vehicle steering when it should be accelerating.
+//
+// The constructors clamp values beyond the extremes and take NaN as the center, as an input source that gets its
+// arithmetic slightly wrong is no reason to bring the service down mid-drive.
+
+fn clamp(value: f64) -> f64 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+// A control as reported by an input device: from -1.0 to 1.0 for controls that center, such as sticks, and from 0.0
+// to 1.0 for controls that only go one way, such as triggers.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct NormalizedAxis(f64);
+
+impl NormalizedAxis {
+    pub const CENTER: NormalizedAxis = NormalizedAxis(0.0);
+
+    pub fn new(value: f64) -> NormalizedAxis {
+        NormalizedAxis(clamp(value))
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+}
+
+// -1.0 for full reverse to 1.0 for full speed forward.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct Throttle(f64);
+
+impl Throttle {
+    pub const NEUTRAL: Throttle = Throttle(0.0);
+
+    pub fn new(value: f64) -> Throttle {
+        Throttle(clamp(value))
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+
+    pub fn is_neutral(self) -> bool {
+        self.0 == 0.0
+    }
+
+    // Scales the throttle so that full throttle corresponds to `factor`, e.g. for a speed limit.
+    pub fn scaled(self, factor: f64) -> Throttle {
+        Throttle::new(self.0 * factor)
+    }
+
+    // Mixes in `other` with the given weight, from 0.0 (none of it) to 1.0 (only it).
+    pub fn blend(self, other: Throttle, weight: f64) -> Throttle {
+        let weight = weight.clamp(0.0, 1.0);
+
+        Throttle::new((1.0 - weight) * self.0 + weight * other.0)
+    }
+}
+
+impl Neg for Throttle {
+    type Output = Throttle;
+
+    fn neg(self) -> Throttle {
+        Throttle(-self.0)
+    }
+}
+
+// -1.0 for steering maximally to the left to 1.0 for steering maximally to the right.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct Steering(f64);
+
+impl Steering {
+    pub const CENTER: Steering = Steering(0.0);
+
+    pub fn new(value: f64) -> Steering {
+        Steering(clamp(value))
+    }
+
+    pub fn value(self) -> f64 {
+        self.0
+    }
+
+    pub fn is_centered(self) -> bool {
+        self.0 == 0.0
+    }
+
+    // Mixes in `other` with the given weight, from 0.0 (none of it) to 1.0 (only it).
+    pub fn blend(self, other: Steering, weight: f64) -> Steering {
+        let weight = weight.clamp(0.0, 1.0);
+
+        Steering::new((1.0 - weight) * self.0 + weight * other.0)
+    }
+}
+
+impl Neg for Steering {
+    type Output = Steering;
+
+    fn neg(self) -> Steering {
+        Steering(-self.0)
+    }
+}
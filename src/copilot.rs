@@ -0,0 +1,150 @@
+use crate::input_source::{AuxiliaryAxis, InputNotification, InputSource};
+use crate::locomotion::LocomotionCommand;
+use crate::subsystems::Subsystem;
+use std::error::Error;
+use std::time::Duration;
+
+// Training mode: an instructor steers along with the driver through a second input source. The driver's throttle is
+// always used as is, but while the instructor steers, their steering is mixed into the driver's according to the
+// instructor's authority, from 0.0 (advisory only, nothing is mixed in) to 1.0 (the instructor overrides the driver).
+// Once the instructor lets go, the driver has full control again.
+//
+// 💁‍♂️ There is no support for using two gamepads at the same time, so the instructor needs a different kind of
+// input source than the driver, e.g. an SBUS radio next to the driver's gamepad.
+
+pub struct CoPilotMixer {
+    driver: Box<dyn InputSource>,
+    instructor: Box<dyn InputSource>,
+    authority: f64,
+    instructor_steering: bool,
+    // Whether the instructor's input failed last time, see `process_input`.
+    instructor_failed: bool,
+}
+
+impl CoPilotMixer {
+    pub fn new(
+        driver: Box<dyn InputSource>,
+        instructor: Box<dyn InputSource>,
+        authority: f64,
+    ) -> CoPilotMixer {
+        log::info!(
+            "Training mode: the instructor has {:.0}% steering authority.",
+            authority * 100.0
+        );
+
+        CoPilotMixer {
+            driver,
+            instructor,
+            authority,
+            instructor_steering: false,
+            instructor_failed: false,
+        }
+    }
+}
+
+impl InputSource for CoPilotMixer {
+    // What matters is whether the driver is connected, as the driver is the only one who can make the vehicle move.
+    fn is_connected(&self) -> bool {
+        self.driver.is_connected()
+    }
+
+    fn device_description(&self) -> Option<String> {
+        self.driver.device_description()
+    }
+
+    fn auxiliary_axis(&self, axis: AuxiliaryAxis) -> Option<f64> {
+        self.driver.auxiliary_axis(axis)
+    }
+
+    fn winch_direction(&self) -> f64 {
+        self.driver.winch_direction()
+    }
+
+    // Either of them being at the controls will do.
+    fn last_activity(&self) -> Option<Duration> {
+        self.driver
+            .last_activity()
+            .max(self.instructor.last_activity())
+    }
+
+    fn process_input(
+        &mut self,
+        notify: &mut dyn FnMut(InputNotification),
+    ) -> Result<LocomotionCommand, Box<dyn Error>> {
+        let command = self.driver.process_input(notify)?;
+
+        // The instructor's notifications are only logged: the instructor coming and going must neither count as the
+        // driver's connection changing nor trigger the failsafe, and their buttons are not for controlling the vehicle.
+        // For the same reason, the instructor's input failing only means the driver steers alone until it works again.
+        let instructor_result =
+            self.instructor
+                .process_input(&mut |notification| match notification {
+                    InputNotification::Connected => log::info!("Instructor connected."),
+                    InputNotification::Disconnected => log::info!("Instructor disconnected."),
+                    _ => (),
+                });
+        let instructor_command = match instructor_result {
+            Ok(instructor_command) => {
+                if self.instructor_failed {
+                    log::info!("Instructor input is working again.");
+                    self.instructor_failed = false;
+                }
+                instructor_command
+            }
+            Err(error) => {
+                if !self.instructor_failed {
+                    log::error!(
+                        "Could not process the instructor's input, carrying on with the driver only. - Cause: {}",
+                        error
+                    );
+                    self.instructor_failed = true;
+                }
+                LocomotionCommand::neutral()
+            }
+        };
+
+        let instructor_direction = instructor_command.get_direction();
+        let instructor_steering = !instructor_direction.is_centered() && self.authority > 0.0;
+        if instructor_steering != self.instructor_steering {
+            self.instructor_steering = instructor_steering;
+            if instructor_steering {
+                log::info!("Instructor steering.");
+            } else {
+                log::info!("Driver steering.");
+            }
+        }
+
+        if !instructor_steering {
+            return Ok(command);
+        }
+
+        let direction = command
+            .get_direction()
+            .blend(instructor_direction, self.authority);
+
+        Ok(command.with_values(command.get_throttle(), direction))
+    }
+
+    fn release_latched_input(&mut self) {
+        self.driver.release_latched_input();
+        self.instructor.release_latched_input();
+    }
+
+    fn expire_received_input(&mut self) {
+        self.driver.expire_received_input();
+        self.instructor.expire_received_input();
+    }
+
+    fn reload_controller_mappings(&mut self) {
+        self.driver.reload_controller_mappings();
+        self.instructor.reload_controller_mappings();
+    }
+
+    // The two are of different kinds (see above), so at most one of them receives input over the network.
+    fn network_input(&mut self) -> Option<&mut dyn Subsystem> {
+        match self.driver.network_input() {
+            Some(network_input) => Some(network_input),
+            None => self.instructor.network_input(),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::error::Error;
+use std::ffi::CString;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Error as IoError;
+use std::io::{ErrorKind, Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// Running as a classic daemon (`--daemon`), for systems without systemd: the service detaches from the terminal and
+// the session it was started from, writes its output to a log file and announces its PID in a PID file.
+//
+// ⚠️ The working directory becomes `/`, so relative paths in the configuration no longer resolve to where the service
+// was started from. Daemonizing happens before anything else is set up, as only the thread calling `fork` survives
+// it and any file descriptors would be shared with the parent until it exits.
+
+pub fn daemonize(log_file: &Path) -> Result<(), DaemonizeError> {
+    // The first child is not a process group leader, which `setsid` requires. The second child is not a session
+    // leader, so it can never acquire a controlling terminal.
+    fork_and_exit_parent()?;
+    if unsafe { libc::setsid() } < 0 {
+        return Err(DaemonizeError::CouldNotCreateSession {
+            source: IoError::last_os_error(),
+        });
+    }
+    fork_and_exit_parent()?;
+
+    unsafe { libc::umask(0o022) };
+    let root = CString::new("/").unwrap();
+    if unsafe { libc::chdir(root.as_ptr()) } < 0 {
+        return Err(DaemonizeError::CouldNotChangeDirectory {
+            source: IoError::last_os_error(),
+        });
+    }
+
+    let null = File::open("/dev/null")
+        .map_err(|source| DaemonizeError::CouldNotRedirectOutput { source })?;
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .mode(0o640)
+        .open(log_file)
+        .map_err(|source| DaemonizeError::CouldNotOpenLogFile {
+            path: log_file.to_path_buf(),
+            source,
+        })?;
+    for (file, target_fd) in [
+        (&null, libc::STDIN_FILENO),
+        (&log, libc::STDOUT_FILENO),
+        (&log, libc::STDERR_FILENO),
+    ] {
+        if unsafe { libc::dup2(file.as_raw_fd(), target_fd) } < 0 {
+            return Err(DaemonizeError::CouldNotRedirectOutput {
+                source: IoError::last_os_error(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn fork_and_exit_parent() -> Result<(), DaemonizeError> {
+    match unsafe { libc::fork() } {
+        -1 => Err(DaemonizeError::CouldNotFork {
+            source: IoError::last_os_error(),
+        }),
+        0 => Ok(()),
+        // 💁‍♂️ `_exit` rather than `exit`, so that nothing the child inherited (e.g. buffered output) is flushed twice.
+        _ => unsafe { libc::_exit(0) },
+    }
+}
+
+// Holds an exclusive lock on the PID file for as long as the service runs, which also keeps a second instance from
+// starting. The file is removed when dropped.
+pub struct PidFile {
+    _file: File,
+    path: PathBuf,
+}
+
+impl PidFile {
+    pub fn create(path: &Path) -> Result<PidFile, PidFileError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o644)
+            .open(path)
+            .map_err(|source| PidFileError::CouldNotOpen {
+                path: path.to_path_buf(),
+                source,
+            })?;
+
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } < 0 {
+            let source = IoError::last_os_error();
+            if source.kind() == ErrorKind::WouldBlock {
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                return Err(PidFileError::AlreadyRunning {
+                    path: path.to_path_buf(),
+                    pid: pid.trim().to_string(),
+                });
+            }
+            return Err(PidFileError::CouldNotOpen {
+                path: path.to_path_buf(),
+                source,
+            });
+        }
+
+        // A PID file left behind by an instance that did not exit cleanly is simply overwritten.
+        file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| writeln!(file, "{}", process::id()))
+            .map_err(|source| PidFileError::CouldNotWrite {
+                path: path.to_path_buf(),
+                source,
+            })?;
+
+        Ok(PidFile {
+            _file: file,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Debug)]
+pub enum DaemonizeError {
+    CouldNotFork { source: IoError },
+    CouldNotCreateSession { source: IoError },
+    CouldNotChangeDirectory { source: IoError },
+    CouldNotOpenLogFile { path: PathBuf, source: IoError },
+    CouldNotRedirectOutput { source: IoError },
+}
+
+impl Error for DaemonizeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            DaemonizeError::CouldNotFork { source } => source,
+            DaemonizeError::CouldNotCreateSession { source } => source,
+            DaemonizeError::CouldNotChangeDirectory { source } => source,
+            DaemonizeError::CouldNotOpenLogFile { path: _, source } => source,
+            DaemonizeError::CouldNotRedirectOutput { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for DaemonizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            DaemonizeError::CouldNotFork { source: _ } => "Could not fork.".to_string(),
+            DaemonizeError::CouldNotCreateSession { source: _ } => {
+                "Could not create a new session.".to_string()
+            }
+            DaemonizeError::CouldNotChangeDirectory { source: _ } => {
+                "Could not change the working directory to /.".to_string()
+            }
+            DaemonizeError::CouldNotOpenLogFile { path, source: _ } => {
+                format!("Could not open log file {}.", path.display())
+            }
+            DaemonizeError::CouldNotRedirectOutput { source: _ } => {
+                "Could not redirect standard input and output.".to_string()
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug)]
+pub enum PidFileError {
+    CouldNotOpen { path: PathBuf, source: IoError },
+    AlreadyRunning { path: PathBuf, pid: String },
+    CouldNotWrite { path: PathBuf, source: IoError },
+}
+
+impl Error for PidFileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PidFileError::CouldNotOpen { path: _, source } => Some(source),
+            PidFileError::AlreadyRunning { path: _, pid: _ } => None,
+            PidFileError::CouldNotWrite { path: _, source } => Some(source),
+        }
+    }
+}
+
+impl std::fmt::Display for PidFileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            PidFileError::CouldNotOpen { path, source: _ } => {
+                format!("Could not open PID file {}.", path.display())
+            }
+            PidFileError::AlreadyRunning { path, pid } => {
+                format!(
+                    "Another instance (PID {}) holds the PID file {}.",
+                    pid,
+                    path.display()
+                )
+            }
+            PidFileError::CouldNotWrite { path, source: _ } => {
+                format!("Could not write PID file {}.", path.display())
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
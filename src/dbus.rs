@@ -0,0 +1,15 @@
+#[cfg(feature = "dbus")]
+mod message;
+#[cfg(feature = "dbus")]
+mod service;
+
+#[cfg(feature = "dbus")]
+pub use service::DBusService;
+
+// What the service announces on the bus. This is decided on even without the `dbus` feature, as the decision is part
+// of handling input.
+#[derive(Debug, Copy, Clone)]
+pub enum Signal {
+    GamepadConnected,
+    FailsafeEngaged,
+}
@@ -0,0 +1,425 @@
+// Just enough of the D-Bus wire format to exchange method calls, replies, errors and signals with simple bodies.
+// See https://dbus.freedesktop.org/doc/dbus-specification.html#message-protocol for the details.
+//
+// 💁‍♂️ Messages are always sent little-endian, but other peers may send big-endian messages, which the bus
+// daemon forwards as-is. Decoding therefore supports both.
+
+// Messages larger than this are refused. The largest messages we expect are a few hundred bytes.
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+const PROTOCOL_VERSION: u8 = 1;
+
+pub const FLAG_NO_REPLY_EXPECTED: u8 = 0x01;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MessageType {
+    MethodCall = 1,
+    MethodReturn = 2,
+    Error = 3,
+    Signal = 4,
+}
+
+// Header field codes.
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub message_type: MessageType,
+    pub flags: u8,
+    pub serial: u32,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub reply_serial: Option<u32>,
+    pub destination: Option<String>,
+    pub sender: Option<String>,
+    pub body: Body,
+}
+
+impl Message {
+    pub fn new(message_type: MessageType) -> Message {
+        Message {
+            message_type,
+            flags: 0,
+            serial: 0,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            reply_serial: None,
+            destination: None,
+            sender: None,
+            body: Body::new(),
+        }
+    }
+
+    pub fn method_call(destination: &str, path: &str, interface: &str, member: &str) -> Message {
+        let mut message = Message::new(MessageType::MethodCall);
+        message.destination = Some(destination.to_string());
+        message.path = Some(path.to_string());
+        message.interface = Some(interface.to_string());
+        message.member = Some(member.to_string());
+        message
+    }
+
+    pub fn signal(path: &str, interface: &str, member: &str) -> Message {
+        let mut message = Message::new(MessageType::Signal);
+        message.path = Some(path.to_string());
+        message.interface = Some(interface.to_string());
+        message.member = Some(member.to_string());
+        message
+    }
+
+    pub fn method_return(call: &Message) -> Message {
+        let mut message = Message::new(MessageType::MethodReturn);
+        message.reply_serial = Some(call.serial);
+        message.destination = call.sender.clone();
+        message
+    }
+
+    pub fn error(call: &Message, error_name: &str, description: &str) -> Message {
+        let mut message = Message::new(MessageType::Error);
+        message.reply_serial = Some(call.serial);
+        message.destination = call.sender.clone();
+        message.error_name = Some(error_name.to_string());
+        message.body.push_string(description);
+        message
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+
+        writer.u8(b'l');
+        writer.u8(self.message_type as u8);
+        writer.u8(self.flags);
+        writer.u8(PROTOCOL_VERSION);
+        writer.u32(self.body.data.len() as u32);
+        writer.u32(self.serial);
+
+        let length_offset = writer.reserve_u32();
+        writer.align(8);
+        let fields_start = writer.data.len();
+
+        let string_fields = [
+            (FIELD_PATH, "o", &self.path),
+            (FIELD_INTERFACE, "s", &self.interface),
+            (FIELD_MEMBER, "s", &self.member),
+            (FIELD_ERROR_NAME, "s", &self.error_name),
+            (FIELD_DESTINATION, "s", &self.destination),
+            (FIELD_SENDER, "s", &self.sender),
+        ];
+
+        for (code, signature, value) in string_fields {
+            if let Some(value) = value {
+                writer.align(8);
+                writer.u8(code);
+                writer.signature(signature);
+                writer.string(value);
+            }
+        }
+
+        if let Some(reply_serial) = self.reply_serial {
+            writer.align(8);
+            writer.u8(FIELD_REPLY_SERIAL);
+            writer.signature("u");
+            writer.u32(reply_serial);
+        }
+
+        if !self.body.signature.is_empty() {
+            writer.align(8);
+            writer.u8(FIELD_SIGNATURE);
+            writer.signature("g");
+            writer.signature(&self.body.signature);
+        }
+
+        let fields_length = (writer.data.len() - fields_start) as u32;
+        writer.patch_u32(length_offset, fields_length);
+
+        // The body always starts on an 8-byte boundary.
+        writer.align(8);
+        writer.data.extend_from_slice(&self.body.data);
+
+        writer.data
+    }
+
+    /// Decode the first message in `buffer`. Returns `None` if the buffer does not hold a complete message yet, or
+    /// the message together with the number of bytes it took up otherwise.
+    pub fn decode(buffer: &[u8]) -> Result<Option<(Message, usize)>, DecodeError> {
+        const FIXED_HEADER_SIZE: usize = 16;
+
+        if buffer.len() < FIXED_HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let big_endian = match buffer[0] {
+            b'l' => false,
+            b'B' => true,
+            _ => return Err(DecodeError::InvalidEndianness),
+        };
+
+        let mut reader = Reader::new(buffer, big_endian);
+        reader.position = 4;
+        let body_length = reader.u32()? as usize;
+        let serial = reader.u32()?;
+        let fields_length = reader.u32()? as usize;
+
+        let header_length = align_to(FIXED_HEADER_SIZE + fields_length, 8);
+        let total_length = header_length + body_length;
+
+        if total_length > MAX_MESSAGE_SIZE {
+            return Err(DecodeError::MessageTooLarge);
+        }
+
+        if buffer.len() < total_length {
+            return Ok(None);
+        }
+
+        let message_type = match buffer[1] {
+            1 => MessageType::MethodCall,
+            2 => MessageType::MethodReturn,
+            3 => MessageType::Error,
+            4 => MessageType::Signal,
+            _ => return Err(DecodeError::InvalidMessageType),
+        };
+
+        let mut message = Message::new(message_type);
+        message.flags = buffer[2];
+        message.serial = serial;
+
+        let fields_end = FIXED_HEADER_SIZE + fields_length;
+        let mut signature = String::new();
+
+        while reader.position < fields_end {
+            reader.align(8)?;
+            let code = reader.u8()?;
+            let field_signature = reader.signature()?;
+
+            match field_signature.as_str() {
+                "s" | "o" => {
+                    let value = reader.string()?;
+                    match code {
+                        FIELD_PATH => message.path = Some(value),
+                        FIELD_INTERFACE => message.interface = Some(value),
+                        FIELD_MEMBER => message.member = Some(value),
+                        FIELD_ERROR_NAME => message.error_name = Some(value),
+                        FIELD_DESTINATION => message.destination = Some(value),
+                        FIELD_SENDER => message.sender = Some(value),
+                        _ => (),
+                    }
+                }
+                "u" => {
+                    let value = reader.u32()?;
+                    if code == FIELD_REPLY_SERIAL {
+                        message.reply_serial = Some(value);
+                    }
+                }
+                "g" => {
+                    let value = reader.signature()?;
+                    if code == FIELD_SIGNATURE {
+                        signature = value;
+                    }
+                }
+                _ => return Err(DecodeError::UnsupportedHeaderField),
+            }
+        }
+
+        message.body = Body {
+            signature,
+            data: buffer[header_length..total_length].to_vec(),
+            big_endian,
+        };
+
+        Ok(Some((message, total_length)))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Body {
+    pub signature: String,
+    data: Vec<u8>,
+    big_endian: bool,
+}
+
+impl Body {
+    fn new() -> Body {
+        Body {
+            signature: String::new(),
+            data: Vec::new(),
+            big_endian: false,
+        }
+    }
+
+    pub fn push_string(&mut self, value: &str) {
+        let mut writer = Writer {
+            data: std::mem::take(&mut self.data),
+        };
+        writer.string(value);
+        self.data = writer.data;
+        self.signature.push('s');
+    }
+
+    pub fn push_u32(&mut self, value: u32) {
+        let mut writer = Writer {
+            data: std::mem::take(&mut self.data),
+        };
+        writer.u32(value);
+        self.data = writer.data;
+        self.signature.push('u');
+    }
+
+    // The accessors below only support bodies consisting of a single value, which is all we need.
+
+    pub fn single_byte(&self) -> Option<u8> {
+        (self.signature == "y").then(|| self.data.first().copied())?
+    }
+
+    pub fn single_u32(&self) -> Option<u32> {
+        (self.signature == "u").then(|| Reader::new(&self.data, self.big_endian).u32().ok())?
+    }
+
+    pub fn single_string(&self) -> Option<String> {
+        (self.signature == "s").then(|| Reader::new(&self.data, self.big_endian).string().ok())?
+    }
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    InvalidEndianness,
+    InvalidMessageType,
+    MessageTooLarge,
+    UnsupportedHeaderField,
+    Truncated,
+    InvalidString,
+}
+
+impl std::error::Error for DecodeError {}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            DecodeError::InvalidEndianness => "Invalid endianness marker in D-Bus message.",
+            DecodeError::InvalidMessageType => "Invalid D-Bus message type.",
+            DecodeError::MessageTooLarge => "D-Bus message too large.",
+            DecodeError::UnsupportedHeaderField => "Unsupported D-Bus header field type.",
+            DecodeError::Truncated => "Truncated D-Bus message.",
+            DecodeError::InvalidString => "Invalid string in D-Bus message.",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+fn align_to(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Writer {
+        Writer { data: Vec::new() }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        self.data.resize(align_to(self.data.len(), alignment), 0);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn reserve_u32(&mut self) -> usize {
+        self.u32(0);
+        self.data.len() - 4
+    }
+
+    fn patch_u32(&mut self, offset: usize, value: u32) {
+        self.data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.data.push(value.len() as u8);
+        self.data.extend_from_slice(value.as_bytes());
+        self.data.push(0);
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], big_endian: bool) -> Reader<'a> {
+        Reader {
+            data,
+            position: 0,
+            big_endian,
+        }
+    }
+
+    fn align(&mut self, alignment: usize) -> Result<(), DecodeError> {
+        self.position = align_to(self.position, alignment);
+        if self.position > self.data.len() {
+            return Err(DecodeError::Truncated);
+        }
+        Ok(())
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], DecodeError> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + count)
+            .ok_or(DecodeError::Truncated)?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        self.align(4)?;
+        let bytes: [u8; 4] = self.bytes(4)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let length = self.u32()? as usize;
+        let bytes = self.bytes(length + 1)?;
+        String::from_utf8(bytes[..length].to_vec()).map_err(|_| DecodeError::InvalidString)
+    }
+
+    fn signature(&mut self) -> Result<String, DecodeError> {
+        let length = self.u8()? as usize;
+        let bytes = self.bytes(length + 1)?;
+        String::from_utf8(bytes[..length].to_vec()).map_err(|_| DecodeError::InvalidString)
+    }
+}
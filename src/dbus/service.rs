@@ -0,0 +1,400 @@
+use super::message::{DecodeError, Message, MessageType, FLAG_NO_REPLY_EXPECTED, MAX_MESSAGE_SIZE};
+use super::Signal;
+use crate::control::{Request, Response};
+use std::error::Error;
+use std::io::Error as IoError;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+pub const BUS_NAME: &str = "io.github.olivhn.Roestbak";
+const OBJECT_PATH: &str = "/io/github/olivhn/Roestbak";
+const INTERFACE: &str = "io.github.olivhn.Roestbak";
+
+const SYSTEM_BUS_SOCKET: &str = "/run/dbus/system_bus_socket";
+
+// Connecting and registering happens before the runloop starts, so blocking is acceptable there, but not forever.
+const SETUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+const INTROSPECTION_XML: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.github.olivhn.Roestbak">
+    <method name="Arm"/>
+    <method name="Disarm"/>
+    <method name="SetSpeedLimit">
+      <arg name="percentage" type="y" direction="in"/>
+    </method>
+    <signal name="GamepadConnected"/>
+    <signal name="FailsafeEngaged"/>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+  </interface>
+</node>
+"#;
+
+/// Exposes the service on the system bus. Method calls are translated into control requests, so they behave
+/// exactly like their counterparts on the control socket.
+pub struct DBusService {
+    stream: UnixStream,
+    next_serial: u32,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+}
+
+impl DBusService {
+    pub fn connect() -> Result<DBusService, SetupError> {
+        Self::connect_to(Path::new(SYSTEM_BUS_SOCKET))
+    }
+
+    fn connect_to(socket_path: &Path) -> Result<DBusService, SetupError> {
+        let stream = UnixStream::connect(socket_path)
+            .map_err(|source| SetupError::CouldNotConnect { source })?;
+        stream
+            .set_read_timeout(Some(SETUP_TIMEOUT))
+            .map_err(|source| SetupError::CouldNotConnect { source })?;
+
+        let mut service = DBusService {
+            stream,
+            next_serial: 1,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        };
+
+        service.authenticate()?;
+
+        let hello = Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+        );
+        service.call_blocking(hello)?;
+
+        let mut request_name = Message::method_call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "RequestName",
+        );
+        request_name.body.push_string(BUS_NAME);
+        // DBUS_NAME_FLAG_DO_NOT_QUEUE: fail right away if another instance owns the name.
+        request_name.body.push_u32(0x4);
+
+        let reply = service.call_blocking(request_name)?;
+        // DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER
+        if reply.body.single_u32() != Some(1) {
+            return Err(SetupError::CouldNotAcquireName);
+        }
+
+        service
+            .stream
+            .set_nonblocking(true)
+            .map_err(|source| SetupError::CouldNotConnect { source })?;
+
+        log::info!("Registered on the system bus as {}.", BUS_NAME);
+
+        Ok(service)
+    }
+
+    pub fn emit(&mut self, signal: Signal) -> Result<(), ProcessingError> {
+        let member = match signal {
+            Signal::GamepadConnected => "GamepadConnected",
+            Signal::FailsafeEngaged => "FailsafeEngaged",
+        };
+
+        self.queue(Message::signal(OBJECT_PATH, INTERFACE, member));
+        self.flush()
+    }
+
+    pub fn process_messages(
+        &mut self,
+        mut handler: impl FnMut(Request) -> Response,
+    ) -> Result<(), ProcessingError> {
+        self.receive()?;
+
+        while let Some((message, length)) = Message::decode(&self.incoming)
+            .map_err(|source| ProcessingError::InvalidMessage { source })?
+        {
+            self.incoming.drain(..length);
+
+            if message.message_type != MessageType::MethodCall {
+                continue;
+            }
+
+            let reply = self.handle_method_call(&message, &mut handler);
+
+            if message.flags & FLAG_NO_REPLY_EXPECTED == 0 {
+                self.queue(reply);
+            }
+        }
+
+        self.flush()
+    }
+
+    fn handle_method_call(
+        &self,
+        call: &Message,
+        handler: &mut impl FnMut(Request) -> Response,
+    ) -> Message {
+        let interface = call.interface.as_deref();
+        let member = call.member.as_deref().unwrap_or("");
+
+        if call.path.as_deref() != Some(OBJECT_PATH) {
+            return Message::error(
+                call,
+                "org.freedesktop.DBus.Error.UnknownObject",
+                "No such object.",
+            );
+        }
+
+        let request = match (interface, member) {
+            (Some("org.freedesktop.DBus.Introspectable") | None, "Introspect") => {
+                let mut reply = Message::method_return(call);
+                reply.body.push_string(INTROSPECTION_XML);
+                return reply;
+            }
+            (Some("org.freedesktop.DBus.Peer") | None, "Ping") => {
+                return Message::method_return(call);
+            }
+            (Some(INTERFACE) | None, "Arm") => Some(Request::Arm),
+            (Some(INTERFACE) | None, "Disarm") => Some(Request::Disarm),
+            (Some(INTERFACE) | None, "SetSpeedLimit") => match call.body.single_byte() {
+                Some(percentage) if percentage <= 100 => Some(Request::SetSpeedLimit(percentage)),
+                _ => {
+                    return Message::error(
+                        call,
+                        "org.freedesktop.DBus.Error.InvalidArgs",
+                        "Expected a single byte percentage between 0 and 100.",
+                    )
+                }
+            },
+            _ => None,
+        };
+
+        match request.map(handler) {
+            Some(Response::Ok(_)) => Message::method_return(call),
+            Some(Response::Error(description)) => Message::error(
+                call,
+                "io.github.olivhn.Roestbak.Error.Rejected",
+                &description,
+            ),
+            None => Message::error(
+                call,
+                "org.freedesktop.DBus.Error.UnknownMethod",
+                "No such method.",
+            ),
+        }
+    }
+
+    // See https://dbus.freedesktop.org/doc/dbus-specification.html#auth-protocol.
+    fn authenticate(&mut self) -> Result<(), SetupError> {
+        let uid = unsafe { libc::getuid() };
+        let hex_uid: String = uid
+            .to_string()
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        let greeting = format!("\0AUTH EXTERNAL {}\r\n", hex_uid);
+        self.stream
+            .write_all(greeting.as_bytes())
+            .map_err(|source| SetupError::CouldNotConnect { source })?;
+
+        let mut response = Vec::new();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n") {
+            self.stream
+                .read_exact(&mut byte)
+                .map_err(|source| SetupError::CouldNotConnect { source })?;
+            response.push(byte[0]);
+        }
+
+        if !response.starts_with(b"OK ") {
+            return Err(SetupError::AuthenticationRejected);
+        }
+
+        self.stream
+            .write_all(b"BEGIN\r\n")
+            .map_err(|source| SetupError::CouldNotConnect { source })
+    }
+
+    fn call_blocking(&mut self, message: Message) -> Result<Message, SetupError> {
+        let serial = self.queue(message);
+        self.stream
+            .write_all(&self.outgoing)
+            .map_err(|source| SetupError::CouldNotConnect { source })?;
+        self.outgoing.clear();
+
+        loop {
+            while let Some((reply, length)) = Message::decode(&self.incoming)
+                .map_err(|source| SetupError::InvalidReply { source })?
+            {
+                self.incoming.drain(..length);
+
+                if reply.reply_serial == Some(serial) {
+                    return match reply.message_type {
+                        MessageType::Error => Err(SetupError::CallFailed {
+                            error_name: reply.error_name.unwrap_or_default(),
+                            description: reply.body.single_string().unwrap_or_default(),
+                        }),
+                        _ => Ok(reply),
+                    };
+                }
+            }
+
+            let mut buffer = [0u8; 4096];
+            let bytes_read = self
+                .stream
+                .read(&mut buffer)
+                .map_err(|source| SetupError::CouldNotConnect { source })?;
+            if bytes_read == 0 {
+                return Err(SetupError::CouldNotConnect {
+                    source: ErrorKind::UnexpectedEof.into(),
+                });
+            }
+            self.incoming.extend_from_slice(&buffer[..bytes_read]);
+        }
+    }
+
+    fn queue(&mut self, mut message: Message) -> u32 {
+        let serial = self.next_serial;
+        self.next_serial = self.next_serial.wrapping_add(1).max(1);
+
+        message.serial = serial;
+        self.outgoing.extend_from_slice(&message.encode());
+
+        serial
+    }
+
+    fn receive(&mut self) -> Result<(), ProcessingError> {
+        let mut buffer = [0u8; 4096];
+
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ProcessingError::Disconnected),
+                Ok(bytes_read) => {
+                    self.incoming.extend_from_slice(&buffer[..bytes_read]);
+                    if self.incoming.len() > 2 * MAX_MESSAGE_SIZE {
+                        return Err(ProcessingError::InvalidMessage {
+                            source: DecodeError::MessageTooLarge,
+                        });
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                Err(source) => return Err(ProcessingError::CouldNotRead { source }),
+            }
+        }
+    }
+
+    // Writes whatever the socket accepts right now. Anything left over is retried on the next iteration.
+    fn flush(&mut self) -> Result<(), ProcessingError> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(ProcessingError::Disconnected),
+                Ok(bytes_written) => {
+                    self.outgoing.drain(..bytes_written);
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) if error.kind() == ErrorKind::Interrupted => (),
+                Err(source) => return Err(ProcessingError::CouldNotWrite { source }),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    CouldNotConnect {
+        source: IoError,
+    },
+    AuthenticationRejected,
+    InvalidReply {
+        source: DecodeError,
+    },
+    CallFailed {
+        error_name: String,
+        description: String,
+    },
+    CouldNotAcquireName,
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SetupError::CouldNotConnect { source } => Some(source),
+            SetupError::InvalidReply { source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::CouldNotConnect { source: _ } => {
+                "Could not connect to the system bus.".to_string()
+            }
+            SetupError::AuthenticationRejected => {
+                "The system bus rejected authentication.".to_string()
+            }
+            SetupError::InvalidReply { source: _ } => {
+                "Received an invalid reply from the system bus.".to_string()
+            }
+            SetupError::CallFailed {
+                error_name,
+                description,
+            } => format!("System bus call failed: {} ({}).", error_name, description),
+            SetupError::CouldNotAcquireName => format!(
+                "Could not acquire bus name {} (is another instance running?).",
+                BUS_NAME
+            ),
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug)]
+pub enum ProcessingError {
+    CouldNotRead { source: IoError },
+    CouldNotWrite { source: IoError },
+    InvalidMessage { source: DecodeError },
+    Disconnected,
+}
+
+impl Error for ProcessingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ProcessingError::CouldNotRead { source } => Some(source),
+            ProcessingError::CouldNotWrite { source } => Some(source),
+            ProcessingError::InvalidMessage { source } => Some(source),
+            ProcessingError::Disconnected => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ProcessingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            ProcessingError::CouldNotRead { source: _ } => "Could not read from the system bus.",
+            ProcessingError::CouldNotWrite { source: _ } => "Could not write to the system bus.",
+            ProcessingError::InvalidMessage { source: _ } => {
+                "Received an invalid message from the system bus."
+            }
+            ProcessingError::Disconnected => "Disconnected from the system bus.",
+        };
+
+        write!(f, "{}", description)
+    }
+}
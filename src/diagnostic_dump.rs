@@ -0,0 +1,82 @@
+use crate::control::Response;
+use std::backtrace::Backtrace;
+use std::fs;
+
+// Everything that might help figure out why a vehicle in the field misbehaves or hangs, logged on SIGQUIT before
+// shutting down as usual: the status (as the control socket would report it), a backtrace of the runloop thread, and
+// what every thread of the process is doing as far as the kernel can tell.
+//
+// 💁‍♂️ Only the thread the dump is made from can be backtraced without a debugger. For the others, the kernel's view
+// (running, sleeping, and in which kernel function) at least tells a thread stuck in a system call from one spinning.
+// Backtraces only name functions when the binary has its symbols.
+
+pub fn log(status: &Response) {
+    log::warn!("Dumping diagnostics before shutting down.");
+
+    match status {
+        Response::Ok(fields) => {
+            for (key, value) in fields {
+                log::info!("Status: {} = {}", key, value);
+            }
+        }
+        Response::Error(message) => log::info!("Status: unavailable ({})", message),
+    }
+
+    log::info!(
+        "Backtrace of the runloop thread:\n{}",
+        Backtrace::force_capture()
+    );
+
+    match fs::read_dir("/proc/self/task") {
+        Ok(entries) => {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let read = |name: &str| {
+                    fs::read_to_string(path.join(name))
+                        .map(|text| text.trim().to_string())
+                        .unwrap_or_else(|_| "?".to_string())
+                };
+                log::info!(
+                    "Thread {} ({}): {}, waiting in {}",
+                    entry.file_name().to_string_lossy(),
+                    read("comm"),
+                    thread_state(&read("stat")),
+                    read("wchan")
+                );
+            }
+        }
+        Err(error) => log::info!("Could not list threads. - Cause: {}", error),
+    }
+}
+
+// The state from a `/proc/<pid>/task/<tid>/stat` line, which follows the parenthesized thread name (which may contain
+// spaces and parentheses itself).
+fn thread_state(stat: &str) -> &'static str {
+    let state = stat
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().next());
+
+    match state {
+        Some("R") => "running",
+        Some("S") => "sleeping",
+        Some("D") => "in uninterruptible sleep",
+        Some("T" | "t") => "stopped",
+        Some("Z") => "zombie",
+        _ => "in an unknown state",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_thread_states() {
+        assert_eq!(thread_state("1234 (roestbak) S 1 1234 1234 0"), "sleeping");
+        assert_eq!(
+            thread_state("1235 (odd (name) ) D 1 1234"),
+            "in uninterruptible sleep"
+        );
+        assert_eq!(thread_state("?"), "in an unknown state");
+    }
+}
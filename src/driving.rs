@@ -0,0 +1,187 @@
+use crate::control_values::{Steering, Throttle};
+use crate::locomotion::LocomotionCommand;
+use std::time::Duration;
+
+// See `DrivingSettings::assist_low_speed`.
+const CREEP_PULSE_PERIOD: Duration = Duration::from_millis(200);
+
+// How input from the driver translates into locomotion, whatever the input source. These settings can be changed
+// while the service is running by editing the configuration file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DrivingSettings {
+    // Input below this magnitude is ignored, e.g. to keep a worn stick from creeping the vehicle along. The remaining
+    // range is stretched so that full input still gives full output.
+    pub deadzone: f64,
+
+    // How much of a cubic curve is mixed in, from 0.0 (linear) to 1.0 (fully cubic). Higher values give finer
+    // control around the center, at the expense of coarser control near the extremes.
+    pub throttle_expo: f64,
+    pub steering_expo: f64,
+
+    // The highest throttle the vehicle is ever driven at, as a percentage. This applies on top of any speed limit
+    // set remotely.
+    pub max_speed_percent: u8,
+
+    // For sensorless brushless motors, which cog rather than turn at very low throttle: the lowest throttle sent
+    // other than neutral, and below which throttle the minimum is pulsed instead. See `assist_low_speed`.
+    pub min_throttle: f64,
+    pub creep_below: f64,
+}
+
+impl Default for DrivingSettings {
+    fn default() -> Self {
+        Self {
+            deadzone: 0.0,
+            throttle_expo: 0.0,
+            steering_expo: 0.0,
+            max_speed_percent: 100,
+            min_throttle: 0.0,
+            creep_below: 0.0,
+        }
+    }
+}
+
+impl DrivingSettings {
+    // Applies the deadzone and curves to a command derived from the driver's input.
+    pub fn shape(&self, command: LocomotionCommand) -> LocomotionCommand {
+        command.with_values(
+            Throttle::new(self.shape_value(command.get_throttle().value(), self.throttle_expo)),
+            Steering::new(self.shape_value(command.get_direction().value(), self.steering_expo)),
+        )
+    }
+
+    pub fn max_speed(&self) -> f64 {
+        self.max_speed_percent as f64 / 100.0
+    }
+
+    pub fn log_changes(&self, new: &DrivingSettings) {
+        for ((key, old_value), (_, new_value)) in self.describe().into_iter().zip(new.describe()) {
+            if old_value != new_value {
+                log::info!("Changed {} from {} to {}.", key, old_value, new_value);
+            }
+        }
+    }
+
+    // Makes up for a motor that cogs at low throttle: any throttle other than neutral is at least `min_throttle`,
+    // with the rest of the range stretched above it. Throttle below `creep_below` instead has `min_throttle` pulsed
+    // for a share of every `CREEP_PULSE_PERIOD` that grows with the throttle, so that the vehicle crawls along at
+    // about the speed asked for. This goes for the command actually executed, after any speed limit, which would
+    // otherwise scale the minimum down as well.
+    pub fn assist_low_speed(&self, command: LocomotionCommand, now: Duration) -> LocomotionCommand {
+        let throttle = command.get_throttle().value();
+        if self.min_throttle == 0.0 || throttle == 0.0 {
+            return command;
+        }
+
+        let magnitude = if throttle.abs() < self.creep_below {
+            let phase = (now.as_secs_f64() / CREEP_PULSE_PERIOD.as_secs_f64()).fract();
+            if phase < throttle.abs() / self.creep_below {
+                self.min_throttle
+            } else {
+                0.0
+            }
+        } else {
+            self.min_throttle
+                + (1.0 - self.min_throttle) * (throttle.abs() - self.creep_below)
+                    / (1.0 - self.creep_below)
+        };
+
+        command.with_values(
+            Throttle::new(magnitude.copysign(throttle)),
+            command.get_direction(),
+        )
+    }
+
+    fn describe(&self) -> [(&'static str, String); 6] {
+        [
+            ("driving.deadzone", self.deadzone.to_string()),
+            ("driving.throttle_expo", self.throttle_expo.to_string()),
+            ("driving.steering_expo", self.steering_expo.to_string()),
+            (
+                "driving.max_speed_percent",
+                self.max_speed_percent.to_string(),
+            ),
+            ("driving.min_throttle", self.min_throttle.to_string()),
+            ("driving.creep_below", self.creep_below.to_string()),
+        ]
+    }
+
+    fn shape_value(&self, value: f64, expo: f64) -> f64 {
+        if value.abs() <= self.deadzone {
+            return 0.0;
+        }
+
+        let value = value.signum() * (value.abs() - self.deadzone) / (1.0 - self.deadzone);
+        let value = (1.0 - expo) * value + expo * value.powi(3);
+
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hint::black_box;
+
+    #[test]
+    fn low_speed_assist() {
+        let settings = DrivingSettings {
+            min_throttle: 0.2,
+            creep_below: 0.1,
+            ..DrivingSettings::default()
+        };
+        let throttle = |value: f64, now: Duration| {
+            settings
+                .assist_low_speed(
+                    LocomotionCommand::new(Throttle::new(value), Steering::CENTER),
+                    now,
+                )
+                .get_throttle()
+                .value()
+        };
+
+        assert_eq!(throttle(0.0, Duration::ZERO), 0.0);
+        assert_eq!(throttle(0.1, Duration::ZERO), 0.2);
+        assert!((throttle(-0.55, Duration::ZERO) + 0.6).abs() < 1e-9);
+        assert_eq!(throttle(1.0, Duration::ZERO), 1.0);
+
+        // A quarter of the way to `creep_below`, the minimum is sent a quarter of the time.
+        let pulsed = (0..200)
+            .map(|millisecond| throttle(-0.025, Duration::from_millis(1000 + millisecond)))
+            .collect::<Vec<_>>();
+        assert!(pulsed.iter().all(|value| [0.0, -0.2].contains(value)));
+        assert_eq!(pulsed.iter().filter(|value| **value != 0.0).count(), 50);
+
+        // Without a minimum, nothing changes.
+        let settings = DrivingSettings::default();
+        let command = LocomotionCommand::new(Throttle::new(0.01), Steering::CENTER);
+        assert_eq!(
+            settings
+                .assist_low_speed(command, Duration::ZERO)
+                .get_throttle(),
+            command.get_throttle()
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_shaping() {
+        let settings = DrivingSettings {
+            deadzone: 0.05,
+            throttle_expo: 0.3,
+            steering_expo: 0.5,
+            max_speed_percent: 80,
+            ..DrivingSettings::default()
+        };
+
+        crate::benchmark::measure(
+            "Shaping a command",
+            Duration::from_micros(10),
+            |iteration| {
+                let value = (iteration % 2001) as f64 / 1000.0 - 1.0;
+                let command = LocomotionCommand::new(Throttle::new(value), Steering::new(-value));
+                black_box(settings.shape(black_box(command)));
+            },
+        );
+    }
+}
@@ -0,0 +1,199 @@
+mod pcm;
+
+use pcm::PcmPlayback;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// A simulated engine sound on a small speaker, purely for show: a train of decaying pulses, one per firing of the
+// imaginary engine, which fire faster and louder as the throttle opens. Like a real engine, it takes a moment to rev
+// up and down rather than following the throttle instantly. It idles while armed and falls silent when disarmed.
+//
+// Samples are synthesized every runloop iteration, just enough to keep `QUEUED_AHEAD` of them waiting to be played, so
+// that the sound follows the throttle with little delay while riding out a late iteration. See `engine_sound/pcm.rs`
+// for the output.
+
+const QUEUED_AHEAD: Duration = Duration::from_millis(60);
+// How quickly the engine revs up and down, as a time constant.
+const REV_TIME: Duration = Duration::from_millis(300);
+// How quickly the engine starts and stops, likewise.
+const START_TIME: Duration = Duration::from_millis(50);
+// How quickly a pulse decays over one firing period.
+const PULSE_DECAY: f64 = 6.0;
+// The loudness at idle, relative to that at full throttle.
+const IDLE_LEVEL: f64 = 0.4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EngineSoundSettings {
+    // Firings per second at idle and at full throttle.
+    pub idle_frequency: f64,
+    pub max_frequency: f64,
+    // From 0.0 to 1.0.
+    pub volume: f64,
+}
+
+struct Synthesizer {
+    settings: EngineSoundSettings,
+    // The fraction of the way to their targets that the frequency and level move every sample.
+    rev_factor: f64,
+    start_factor: f64,
+    frequency: f64,
+    level: f64,
+    // Within the current firing period, from 0.0 to 1.0.
+    phase: f64,
+    // State of the xorshift generator that roughens the pulses.
+    noise: u32,
+}
+
+impl Synthesizer {
+    fn new(settings: EngineSoundSettings, sample_rate: u32) -> Synthesizer {
+        let factor = |time_constant: Duration| {
+            1.0 - (-1.0 / (sample_rate as f64 * time_constant.as_secs_f64())).exp()
+        };
+
+        Synthesizer {
+            settings,
+            rev_factor: factor(REV_TIME),
+            start_factor: factor(START_TIME),
+            frequency: settings.idle_frequency,
+            level: 0.0,
+            phase: 0.0,
+            noise: 0x9e37_79b9,
+        }
+    }
+
+    fn next_noise(&mut self) -> f64 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+
+        self.noise as f64 / u32::MAX as f64 * 2.0 - 1.0
+    }
+
+    // `throttle` goes from -1.0 to 1.0, either way revving the engine.
+    fn render(&mut self, throttle: f64, running: bool, sample_rate: u32, samples: &mut [i16]) {
+        let throttle = throttle.abs().min(1.0);
+        let target_frequency = self.settings.idle_frequency
+            + (self.settings.max_frequency - self.settings.idle_frequency) * throttle;
+        let target_level = if running {
+            IDLE_LEVEL + (1.0 - IDLE_LEVEL) * throttle
+        } else {
+            0.0
+        };
+        // Pulses average out to this, which is taken off so as not to push the speaker's cone to one side.
+        let pulse_mean = (1.0 - (-PULSE_DECAY).exp()) / PULSE_DECAY;
+
+        for sample in samples {
+            self.frequency += (target_frequency - self.frequency) * self.rev_factor;
+            self.level += (target_level - self.level) * self.start_factor;
+
+            self.phase += self.frequency / sample_rate as f64;
+            if self.phase >= 1.0 {
+                self.phase -= 1.0;
+            }
+            let pulse = (-PULSE_DECAY * self.phase).exp() - pulse_mean;
+            let roughness = 1.0 + 0.2 * self.next_noise();
+            let value = pulse * roughness * self.level * self.settings.volume;
+
+            *sample = (value.clamp(-1.0, 1.0) * i16::MAX as f64) as i16;
+        }
+    }
+}
+
+pub struct EngineSound {
+    playback: PcmPlayback,
+    device_file_path: PathBuf,
+    synthesizer: Synthesizer,
+    samples: Vec<i16>,
+    // Whether the previous update failed, so that only the first failure of a series is logged.
+    failing: bool,
+}
+
+impl EngineSound {
+    pub fn new(
+        device_file_path: &Path,
+        settings: EngineSoundSettings,
+    ) -> Result<EngineSound, IoError> {
+        let playback = PcmPlayback::open(device_file_path)?;
+        log::info!(
+            "Playing engine sound on {} at {} Hz, with a buffer of {} samples.",
+            device_file_path.display(),
+            playback.sample_rate(),
+            playback.buffer_size()
+        );
+
+        Ok(EngineSound {
+            synthesizer: Synthesizer::new(settings, playback.sample_rate()),
+            samples: Vec::with_capacity(playback.buffer_size()),
+            playback,
+            device_file_path: device_file_path.to_path_buf(),
+            failing: false,
+        })
+    }
+
+    // Expected to be called every runloop iteration, with the throttle being executed and whether the vehicle is
+    // armed.
+    pub fn update(&mut self, throttle: f64, armed: bool) {
+        match self.play(throttle, armed) {
+            Ok(()) => self.failing = false,
+            Err(error) => {
+                if !self.failing {
+                    log::warn!(
+                        "Could not play engine sound on {}. - Cause: {}",
+                        self.device_file_path.display(),
+                        error
+                    );
+                    self.failing = true;
+                }
+            }
+        }
+    }
+
+    fn play(&mut self, throttle: f64, armed: bool) -> Result<(), IoError> {
+        let sample_rate = self.playback.sample_rate();
+        let queued_ahead = ((sample_rate as f64 * QUEUED_AHEAD.as_secs_f64()) as usize)
+            .min(self.playback.buffer_size());
+        let missing = queued_ahead.saturating_sub(self.playback.queued_frames()?);
+        if missing == 0 {
+            return Ok(());
+        }
+
+        self.samples.resize(missing, 0);
+        self.synthesizer
+            .render(throttle, armed, sample_rate, &mut self.samples);
+        // Whatever does not fit is dropped. It would be out of date by the time there is room for it anyway.
+        self.playback.write(&self.samples)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engine_revs_and_stops() {
+        let settings = EngineSoundSettings {
+            idle_frequency: 20.0,
+            max_frequency: 100.0,
+            volume: 1.0,
+        };
+        let sample_rate = 8000;
+        let mut synthesizer = Synthesizer::new(settings, sample_rate);
+        let mut samples = vec![0; sample_rate as usize];
+
+        // Revving up takes a moment, but a second is plenty.
+        synthesizer.render(1.0, true, sample_rate, &mut samples[..800]);
+        assert!(synthesizer.frequency > 30.0 && synthesizer.frequency < 60.0);
+        synthesizer.render(1.0, true, sample_rate, &mut samples);
+        assert!((synthesizer.frequency - 100.0).abs() < 5.0);
+        assert!(samples.iter().any(|sample| sample.unsigned_abs() > 10_000));
+
+        // Disarming silences it.
+        synthesizer.render(0.0, false, sample_rate, &mut samples);
+        assert!(samples[samples.len() - 100..]
+            .iter()
+            .all(|sample| sample.unsigned_abs() < 10));
+    }
+}
@@ -0,0 +1,240 @@
+use std::ffi::CString;
+use std::io::Error as IoError;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::prelude::OsStrExt;
+use std::path::Path;
+
+// Plays 16-bit mono samples on an ALSA PCM device (e.g. `/dev/snd/pcmC0D0p`), through the kernel's interface rather
+// than alsa-lib: the hardware parameters are set with a single `HW_PARAMS` ioctl, after which samples are written to
+// the device file, which never blocks.
+//
+// The kernel picks the parameters from the ranges it is given: the lowest supported sample rate from 22.05 kHz up,
+// and a buffer short enough to keep the sound in step with the throttle. Running out of samples (an underrun) stops
+// playback, which is simply prepared again.
+
+// asound.h
+const SNDRV_PCM_HW_PARAM_ACCESS: usize = 0;
+const SNDRV_PCM_HW_PARAM_FORMAT: usize = 1;
+const SNDRV_PCM_HW_PARAM_FIRST_INTERVAL: usize = 8;
+const SNDRV_PCM_HW_PARAM_CHANNELS: usize = 10;
+const SNDRV_PCM_HW_PARAM_RATE: usize = 11;
+const SNDRV_PCM_HW_PARAM_PERIOD_TIME: usize = 12;
+const SNDRV_PCM_HW_PARAM_BUFFER_TIME: usize = 16;
+const SNDRV_PCM_HW_PARAM_BUFFER_SIZE: usize = 17;
+const SNDRV_PCM_ACCESS_RW_INTERLEAVED: u32 = 3;
+const SNDRV_PCM_FORMAT_S16_LE: u32 = 2;
+// The `integer` bit of `struct snd_interval`'s flags.
+const INTERVAL_INTEGER: u32 = 1 << 2;
+
+const MINIMUM_SAMPLE_RATE: u32 = 22_050;
+const MAXIMUM_SAMPLE_RATE: u32 = 48_000;
+// In microseconds.
+const PERIOD_TIME_RANGE: (u32, u32) = (5_000, 20_000);
+const BUFFER_TIME_RANGE: (u32, u32) = (40_000, 100_000);
+
+// struct snd_mask
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Mask {
+    bits: [u32; 8],
+}
+
+// struct snd_interval
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct Interval {
+    min: u32,
+    max: u32,
+    flags: u32,
+}
+
+// struct snd_pcm_hw_params
+#[repr(C)]
+struct HwParams {
+    flags: u32,
+    masks: [Mask; 3],
+    reserved_masks: [Mask; 5],
+    intervals: [Interval; 12],
+    reserved_intervals: [Interval; 9],
+    rmask: u32,
+    cmask: u32,
+    info: u32,
+    msbits: u32,
+    rate_num: u32,
+    rate_den: u32,
+    fifo_size: libc::c_ulong,
+    reserved: [u8; 64],
+}
+
+impl HwParams {
+    // Anything goes, until narrowed down.
+    fn unrestricted() -> HwParams {
+        HwParams {
+            flags: 0,
+            masks: [Mask {
+                bits: [u32::MAX; 8],
+            }; 3],
+            reserved_masks: [Mask { bits: [0; 8] }; 5],
+            intervals: [Interval {
+                min: 0,
+                max: u32::MAX,
+                flags: 0,
+            }; 12],
+            reserved_intervals: [Interval {
+                min: 0,
+                max: 0,
+                flags: 0,
+            }; 9],
+            rmask: u32::MAX,
+            cmask: 0,
+            info: 0,
+            msbits: 0,
+            rate_num: 0,
+            rate_den: 0,
+            fifo_size: 0,
+            reserved: [0; 64],
+        }
+    }
+
+    fn set_mask(&mut self, parameter: usize, value: u32) {
+        let mut bits = [0; 8];
+        bits[value as usize / 32] = 1 << (value % 32);
+        self.masks[parameter].bits = bits;
+    }
+
+    fn interval(&mut self, parameter: usize) -> &mut Interval {
+        &mut self.intervals[parameter - SNDRV_PCM_HW_PARAM_FIRST_INTERVAL]
+    }
+
+    fn set_interval(&mut self, parameter: usize, (min, max): (u32, u32)) {
+        let interval = self.interval(parameter);
+        interval.min = min;
+        interval.max = max;
+        interval.flags = INTERVAL_INTEGER;
+    }
+}
+
+pub struct PcmPlayback {
+    device_fd: OwnedFd,
+    sample_rate: u32,
+    buffer_size: usize,
+}
+
+impl PcmPlayback {
+    pub fn open(device_file_path: &Path) -> Result<PcmPlayback, IoError> {
+        let device_file_path = CString::new(device_file_path.as_os_str().as_bytes()).unwrap();
+        let fd = unsafe {
+            libc::open(
+                device_file_path.as_ptr(),
+                libc::O_WRONLY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        };
+        if fd == -1 {
+            return Err(IoError::last_os_error());
+        }
+        let device_fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let mut hw_params = HwParams::unrestricted();
+        hw_params.set_mask(SNDRV_PCM_HW_PARAM_ACCESS, SNDRV_PCM_ACCESS_RW_INTERLEAVED);
+        hw_params.set_mask(SNDRV_PCM_HW_PARAM_FORMAT, SNDRV_PCM_FORMAT_S16_LE);
+        hw_params.set_interval(SNDRV_PCM_HW_PARAM_CHANNELS, (1, 1));
+        hw_params.set_interval(
+            SNDRV_PCM_HW_PARAM_RATE,
+            (MINIMUM_SAMPLE_RATE, MAXIMUM_SAMPLE_RATE),
+        );
+        hw_params.set_interval(SNDRV_PCM_HW_PARAM_PERIOD_TIME, PERIOD_TIME_RANGE);
+        hw_params.set_interval(SNDRV_PCM_HW_PARAM_BUFFER_TIME, BUFFER_TIME_RANGE);
+
+        // _IOWR('A', 0x11, struct snd_pcm_hw_params)
+        const SNDRV_PCM_IOCTL_HW_PARAMS: libc::c_ulong =
+            0xc0000000 | ((mem::size_of::<HwParams>() as libc::c_ulong) << 16) | 0x4111;
+        let result = unsafe {
+            libc::ioctl(
+                device_fd.as_raw_fd(),
+                SNDRV_PCM_IOCTL_HW_PARAMS as _,
+                &mut hw_params,
+            )
+        };
+        if result < 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        let playback = PcmPlayback {
+            device_fd,
+            sample_rate: hw_params.interval(SNDRV_PCM_HW_PARAM_RATE).min,
+            buffer_size: hw_params.interval(SNDRV_PCM_HW_PARAM_BUFFER_SIZE).min as usize,
+        };
+        playback.prepare()?;
+
+        Ok(playback)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // In frames.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    // How many frames are waiting to be played.
+    pub fn queued_frames(&self) -> Result<usize, IoError> {
+        // _IOR('A', 0x21, snd_pcm_sframes_t)
+        const SNDRV_PCM_IOCTL_DELAY: libc::c_ulong =
+            0x80000000 | ((mem::size_of::<libc::c_long>() as libc::c_ulong) << 16) | 0x4121;
+
+        let mut delay: libc::c_long = 0;
+        let result = unsafe {
+            libc::ioctl(
+                self.device_fd.as_raw_fd(),
+                SNDRV_PCM_IOCTL_DELAY as _,
+                &mut delay,
+            )
+        };
+        if result < 0 {
+            let error = IoError::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::EPIPE | libc::ESTRPIPE) => self.prepare().map(|()| 0),
+                _ => Err(error),
+            };
+        }
+
+        Ok(delay.max(0) as usize)
+    }
+
+    // Writes as many of `samples` as there is room for, and returns how many that was.
+    pub fn write(&self, samples: &[i16]) -> Result<usize, IoError> {
+        let bytes_written = unsafe {
+            libc::write(
+                self.device_fd.as_raw_fd(),
+                samples.as_ptr() as *const libc::c_void,
+                mem::size_of_val(samples),
+            )
+        };
+        if bytes_written < 0 {
+            let error = IoError::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::EAGAIN) => Ok(0),
+                Some(libc::EPIPE | libc::ESTRPIPE) => self.prepare().map(|()| 0),
+                _ => Err(error),
+            };
+        }
+
+        Ok(bytes_written as usize / mem::size_of::<i16>())
+    }
+
+    fn prepare(&self) -> Result<(), IoError> {
+        // _IO('A', 0x40)
+        const SNDRV_PCM_IOCTL_PREPARE: libc::c_ulong = 0x4140;
+
+        let result =
+            unsafe { libc::ioctl(self.device_fd.as_raw_fd(), SNDRV_PCM_IOCTL_PREPARE as _) };
+        if result < 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        Ok(())
+    }
+}
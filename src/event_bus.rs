@@ -0,0 +1,100 @@
+use crate::application_state::ApplicationState;
+use crate::gamepads::PermissionDiagnostic;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+
+// Lets modules tell each other about noteworthy things happening without having to know about each other: whoever
+// has something to report publishes an event, and the runloop hands all events published since the last iteration
+// to every subscriber once per iteration. Everything runs on the runloop thread, so the bus is merely a shared queue.
+//
+// The queue is bounded, as a subscriber falling behind is no reason to run out of memory. Should it fill up, the
+// oldest events are dropped.
+
+const CAPACITY: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event {
+    InputConnected,
+    InputDisconnected,
+    FailsafeEngaged,
+    Armed,
+    Disarmed,
+    // The throttle is being brought down from `throttle` after input was lost.
+    BrakeRampStarted {
+        throttle: f64,
+    },
+    StateChanged {
+        previous_state: ApplicationState,
+        state: ApplicationState,
+    },
+    // Writing to the PCA9685 has become slow, which hints at a problem with the I2C bus.
+    I2CLatencyExceeded {
+        p99: Duration,
+    },
+    I2CLatencyRecovered,
+    // Input devices have kept refusing to be opened for lack of permissions. See `gamepads/permissions.rs`.
+    InputPermissionDenied(PermissionDiagnostic),
+}
+
+// Implemented by whatever needs to react to events.
+pub trait EventSubscriber {
+    fn handle_event(&mut self, event: Event);
+}
+
+// Cloning gives another handle on the same bus, for handing to publishers.
+#[derive(Clone)]
+pub struct EventBus {
+    queue: Rc<RefCell<EventQueue>>,
+}
+
+struct EventQueue {
+    events: VecDeque<Event>,
+    dropped_events: u64,
+}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus {
+            queue: Rc::new(RefCell::new(EventQueue {
+                events: VecDeque::with_capacity(CAPACITY),
+                dropped_events: 0,
+            })),
+        }
+    }
+
+    pub fn publish(&self, event: Event) {
+        let mut queue = self.queue.borrow_mut();
+
+        if queue.events.len() == CAPACITY {
+            queue.events.pop_front();
+            queue.dropped_events += 1;
+        }
+        queue.events.push_back(event);
+    }
+
+    // Hands every pending event to every subscriber, in the order they were published. Events published by
+    // subscribers while handling an event are delivered on the next dispatch.
+    pub fn dispatch(&self, subscribers: &mut [&mut dyn EventSubscriber]) {
+        let (events, dropped_events) = {
+            let mut queue = self.queue.borrow_mut();
+            let dropped_events = std::mem::take(&mut queue.dropped_events);
+            (std::mem::take(&mut queue.events), dropped_events)
+        };
+
+        if dropped_events > 0 {
+            log::warn!(
+                "Dropped {} event(s), as they were not dispatched in time.",
+                dropped_events
+            );
+        }
+
+        for event in events {
+            log::debug!("Dispatching {:?}.", event);
+            for subscriber in subscribers.iter_mut() {
+                subscriber.handle_event(event);
+            }
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::gamepads::RecordError;
+use crate::{choreography, configuration, gpio, i2c, serial};
+use std::error::Error;
+use std::io::{Error as IoError, ErrorKind};
+use std::process::ExitCode;
+
+// What the process exits with when it cannot carry on, so that supervisors and scripts can tell a mistake in the
+// configuration (no point in restarting) from hardware that is missing or a service that is not allowed to use it
+// (worth pointing out to whoever deploys it) and from a fault while running (worth restarting).
+//
+// The kind is worked out from the error that ended the process, going by the first error along its chain of causes
+// that says something about it. The codes are listed in `--help`, so they are part of the interface: keep them as
+// they are.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FatalErrorKind {
+    // The command line could not be made sense of.
+    InvalidArguments,
+    // The configuration file (or a file it refers to) could not be read or is invalid.
+    InvalidConfiguration,
+    // A device the configuration calls for is not there, or does not answer.
+    HardwareMissing,
+    // The service is not allowed to use a device or file it needs.
+    PermissionDenied,
+    // Anything else, most likely something going wrong while running.
+    RuntimeFault,
+}
+
+impl FatalErrorKind {
+    pub const ALL: [FatalErrorKind; 5] = [
+        FatalErrorKind::InvalidArguments,
+        FatalErrorKind::InvalidConfiguration,
+        FatalErrorKind::HardwareMissing,
+        FatalErrorKind::PermissionDenied,
+        FatalErrorKind::RuntimeFault,
+    ];
+
+    // 1 is left for commands that ran fine but did not succeed, e.g. a control request that was refused.
+    pub fn code(self) -> u8 {
+        match self {
+            FatalErrorKind::InvalidArguments => 2,
+            FatalErrorKind::InvalidConfiguration => 3,
+            FatalErrorKind::HardwareMissing => 4,
+            FatalErrorKind::PermissionDenied => 5,
+            FatalErrorKind::RuntimeFault => 6,
+        }
+    }
+
+    pub fn classify(error: &(dyn Error + 'static)) -> FatalErrorKind {
+        let mut next_error = Some(error);
+        let mut opening_device_file = false;
+        while let Some(error) = next_error {
+            if error.is::<configuration::LoadError>() || error.is::<choreography::LoadError>() {
+                return FatalErrorKind::InvalidConfiguration;
+            }
+            opening_device_file |= is_opening_device_file(error);
+            if let Some(io_error) = error.downcast_ref::<IoError>() {
+                if let Some(kind) = classify_io_error(io_error, opening_device_file) {
+                    return kind;
+                }
+            }
+            next_error = error.source();
+        }
+
+        FatalErrorKind::RuntimeFault
+    }
+}
+
+impl From<FatalErrorKind> for ExitCode {
+    fn from(kind: FatalErrorKind) -> Self {
+        ExitCode::from(kind.code())
+    }
+}
+
+impl std::fmt::Display for FatalErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            FatalErrorKind::InvalidArguments => "invalid arguments",
+            FatalErrorKind::InvalidConfiguration => "invalid configuration",
+            FatalErrorKind::HardwareMissing => "hardware missing or not responding",
+            FatalErrorKind::PermissionDenied => "permission denied",
+            FatalErrorKind::RuntimeFault => "runtime fault",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+// Whether `error` is about opening a device file, which not being there means the device is missing. Any other file
+// that is not there was most likely named wrongly in the configuration.
+fn is_opening_device_file(error: &(dyn Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref(),
+        Some(i2c::SetupError::CouldNotOpenI2CDevice { .. })
+    ) || matches!(
+        error.downcast_ref(),
+        Some(serial::SetupError::CouldNotOpenSerialDevice { .. })
+    ) || matches!(
+        error.downcast_ref(),
+        Some(gpio::SetupError::CouldNotOpenChip { .. })
+    ) || matches!(
+        error.downcast_ref(),
+        Some(RecordError::CouldNotOpenDevice { .. })
+    )
+}
+
+fn classify_io_error(error: &IoError, opening_device_file: bool) -> Option<FatalErrorKind> {
+    if error.kind() == ErrorKind::PermissionDenied {
+        return Some(FatalErrorKind::PermissionDenied);
+    }
+
+    match error.raw_os_error()? {
+        libc::ENOENT if opening_device_file => Some(FatalErrorKind::HardwareMissing),
+        libc::ENOENT => Some(FatalErrorKind::InvalidConfiguration),
+        // A device that is gone, or does not acknowledge on the I2C bus.
+        libc::ENODEV | libc::ENXIO | libc::EREMOTEIO => Some(FatalErrorKind::HardwareMissing),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn classifying_by_cause() {
+        let configuration_error = configuration::LoadError::InvalidSyntax { line: 3 };
+        assert_eq!(
+            FatalErrorKind::classify(&configuration_error),
+            FatalErrorKind::InvalidConfiguration
+        );
+
+        let missing_bus = crate::i2c::SetupError::CouldNotOpenI2CDevice {
+            path: PathBuf::from("/dev/i2c-1"),
+            source: IoError::from_raw_os_error(libc::ENOENT),
+        };
+        assert_eq!(
+            FatalErrorKind::classify(&missing_bus),
+            FatalErrorKind::HardwareMissing
+        );
+
+        let missing_log_file = crate::daemon::DaemonizeError::CouldNotOpenLogFile {
+            path: PathBuf::from("/var/log/roestbak/roestbak.log"),
+            source: IoError::from_raw_os_error(libc::ENOENT),
+        };
+        assert_eq!(
+            FatalErrorKind::classify(&missing_log_file),
+            FatalErrorKind::InvalidConfiguration
+        );
+
+        let boxed: Box<dyn Error> = Box::new(IoError::from_raw_os_error(libc::EACCES));
+        assert_eq!(
+            FatalErrorKind::classify(boxed.as_ref()),
+            FatalErrorKind::PermissionDenied
+        );
+
+        let boxed: Box<dyn Error> = "Something went wrong.".into();
+        assert_eq!(
+            FatalErrorKind::classify(boxed.as_ref()),
+            FatalErrorKind::RuntimeFault
+        );
+    }
+}
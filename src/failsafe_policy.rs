@@ -0,0 +1,168 @@
+use crate::clock::Clock;
+use crate::i2c::I2CTransport;
+use crate::locomotion::LocomotionController;
+use std::str::FromStr;
+use std::time::Duration;
+
+// How the vehicle reacts to each kind of failure. A response is written as `<coast|brake>:<duration in ms>`, optionally
+// followed by `:disarm`, e.g. `brake:300:disarm`:
+// - coasting brings the throttle down to neutral over (up to) the duration, see `LocomotionController::start_brake_ramp`,
+// - braking applies the ESC's brake for the duration when going forward, and coasts when going backwards, see
+//   `LocomotionController::start_active_brake`. Only for ESCs that brake on reverse throttle,
+// - disarming keeps the vehicle from driving off again once input is back, until it is armed again.
+//
+// Either way, input from the driver takes over again right away unless the vehicle was disarmed, except after a geofence
+// breach: the throttle is then held at neutral until the driver has let go of it, so that the response is not undone by
+// a throttle that is still being held open.
+
+const MAXIMUM_DURATION: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FailureSource {
+    // A gamepad, keyboard or SBUS receiver went away.
+    InputLost,
+    // The network link to the ground control station (MAVLink) was lost.
+    LinkLost,
+    GeofenceBreach,
+}
+
+impl std::fmt::Display for FailureSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            FailureSource::InputLost => "input lost",
+            FailureSource::LinkLost => "link lost",
+            FailureSource::GeofenceBreach => "geofence breach",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StopMode {
+    Coast,
+    Brake,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FailsafeResponse {
+    pub stop_mode: StopMode,
+    pub duration: Duration,
+    pub disarm: bool,
+}
+
+impl FailsafeResponse {
+    // Coasting to neutral over `brake_ramp`, which is how input being lost has always been handled.
+    pub fn coast(brake_ramp: Duration) -> FailsafeResponse {
+        FailsafeResponse {
+            stop_mode: StopMode::Coast,
+            duration: brake_ramp,
+            disarm: false,
+        }
+    }
+
+    pub fn stop<T: I2CTransport, C: Clock>(
+        &self,
+        locomotion_controller: &mut LocomotionController<T, C>,
+    ) {
+        match self.stop_mode {
+            StopMode::Coast => locomotion_controller.start_brake_ramp(self.duration),
+            StopMode::Brake => locomotion_controller.start_active_brake(self.duration),
+        }
+    }
+}
+
+impl FromStr for FailsafeResponse {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts = text.split(':').map(str::trim);
+        let stop_mode = match parts.next() {
+            Some("coast") => StopMode::Coast,
+            Some("brake") => StopMode::Brake,
+            _ => return Err(()),
+        };
+        let duration = parts
+            .next()
+            .and_then(|duration_ms| duration_ms.parse().ok())
+            .map(Duration::from_millis)
+            .filter(|duration| *duration <= MAXIMUM_DURATION)
+            .ok_or(())?;
+        let disarm = match parts.next() {
+            Some("disarm") => true,
+            Some(_) => return Err(()),
+            None => false,
+        };
+        if parts.next().is_some() {
+            return Err(());
+        }
+
+        Ok(FailsafeResponse {
+            stop_mode,
+            duration,
+            disarm,
+        })
+    }
+}
+
+// The response to each failure source, where configured.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct FailsafePolicy {
+    pub input_lost: Option<FailsafeResponse>,
+    pub link_lost: Option<FailsafeResponse>,
+    pub geofence_breach: Option<FailsafeResponse>,
+}
+
+impl FailsafePolicy {
+    // Losing input or the link is always responded to, by coasting over `brake_ramp` unless configured otherwise. A
+    // geofence breach is only responded to if configured, beyond the geofence capping the throttle by itself.
+    pub fn response_to(
+        &self,
+        source: FailureSource,
+        brake_ramp: Duration,
+    ) -> Option<FailsafeResponse> {
+        match source {
+            FailureSource::InputLost => Some(
+                self.input_lost
+                    .unwrap_or(FailsafeResponse::coast(brake_ramp)),
+            ),
+            FailureSource::LinkLost => Some(
+                self.link_lost
+                    .unwrap_or(FailsafeResponse::coast(brake_ramp)),
+            ),
+            FailureSource::GeofenceBreach => self.geofence_breach,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_responses() {
+        assert_eq!(
+            "coast:500".parse(),
+            Ok(FailsafeResponse::coast(Duration::from_millis(500)))
+        );
+        assert_eq!(
+            " brake : 300 : disarm ".parse(),
+            Ok(FailsafeResponse {
+                stop_mode: StopMode::Brake,
+                duration: Duration::from_millis(300),
+                disarm: true,
+            })
+        );
+
+        for text in [
+            "",
+            "coast",
+            "drift:500",
+            "brake:9000",
+            "brake:300:stop",
+            "coast:0:disarm:now",
+        ] {
+            assert_eq!(text.parse::<FailsafeResponse>(), Err(()));
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::gpio::{self, OutputLine};
+use crate::locomotion::{PCA9685Driver, SetPWMError};
+use std::error::Error;
+use std::path::Path;
+use std::rc::Rc;
+
+// A fan cooling the ESC or motor, switched on and off by the temperature the thermal sensor reports. It is switched
+// through a MOSFET on either a spare PCA9685 channel or a GPIO line. It comes on above `on_above` and only goes off
+// again once the temperature has dropped below `off_below`, so that it does not keep clicking on and off around a
+// single temperature.
+//
+// 💁‍♂️ The fan is off until the first temperature has been read, and keeps going by the last temperature read while
+// the sensor cannot be read.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FanSettings {
+    // In °C.
+    pub on_above: f64,
+    pub off_below: f64,
+}
+
+impl FanSettings {
+    fn should_run(&self, temperature: f64, running: bool) -> bool {
+        if running {
+            temperature >= self.off_below
+        } else {
+            temperature > self.on_above
+        }
+    }
+}
+
+pub enum FanOutput {
+    PCA9685Channel {
+        pca9685_driver: Rc<PCA9685Driver>,
+        channel: u8,
+    },
+    GPIOLine(OutputLine),
+}
+
+impl FanOutput {
+    pub fn gpio(chip_path: &Path, offset: u32) -> Result<FanOutput, gpio::SetupError> {
+        Ok(FanOutput::GPIOLine(OutputLine::request(
+            chip_path, offset, false, false,
+        )?))
+    }
+
+    fn switch(&self, on: bool) -> Result<(), SwitchError> {
+        match self {
+            FanOutput::PCA9685Channel {
+                pca9685_driver,
+                channel,
+            } => {
+                let result = if on {
+                    pca9685_driver.set_full_on(*channel)
+                } else {
+                    pca9685_driver.set_full_off(*channel)
+                };
+                result.map_err(|source| SwitchError::CouldNotSetPCA9685Channel { source })
+            }
+            FanOutput::GPIOLine(line) => line
+                .set_value(on)
+                .map_err(|source| SwitchError::CouldNotSetGPIOLine { source }),
+        }
+    }
+}
+
+pub struct Fan {
+    output: FanOutput,
+    settings: FanSettings,
+    // `None` while it is not known what the output was last set to, such as when setting it failed.
+    running: Option<bool>,
+}
+
+impl Fan {
+    pub fn new(output: FanOutput, settings: FanSettings) -> Fan {
+        Fan {
+            output,
+            settings,
+            running: None,
+        }
+    }
+
+    // Expected to be called every runloop iteration, with the last temperature read.
+    pub fn update(&mut self, temperature: Option<f64>) -> Result<(), SwitchError> {
+        let running = self.running.unwrap_or(false);
+        let run =
+            temperature.is_some_and(|temperature| self.settings.should_run(temperature, running));
+
+        if self.running != Some(run) {
+            if run {
+                log::info!("Turning on the fan.");
+            } else if self.running.is_some() {
+                log::info!("Turning off the fan.");
+            }
+            self.set_running(run)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running == Some(true)
+    }
+
+    // For when the service stops.
+    pub fn turn_off(&mut self) -> Result<(), SwitchError> {
+        self.set_running(false)
+    }
+
+    fn set_running(&mut self, running: bool) -> Result<(), SwitchError> {
+        self.running = None;
+        self.output.switch(running)?;
+        self.running = Some(running);
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum SwitchError {
+    CouldNotSetPCA9685Channel { source: SetPWMError },
+    CouldNotSetGPIOLine { source: gpio::WriteError },
+}
+
+impl Error for SwitchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            SwitchError::CouldNotSetPCA9685Channel { source } => source,
+            SwitchError::CouldNotSetGPIOLine { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for SwitchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SwitchError::CouldNotSetPCA9685Channel { source: _ } => {
+                "Could not set the PCA9685 channel of the fan."
+            }
+            SwitchError::CouldNotSetGPIOLine { source: _ } => {
+                "Could not set the GPIO line of the fan."
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hysteresis() {
+        let settings = FanSettings {
+            on_above: 50.0,
+            off_below: 40.0,
+        };
+
+        assert!(!settings.should_run(50.0, false));
+        assert!(settings.should_run(50.5, false));
+        assert!(settings.should_run(45.0, true));
+        assert!(settings.should_run(40.0, true));
+        assert!(!settings.should_run(39.9, true));
+        assert!(!settings.should_run(45.0, false));
+    }
+}
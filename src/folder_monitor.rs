@@ -13,6 +13,8 @@ pub enum FolderEvent {
     Added(PathBuf),
     Removed(PathBuf),
     AttributesChanged(PathBuf),
+    // A file that was open for writing was closed.
+    Modified(PathBuf),
     EventQueueOverflowed,
 }
 
@@ -38,7 +40,7 @@ impl FolderMonitor {
 
     pub fn process_filesystem_events(
         &self,
-        mut block: impl FnMut(FolderEvent) -> (),
+        mut block: impl FnMut(FolderEvent),
     ) -> Result<(), ProcessingError> {
         // Reading from inotify is a bit peculiar: for each event, the buffer will contain a `libc::inotify_event`
         // structure, optionally followed by a variable length character string for the associated filename.
@@ -112,7 +114,7 @@ impl FolderMonitor {
 
                     // The filename may be padded for alignment reasons, but the padding bytes should all be
                     // NUL characters.
-                    assert!(unsafe { *filename_field_ptr.add(filename_field_length - 1) } == b'\0');
+                    assert!(unsafe { *filename_field_ptr.add(filename_field_length - 1) } == 0);
 
                     let file_name = unsafe { CStr::from_ptr(filename_field_ptr) };
                     let file_name = OsStr::from_bytes(file_name.to_bytes());
@@ -127,6 +129,8 @@ impl FolderMonitor {
                         Some(FolderEvent::Removed(file_path()))
                     } else if (inotify_event.mask & libc::IN_ATTRIB) != 0 {
                         Some(FolderEvent::AttributesChanged(file_path()))
+                    } else if (inotify_event.mask & libc::IN_CLOSE_WRITE) != 0 {
+                        Some(FolderEvent::Modified(file_path()))
                     } else {
                         None
                     };
@@ -143,6 +147,12 @@ impl FolderMonitor {
     }
 }
 
+impl AsFd for FolderMonitor {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify_fd.as_fd()
+    }
+}
+
 #[derive(Debug)]
 pub enum SetupError {
     CouldNotCreateFileDescriptor { source: IoError },
@@ -211,6 +221,7 @@ fn add_inotify_folder_watch(fd: BorrowedFd<'_>, folder: &Path) -> Result<(), IoE
     const WATCH_MASK: u32 = libc::IN_CREATE
         | libc::IN_MOVED_TO
         | libc::IN_ATTRIB
+        | libc::IN_CLOSE_WRITE
         | libc::IN_DELETE
         | libc::IN_MOVED_FROM
         | libc::IN_ONLYDIR;
@@ -1,10 +1,29 @@
 mod any_gamepad;
+mod capture;
 mod detection;
 mod gamepad;
+mod haptics;
 mod input_interpreter;
+mod lights;
+mod mapping;
+mod motion;
+mod permissions;
+mod rumble;
+mod touchpad;
 
 pub use any_gamepad::{AnyGamepad, AnyGamepadEvent};
-pub use detection::GamepadDetector;
+pub use capture::{record_capture, RecordError};
+pub use detection::{GamepadDetector, GamepadDeviceRules};
 pub use gamepad::Gamepad;
-pub use gamepad::{Button, DpadAxis, GamepadEvent, Stick, StickAxis, Trigger};
-pub use input_interpreter::GamepadInputInterpreter;
+pub(crate) use gamepad::{
+    event_timestamp, use_monotonic_event_timestamps, EV_SYN, SYN_DROPPED, SYN_REPORT,
+};
+pub use gamepad::{Button, DpadAxis, GamepadEvent, Pedal, Stick, StickAxis, Trigger};
+pub use haptics::{GamepadHaptics, RumblePatterns};
+#[cfg(test)]
+pub use input_interpreter::mock;
+pub use input_interpreter::{GamepadInputInterpreter, GamepadSettings};
+pub use lights::GamepadLights;
+pub use mapping::MappingDatabase;
+pub use permissions::PermissionDiagnostic;
+pub use rumble::GamepadRumble;
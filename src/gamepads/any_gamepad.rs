@@ -1,53 +1,288 @@
-use super::{Button, DpadAxis, Gamepad, GamepadDetector, GamepadEvent, Stick, StickAxis, Trigger};
+use super::gamepad::GamepadIdentity;
+use super::mapping::ControllerMappings;
+use super::permissions::PermissionDiagnostic;
+use super::{
+    Button, DpadAxis, Gamepad, GamepadDetector, GamepadDeviceRules, GamepadEvent, Pedal, Stick,
+    StickAxis, Trigger,
+};
+use crate::control_values::NormalizedAxis;
+use crate::runloop;
+use std::collections::HashSet;
 use std::error::Error;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 #[derive(Debug, Copy, Clone)]
 pub enum AnyGamepadEvent {
     ButtonPressed(Button),
-    StickAdjusted(Stick, StickAxis, f64),
-    TriggerAdjusted(Trigger, f64),
-    DpadAdjusted(DpadAxis, f64),
+    ButtonReleased(Button),
+    StickAdjusted(Stick, StickAxis, NormalizedAxis),
+    TriggerAdjusted(Trigger, NormalizedAxis),
+    DpadAdjusted(DpadAxis, NormalizedAxis),
+    WheelAdjusted(NormalizedAxis),
+    PedalAdjusted(Pedal, NormalizedAxis),
+    ThrottleLeverAdjusted(NormalizedAxis),
+    Connected,
     Disconnected,
+    // The connected gamepad is decoded by a different mapping from now on. Controls held until now might no longer be
+    // decoded, or be decoded as something else, so this is to be taken like the gamepad reconnecting.
+    Remapped,
+    // Gamepads have been refused to be opened for lack of permissions for `PERMISSION_DENIED_REPORT_AFTER`. See
+    // `permissions.rs`.
+    PermissionDenied(PermissionDiagnostic),
 }
 
+// Opening a gamepad that is there but cannot be opened (e.g. because udev has not fixed its permissions yet) is
+// retried with exponential backoff, rather than on every runloop iteration. Only the first failure is logged as a
+// warning. A gamepad device appearing or changing its permissions ends the backoff.
+const INITIAL_OPEN_RETRY_DELAY: Duration = Duration::from_millis(100);
+const MAXIMUM_OPEN_RETRY_DELAY: Duration = Duration::from_secs(5);
+const PERMISSION_DENIED_REPORT_AFTER: Duration = Duration::from_secs(120);
+
 pub struct AnyGamepad {
     detector: GamepadDetector,
-    current_gamepad: Option<Gamepad>,
+    rules: GamepadDeviceRules,
+    mappings: ControllerMappings,
+    // Devices that turned out not to be among the allowed ones. They are given another chance when the device files
+    // change.
+    disallowed_devices: HashSet<PathBuf>,
+    current_gamepad: Option<(Gamepad, PathBuf)>,
+    next_open_attempt_at: Duration,
+    open_retry_delay: Duration,
+    // Since when every attempt to open a gamepad was refused for lack of permissions, and whether this was reported.
+    permission_denied_since: Option<Duration>,
+    permission_denied_reported: bool,
+    waiting: bool,
+    // Whether the connected gamepad's mapping changed since events were last read, see `AnyGamepadEvent::Remapped`.
+    remapped: bool,
 }
 
 impl AnyGamepad {
-    pub fn new() -> Result<AnyGamepad, Box<dyn Error>> {
-        let detector = GamepadDetector::new()?;
+    pub fn new(
+        rules: &GamepadDeviceRules,
+        mapping_file: Option<&Path>,
+    ) -> Result<AnyGamepad, Box<dyn Error>> {
+        let detector = GamepadDetector::new(rules)?;
+        let mappings = ControllerMappings::new(mapping_file)?;
 
         Ok(AnyGamepad {
             detector,
+            rules: rules.clone(),
+            mappings,
+            disallowed_devices: HashSet::new(),
             current_gamepad: None,
+            next_open_attempt_at: Duration::ZERO,
+            open_retry_delay: Duration::ZERO,
+            permission_denied_since: None,
+            permission_denied_reported: false,
+            waiting: false,
+            remapped: false,
         })
     }
 
+    pub fn is_connected(&self) -> bool {
+        self.current_gamepad.is_some()
+    }
+
+    pub fn identity(&self) -> Option<&GamepadIdentity> {
+        self.current_gamepad
+            .as_ref()
+            .map(|(gamepad, _)| gamepad.identity())
+    }
+
+    pub fn device_file(&self) -> Option<&Path> {
+        self.current_gamepad
+            .as_ref()
+            .map(|(_, device_file)| device_file.as_path())
+    }
+
+    // Checks, without waiting, whether the device folder or the gamepad have something to read, so that neither gets
+    // read in vain. A signal interrupting the check counts as nothing being ready.
+    //
+    // 💁‍♂️ This does not make the runloop wake up on input: it keeps its fixed schedule, which the PCA9685 refresh and
+    // the failsafe timers depend on, and input waits for the next iteration as before.
+    fn readiness(&self) -> Result<Readiness, IoError> {
+        let mut poll_fds = vec![libc::pollfd {
+            fd: self.detector.as_fd().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        if let Some((gamepad, _)) = &self.current_gamepad {
+            poll_fds.push(libc::pollfd {
+                fd: gamepad.as_fd().as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            });
+        }
+
+        let timeout = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        let result = unsafe {
+            libc::ppoll(
+                poll_fds.as_mut_ptr(),
+                poll_fds.len() as libc::nfds_t,
+                &timeout,
+                std::ptr::null(),
+            )
+        };
+
+        if result < 0 {
+            let error = IoError::last_os_error();
+            if error.raw_os_error() == Some(libc::EINTR) {
+                return Ok(Readiness::default());
+            }
+            return Err(error);
+        }
+
+        // Errors and hangups count as ready, so that reading brings them to light.
+        Ok(Readiness {
+            detector: poll_fds[0].revents != 0,
+            gamepad: poll_fds.get(1).is_some_and(|poll_fd| poll_fd.revents != 0),
+        })
+    }
+
+    // Loads the controller mapping file again, and switches a connected gamepad over to its new mapping. That is
+    // reported as `AnyGamepadEvent::Remapped` with the next events read.
+    pub fn reload_mappings(&mut self) {
+        if !self.mappings.reload() {
+            return;
+        }
+
+        if let Some((gamepad, _)) = &mut self.current_gamepad {
+            let mapping = self.mappings.mapping_for(gamepad.identity());
+            self.remapped |= gamepad.set_mapping(mapping);
+        }
+    }
+
+    // Events are passed to `handler` together with the time they occurred, see `Gamepad::read_events`.
     pub fn read_events(
         &mut self,
-        mut handler: impl FnMut(AnyGamepadEvent) -> (),
+        mut handler: impl FnMut(AnyGamepadEvent, Duration),
     ) -> Result<(), Box<dyn Error>> {
-        self.detector.process_updates()?;
-
-        if self.current_gamepad.is_none() {
-            if let Some(gamepad_device_file_path) = self.detector.next_gamepad_device() {
-                match Gamepad::new(&gamepad_device_file_path) {
-                    Ok(gamepad) => {
-                        log::info!("Using gamepad at {}", gamepad_device_file_path.display());
-                        self.current_gamepad = Some(gamepad);
+        let readiness = self.readiness()?;
+
+        match self.mappings.file_changed() {
+            Ok(true) => self.reload_mappings(),
+            Ok(false) => (),
+            Err(error) => log::warn!(
+                "Could not check the controller mapping file for changes. - Cause: {}",
+                error
+            ),
+        }
+        if self.remapped {
+            self.remapped = false;
+            if self.current_gamepad.is_some() {
+                log::info!(
+                    "The gamepad's mapping changed, so its controls start out released again."
+                );
+                handler(AnyGamepadEvent::Remapped, runloop::now());
+            }
+        }
+
+        if readiness.detector && self.detector.process_updates()? {
+            self.next_open_attempt_at = Duration::ZERO;
+            self.disallowed_devices.clear();
+        }
+
+        let now = runloop::now();
+        let was_connected = self.current_gamepad.is_some();
+        if self.current_gamepad.is_none() && now >= self.next_open_attempt_at {
+            match self.detector.next_gamepad_device() {
+                Some(gamepad_device_file_path)
+                    if self.disallowed_devices.contains(gamepad_device_file_path) => {}
+                Some(gamepad_device_file_path) => match Gamepad::new(gamepad_device_file_path) {
+                    Ok(gamepad)
+                        if !self
+                            .rules
+                            .is_allowed(gamepad.identity().vendor, gamepad.identity().product) =>
+                    {
+                        log::info!(
+                            "Ignoring {} at {}, as it is not among the allowed gamepads.",
+                            gamepad.identity(),
+                            gamepad_device_file_path.display()
+                        );
+                        self.disallowed_devices
+                            .insert(gamepad_device_file_path.to_path_buf());
+                    }
+                    Ok(mut gamepad) => {
+                        gamepad.set_mapping(self.mappings.mapping_for(gamepad.identity()));
+                        log::info!(
+                            "Using {} at {}",
+                            gamepad.identity(),
+                            gamepad_device_file_path.display()
+                        );
+                        if !gamepad.identity().physical_location.is_empty() {
+                            log::debug!(
+                                "The gamepad is attached at {}.",
+                                gamepad.identity().physical_location
+                            );
+                        }
+                        self.current_gamepad =
+                            Some((gamepad, gamepad_device_file_path.to_path_buf()));
+                        self.open_retry_delay = Duration::ZERO;
+                        self.permission_denied_since = None;
+                        self.permission_denied_reported = false;
+                        self.waiting = false;
+                        handler(AnyGamepadEvent::Connected, now);
                     }
                     Err(error) => {
-                        log::warn!("Could not open gamepad at {} (udev might still be fixing permissions). - Cause: {}", gamepad_device_file_path.display(), error);
+                        if self.open_retry_delay.is_zero() {
+                            log::warn!("Could not open gamepad at {} (udev might still be fixing permissions). - Cause: {}", gamepad_device_file_path.display(), error);
+                        } else {
+                            log::debug!(
+                                "Could not open gamepad at {}, retrying in {:?}. - Cause: {}",
+                                gamepad_device_file_path.display(),
+                                self.open_retry_delay,
+                                error
+                            );
+                        }
+
+                        if error.kind() == ErrorKind::PermissionDenied {
+                            let since = *self.permission_denied_since.get_or_insert(now);
+                            if !self.permission_denied_reported
+                                && now - since >= PERMISSION_DENIED_REPORT_AFTER
+                            {
+                                let diagnostic =
+                                    PermissionDiagnostic::observe(gamepad_device_file_path);
+                                log::error!(
+                                    "Gamepads have been refused to be opened for {:?}, {}. {}",
+                                    now - since,
+                                    diagnostic,
+                                    diagnostic.remediation()
+                                );
+                                self.permission_denied_reported = true;
+                                handler(AnyGamepadEvent::PermissionDenied(diagnostic), now);
+                            }
+                        } else {
+                            self.permission_denied_since = None;
+                        }
+
+                        self.open_retry_delay = (self.open_retry_delay * 2)
+                            .clamp(INITIAL_OPEN_RETRY_DELAY, MAXIMUM_OPEN_RETRY_DELAY);
+                        self.next_open_attempt_at = now + self.open_retry_delay;
                     }
-                };
+                },
+                None => {
+                    if !self.waiting {
+                        log::info!("Waiting for a gamepad.");
+                        self.waiting = true;
+                    }
+                    self.open_retry_delay = Duration::ZERO;
+                    self.permission_denied_since = None;
+                }
             }
         }
 
-        if let Some(ref mut gamepad) = self.current_gamepad {
-            let gamepad_handler = |gamepad_event: GamepadEvent| {
-                handler(gamepad_event.into());
+        // A gamepad that was only just opened is read right away, as it was not polled.
+        let gamepad_ready = readiness.gamepad || !was_connected;
+        if let (true, Some((gamepad, _))) = (gamepad_ready, &mut self.current_gamepad) {
+            let gamepad_handler = |gamepad_event: GamepadEvent, timestamp: Duration| {
+                handler(gamepad_event.into(), timestamp);
             };
 
             match gamepad.read_events(gamepad_handler) {
@@ -55,7 +290,7 @@ impl AnyGamepad {
                 Err(error) => {
                     log::warn!("Closing gamepad due to read error (this could be an intentional disconnect). - Cause: {}", error);
                     self.current_gamepad = None;
-                    handler(AnyGamepadEvent::Disconnected);
+                    handler(AnyGamepadEvent::Disconnected, runloop::now());
                 }
             };
         }
@@ -64,10 +299,18 @@ impl AnyGamepad {
     }
 }
 
+// Which of the polled file descriptors have something to read.
+#[derive(Debug, Copy, Clone, Default)]
+struct Readiness {
+    detector: bool,
+    gamepad: bool,
+}
+
 impl From<GamepadEvent> for AnyGamepadEvent {
     fn from(gamepad_event: GamepadEvent) -> Self {
         match gamepad_event {
             GamepadEvent::ButtonPressed(button) => AnyGamepadEvent::ButtonPressed(button),
+            GamepadEvent::ButtonReleased(button) => AnyGamepadEvent::ButtonReleased(button),
             GamepadEvent::StickAdjusted(stick, axis, value) => {
                 AnyGamepadEvent::StickAdjusted(stick, axis, value)
             }
@@ -75,6 +318,13 @@ impl From<GamepadEvent> for AnyGamepadEvent {
                 AnyGamepadEvent::TriggerAdjusted(trigger, value)
             }
             GamepadEvent::DpadAdjusted(axis, value) => AnyGamepadEvent::DpadAdjusted(axis, value),
+            GamepadEvent::WheelAdjusted(value) => AnyGamepadEvent::WheelAdjusted(value),
+            GamepadEvent::PedalAdjusted(pedal, value) => {
+                AnyGamepadEvent::PedalAdjusted(pedal, value)
+            }
+            GamepadEvent::ThrottleLeverAdjusted(value) => {
+                AnyGamepadEvent::ThrottleLeverAdjusted(value)
+            }
         }
     }
 }
@@ -0,0 +1,369 @@
+use super::gamepad::{self, AxisRange, GamepadIdentity};
+use crate::signals::{ReceiveError, SignalIntention, SignalManager};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Error as IoError, Write};
+use std::mem;
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::{Path, PathBuf};
+
+// Captures of what a device sends, exactly as the kernel hands it out, for working on the mapping of controllers one
+// does not own: whoever has one records a capture with `roestbak record-raw <device file> <capture file>` while
+// going through its buttons and axes, and the capture is replayed through the same decoding as a live device (see
+// the tests below).
+//
+// A capture starts with a header of `key=value` lines, which describes the device as far as decoding goes:
+//
+//     roestbak-evdev-capture=1
+//     event_size=24
+//     name=Xbox Wireless Controller
+//     bus_type=5
+//     vendor=1118
+//     product=765
+//     version=2307
+//     axis=0 -32768 32767
+//     ...
+//
+// An empty line ends the header. The raw `input_event` structs follow, in the byte order and layout of the machine
+// they were recorded on. The layout only differs in the size of the timestamp, which `event_size` tells: 16 bytes
+// with 32-bit time fields (e.g. 32-bit ARM), 24 bytes with 64-bit ones.
+
+const FORMAT_VERSION: u32 = 1;
+
+// Records until a termination signal (e.g. Ctrl-C) arrives, and returns how many events were recorded.
+pub fn record_capture(
+    device_file_path: &Path,
+    capture_file_path: &Path,
+    signal_manager: &SignalManager,
+) -> Result<u64, RecordError> {
+    let device_fd = gamepad::open_gamepad_device(device_file_path).map_err(|source| {
+        RecordError::CouldNotOpenDevice {
+            path: device_file_path.to_path_buf(),
+            source,
+        }
+    })?;
+    let query_error = |source| RecordError::CouldNotQueryDevice {
+        path: device_file_path.to_path_buf(),
+        source,
+    };
+    gamepad::use_monotonic_event_timestamps(&device_fd).map_err(query_error)?;
+    let identity = GamepadIdentity::query(&device_fd).map_err(query_error)?;
+    let axis_ranges = gamepad::query_axis_ranges(&device_fd);
+
+    let write_error = |source| RecordError::CouldNotWriteCapture {
+        path: capture_file_path.to_path_buf(),
+        source,
+    };
+    let mut capture = BufWriter::new(File::create(capture_file_path).map_err(write_error)?);
+    capture
+        .write_all(header(&identity, &axis_ranges).as_bytes())
+        .map_err(write_error)?;
+
+    println!(
+        "Recording {} to {}. Press Ctrl-C to stop.",
+        identity,
+        capture_file_path.display()
+    );
+
+    const INPUT_EVENT_SIZE: usize = mem::size_of::<libc::input_event>();
+    let mut buffer = [0u8; 256 * INPUT_EVENT_SIZE];
+    let mut bytes_recorded: u64 = 0;
+    loop {
+        let mut poll_fds = [
+            libc::pollfd {
+                fd: device_fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd {
+                fd: signal_manager.as_fd().as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+        ];
+        let result = unsafe { libc::poll(poll_fds.as_mut_ptr(), poll_fds.len() as _, -1) };
+        if result < 0 {
+            let error = IoError::last_os_error();
+            if error.raw_os_error() == Some(libc::EINTR) {
+                continue;
+            }
+            return Err(RecordError::CouldNotWaitForEvents { source: error });
+        }
+
+        let terminating = signal_manager
+            .pending_signals()
+            .map_err(|source| RecordError::CouldNotReceiveSignals { source })?
+            .iter()
+            .any(|intention| {
+                matches!(
+                    intention,
+                    SignalIntention::Terminate | SignalIntention::DumpAndTerminate
+                )
+            });
+        if terminating {
+            break;
+        }
+
+        // Unlike when driving, whatever is read is written as is: a capture is to show what the device sent.
+        let bytes_read = unsafe {
+            libc::read(
+                device_fd.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if bytes_read < 0 {
+            let error = IoError::last_os_error();
+            match error.raw_os_error() {
+                Some(libc::EAGAIN | libc::EINTR) => continue,
+                _ => return Err(RecordError::CouldNotReadDevice { source: error }),
+            }
+        }
+        capture
+            .write_all(&buffer[..bytes_read as usize])
+            .map_err(write_error)?;
+        bytes_recorded += bytes_read as u64;
+    }
+    capture.flush().map_err(write_error)?;
+
+    Ok(bytes_recorded / INPUT_EVENT_SIZE as u64)
+}
+
+fn header(identity: &GamepadIdentity, axis_ranges: &HashMap<libc::__u16, AxisRange>) -> String {
+    let mut header = format!(
+        "roestbak-evdev-capture={}\nevent_size={}\nname={}\nbus_type={}\nvendor={}\nproduct={}\nversion={}\n",
+        FORMAT_VERSION,
+        mem::size_of::<libc::input_event>(),
+        identity.name.replace('\n', " "),
+        identity.bus_type,
+        identity.vendor,
+        identity.product,
+        identity.version
+    );
+
+    let mut codes: Vec<&libc::__u16> = axis_ranges.keys().collect();
+    codes.sort();
+    for code in codes {
+        let range = axis_ranges[code];
+        header.push_str(&format!(
+            "axis={} {} {}\n",
+            code, range.minimum, range.maximum
+        ));
+    }
+    header.push('\n');
+
+    header
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    CouldNotOpenDevice { path: PathBuf, source: IoError },
+    CouldNotQueryDevice { path: PathBuf, source: IoError },
+    CouldNotWriteCapture { path: PathBuf, source: IoError },
+    CouldNotWaitForEvents { source: IoError },
+    CouldNotReadDevice { source: IoError },
+    CouldNotReceiveSignals { source: ReceiveError },
+}
+
+impl Error for RecordError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            RecordError::CouldNotOpenDevice { path: _, source } => source,
+            RecordError::CouldNotQueryDevice { path: _, source } => source,
+            RecordError::CouldNotWriteCapture { path: _, source } => source,
+            RecordError::CouldNotWaitForEvents { source } => source,
+            RecordError::CouldNotReadDevice { source } => source,
+            RecordError::CouldNotReceiveSignals { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            RecordError::CouldNotOpenDevice { path, source: _ } => {
+                format!("Could not open input device {}.", path.display())
+            }
+            RecordError::CouldNotQueryDevice { path, source: _ } => {
+                format!("Could not query input device {}.", path.display())
+            }
+            RecordError::CouldNotWriteCapture { path, source: _ } => {
+                format!("Could not write capture {}.", path.display())
+            }
+            RecordError::CouldNotWaitForEvents { source: _ } => {
+                "Could not wait for input events.".to_string()
+            }
+            RecordError::CouldNotReadDevice { source: _ } => {
+                "Could not read from the input device.".to_string()
+            }
+            RecordError::CouldNotReceiveSignals { source: _ } => {
+                "Could not receive signals.".to_string()
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control_values::NormalizedAxis;
+    use crate::gamepads::{Button, Gamepad, GamepadEvent, Stick, StickAxis};
+    use std::os::fd::{FromRawFd, OwnedFd};
+
+    struct Capture {
+        identity: GamepadIdentity,
+        axis_ranges: HashMap<libc::__u16, AxisRange>,
+        events: Vec<libc::input_event>,
+    }
+
+    fn parse(bytes: &[u8]) -> Capture {
+        let header_end = bytes
+            .windows(2)
+            .position(|window| window == b"\n\n")
+            .expect("The header is ended by an empty line.");
+        let header = std::str::from_utf8(&bytes[..header_end]).unwrap();
+
+        let mut identity = GamepadIdentity {
+            name: String::new(),
+            bus_type: 0,
+            vendor: 0,
+            product: 0,
+            version: 0,
+            physical_location: String::new(),
+        };
+        let mut axis_ranges = HashMap::new();
+        let mut event_size = 0;
+        for line in header.lines() {
+            let (key, value) = line.split_once('=').unwrap();
+            match key {
+                "roestbak-evdev-capture" => assert_eq!(value, FORMAT_VERSION.to_string()),
+                "event_size" => event_size = value.parse().unwrap(),
+                "name" => identity.name = value.to_string(),
+                "bus_type" => identity.bus_type = value.parse().unwrap(),
+                "vendor" => identity.vendor = value.parse().unwrap(),
+                "product" => identity.product = value.parse().unwrap(),
+                "version" => identity.version = value.parse().unwrap(),
+                "axis" => {
+                    let numbers: Vec<i32> = value
+                        .split_whitespace()
+                        .map(|number| number.parse().unwrap())
+                        .collect();
+                    axis_ranges.insert(
+                        numbers[0] as libc::__u16,
+                        AxisRange {
+                            minimum: numbers[1],
+                            maximum: numbers[2],
+                        },
+                    );
+                }
+                _ => panic!("Unknown header line {}", line),
+            }
+        }
+
+        let events = bytes[header_end + 2..]
+            .chunks_exact(event_size)
+            .map(|bytes| {
+                let (time_size, rest) = match event_size {
+                    16 => (4, &bytes[8..]),
+                    24 => (8, &bytes[16..]),
+                    _ => panic!("Unknown event size {}", event_size),
+                };
+                let time_field = |offset: usize| {
+                    let mut field = [0u8; 8];
+                    field[..time_size].copy_from_slice(&bytes[offset..offset + time_size]);
+                    i64::from_le_bytes(field)
+                };
+                libc::input_event {
+                    time: libc::timeval {
+                        tv_sec: time_field(0) as _,
+                        tv_usec: time_field(time_size) as _,
+                    },
+                    type_: u16::from_le_bytes([rest[0], rest[1]]),
+                    code: u16::from_le_bytes([rest[2], rest[3]]),
+                    value: i32::from_le_bytes([rest[4], rest[5], rest[6], rest[7]]),
+                }
+            })
+            .collect();
+
+        Capture {
+            identity,
+            axis_ranges,
+            events,
+        }
+    }
+
+    // Feeds the events through a pipe standing in for the device file, so that they are decoded as a live device's.
+    fn replay(capture: Capture) -> Vec<GamepadEvent> {
+        let mut fds = [0; 2];
+        let result = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+        assert_eq!(result, 0);
+        let (read_end, write_end) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        let mut gamepad = Gamepad::with_device_fd(read_end, capture.identity, capture.axis_ranges);
+
+        let mut decoded = Vec::new();
+        // Well below the capacity of a pipe.
+        for events in capture.events.chunks(256) {
+            let size = mem::size_of_val(events);
+            let written = unsafe {
+                libc::write(
+                    write_end.as_raw_fd(),
+                    events.as_ptr() as *const libc::c_void,
+                    size,
+                )
+            };
+            assert_eq!(written, size as isize);
+            gamepad.read_events(|event, _| decoded.push(event)).unwrap();
+        }
+
+        decoded
+    }
+
+    // A 32-bit ARM capture, as made on a Raspberry Pi running a 32-bit OS, of pressing A with the left stick pushed
+    // all the way right.
+    fn capture_32_bit() -> Vec<u8> {
+        let mut bytes = b"roestbak-evdev-capture=1\nevent_size=16\nname=Xbox Wireless Controller\n\
+            bus_type=5\nvendor=1118\nproduct=765\naxis=0 -32768 32767\n\n"
+            .to_vec();
+        for (type_, code, value) in [(1u16, 0x130u16, 1i32), (3, 0, 32767), (0, 0, 0)] {
+            bytes.extend(12i32.to_le_bytes());
+            bytes.extend(500_000i32.to_le_bytes());
+            bytes.extend(type_.to_le_bytes());
+            bytes.extend(code.to_le_bytes());
+            bytes.extend(value.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn replaying_captures() {
+        let capture = parse(&capture_32_bit());
+        assert_eq!(capture.identity.vendor, 0x045e);
+        assert_eq!(capture.events[0].time.tv_usec, 500_000);
+
+        // What this machine records is read back just the same.
+        let mut recorded = header(&capture.identity, &capture.axis_ranges).into_bytes();
+        for event in &capture.events {
+            recorded.extend_from_slice(unsafe {
+                std::slice::from_raw_parts(
+                    event as *const libc::input_event as *const u8,
+                    mem::size_of::<libc::input_event>(),
+                )
+            });
+        }
+        let expected = [
+            GamepadEvent::ButtonPressed(Button::A),
+            GamepadEvent::StickAdjusted(
+                Stick::Left,
+                StickAxis::Horizontal,
+                NormalizedAxis::new(1.0),
+            ),
+        ];
+        assert_eq!(replay(parse(&recorded)), expected);
+        assert_eq!(replay(capture), expected);
+    }
+}
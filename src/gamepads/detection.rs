@@ -2,35 +2,129 @@ use crate::folder_monitor::{
     FolderEvent, FolderMonitor, ProcessingError as FolderMonitorProcessingError,
     SetupError as FolderMonitorSetupError,
 };
-use once_cell::sync::Lazy;
 use regex::bytes::Regex;
 use std::collections::VecDeque;
 use std::error::Error;
 use std::fs;
 use std::io::Error as IoError;
+use std::os::fd::{AsFd, BorrowedFd};
 use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-const GAMEPAD_DEVICE_FOLDER: &str = "/dev/input/";
-static GAMEPAD_DEVICE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^js-evdev\d*$").unwrap());
+// input-event-codes.h: properties of input devices.
+pub const INPUT_PROP_BUTTONPAD: u32 = 0x02;
+pub const INPUT_PROP_ACCELEROMETER: u32 = 0x06;
+
+// Which device files are gamepads. By default, these are the links the udev rules shipped with the service create in
+// /dev/input/, but setups with their own udev naming, or with /dev/input/ mounted elsewhere (e.g. in a container), can
+// change where and what to look for. Gamepads can further be limited to certain vendor and product IDs, which is
+// checked once a device has been opened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamepadDeviceRules {
+    pub folder: PathBuf,
+    pub file_name_pattern: FileNamePattern,
+    // Any gamepad is allowed if empty.
+    pub allowed_ids: Vec<DeviceId>,
+}
+
+impl Default for GamepadDeviceRules {
+    fn default() -> Self {
+        Self {
+            folder: PathBuf::from("/dev/input/"),
+            file_name_pattern: r"^js-evdev\d*$".parse().unwrap(),
+            allowed_ids: Vec::new(),
+        }
+    }
+}
+
+impl GamepadDeviceRules {
+    pub fn is_allowed(&self, vendor: u16, product: u16) -> bool {
+        self.allowed_ids.is_empty()
+            || self
+                .allowed_ids
+                .iter()
+                .any(|id| id.matches(vendor, product))
+    }
+
+    fn is_gamepad_device_file(&self, path: &Path) -> bool {
+        !path.is_dir()
+            && path
+                .file_name()
+                .map(|name| name.as_bytes())
+                .is_some_and(|name| self.file_name_pattern.0.is_match(name))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileNamePattern(Regex);
+
+impl FromStr for FileNamePattern {
+    type Err = regex::Error;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Regex::new(text).map(FileNamePattern)
+    }
+}
+
+impl PartialEq for FileNamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+// A vendor ID, optionally with a product ID, in hexadecimal as `lsusb` shows them: `045e:02fd` or `045e`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DeviceId {
+    vendor: u16,
+    product: Option<u16>,
+}
+
+impl DeviceId {
+    fn matches(&self, vendor: u16, product: u16) -> bool {
+        self.vendor == vendor && self.product.is_none_or(|allowed| allowed == product)
+    }
+}
+
+impl FromStr for DeviceId {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let parse = |text: &str| u16::from_str_radix(text, 16).map_err(|_| ());
+
+        match text.split_once(':') {
+            Some((vendor, product)) => Ok(DeviceId {
+                vendor: parse(vendor)?,
+                product: Some(parse(product)?),
+            }),
+            None => Ok(DeviceId {
+                vendor: parse(text)?,
+                product: None,
+            }),
+        }
+    }
+}
 
 pub struct GamepadDetector {
+    rules: GamepadDeviceRules,
     gamepad_devices: VecDeque<PathBuf>,
     folder_monitor: FolderMonitor,
 }
 
 impl GamepadDetector {
-    pub fn new() -> Result<GamepadDetector, SetupError> {
+    pub fn new(rules: &GamepadDeviceRules) -> Result<GamepadDetector, SetupError> {
         // The order is important here: We should not risk missing out on events by scanning the file system
         // first and only setting up folder monitoring afterwards.
 
-        let folder_monitor = FolderMonitor::new(Path::new(GAMEPAD_DEVICE_FOLDER))
+        let folder_monitor = FolderMonitor::new(&rules.folder)
             .map_err(|source| SetupError::CouldNotSetupFolderMonitor { source })?;
 
-        let gamepad_devices = scan_for_gamepad_devices()
+        let gamepad_devices = scan_for_gamepad_devices(rules)
             .map_err(|source| SetupError::CouldNotScanForDeviceFiles { source })?;
 
         let gamepad_detector = GamepadDetector {
+            rules: rules.clone(),
             gamepad_devices,
             folder_monitor,
         };
@@ -47,30 +141,41 @@ impl GamepadDetector {
         self.gamepad_devices.front().map(|path| path.as_path())
     }
 
-    pub fn process_updates(&mut self) -> Result<(), ProcessingError> {
+    // Returns whether a gamepad device file was added or had its permissions changed, i.e. whether there is reason to
+    // believe that opening a gamepad might now succeed where it failed before.
+    pub fn process_updates(&mut self) -> Result<bool, ProcessingError> {
+        let mut devices_changed = false;
+
         self.folder_monitor
             .process_filesystem_events(|event| {
                 match event {
                     FolderEvent::Added(path) => {
-                        if is_gamepad_device_file(&path) {
+                        if self.rules.is_gamepad_device_file(&path) {
+                            devices_changed = true;
                             if !self.gamepad_devices.contains(&path) {
                                 self.gamepad_devices.push_back(path);
                             }
                         }
                     }
                     FolderEvent::Removed(path) => {
-                        if is_gamepad_device_file(&path) {
+                        if self.rules.is_gamepad_device_file(&path) {
                             self.gamepad_devices.retain(|element| element != &path);
                         }
                     }
-                    FolderEvent::AttributesChanged(_) => {
+                    FolderEvent::AttributesChanged(path) => {
+                        devices_changed |= self.rules.is_gamepad_device_file(&path);
+
                         // A device file created by udev might—at least in certain cases—not yet be readable by
                         // us when we receive an `Added` event for it. When the permissions are fixed in a
                         // separate step we'll receive an `AttributesChanged` event for the device file.
                         //
-                        // This is entirely ignored here, though: A read error on a device will not cause it to
-                        // be removed from the list of detected devices. As long as the list is not empty, each
-                        // device file can be tried periodically.
+                        // The list itself is left alone here, though: A read error on a device will not cause it
+                        // to be removed from the list of detected devices. As long as the list is not empty, each
+                        // device file can be tried periodically. The change only serves to retry right away
+                        // rather than waiting for the next periodic attempt.
+                    }
+                    FolderEvent::Modified(_) => {
+                        // Device files are not written to in a way that changes which devices are available.
                     }
                     FolderEvent::EventQueueOverflowed => {
                         // Events may have been irretrievably lost in this case, so the only way to re-sync the 
@@ -87,7 +192,15 @@ impl GamepadDetector {
                     }
                 }
             })
-            .map_err(|source| ProcessingError::FolderMonitorCouldNotProcessEvents { source })
+            .map_err(|source| ProcessingError::FolderMonitorCouldNotProcessEvents { source })?;
+
+        Ok(devices_changed)
+    }
+}
+
+impl AsFd for GamepadDetector {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.folder_monitor.as_fd()
     }
 }
 
@@ -148,15 +261,15 @@ impl std::fmt::Display for ProcessingError {
     }
 }
 
-fn scan_for_gamepad_devices() -> Result<VecDeque<PathBuf>, IoError> {
-    let iterator = fs::read_dir(Path::new(GAMEPAD_DEVICE_FOLDER))?;
+pub fn scan_for_gamepad_devices(rules: &GamepadDeviceRules) -> Result<VecDeque<PathBuf>, IoError> {
+    let iterator = fs::read_dir(&rules.folder)?;
 
     let mut devices = VecDeque::<PathBuf>::new();
 
     for entry in iterator {
         let path = entry?.path();
 
-        if is_gamepad_device_file(&path) {
+        if rules.is_gamepad_device_file(&path) {
             devices.push_back(path);
         }
     }
@@ -164,10 +277,45 @@ fn scan_for_gamepad_devices() -> Result<VecDeque<PathBuf>, IoError> {
     Ok(devices)
 }
 
-fn is_gamepad_device_file(path: &Path) -> bool {
-    !path.is_dir()
-        && path
-            .file_name()
-            .map(|name| name.as_bytes())
-            .is_some_and(|name| GAMEPAD_DEVICE_REGEX.is_match(name))
+// Finds the device file of another input device of the same gamepad, i.e. below the same HID device, with the given
+// property. Controllers such as the DualSense have one for their motion sensors and one for their touchpad.
+pub fn find_companion_device(
+    gamepad_device_file: &Path,
+    input_property: u32,
+) -> Result<Option<PathBuf>, IoError> {
+    // 💁‍♂️ The devices are found by device number rather than by resolving the device file's link, as resolving links
+    // is not among the system calls the seccomp filter allows.
+    let rdev = fs::metadata(gamepad_device_file)?.rdev();
+    let (major, minor) = unsafe { (libc::major(rdev), libc::minor(rdev)) };
+    let input_folder = PathBuf::from(format!(
+        "/sys/dev/char/{}:{}/device/device/input",
+        major, minor
+    ));
+
+    let Ok(input_devices) = fs::read_dir(&input_folder) else {
+        return Ok(None);
+    };
+    for input_device in input_devices.flatten() {
+        let input_device = input_device.path();
+        let has_property = fs::read_to_string(input_device.join("properties"))
+            .ok()
+            .and_then(|properties| u64::from_str_radix(properties.trim(), 16).ok())
+            .is_some_and(|properties| properties & (1 << input_property) != 0);
+        if !has_property {
+            continue;
+        }
+
+        for entry in fs::read_dir(&input_device)?.flatten() {
+            let name = entry.file_name();
+            if name.to_string_lossy().starts_with("event") {
+                // The companion's device file is next to the gamepad's.
+                let device_folder = gamepad_device_file
+                    .parent()
+                    .unwrap_or(Path::new("/dev/input/"));
+                return Ok(Some(device_folder.join(name)));
+            }
+        }
+    }
+
+    Ok(None)
 }
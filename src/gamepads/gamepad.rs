@@ -1,23 +1,43 @@
+use super::mapping::{AxisTarget, ControllerMapping};
+use crate::control_values::NormalizedAxis;
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::io::Error as IoError;
 use std::mem;
 use std::mem::MaybeUninit;
+use std::os::fd::AsFd;
 use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
 use std::os::fd::FromRawFd;
 use std::os::fd::OwnedFd;
 use std::os::unix::prelude::OsStrExt;
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
-// 💁‍♂️ At present, this is hard-wired to support an Xbox controller via Bluetooth using the xpadneo driver.
-// No attempt has been made to deal with different values and/or events that might be reported by different
-// controllers.
+// 💁‍♂️ This was written for an Xbox controller via Bluetooth using the xpadneo driver, whose button and axis codes
+// are assumed unless a controller mapping says otherwise (see `mapping.rs`). Axis ranges are queried from the device,
+// which also makes steering wheels (wheel and pedal axes) and flight sticks (throttle lever) usable.
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum GamepadEvent {
     ButtonPressed(Button),
-    StickAdjusted(Stick, StickAxis, f64),
-    TriggerAdjusted(Trigger, f64),
-    DpadAdjusted(DpadAxis, f64),
+    ButtonReleased(Button),
+    StickAdjusted(Stick, StickAxis, NormalizedAxis),
+    TriggerAdjusted(Trigger, NormalizedAxis),
+    DpadAdjusted(DpadAxis, NormalizedAxis),
+    // -1.0 for fully left to 1.0 for fully right.
+    WheelAdjusted(NormalizedAxis),
+    // 0.0 when released to 1.0 when pressed all the way.
+    PedalAdjusted(Pedal, NormalizedAxis),
+    // 0.0 to 1.0.
+    ThrottleLeverAdjusted(NormalizedAxis),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pedal {
+    Gas,
+    Brake,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -44,6 +64,7 @@ pub enum DpadAxis {
     Horizontal,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Button {
     A,
@@ -59,28 +80,223 @@ pub enum Button {
     THUMBR,
 }
 
+// Buttons are named in configuration files like the kernel names them, without the `BTN_` prefix.
+impl FromStr for Button {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "a" => Ok(Button::A),
+            "b" => Ok(Button::B),
+            "x" => Ok(Button::X),
+            "y" => Ok(Button::Y),
+            "tl" => Ok(Button::TL),
+            "tr" => Ok(Button::TR),
+            "select" => Ok(Button::SELECT),
+            "start" => Ok(Button::START),
+            "mode" => Ok(Button::MODE),
+            "thumbl" => Ok(Button::THUMBL),
+            "thumbr" => Ok(Button::THUMBR),
+            _ => Err(()),
+        }
+    }
+}
+
 const DEADZONE_THRESHOLD: f64 = 0.15;
 
+// A gamepad that keeps failing to be read is given up on, as if it was disconnected, so that the failsafe engages
+// rather than the last input staying in effect. This is about a tenth of a second at the default runloop interval.
+const MAX_READ_ERRORS_IN_A_ROW: u32 = 10;
+
 pub struct Gamepad {
     device_fd: OwnedFd,
+    identity: GamepadIdentity,
     recovering_from_dropped: bool,
+    mapping: ControllerMapping,
+    axis_ranges: HashMap<libc::__u16, AxisRange>,
+    // The events of the packet being read, see `read_events`.
+    packet: Vec<(GamepadEvent, Duration)>,
+    // The start of an event that was cut off by the end of the previous read.
+    fragment: Vec<u8>,
+    read_errors_in_a_row: u32,
+}
+
+impl AsFd for Gamepad {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.device_fd.as_fd()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum ReadError {
+    // The read was interrupted by a signal before anything was read, and can be retried right away.
+    Interrupted,
+    NothingToRead,
+    // The device is gone, e.g. unplugged or out of Bluetooth range.
+    Disconnected,
+    Other,
+}
+
+fn classify_read_error(error: &IoError) -> ReadError {
+    match error.raw_os_error() {
+        Some(libc::EINTR) => ReadError::Interrupted,
+        Some(libc::EAGAIN) => ReadError::NothingToRead,
+        Some(libc::ENODEV) => ReadError::Disconnected,
+        _ => ReadError::Other,
+    }
+}
+
+// The values an axis reports at its extremes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AxisRange {
+    pub minimum: libc::__s32,
+    pub maximum: libc::__s32,
+}
+
+impl AxisRange {
+    // Maps the range onto [-1.0, 1.0], with the center of the range at 0.0.
+    fn normalize_centered(&self, value: libc::__s32) -> f64 {
+        let center = (self.minimum as f64 + self.maximum as f64) / 2.0;
+        let value = value as f64;
+
+        let normalized = if value < center {
+            (value - center) / (center - self.minimum as f64)
+        } else {
+            (value - center) / (self.maximum as f64 - center)
+        };
+
+        normalized.clamp(-1.0, 1.0)
+    }
+
+    // Maps the range onto [0.0, 1.0].
+    pub fn normalize(&self, value: libc::__s32) -> f64 {
+        let normalized =
+            (value as f64 - self.minimum as f64) / (self.maximum as f64 - self.minimum as f64);
+
+        normalized.clamp(0.0, 1.0)
+    }
+}
+
+// What a gamepad says about itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamepadIdentity {
+    pub name: String,
+    pub bus_type: u16,
+    pub vendor: u16,
+    pub product: u16,
+    // The hardware or firmware revision, as far as the driver tells. Controller mappings may depend on it.
+    pub version: u16,
+    // Where the device is attached, e.g. the Bluetooth address of the adapter. Not all drivers set this.
+    pub physical_location: String,
+}
+
+impl GamepadIdentity {
+    pub(super) fn query(device_fd: &OwnedFd) -> Result<GamepadIdentity, IoError> {
+        // _IOR('E', 0x02, struct input_id)
+        const EVIOCGID: libc::c_ulong = 0x80084502;
+
+        let mut id = MaybeUninit::<libc::input_id>::zeroed();
+        let result = unsafe { libc::ioctl(device_fd.as_raw_fd(), EVIOCGID as _, id.as_mut_ptr()) };
+        if result < 0 {
+            return Err(IoError::last_os_error());
+        }
+        let id = unsafe { id.assume_init() };
+
+        Ok(GamepadIdentity {
+            name: query_string(device_fd, 0x06)?,
+            bus_type: id.bustype,
+            vendor: id.vendor,
+            product: id.product,
+            version: id.version,
+            physical_location: query_string(device_fd, 0x07).unwrap_or_default(),
+        })
+    }
+
+    fn bus_name(&self) -> String {
+        // input.h: BUS_*
+        match self.bus_type {
+            0x03 => "usb".to_string(),
+            0x05 => "bluetooth".to_string(),
+            0x06 => "virtual".to_string(),
+            bus_type => format!("bus 0x{:02x}", bus_type),
+        }
+    }
+}
+
+impl std::fmt::Display for GamepadIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "'{}' ({}, {:04x}:{:04x})",
+            self.name,
+            self.bus_name(),
+            self.vendor,
+            self.product
+        )
+    }
+}
+
+// Queries one of the string properties of a device: the name (0x06) or the physical location (0x07).
+fn query_string(device_fd: &OwnedFd, number: libc::c_ulong) -> Result<String, IoError> {
+    const LENGTH: usize = 256;
+
+    // _IOC(_IOC_READ, 'E', number, LENGTH)
+    let request: libc::c_ulong = 0x80000000 | ((LENGTH as libc::c_ulong) << 16) | 0x4500 | number;
+
+    let mut buffer = [0u8; LENGTH];
+    let result = unsafe { libc::ioctl(device_fd.as_raw_fd(), request as _, buffer.as_mut_ptr()) };
+    if result < 0 {
+        return Err(IoError::last_os_error());
+    }
+
+    let length = buffer.iter().position(|&byte| byte == 0).unwrap_or(LENGTH);
+    Ok(String::from_utf8_lossy(&buffer[..length]).into_owned())
 }
 
 impl Gamepad {
     pub fn new(device_file_path: &Path) -> Result<Gamepad, IoError> {
-        let device_fd = open_gamepad_device(&device_file_path)?;
+        let device_fd = open_gamepad_device(device_file_path)?;
+        use_monotonic_event_timestamps(&device_fd)?;
+        let axis_ranges = query_axis_ranges(&device_fd);
+        let identity = GamepadIdentity::query(&device_fd)?;
+
+        Ok(Gamepad::with_device_fd(device_fd, identity, axis_ranges))
+    }
 
-        let gamepad = Gamepad {
+    pub(super) fn with_device_fd(
+        device_fd: OwnedFd,
+        identity: GamepadIdentity,
+        axis_ranges: HashMap<libc::__u16, AxisRange>,
+    ) -> Gamepad {
+        Gamepad {
             device_fd,
+            identity,
             recovering_from_dropped: false,
-        };
+            mapping: ControllerMapping::builtin(),
+            axis_ranges,
+            packet: Vec::new(),
+            fragment: Vec::new(),
+            read_errors_in_a_row: 0,
+        }
+    }
 
-        Ok(gamepad)
+    pub fn identity(&self) -> &GamepadIdentity {
+        &self.identity
     }
 
+    // Takes effect with the next event read. Returns whether the mapping is a different one.
+    pub fn set_mapping(&mut self, mapping: ControllerMapping) -> bool {
+        let changed = mapping != self.mapping;
+        self.mapping = mapping;
+
+        changed
+    }
+
+    // Events are passed to `handler` together with the time the kernel received them, on the same monotonic clock
+    // as `runloop::now()`. Only complete packets are passed on, see below.
     pub fn read_events(
         &mut self,
-        mut handler: impl FnMut(GamepadEvent) -> (),
+        mut handler: impl FnMut(GamepadEvent, Duration),
     ) -> std::io::Result<()> {
         // The kernel caches input events in an internal buffer until they are read via the device file
         // descriptor. If events are not read fast enough, the internal buffer can fill up. If there is no space
@@ -98,37 +314,55 @@ impl Gamepad {
         const NUMBER_OF_EVENTS_IN_BUFFER: usize = 256;
         const INPUT_EVENT_SIZE: usize = mem::size_of::<libc::input_event>();
 
-        let mut buffer = [MaybeUninit::<libc::input_event>::uninit(); NUMBER_OF_EVENTS_IN_BUFFER];
-
-        let bytes_read = unsafe {
-            libc::read(
-                self.device_fd.as_raw_fd(),
-                buffer.as_mut_ptr() as *mut libc::c_void,
-                NUMBER_OF_EVENTS_IN_BUFFER * INPUT_EVENT_SIZE,
-            )
-        };
-
-        if bytes_read < 0 {
-            let error = std::io::Error::last_os_error();
-
-            if error
-                .raw_os_error()
-                .is_some_and(|code| code == libc::EAGAIN)
-            {
-                return Ok(());
+        let mut buffer = [0u8; NUMBER_OF_EVENTS_IN_BUFFER * INPUT_EVENT_SIZE];
+        let fragment_size = self.fragment.len();
+        buffer[..fragment_size].copy_from_slice(&self.fragment);
+
+        let bytes_read = loop {
+            let bytes_read = unsafe {
+                libc::read(
+                    self.device_fd.as_raw_fd(),
+                    buffer[fragment_size..].as_mut_ptr() as *mut libc::c_void,
+                    buffer.len() - fragment_size,
+                )
+            };
+            if bytes_read >= 0 {
+                self.read_errors_in_a_row = 0;
+                break bytes_read as usize;
             }
 
-            return Err(error);
-        }
-
-        let bytes_read = bytes_read as usize;
+            let error = IoError::last_os_error();
+            match classify_read_error(&error) {
+                ReadError::Interrupted => continue,
+                ReadError::NothingToRead => return Ok(()),
+                ReadError::Disconnected => return Err(error),
+                // Anything else might be temporary, so the device is kept and read again next time, unless it failed
+                // too often in a row. Only the first of a series of failures is warned about, as the runloop would
+                // otherwise repeat it many times a second.
+                ReadError::Other => {
+                    self.read_errors_in_a_row += 1;
+                    if self.read_errors_in_a_row >= MAX_READ_ERRORS_IN_A_ROW {
+                        return Err(error);
+                    }
+                    if self.read_errors_in_a_row == 1 {
+                        log::warn!("Could not read from the gamepad. - Cause: {}", error);
+                    }
+                    return Ok(());
+                }
+            }
+        };
 
-        assert!(bytes_read % INPUT_EVENT_SIZE == 0);
-        let events_read: usize = bytes_read / INPUT_EVENT_SIZE;
+        // The kernel only ever hands out whole events, but the bytes of an event that was cut off are kept for the
+        // next read all the same.
+        let bytes_available = fragment_size + bytes_read;
+        let events_read: usize = bytes_available / INPUT_EVENT_SIZE;
+        self.fragment = buffer[events_read * INPUT_EVENT_SIZE..bytes_available].to_vec();
 
-        for event in &buffer[0..events_read] {
-            let event = unsafe { event.assume_init() };
+        let events = buffer[..events_read * INPUT_EVENT_SIZE]
+            .chunks_exact(INPUT_EVENT_SIZE)
+            .map(|bytes| unsafe { (bytes.as_ptr() as *const libc::input_event).read_unaligned() });
 
+        for event in events {
             if self.recovering_from_dropped {
                 if event.type_ == EV_SYN && event.code == SYN_REPORT {
                     self.recovering_from_dropped = false;
@@ -145,22 +379,32 @@ impl Gamepad {
                 if event.type_ == EV_SYN && event.code == SYN_DROPPED {
                     log::error!("Gamepad event buffer overflow. Events may have been dropped.");
                     self.recovering_from_dropped = true;
-                } else {
+                    self.packet.clear();
+                } else if event.type_ == EV_SYN && event.code == SYN_REPORT {
                     // Multiple input events may be grouped together into "packets of input data changes occurring
                     // at the same moment in time". Each group of one or more input events is therefore followed
                     // by a SYN_REPORT event that marks the end of the "packet".
 
-                    // This grouping is ignored here: each individual input event is dispatched immediately (This
-                    // matches the behaviour of SDL.).
-
-                    let event = match event.type_ {
-                        EV_KEY => process_key_event(event.code, event.value),
-                        EV_ABS => process_absolute_event(event.code, event.value),
+                    // The events of a packet are only dispatched once the packet is complete, so that e.g. throttle
+                    // and steering from the same report are always acted on together. A packet split across reads
+                    // is held back until the rest of it has been read.
+                    for (gamepad_event, timestamp) in self.packet.drain(..) {
+                        handler(gamepad_event, timestamp);
+                    }
+                } else {
+                    let gamepad_event = match event.type_ {
+                        EV_KEY => process_key_event(&self.mapping, event.code, event.value),
+                        EV_ABS => process_absolute_event(
+                            &self.mapping,
+                            &self.axis_ranges,
+                            event.code,
+                            event.value,
+                        ),
                         _ => None,
                     };
 
-                    if let Some(event) = event {
-                        handler(event);
+                    if let Some(gamepad_event) = gamepad_event {
+                        self.packet.push((gamepad_event, event_timestamp(&event)));
                     }
                 }
             }
@@ -171,124 +415,138 @@ impl Gamepad {
 }
 
 // Event types of interest.
-const EV_SYN: libc::__u16 = 0x00;
+pub(crate) const EV_SYN: libc::__u16 = 0x00;
 const EV_KEY: libc::__u16 = 0x01;
-const EV_ABS: libc::__u16 = 0x03;
+pub const EV_ABS: libc::__u16 = 0x03;
 
 // EV_SYN event codes of interest.
-const SYN_REPORT: libc::__u16 = 0;
-const SYN_DROPPED: libc::__u16 = 3;
+pub(crate) const SYN_REPORT: libc::__u16 = 0;
+pub(crate) const SYN_DROPPED: libc::__u16 = 3;
 
 // EV_KEY event codes of interest.
-const BTN_A: libc::__u16 = 0x130;
-const BTN_B: libc::__u16 = 0x131;
-const BTN_X: libc::__u16 = 0x133;
-const BTN_Y: libc::__u16 = 0x134;
-const BTN_TL: libc::__u16 = 0x136;
-const BTN_TR: libc::__u16 = 0x137;
-const BTN_SELECT: libc::__u16 = 0x13a;
-const BTN_START: libc::__u16 = 0x13b;
-const BTN_MODE: libc::__u16 = 0x13c;
-const BTN_THUMBL: libc::__u16 = 0x13d;
-const BTN_THUMBR: libc::__u16 = 0x13e;
+pub(super) const BTN_A: libc::__u16 = 0x130;
+pub(super) const BTN_B: libc::__u16 = 0x131;
+pub(super) const BTN_X: libc::__u16 = 0x133;
+pub(super) const BTN_Y: libc::__u16 = 0x134;
+pub(super) const BTN_TL: libc::__u16 = 0x136;
+pub(super) const BTN_TR: libc::__u16 = 0x137;
+pub(super) const BTN_SELECT: libc::__u16 = 0x13a;
+pub(super) const BTN_START: libc::__u16 = 0x13b;
+pub(super) const BTN_MODE: libc::__u16 = 0x13c;
+pub(super) const BTN_THUMBL: libc::__u16 = 0x13d;
+pub(super) const BTN_THUMBR: libc::__u16 = 0x13e;
 
 // EV_ABS event codes of interest.
-const ABS_X: libc::__u16 = 0x00;
-const ABS_Y: libc::__u16 = 0x01;
-const ABS_Z: libc::__u16 = 0x02;
-const ABS_RX: libc::__u16 = 0x03;
-const ABS_RY: libc::__u16 = 0x04;
-const ABS_RZ: libc::__u16 = 0x05;
-const ABS_HAT0X: libc::__u16 = 0x10;
-const ABS_HAT0Y: libc::__u16 = 0x11;
-
-fn process_key_event(code: libc::__u16, value: libc::__s32) -> Option<GamepadEvent> {
-    // For now an event will be raised immediately on key down.
-    if value != 1 {
-        return None;
-    }
-
-    match code {
-        BTN_A => Some(GamepadEvent::ButtonPressed(Button::A)),
-        BTN_B => Some(GamepadEvent::ButtonPressed(Button::B)),
-        BTN_X => Some(GamepadEvent::ButtonPressed(Button::X)),
-        BTN_Y => Some(GamepadEvent::ButtonPressed(Button::Y)),
-        BTN_TL => Some(GamepadEvent::ButtonPressed(Button::TL)),
-        BTN_TR => Some(GamepadEvent::ButtonPressed(Button::TR)),
-        BTN_SELECT => Some(GamepadEvent::ButtonPressed(Button::SELECT)),
-        BTN_START => Some(GamepadEvent::ButtonPressed(Button::START)),
-        BTN_MODE => Some(GamepadEvent::ButtonPressed(Button::MODE)),
-        BTN_THUMBL => Some(GamepadEvent::ButtonPressed(Button::THUMBL)),
-        BTN_THUMBR => Some(GamepadEvent::ButtonPressed(Button::THUMBR)),
+pub const ABS_X: libc::__u16 = 0x00;
+pub const ABS_Y: libc::__u16 = 0x01;
+pub const ABS_Z: libc::__u16 = 0x02;
+pub(super) const ABS_RX: libc::__u16 = 0x03;
+pub(super) const ABS_RY: libc::__u16 = 0x04;
+pub(super) const ABS_RZ: libc::__u16 = 0x05;
+pub(super) const ABS_THROTTLE: libc::__u16 = 0x06;
+pub(super) const ABS_WHEEL: libc::__u16 = 0x08;
+pub(super) const ABS_GAS: libc::__u16 = 0x09;
+pub(super) const ABS_BRAKE: libc::__u16 = 0x0a;
+pub(super) const ABS_HAT0X: libc::__u16 = 0x10;
+pub(super) const ABS_HAT0Y: libc::__u16 = 0x11;
+
+// What the xpadneo driver reports, for when a device's axis ranges cannot be queried.
+const DEFAULT_STICK_RANGE: AxisRange = AxisRange {
+    minimum: -32768,
+    maximum: 32767,
+};
+const DEFAULT_TRIGGER_RANGE: AxisRange = AxisRange {
+    minimum: 0,
+    maximum: 1023,
+};
+
+fn process_key_event(
+    mapping: &ControllerMapping,
+    code: libc::__u16,
+    value: libc::__s32,
+) -> Option<GamepadEvent> {
+    let button = mapping.button(code)?;
+
+    // Key repeats (a value of 2) are of no interest.
+    match value {
+        1 => Some(GamepadEvent::ButtonPressed(button)),
+        0 => Some(GamepadEvent::ButtonReleased(button)),
         _ => None,
     }
 }
 
-fn process_absolute_event(code: libc::__u16, value: libc::__s32) -> Option<GamepadEvent> {
-    match code {
-        ABS_X => Some(create_stick_event(
-            Stick::Left,
-            StickAxis::Horizontal,
-            value,
+fn process_absolute_event(
+    mapping: &ControllerMapping,
+    axis_ranges: &HashMap<libc::__u16, AxisRange>,
+    code: libc::__u16,
+    value: libc::__s32,
+) -> Option<GamepadEvent> {
+    let assignment = mapping.axis(code)?;
+    // Axes without a sensible default range are only decoded if the mapping or the device told us about their range.
+    let queried_range = assignment.range.or_else(|| axis_ranges.get(&code).copied());
+    let normalize_centered = |range: AxisRange| {
+        let value = range.normalize_centered(value);
+        if assignment.inverted {
+            -value
+        } else {
+            value
+        }
+    };
+    let normalize = |range: AxisRange| {
+        let value = range.normalize(value);
+        if assignment.inverted {
+            1.0 - value
+        } else {
+            value
+        }
+    };
+
+    match assignment.target {
+        AxisTarget::Stick(stick, axis) => Some(create_stick_event(
+            stick,
+            axis,
+            normalize_centered(queried_range.unwrap_or(DEFAULT_STICK_RANGE)),
         )),
-        ABS_Y => Some(create_stick_event(Stick::Left, StickAxis::Vertical, value)),
-        ABS_RX => Some(create_stick_event(
-            Stick::Right,
-            StickAxis::Horizontal,
-            value,
+        AxisTarget::Trigger(trigger) => Some(create_trigger_event(
+            trigger,
+            normalize(queried_range.unwrap_or(DEFAULT_TRIGGER_RANGE)),
         )),
-        ABS_RY => Some(create_stick_event(Stick::Right, StickAxis::Vertical, value)),
-
-        ABS_Z => Some(create_trigger_event(Trigger::Left, value)),
-        ABS_RZ => Some(create_trigger_event(Trigger::Right, value)),
-
-        ABS_HAT0X => Some(create_dpad_event(DpadAxis::Horizontal, value)),
-        ABS_HAT0Y => Some(create_dpad_event(DpadAxis::Vertical, value)),
-
-        _ => None,
+        AxisTarget::Dpad(axis) => Some(create_dpad_event(
+            axis,
+            if assignment.inverted {
+                value.saturating_neg()
+            } else {
+                value
+            },
+        )),
+        // A wheel is not expected to wobble like a stick, but centering it by hand is just as imprecise.
+        AxisTarget::Wheel => queried_range
+            .map(|range| GamepadEvent::WheelAdjusted(apply_deadzone(normalize_centered(range)))),
+        AxisTarget::Pedal(pedal) => queried_range
+            .map(|range| GamepadEvent::PedalAdjusted(pedal, apply_deadzone(normalize(range)))),
+        // A lever stays where it is put, so it needs no deadzone.
+        AxisTarget::ThrottleLever => queried_range.map(|range| {
+            GamepadEvent::ThrottleLeverAdjusted(NormalizedAxis::new(normalize(range)))
+        }),
     }
 }
 
-fn create_stick_event(stick: Stick, axis: StickAxis, value: libc::__s32) -> GamepadEvent {
-    // `value` is expected to be in the range [-32768, 32767].
-    let value = if value <= -32768 {
-        -1.0
-    } else if value >= 32767 {
-        1.0
-    } else {
-        let value = if value < 0 {
-            value as f64 / 32768.0
-        } else {
-            value as f64 / 32767.0
-        };
-
-        apply_deadzone(value)
-    };
-
-    GamepadEvent::StickAdjusted(stick, axis, value)
+fn create_stick_event(stick: Stick, axis: StickAxis, value: f64) -> GamepadEvent {
+    GamepadEvent::StickAdjusted(stick, axis, apply_deadzone(value))
 }
 
-fn create_trigger_event(trigger: Trigger, value: libc::__s32) -> GamepadEvent {
-    // `value` is expected to be in the range [0, 1023].
-    let value = if value <= 0 {
-        0.0
-    } else if value >= 1023 {
-        1.0
-    } else {
-        apply_deadzone(value as f64 / 1023.0)
-    };
-
-    GamepadEvent::TriggerAdjusted(trigger, value)
+fn create_trigger_event(trigger: Trigger, value: f64) -> GamepadEvent {
+    GamepadEvent::TriggerAdjusted(trigger, apply_deadzone(value))
 }
 
 fn create_dpad_event(axis: DpadAxis, value: libc::__s32) -> GamepadEvent {
     // `value` is expected to be -1, 0 or 1.
     let value = if value <= -1 {
-        -1.0
+        NormalizedAxis::new(-1.0)
     } else if value >= 1 {
-        1.0
+        NormalizedAxis::new(1.0)
     } else {
-        0.0
+        NormalizedAxis::CENTER
     };
 
     GamepadEvent::DpadAdjusted(axis, value)
@@ -297,15 +555,65 @@ fn create_dpad_event(axis: DpadAxis, value: libc::__s32) -> GamepadEvent {
 // Even just moving around the controller will cause the sticks to wobble and register events. Using and then
 // releasing the triggers will also not land them perfectly on the all zero mark. Values below a small threshold
 // are therefore ignored.
-fn apply_deadzone(value: f64) -> f64 {
+pub fn apply_deadzone(value: f64) -> NormalizedAxis {
     if value.abs() < DEADZONE_THRESHOLD {
-        0.0
+        NormalizedAxis::CENTER
+    } else {
+        NormalizedAxis::new(value)
+    }
+}
+
+// By default, events are timestamped using the realtime clock, which is not suitable for measuring intervals.
+pub(crate) fn use_monotonic_event_timestamps(device_fd: &OwnedFd) -> Result<(), IoError> {
+    // _IOW('E', 0xa0, int)
+    const EVIOCSCLOCKID: libc::c_ulong = 0x400445a0;
+
+    let clock_id: libc::c_int = libc::CLOCK_MONOTONIC;
+    let result = unsafe { libc::ioctl(device_fd.as_raw_fd(), EVIOCSCLOCKID as _, &clock_id) };
+
+    if result < 0 {
+        Err(IoError::last_os_error())
     } else {
-        value
+        Ok(())
     }
 }
 
-fn open_gamepad_device(device_file_path: &Path) -> Result<OwnedFd, IoError> {
+// Every axis a controller mapping might assign, not only those of the built-in one.
+pub(super) fn query_axis_ranges(device_fd: &OwnedFd) -> HashMap<libc::__u16, AxisRange> {
+    // ABS_MISC is the last of the axes of general purpose.
+    const ABS_MISC: libc::__u16 = 0x28;
+
+    (ABS_X..=ABS_MISC)
+        .filter_map(|code| Some((code, query_axis_range(device_fd, code)?)))
+        .collect()
+}
+
+pub fn query_axis_range(device_fd: &OwnedFd, code: libc::__u16) -> Option<AxisRange> {
+    // _IOR('E', 0x40 + code, struct input_absinfo)
+    let request: libc::c_ulong = 0x80184540 + code as libc::c_ulong;
+
+    let mut absinfo = MaybeUninit::<libc::input_absinfo>::zeroed();
+    let result = unsafe { libc::ioctl(device_fd.as_raw_fd(), request as _, absinfo.as_mut_ptr()) };
+
+    // Axes the device does not have either fail to be queried or report an empty range.
+    if result < 0 {
+        return None;
+    }
+    let absinfo = unsafe { absinfo.assume_init() };
+    (absinfo.maximum > absinfo.minimum).then_some(AxisRange {
+        minimum: absinfo.minimum,
+        maximum: absinfo.maximum,
+    })
+}
+
+pub(crate) fn event_timestamp(event: &libc::input_event) -> Duration {
+    Duration::new(
+        event.time.tv_sec.max(0) as u64,
+        (event.time.tv_usec.max(0) as u32) * 1000,
+    )
+}
+
+pub fn open_gamepad_device(device_file_path: &Path) -> Result<OwnedFd, IoError> {
     let device_file_path = CString::new(device_file_path.as_os_str().as_bytes()).unwrap();
 
     let fd = unsafe {
@@ -321,3 +629,273 @@ fn open_gamepad_device(device_file_path: &Path) -> Result<OwnedFd, IoError> {
         Ok(unsafe { OwnedFd::from_raw_fd(fd) })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::input_interpreter::{GamepadEventSource, GamepadInputInterpreter};
+    use super::super::{AnyGamepadEvent, GamepadSettings};
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::input_source::InputSource;
+    use std::error::Error;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn event_bytes(type_: libc::__u16, code: libc::__u16, value: i32) -> Vec<u8> {
+        let event = libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        };
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const libc::input_event as *const u8,
+                mem::size_of::<libc::input_event>(),
+            )
+        };
+
+        bytes.to_vec()
+    }
+
+    fn test_identity() -> GamepadIdentity {
+        GamepadIdentity {
+            name: "Xbox Wireless Controller".to_string(),
+            bus_type: 0x05,
+            vendor: 0x045e,
+            product: 0x02fd,
+            version: 0x0903,
+            physical_location: String::new(),
+        }
+    }
+
+    // A non-blocking pipe standing in for the device file.
+    fn pipe() -> (OwnedFd, OwnedFd) {
+        let mut fds = [0; 2];
+        let result = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+        assert_eq!(result, 0);
+
+        unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+    }
+
+    fn write(fd: &OwnedFd, bytes: &[u8]) {
+        let written = unsafe {
+            libc::write(
+                fd.as_raw_fd(),
+                bytes.as_ptr() as *const libc::c_void,
+                bytes.len(),
+            )
+        };
+        assert_eq!(written, bytes.len() as isize);
+    }
+
+    fn read_events(gamepad: &mut Gamepad) -> std::io::Result<Vec<GamepadEvent>> {
+        let mut events = Vec::new();
+        gamepad.read_events(|event, _| events.push(event))?;
+
+        Ok(events)
+    }
+
+    #[test]
+    fn packets_are_delivered_whole() {
+        let (read_end, write_end) = pipe();
+        let mut gamepad = Gamepad::with_device_fd(read_end, test_identity(), HashMap::new());
+
+        let mut packet = event_bytes(EV_KEY, BTN_A, 1);
+        packet.extend(event_bytes(EV_ABS, ABS_X, 32767));
+        packet.extend(event_bytes(EV_SYN, SYN_REPORT, 0));
+
+        // Cut off in the middle of the second event.
+        let cut = mem::size_of::<libc::input_event>() * 3 / 2;
+        write(&write_end, &packet[..cut]);
+        assert_eq!(read_events(&mut gamepad).unwrap(), []);
+        // Nothing to read.
+        assert_eq!(read_events(&mut gamepad).unwrap(), []);
+
+        write(&write_end, &packet[cut..]);
+        assert_eq!(
+            read_events(&mut gamepad).unwrap(),
+            [
+                GamepadEvent::ButtonPressed(Button::A),
+                GamepadEvent::StickAdjusted(
+                    Stick::Left,
+                    StickAxis::Horizontal,
+                    NormalizedAxis::new(1.0)
+                ),
+            ]
+        );
+    }
+
+    // Reads from the gamepad fail with `errno`, while there is a packet to read. The gamepad is read on a thread of its
+    // own, which is the only one the failure is injected into.
+    fn read_events_failing_with(errno: i32) -> std::io::Result<Vec<GamepadEvent>> {
+        let (read_end, write_end) = pipe();
+        let mut packet = event_bytes(EV_KEY, BTN_A, 1);
+        packet.extend(event_bytes(EV_SYN, SYN_REPORT, 0));
+        write(&write_end, &packet);
+
+        std::thread::spawn(move || {
+            crate::seccomp::fail_reads_on_this_thread(read_end.as_raw_fd(), errno).unwrap();
+            let mut gamepad = Gamepad::with_device_fd(read_end, test_identity(), HashMap::new());
+            read_events(&mut gamepad)
+        })
+        .join()
+        .unwrap()
+    }
+
+    #[test]
+    fn read_errors() {
+        // Reading from the write end of a pipe fails with EBADF, which keeps the device.
+        let (_, write_end) = pipe();
+        let mut gamepad = Gamepad::with_device_fd(write_end, test_identity(), HashMap::new());
+        assert!(read_events(&mut gamepad).is_ok());
+
+        // A device that is gone is given up on.
+        let error = read_events_failing_with(libc::ENODEV).unwrap_err();
+        assert_eq!(error.raw_os_error(), Some(libc::ENODEV));
+
+        // Anything else is taken to be temporary, at first.
+        assert_eq!(read_events_failing_with(libc::EIO).unwrap(), []);
+    }
+
+    // Stands in for `AnyGamepad`, which gives up on a gamepad once reading it fails.
+    struct SingleGamepad(Option<Gamepad>);
+
+    impl GamepadEventSource for SingleGamepad {
+        fn is_connected(&self) -> bool {
+            self.0.is_some()
+        }
+
+        fn read_events(
+            &mut self,
+            handler: &mut dyn FnMut(AnyGamepadEvent, Duration),
+        ) -> Result<(), Box<dyn Error>> {
+            if let Some(gamepad) = &mut self.0 {
+                if gamepad
+                    .read_events(|event, timestamp| handler(event.into(), timestamp))
+                    .is_err()
+                {
+                    self.0 = None;
+                    handler(AnyGamepadEvent::Disconnected, Duration::ZERO);
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_gamepad_that_keeps_failing_to_be_read_stops_the_vehicle() {
+        let (read_end, write_end) = pipe();
+        let mut packet = event_bytes(EV_ABS, ABS_RZ, 1023);
+        packet.extend(event_bytes(EV_SYN, SYN_REPORT, 0));
+        write(&write_end, &packet);
+
+        let throttles = std::thread::spawn(move || {
+            let fd = read_end.as_raw_fd();
+            let gamepad = Gamepad::with_device_fd(read_end, test_identity(), HashMap::new());
+            let mut interpreter = GamepadInputInterpreter::with_event_source(
+                SingleGamepad(Some(gamepad)),
+                ManualClock::new(Duration::ZERO),
+                GamepadSettings::default(),
+            );
+            let mut throttle = || {
+                interpreter
+                    .process_input(&mut |_| ())
+                    .unwrap()
+                    .get_throttle()
+                    .value()
+            };
+
+            let full_throttle = throttle();
+            crate::seccomp::fail_reads_on_this_thread(fd, libc::EIO).unwrap();
+            let failing: Vec<f64> = (0..MAX_READ_ERRORS_IN_A_ROW).map(|_| throttle()).collect();
+
+            (full_throttle, failing)
+        })
+        .join()
+        .unwrap();
+
+        // The last input stays in effect for a few failed reads, but not for good.
+        assert_eq!(throttles.0, 1.0);
+        assert_eq!(throttles.1[0], 1.0);
+        assert_eq!(throttles.1.last(), Some(&0.0));
+    }
+
+    #[test]
+    fn interrupted_reads_are_retried() {
+        static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+        extern "C" fn interrupt(_: libc::c_int) {
+            INTERRUPTED.store(true, Ordering::SeqCst);
+        }
+        // Without `SA_RESTART`, a signal makes a blocking read fail with EINTR.
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut());
+        }
+
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        let (read_end, write_end) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        let (thread_sender, thread_receiver) = std::sync::mpsc::channel();
+        let reader = std::thread::spawn(move || {
+            let mut gamepad = Gamepad::with_device_fd(read_end, test_identity(), HashMap::new());
+            thread_sender.send(unsafe { libc::pthread_self() }).unwrap();
+            read_events(&mut gamepad)
+        });
+
+        // The reader is interrupted while waiting for the packet, which only arrives afterwards.
+        let reader_thread = thread_receiver.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        unsafe { libc::pthread_kill(reader_thread, libc::SIGUSR1) };
+        std::thread::sleep(Duration::from_millis(50));
+        let mut packet = event_bytes(EV_KEY, BTN_A, 1);
+        packet.extend(event_bytes(EV_SYN, SYN_REPORT, 0));
+        write(&write_end, &packet);
+
+        assert_eq!(
+            reader.join().unwrap().unwrap(),
+            [GamepadEvent::ButtonPressed(Button::A)]
+        );
+        assert!(INTERRUPTED.load(Ordering::SeqCst));
+    }
+
+    // Decoding as many events as a busy gamepad sends in an iteration: a few packets of stick, trigger and button
+    // changes. This includes writing them to the pipe, which takes about as long as reading them does.
+    #[test]
+    #[ignore]
+    fn benchmark_event_decoding() {
+        let (read_end, write_end) = pipe();
+        let mut gamepad = Gamepad::with_device_fd(read_end, test_identity(), HashMap::new());
+
+        let mut packets = Vec::new();
+        for value in [-20000, 12000, 32767, -32768] {
+            packets.extend(event_bytes(EV_ABS, ABS_X, value));
+            packets.extend(event_bytes(EV_ABS, ABS_Y, -value));
+            packets.extend(event_bytes(EV_ABS, ABS_RZ, value.abs() / 32));
+            packets.extend(event_bytes(EV_KEY, BTN_A, (value > 0) as i32));
+            packets.extend(event_bytes(EV_SYN, SYN_REPORT, 0));
+        }
+
+        let mut event_count = 0;
+        crate::benchmark::measure(
+            "Decoding 16 gamepad events",
+            Duration::from_micros(500),
+            |_| {
+                write(&write_end, &packets);
+                gamepad
+                    .read_events(|event, _| {
+                        std::hint::black_box(event);
+                        event_count += 1;
+                    })
+                    .unwrap();
+            },
+        );
+        assert!(event_count > 0);
+    }
+}
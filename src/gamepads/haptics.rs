@@ -0,0 +1,189 @@
+use super::rumble::GamepadRumble;
+use crate::event_bus::{Event, EventSubscriber};
+use crate::runloop;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Rumble patterns for getting the driver's attention to what is happening, much like the buzzer's beep patterns (see
+// `buzzer.rs`), but felt in the hands. Every pattern is configured as a sequence of `strength:duration` steps, e.g.
+// `gamepad.rumble_armed = 0.6:80, 0:60, 0.6:80` for two short pulses, with strength from 0.0 (a pause) to 1.0 and the
+// duration in milliseconds. Patterns that are not configured are not played, so gamepads only rumble when asked to.
+//
+// Patterns are advanced by the runloop: every step is a rumble effect of its own, played for the length of the step.
+// A pattern cuts short whatever was playing before, as does the geofence alert.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum HapticEffect {
+    Armed,
+    Disarmed,
+    // 💁‍♂️ There is neither battery monitoring nor obstacle detection yet, so nothing plays these for now.
+    #[allow(dead_code)]
+    LowBattery,
+    #[allow(dead_code)]
+    CollisionWarning,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RumbleStep {
+    pub strength: f64,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RumblePattern {
+    steps: Vec<RumbleStep>,
+}
+
+impl RumblePattern {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    // The step to play at `elapsed` into the pattern, by index, or `None` once the pattern is over.
+    fn step_at(&self, elapsed: Duration) -> Option<(usize, RumbleStep)> {
+        let mut step_end = Duration::ZERO;
+
+        for (index, step) in self.steps.iter().enumerate() {
+            step_end += step.duration;
+            if elapsed < step_end {
+                return Some((index, *step));
+            }
+        }
+
+        None
+    }
+}
+
+// E.g. `0.6:80, 0:60, 0.6:80`. Steps last from 1 ms to 5 s, which the force feedback interface can represent.
+impl FromStr for RumblePattern {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let steps = text
+            .split(',')
+            .map(|step| {
+                let (strength, duration_ms) = step.trim().split_once(':').ok_or(())?;
+                let strength: f64 = strength.parse().map_err(|_| ())?;
+                let duration_ms: u64 = duration_ms.parse().map_err(|_| ())?;
+
+                if !(0.0..=1.0).contains(&strength) || !(1..=5_000).contains(&duration_ms) {
+                    return Err(());
+                }
+
+                Ok(RumbleStep {
+                    strength,
+                    duration: Duration::from_millis(duration_ms),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(RumblePattern { steps })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RumblePatterns {
+    pub armed: RumblePattern,
+    pub disarmed: RumblePattern,
+    pub low_battery: RumblePattern,
+    pub collision_warning: RumblePattern,
+}
+
+impl RumblePatterns {
+    pub fn is_empty(&self) -> bool {
+        [
+            &self.armed,
+            &self.disarmed,
+            &self.low_battery,
+            &self.collision_warning,
+        ]
+        .into_iter()
+        .all(RumblePattern::is_empty)
+    }
+
+    fn pattern(&self, effect: HapticEffect) -> &RumblePattern {
+        match effect {
+            HapticEffect::Armed => &self.armed,
+            HapticEffect::Disarmed => &self.disarmed,
+            HapticEffect::LowBattery => &self.low_battery,
+            HapticEffect::CollisionWarning => &self.collision_warning,
+        }
+    }
+}
+
+pub struct GamepadHaptics {
+    rumble: GamepadRumble,
+    patterns: RumblePatterns,
+    // The effect being played, when it started, and which of its steps is rumbling.
+    playing: Option<(HapticEffect, Duration, Option<usize>)>,
+}
+
+impl GamepadHaptics {
+    pub fn new(rumble: GamepadRumble, patterns: &RumblePatterns) -> GamepadHaptics {
+        GamepadHaptics {
+            rumble,
+            patterns: patterns.clone(),
+            playing: None,
+        }
+    }
+
+    // Starts playing the pattern for `effect`, cutting short whatever was playing before.
+    pub fn play(&mut self, effect: HapticEffect) {
+        if !self.patterns.pattern(effect).is_empty() {
+            self.playing = Some((effect, runloop::now(), None));
+        }
+    }
+
+    // Rumbles right away, e.g. for the geofence alert, whose strength varies. Ends any pattern being played.
+    pub fn rumble(&mut self, strength: f64, duration: Duration) {
+        self.playing = None;
+        self.rumble.rumble(strength, duration);
+    }
+
+    // Advances the pattern being played. Expected to be called every runloop iteration.
+    pub fn update(&mut self) {
+        let Some((effect, started_at, played_step)) = self.playing else {
+            return;
+        };
+
+        let elapsed = runloop::now().saturating_sub(started_at);
+        match self.patterns.pattern(effect).step_at(elapsed) {
+            Some((index, step)) if played_step != Some(index) => {
+                self.playing = Some((effect, started_at, Some(index)));
+                self.rumble.rumble(step.strength, step.duration);
+            }
+            Some(_) => (),
+            None => self.playing = None,
+        }
+    }
+}
+
+impl EventSubscriber for GamepadHaptics {
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::Armed => self.play(HapticEffect::Armed),
+            Event::Disarmed => self.play(HapticEffect::Disarmed),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_and_stepping_through_patterns() {
+        let pattern: RumblePattern = "0.6:80, 0:60,1:100".parse().unwrap();
+        let at = Duration::from_millis;
+
+        assert_eq!(pattern.step_at(at(0)).unwrap().0, 0);
+        assert_eq!(pattern.step_at(at(100)).unwrap().1.strength, 0.0);
+        assert_eq!(pattern.step_at(at(239)).unwrap().0, 2);
+        assert_eq!(pattern.step_at(at(240)), None);
+
+        assert_eq!("1.5:80".parse::<RumblePattern>(), Err(()));
+        assert_eq!("0.5:0".parse::<RumblePattern>(), Err(()));
+        assert_eq!("0.5".parse::<RumblePattern>(), Err(()));
+    }
+}
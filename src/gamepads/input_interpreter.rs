@@ -1,52 +1,575 @@
-use super::{AnyGamepad, AnyGamepadEvent, Stick, StickAxis, Trigger};
+use super::gamepad::GamepadIdentity;
+use super::motion::MotionSensors;
+use super::touchpad::{Touchpad, TouchpadAction, TouchpadActions};
+use super::{
+    AnyGamepad, AnyGamepadEvent, Button, DpadAxis, GamepadDeviceRules, Pedal, Stick, StickAxis,
+    Trigger,
+};
+use crate::clock::{Clock, MonotonicClock};
+use crate::control_values::{Steering, Throttle};
+use crate::input_source::{AuxiliaryAxis, InputNotification, InputSource};
 use crate::locomotion::LocomotionCommand;
 use std::error::Error;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
-pub struct GamepadInputInterpreter {
-    gamepad: AnyGamepad,
+// Which kind of device is used to drive, which determines the controls that throttle and steer. The buttons work
+// the same on all of them, as far as the device has them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ControlProfile {
+    // The right trigger accelerates, the left trigger brakes and reverses, the left stick steers.
+    Gamepad,
+    // The wheel steers, the gas pedal accelerates and the brake pedal brakes and reverses.
+    Wheel,
+    // Pushing the stick forward or pulling it back drives, tilting it sideways steers. The throttle lever, if there
+    // is one, scales the throttle.
+    Joystick,
+}
+
+impl FromStr for ControlProfile {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "gamepad" => Ok(ControlProfile::Gamepad),
+            "wheel" => Ok(ControlProfile::Wheel),
+            "joystick" => Ok(ControlProfile::Joystick),
+            _ => Err(()),
+        }
+    }
+}
+
+// How the gamepad's controls translate into commands.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GamepadSettings {
+    pub profile: ControlProfile,
+
+    // Whether pulling both triggers brakes, like the brake lever of a pistol grip transmitter does, rather than the
+    // triggers cancelling each other out. The left trigger then sets how hard to brake.
+    pub trigger_brake: bool,
+
+    // Holding this button lifts the speed cap (`driving.max_speed_percent`) for up to `boost_duration`. Another
+    // boost is only possible once `boost_cooldown` has passed after the previous one ended.
+    pub boost_button: Option<Button>,
+    pub boost_duration: Duration,
+    pub boost_cooldown: Duration,
+
+    // While this button is held, tilting the controller steers instead of the stick, with `tilt_steering_angle`
+    // degrees of tilt for full steering. This needs a controller with motion sensors (DualShock 4, DualSense). See
+    // `motion.rs`.
+    pub tilt_steering_button: Option<Button>,
+    pub tilt_steering_angle: f64,
+
+    // What swiping across or tapping the touchpad does (DualShock 4, DualSense). The speed limit actions change the
+    // limit by `touchpad_speed_limit_step` percentage points. See `touchpad.rs`.
+    pub touchpad: TouchpadActions,
+    pub touchpad_speed_limit_step: u8,
+
+    // Pressing this button switches the right stick over to aiming the camera gimbal and back. See `gimbal.rs`.
+    pub gimbal_button: Option<Button>,
+
+    // While this button is held, the dpad runs the winch: up pulls in, down lets out. See `winch.rs`.
+    pub winch_modifier: Option<Button>,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            profile: ControlProfile::Gamepad,
+            trigger_brake: false,
+            boost_button: None,
+            boost_duration: Duration::from_secs(3),
+            boost_cooldown: Duration::from_secs(10),
+            tilt_steering_button: None,
+            tilt_steering_angle: 30.0,
+            touchpad: TouchpadActions::default(),
+            touchpad_speed_limit_step: 10,
+            gimbal_button: None,
+            winch_modifier: None,
+        }
+    }
+}
+
+/// Where the interpreter gets its events from. This is `AnyGamepad`, other than in tests, which feed it scripted
+/// events instead.
+pub trait GamepadEventSource {
+    fn is_connected(&self) -> bool;
+
+    // Passes pending events to `handler`, together with the time they occurred.
+    fn read_events(
+        &mut self,
+        handler: &mut dyn FnMut(AnyGamepadEvent, Duration),
+    ) -> Result<(), Box<dyn Error>>;
+
+    // The event device of the gamepad, next to which its motion sensors and touchpad are found.
+    fn device_file(&self) -> Option<&Path> {
+        None
+    }
+
+    fn identity(&self) -> Option<&GamepadIdentity> {
+        None
+    }
+
+    // See `mapping.rs`.
+    fn reload_mappings(&mut self) {}
+}
+
+impl GamepadEventSource for AnyGamepad {
+    fn is_connected(&self) -> bool {
+        AnyGamepad::is_connected(self)
+    }
+
+    fn read_events(
+        &mut self,
+        handler: &mut dyn FnMut(AnyGamepadEvent, Duration),
+    ) -> Result<(), Box<dyn Error>> {
+        AnyGamepad::read_events(self, handler)
+    }
+
+    fn device_file(&self) -> Option<&Path> {
+        AnyGamepad::device_file(self)
+    }
+
+    fn identity(&self) -> Option<&GamepadIdentity> {
+        AnyGamepad::identity(self)
+    }
+
+    fn reload_mappings(&mut self) {
+        AnyGamepad::reload_mappings(self)
+    }
+}
+
+pub struct GamepadInputInterpreter<S: GamepadEventSource = AnyGamepad, C: Clock = MonotonicClock> {
+    gamepad: S,
+    clock: C,
+    settings: GamepadSettings,
     state: GamepadState,
+    boost: BoostState,
+    motion_sensors: Option<MotionSensors>,
+    touchpad: Option<Touchpad>,
+    // When a button or axis last moved, see `InputSource::last_activity`.
+    last_activity: Option<Duration>,
+}
+
+struct BoostState {
+    started_at: Option<Duration>,
+    available_at: Duration,
 }
 
-impl GamepadInputInterpreter {
-    pub fn new() -> Result<GamepadInputInterpreter, Box<dyn Error>> {
-        Ok(GamepadInputInterpreter {
-            gamepad: AnyGamepad::new()?,
+impl GamepadInputInterpreter<AnyGamepad> {
+    pub fn new(
+        settings: GamepadSettings,
+        device_rules: &GamepadDeviceRules,
+        mapping_file: Option<&Path>,
+    ) -> Result<GamepadInputInterpreter, Box<dyn Error>> {
+        Ok(Self::with_event_source(
+            AnyGamepad::new(device_rules, mapping_file)?,
+            MonotonicClock,
+            settings,
+        ))
+    }
+}
+
+impl<S: GamepadEventSource, C: Clock> GamepadInputInterpreter<S, C> {
+    // `clock` has to be the one `gamepad` timestamps its events on.
+    pub fn with_event_source(gamepad: S, clock: C, settings: GamepadSettings) -> Self {
+        Self {
+            gamepad,
+            clock,
+            settings,
             state: GamepadState::new(),
-        })
+            boost: BoostState {
+                started_at: None,
+                available_at: Duration::ZERO,
+            },
+            motion_sensors: None,
+            touchpad: None,
+            last_activity: None,
+        }
+    }
+
+    fn start_boost(&mut self, now: Duration) {
+        if self.boost.started_at.is_some() {
+            return;
+        }
+
+        if now < self.boost.available_at {
+            log::info!(
+                "Boost not available for another {:.1}s.",
+                (self.boost.available_at - now).as_secs_f64()
+            );
+            return;
+        }
+
+        log::info!("Boost engaged.");
+        self.boost.started_at = Some(now);
     }
 
-    pub fn process_input(&mut self) -> Result<LocomotionCommand, Box<dyn Error>> {
-        self.gamepad.read_events(|event| {
+    fn end_boost(&mut self, now: Duration) {
+        if self.boost.started_at.take().is_some() {
+            log::info!("Boost ended.");
+            self.boost.available_at = now + self.settings.boost_cooldown;
+        }
+    }
+
+    // Motion sensors are only looked for when tilt steering is configured, as they report hundreds of times a second.
+    fn open_motion_sensors(&mut self) {
+        if self.settings.tilt_steering_button.is_none() {
+            return;
+        }
+        let Some(device_file) = self.gamepad.device_file() else {
+            return;
+        };
+
+        self.motion_sensors = match MotionSensors::find(device_file) {
+            Ok(Some(motion_sensors)) => Some(motion_sensors),
+            Ok(None) => {
+                log::info!("The gamepad has no motion sensors, so tilt steering is not available.");
+                None
+            }
+            Err(error) => {
+                log::warn!(
+                    "Could not open the gamepad's motion sensors, so tilt steering is not available. - Cause: {}",
+                    error
+                );
+                None
+            }
+        };
+    }
+
+    fn read_tilt(&mut self) {
+        let Some(motion_sensors) = &mut self.motion_sensors else {
+            return;
+        };
+
+        match motion_sensors.read_tilt(self.settings.tilt_steering_angle) {
+            Ok(Some(tilt)) => self.state.tilt = tilt.value(),
+            Ok(None) => (),
+            Err(error) => {
+                log::warn!(
+                    "Closing the gamepad's motion sensors due to read error. - Cause: {}",
+                    error
+                );
+                self.motion_sensors = None;
+                self.state.tilt = 0.0;
+            }
+        }
+    }
+
+    // Like the motion sensors, the touchpad is only looked for when it is used.
+    fn open_touchpad(&mut self) {
+        if self.settings.touchpad.is_empty() {
+            return;
+        }
+        let Some(device_file) = self.gamepad.device_file() else {
+            return;
+        };
+
+        self.touchpad = match Touchpad::find(device_file) {
+            Ok(Some(touchpad)) => Some(touchpad),
+            Ok(None) => {
+                log::info!("The gamepad has no touchpad, so touchpad actions are not available.");
+                None
+            }
+            Err(error) => {
+                log::warn!(
+                    "Could not open the gamepad's touchpad, so touchpad actions are not available. - Cause: {}",
+                    error
+                );
+                None
+            }
+        };
+    }
+
+    fn read_touchpad(&mut self, notify: &mut dyn FnMut(InputNotification)) {
+        let Some(touchpad) = &mut self.touchpad else {
+            return;
+        };
+
+        let actions = self.settings.touchpad;
+        let step = self.settings.touchpad_speed_limit_step as i8;
+        let result = touchpad.read_gestures(|gesture| match actions.action(gesture) {
+            Some(TouchpadAction::IncreaseSpeedLimit) => {
+                notify(InputNotification::SpeedLimitAdjusted(step))
+            }
+            Some(TouchpadAction::DecreaseSpeedLimit) => {
+                notify(InputNotification::SpeedLimitAdjusted(-step))
+            }
+            Some(TouchpadAction::ToggleChoreography) => {
+                notify(InputNotification::ChoreographyToggled)
+            }
+            None => log::debug!("Ignoring touchpad gesture {:?}.", gesture),
+        });
+
+        if let Err(error) = result {
+            log::warn!(
+                "Closing the gamepad's touchpad due to read error. - Cause: {}",
+                error
+            );
+            self.touchpad = None;
+        }
+    }
+
+    fn is_boosting(&mut self, now: Duration) -> bool {
+        let Some(started_at) = self.boost.started_at else {
+            return false;
+        };
+
+        if now - started_at >= self.settings.boost_duration {
+            self.end_boost(now);
+            return false;
+        }
+
+        true
+    }
+}
+
+impl<S: GamepadEventSource, C: Clock> InputSource for GamepadInputInterpreter<S, C> {
+    fn is_connected(&self) -> bool {
+        self.gamepad.is_connected()
+    }
+
+    fn device_description(&self) -> Option<String> {
+        self.gamepad.identity().map(|identity| identity.to_string())
+    }
+
+    fn reload_controller_mappings(&mut self) {
+        self.gamepad.reload_mappings();
+    }
+
+    fn process_input(
+        &mut self,
+        notify: &mut dyn FnMut(InputNotification),
+    ) -> Result<LocomotionCommand, Box<dyn Error>> {
+        // The time of the most recent input affecting locomotion, for measuring how long it takes to act on it.
+        let mut input_timestamp = None;
+
+        let now = self.clock.now();
+        let boost_button = self.settings.boost_button;
+        let tilt_steering_button = self.settings.tilt_steering_button;
+        let gimbal_button = self.settings.gimbal_button;
+        let winch_modifier = self.settings.winch_modifier;
+        let profile = self.settings.profile;
+        let mut boost_pressed = None;
+        let mut connected = false;
+        let mut last_activity = None;
+
+        self.gamepad.read_events(&mut |event, timestamp| {
+            if !matches!(
+                event,
+                AnyGamepadEvent::Connected
+                    | AnyGamepadEvent::Disconnected
+                    | AnyGamepadEvent::Remapped
+                    | AnyGamepadEvent::PermissionDenied(_)
+            ) {
+                last_activity = Some(timestamp);
+            }
+            if let AnyGamepadEvent::ButtonPressed(button) = event {
+                notify(InputNotification::ButtonPressed(button));
+            }
+
             match event {
-                AnyGamepadEvent::StickAdjusted(stick, axis, value) => {
-                    if stick == Stick::Left && axis == StickAxis::Horizontal {
-                        self.state.left_stick_horizontal = value;
+                AnyGamepadEvent::ButtonPressed(button) if Some(button) == boost_button => {
+                    boost_pressed = Some(true);
+                }
+
+                AnyGamepadEvent::ButtonReleased(button) if Some(button) == boost_button => {
+                    boost_pressed = Some(false);
+                }
+
+                AnyGamepadEvent::ButtonPressed(button) if Some(button) == tilt_steering_button => {
+                    self.state.tilt_steering = true;
+                    input_timestamp = Some(timestamp);
+                }
+
+                AnyGamepadEvent::ButtonReleased(button) if Some(button) == tilt_steering_button => {
+                    self.state.tilt_steering = false;
+                    input_timestamp = Some(timestamp);
+                }
+
+                AnyGamepadEvent::ButtonPressed(button) if Some(button) == gimbal_button => {
+                    notify(InputNotification::GimbalToggled);
+                }
+
+                AnyGamepadEvent::ButtonPressed(button) if Some(button) == winch_modifier => {
+                    self.state.winch_modifier_held = true;
+                }
+
+                AnyGamepadEvent::ButtonReleased(button) if Some(button) == winch_modifier => {
+                    self.state.winch_modifier_held = false;
+                }
+
+                // Up is negative on evdev.
+                AnyGamepadEvent::DpadAdjusted(DpadAxis::Vertical, value) => {
+                    self.state.dpad_vertical = -value.value();
+                }
+
+                AnyGamepadEvent::StickAdjusted(Stick::Left, StickAxis::Horizontal, value) => {
+                    self.state.left_stick_horizontal = value.value();
+                    input_timestamp = Some(timestamp);
+                }
+
+                AnyGamepadEvent::StickAdjusted(Stick::Left, StickAxis::Vertical, value) => {
+                    let value = value.value();
+                    input_timestamp = Some(timestamp);
+                    let previous_value =
+                        std::mem::replace(&mut self.state.left_stick_vertical, value);
+
+                    // Only the joystick drives with the stick pushed forward or pulled back.
+                    if profile == ControlProfile::Joystick && value.abs() > previous_value.abs() {
+                        self.state.release_cruise();
+                    }
+                }
+
+                // Not for driving, but for an auxiliary channel. Up is negative on evdev.
+                AnyGamepadEvent::StickAdjusted(Stick::Right, StickAxis::Horizontal, value) => {
+                    self.state.right_stick_horizontal = value.value();
+                }
+
+                AnyGamepadEvent::StickAdjusted(Stick::Right, StickAxis::Vertical, value) => {
+                    self.state.right_stick_vertical = -value.value();
+                }
+
+                AnyGamepadEvent::WheelAdjusted(value) => {
+                    self.state.wheel = value.value();
+                    input_timestamp = Some(timestamp);
+                }
+
+                AnyGamepadEvent::PedalAdjusted(pedal, value) => {
+                    let value = value.value();
+                    input_timestamp = Some(timestamp);
+                    let previous_value = match pedal {
+                        Pedal::Gas => std::mem::replace(&mut self.state.gas_pedal, value),
+                        Pedal::Brake => std::mem::replace(&mut self.state.brake_pedal, value),
                     };
+
+                    if value > previous_value {
+                        self.state.release_cruise();
+                    }
+                }
+
+                AnyGamepadEvent::ThrottleLeverAdjusted(value) => {
+                    self.state.throttle_lever = value.value();
+                    input_timestamp = Some(timestamp);
                 }
 
                 AnyGamepadEvent::TriggerAdjusted(trigger, value) => {
-                    match trigger {
-                        Trigger::Left => {
-                            self.state.left_trigger = value;
-                        }
-                        Trigger::Right => {
-                            self.state.right_trigger = value;
-                        }
+                    let value = value.value();
+                    input_timestamp = Some(timestamp);
+                    let previous_value = match trigger {
+                        Trigger::Left => std::mem::replace(&mut self.state.left_trigger, value),
+                        Trigger::Right => std::mem::replace(&mut self.state.right_trigger, value),
                     };
+
+                    // Letting go of the trigger after latching the cruise throttle is expected, but pulling either
+                    // trigger means the driver takes over again.
+                    if value > previous_value {
+                        self.state.release_cruise();
+                    }
+                }
+
+                AnyGamepadEvent::ButtonPressed(Button::THUMBR) => {
+                    if self.state.cruise_throttle.is_some() {
+                        self.state.release_cruise();
+                    } else {
+                        let throttle = self.state.throttle(&self.settings);
+                        if throttle != 0.0 {
+                            log::info!("Cruising at {:.0}% throttle.", throttle * 100.0);
+                            self.state.cruise_throttle = Some(throttle);
+                        }
+                    }
+                }
+
+                AnyGamepadEvent::PermissionDenied(diagnostic) => {
+                    notify(InputNotification::PermissionDenied(diagnostic));
+                }
+
+                AnyGamepadEvent::ButtonPressed(Button::Y) => {
+                    notify(InputNotification::ChoreographyToggled);
+                }
+
+                AnyGamepadEvent::ButtonPressed(Button::START) => {
+                    notify(InputNotification::ArmRequested);
                 }
 
-                AnyGamepadEvent::Disconnected => {
+                AnyGamepadEvent::Connected => {
+                    notify(InputNotification::Connected);
+                    connected = true;
+                }
+
+                // A remapped gamepad is taken to go away and come back right away, so that nothing stays latched
+                // from controls that the new mapping decodes differently.
+                AnyGamepadEvent::Disconnected | AnyGamepadEvent::Remapped => {
+                    notify(InputNotification::Disconnected);
+
+                    // Resetting the state (including any cruise throttle) is what stops the vehicle when the gamepad
+                    // goes away. This only counts as the failsafe kicking in if the vehicle was actually being
+                    // driven at that point.
+                    if !self.state.is_neutral(profile) {
+                        notify(InputNotification::FailsafeEngaged);
+                    }
+
                     self.state = GamepadState::new();
+                    self.motion_sensors = None;
+                    self.touchpad = None;
+                    boost_pressed = Some(false);
+
+                    if matches!(event, AnyGamepadEvent::Remapped) {
+                        notify(InputNotification::Connected);
+                        connected = true;
+                    }
                 }
 
                 _ => (),
             };
         })?;
 
+        if last_activity.is_some() {
+            self.last_activity = last_activity;
+        }
+        if connected {
+            self.open_motion_sensors();
+            self.open_touchpad();
+        }
+        self.read_tilt();
+        self.read_touchpad(notify);
+
+        match boost_pressed {
+            Some(true) => self.start_boost(now),
+            Some(false) => self.end_boost(now),
+            None => (),
+        }
+
         Ok(LocomotionCommand::new(
-            self.state.right_trigger - self.state.left_trigger,
-            self.state.left_stick_horizontal,
-        ))
+            Throttle::new(self.state.throttle(&self.settings)),
+            Steering::new(self.state.steering(self.settings.profile)),
+        )
+        .with_input_timestamp(input_timestamp)
+        .with_boost(self.is_boosting(now)))
+    }
+
+    fn release_latched_input(&mut self) {
+        self.state.release_cruise();
+    }
+
+    fn last_activity(&self) -> Option<Duration> {
+        self.last_activity
+    }
+
+    fn auxiliary_axis(&self, axis: AuxiliaryAxis) -> Option<f64> {
+        match axis {
+            AuxiliaryAxis::RightStickHorizontal => Some(self.state.right_stick_horizontal),
+            AuxiliaryAxis::RightStickVertical => Some(self.state.right_stick_vertical),
+        }
+    }
+
+    fn winch_direction(&self) -> f64 {
+        if self.state.winch_modifier_held {
+            self.state.dpad_vertical
+        } else {
+            0.0
+        }
     }
 }
 
@@ -54,6 +577,20 @@ struct GamepadState {
     right_trigger: f64,
     left_trigger: f64,
     left_stick_horizontal: f64,
+    left_stick_vertical: f64,
+    right_stick_horizontal: f64,
+    right_stick_vertical: f64,
+    dpad_vertical: f64,
+    winch_modifier_held: bool,
+    wheel: f64,
+    gas_pedal: f64,
+    brake_pedal: f64,
+    throttle_lever: f64,
+    // The throttle latched by pressing the right stick, which is held without keeping a trigger pulled.
+    cruise_throttle: Option<f64>,
+    // The controller's tilt, as a steering value, and whether it steers rather than the stick.
+    tilt: f64,
+    tilt_steering: bool,
 }
 
 impl GamepadState {
@@ -62,6 +599,388 @@ impl GamepadState {
             right_trigger: 0.0,
             left_trigger: 0.0,
             left_stick_horizontal: 0.0,
+            left_stick_vertical: 0.0,
+            right_stick_horizontal: 0.0,
+            right_stick_vertical: 0.0,
+            dpad_vertical: 0.0,
+            winch_modifier_held: false,
+            wheel: 0.0,
+            gas_pedal: 0.0,
+            brake_pedal: 0.0,
+            // Until the lever is moved, or if there is none, the stick alone sets the throttle.
+            throttle_lever: 1.0,
+            cruise_throttle: None,
+            tilt: 0.0,
+            tilt_steering: false,
+        }
+    }
+
+    // The left stick's vertical axis is only a control with the joystick, which drives with it.
+    fn is_neutral(&self, profile: ControlProfile) -> bool {
+        self.right_trigger == 0.0
+            && self.left_trigger == 0.0
+            && self.left_stick_horizontal == 0.0
+            && (profile != ControlProfile::Joystick || self.left_stick_vertical == 0.0)
+            && self.wheel == 0.0
+            && self.gas_pedal == 0.0
+            && self.brake_pedal == 0.0
+            && self.cruise_throttle.is_none()
+            && (!self.tilt_steering || self.tilt == 0.0)
+    }
+
+    // The negative range is both braking and reversing: an ESC brakes on it while the vehicle moves forward, and only
+    // reverses after having seen neutral.
+    fn throttle(&self, settings: &GamepadSettings) -> f64 {
+        if let Some(cruise_throttle) = self.cruise_throttle {
+            return cruise_throttle;
+        }
+
+        match settings.profile {
+            ControlProfile::Gamepad => forward_and_back(
+                self.right_trigger,
+                self.left_trigger,
+                settings.trigger_brake,
+            ),
+            // Pressing both pedals is what `trigger_brake` was modelled on to begin with.
+            ControlProfile::Wheel => {
+                forward_and_back(self.gas_pedal, self.brake_pedal, settings.trigger_brake)
+            }
+            // Pushing the stick forward reports negative values.
+            ControlProfile::Joystick => -self.left_stick_vertical * self.throttle_lever,
+        }
+    }
+
+    fn steering(&self, profile: ControlProfile) -> f64 {
+        match profile {
+            ControlProfile::Gamepad | ControlProfile::Joystick if self.tilt_steering => self.tilt,
+            ControlProfile::Gamepad | ControlProfile::Joystick => self.left_stick_horizontal,
+            ControlProfile::Wheel => self.wheel,
+        }
+    }
+
+    fn release_cruise(&mut self) {
+        if self.cruise_throttle.take().is_some() {
+            log::info!("Cruise released.");
+        }
+    }
+}
+
+// Combines an accelerating and a braking control, each from 0.0 to 1.0, into a throttle.
+fn forward_and_back(forward: f64, back: f64, brake_when_both: bool) -> f64 {
+    if brake_when_both && forward > 0.0 && back > 0.0 {
+        -back
+    } else {
+        forward - back
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::GamepadEventSource;
+    use crate::clock::{Clock, ManualClock};
+    use crate::gamepads::AnyGamepadEvent;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::error::Error;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    /// Delivers the events it is given on the next read, like a gamepad whose events have all arrived by then. They
+    /// are timestamped with the time of the read, on `clock`. Clones share the pending events, so that a test can keep one to push
+    /// events to while an interpreter owns another.
+    #[derive(Clone)]
+    pub struct ScriptedGamepad {
+        clock: ManualClock,
+        connected: bool,
+        pending_events: Rc<RefCell<VecDeque<AnyGamepadEvent>>>,
+    }
+
+    impl ScriptedGamepad {
+        pub fn new(clock: ManualClock) -> Self {
+            Self {
+                clock,
+                connected: false,
+                pending_events: Rc::new(RefCell::new(VecDeque::new())),
+            }
+        }
+
+        pub fn push(&self, events: &[AnyGamepadEvent]) {
+            self.pending_events
+                .borrow_mut()
+                .extend(events.iter().copied());
         }
     }
+
+    impl GamepadEventSource for ScriptedGamepad {
+        fn is_connected(&self) -> bool {
+            self.connected
+        }
+
+        fn read_events(
+            &mut self,
+            handler: &mut dyn FnMut(AnyGamepadEvent, Duration),
+        ) -> Result<(), Box<dyn Error>> {
+            while let Some(event) = self.pending_events.borrow_mut().pop_front() {
+                match event {
+                    AnyGamepadEvent::Connected => self.connected = true,
+                    AnyGamepadEvent::Disconnected => self.connected = false,
+                    _ => (),
+                }
+                handler(event, self.clock.now());
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::gamepad::apply_deadzone;
+    use super::mock::ScriptedGamepad;
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::control_values::NormalizedAxis;
+
+    struct Harness {
+        interpreter: GamepadInputInterpreter<ScriptedGamepad, ManualClock>,
+        notifications: Vec<InputNotification>,
+    }
+
+    impl Harness {
+        fn new(settings: GamepadSettings) -> Self {
+            let clock = ManualClock::new(Duration::ZERO);
+            let mut harness = Self {
+                interpreter: GamepadInputInterpreter::with_event_source(
+                    ScriptedGamepad::new(clock.clone()),
+                    clock,
+                    settings,
+                ),
+                notifications: Vec::new(),
+            };
+            harness.process(&[AnyGamepadEvent::Connected]);
+            harness.notifications.clear();
+
+            harness
+        }
+
+        // Returns the throttle and steering the events result in.
+        fn process(&mut self, events: &[AnyGamepadEvent]) -> (f64, f64) {
+            self.interpreter.gamepad.push(events);
+            let notifications = &mut self.notifications;
+            let command = self
+                .interpreter
+                .process_input(&mut |notification| notifications.push(notification))
+                .unwrap();
+
+            (
+                command.get_throttle().value(),
+                command.get_direction().value(),
+            )
+        }
+    }
+
+    fn axis(value: f64) -> NormalizedAxis {
+        NormalizedAxis::new(value)
+    }
+
+    fn trigger(trigger: Trigger, value: f64) -> AnyGamepadEvent {
+        AnyGamepadEvent::TriggerAdjusted(trigger, axis(value))
+    }
+
+    fn stick(stick_axis: StickAxis, value: f64) -> AnyGamepadEvent {
+        AnyGamepadEvent::StickAdjusted(Stick::Left, stick_axis, axis(value))
+    }
+
+    #[test]
+    fn combined_triggers() {
+        let mut harness = Harness::new(GamepadSettings::default());
+        assert_eq!(
+            harness.process(&[trigger(Trigger::Right, 0.75)]),
+            (0.75, 0.0)
+        );
+        // The triggers cancel each other out.
+        assert_eq!(harness.process(&[trigger(Trigger::Left, 0.25)]), (0.5, 0.0));
+        assert_eq!(
+            harness.process(&[trigger(Trigger::Right, 0.0)]),
+            (-0.25, 0.0)
+        );
+
+        let mut harness = Harness::new(GamepadSettings {
+            trigger_brake: true,
+            ..GamepadSettings::default()
+        });
+        assert_eq!(
+            harness.process(&[trigger(Trigger::Right, 0.75)]),
+            (0.75, 0.0)
+        );
+        // Both triggers brake, as hard as the left one says.
+        assert_eq!(
+            harness.process(&[trigger(Trigger::Left, 0.25)]),
+            (-0.25, 0.0)
+        );
+        assert_eq!(harness.process(&[trigger(Trigger::Left, 0.0)]), (0.75, 0.0));
+    }
+
+    #[test]
+    fn disconnecting_resets_input() {
+        let mut harness = Harness::new(GamepadSettings::default());
+        harness.process(&[
+            trigger(Trigger::Right, 0.5),
+            stick(StickAxis::Horizontal, -0.5),
+            AnyGamepadEvent::ButtonPressed(Button::THUMBR),
+            trigger(Trigger::Right, 0.0),
+        ]);
+        assert_eq!(harness.process(&[]), (0.5, -0.5));
+
+        assert_eq!(
+            harness.process(&[AnyGamepadEvent::Disconnected]),
+            (0.0, 0.0)
+        );
+        assert_eq!(
+            harness.notifications,
+            [
+                InputNotification::ButtonPressed(Button::THUMBR),
+                InputNotification::Disconnected,
+                InputNotification::FailsafeEngaged
+            ]
+        );
+        assert!(!harness.interpreter.is_connected());
+
+        // The cruise throttle does not come back with the gamepad.
+        harness.notifications.clear();
+        assert_eq!(harness.process(&[AnyGamepadEvent::Connected]), (0.0, 0.0));
+        assert_eq!(harness.notifications, [InputNotification::Connected]);
+
+        // Losing a gamepad that was not driving anything is no failsafe.
+        harness.notifications.clear();
+        harness.process(&[AnyGamepadEvent::Disconnected]);
+        assert_eq!(harness.notifications, [InputNotification::Disconnected]);
+    }
+
+    #[test]
+    fn remapping_resets_input() {
+        let mut harness = Harness::new(GamepadSettings::default());
+        assert_eq!(harness.process(&[trigger(Trigger::Right, 0.8)]), (0.8, 0.0));
+
+        // The trigger might not even be decoded as one any more, so it must not keep the throttle open.
+        assert_eq!(harness.process(&[AnyGamepadEvent::Remapped]), (0.0, 0.0));
+        assert_eq!(
+            harness.notifications,
+            [
+                InputNotification::Disconnected,
+                InputNotification::FailsafeEngaged,
+                InputNotification::Connected
+            ]
+        );
+        assert!(harness.interpreter.is_connected());
+    }
+
+    #[test]
+    fn start_asks_for_arming_and_counts_as_activity() {
+        let mut harness = Harness::new(GamepadSettings::default());
+        assert_eq!(harness.interpreter.last_activity(), None);
+
+        harness.process(&[AnyGamepadEvent::ButtonPressed(Button::START)]);
+        assert_eq!(
+            harness.notifications,
+            [
+                InputNotification::ButtonPressed(Button::START),
+                InputNotification::ArmRequested
+            ]
+        );
+        assert_eq!(harness.interpreter.last_activity(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn winch_runs_while_modifier_is_held() {
+        let mut harness = Harness::new(GamepadSettings {
+            winch_modifier: Some(Button::TL),
+            ..GamepadSettings::default()
+        });
+        let dpad_vertical =
+            |value: f64| AnyGamepadEvent::DpadAdjusted(DpadAxis::Vertical, axis(value));
+
+        // The dpad alone does not run it.
+        harness.process(&[dpad_vertical(-1.0)]);
+        assert_eq!(harness.interpreter.winch_direction(), 0.0);
+
+        harness.process(&[AnyGamepadEvent::ButtonPressed(Button::TL)]);
+        assert_eq!(harness.interpreter.winch_direction(), 1.0);
+        harness.process(&[dpad_vertical(1.0)]);
+        assert_eq!(harness.interpreter.winch_direction(), -1.0);
+
+        // Letting go of either stops it.
+        harness.process(&[dpad_vertical(0.0)]);
+        assert_eq!(harness.interpreter.winch_direction(), 0.0);
+        harness.process(&[
+            dpad_vertical(-1.0),
+            AnyGamepadEvent::ButtonReleased(Button::TL),
+        ]);
+        assert_eq!(harness.interpreter.winch_direction(), 0.0);
+
+        // As does the gamepad going away.
+        harness.process(&[AnyGamepadEvent::ButtonPressed(Button::TL)]);
+        harness.process(&[AnyGamepadEvent::Disconnected]);
+        assert_eq!(harness.interpreter.winch_direction(), 0.0);
+    }
+
+    #[test]
+    fn deadzone_leaves_cruise_alone() {
+        let mut harness = Harness::new(GamepadSettings::default());
+        harness.process(&[
+            trigger(Trigger::Right, 0.5),
+            AnyGamepadEvent::ButtonPressed(Button::THUMBR),
+            trigger(Trigger::Right, 0.0),
+        ]);
+
+        // Triggers not quite landing on zero and sticks wobbling stay within the deadzone, which is no reason to
+        // take over from the cruise throttle or to steer.
+        let wobble = [
+            AnyGamepadEvent::TriggerAdjusted(Trigger::Right, apply_deadzone(0.1)),
+            AnyGamepadEvent::TriggerAdjusted(Trigger::Left, apply_deadzone(0.05)),
+            AnyGamepadEvent::StickAdjusted(Stick::Left, StickAxis::Horizontal, apply_deadzone(0.1)),
+        ];
+        assert_eq!(harness.process(&wobble), (0.5, 0.0));
+
+        // Neither is a control the profile does not drive with, such as the stick's vertical axis.
+        assert_eq!(
+            harness.process(&[stick(StickAxis::Vertical, 0.8)]),
+            (0.5, 0.0)
+        );
+
+        // Pulling a trigger for real does.
+        assert_eq!(
+            harness.process(&[AnyGamepadEvent::TriggerAdjusted(
+                Trigger::Left,
+                apply_deadzone(0.25)
+            )]),
+            (-0.25, 0.0)
+        );
+    }
+
+    #[test]
+    fn profiles_use_their_own_controls() {
+        let events = [
+            trigger(Trigger::Right, 1.0),
+            stick(StickAxis::Horizontal, 0.5),
+            stick(StickAxis::Vertical, -0.5),
+            AnyGamepadEvent::WheelAdjusted(axis(-0.25)),
+            AnyGamepadEvent::PedalAdjusted(Pedal::Gas, axis(0.75)),
+            AnyGamepadEvent::PedalAdjusted(Pedal::Brake, axis(0.25)),
+            AnyGamepadEvent::ThrottleLeverAdjusted(axis(0.5)),
+        ];
+        let command = |profile| {
+            Harness::new(GamepadSettings {
+                profile,
+                ..GamepadSettings::default()
+            })
+            .process(&events)
+        };
+
+        assert_eq!(command(ControlProfile::Gamepad), (1.0, 0.5));
+        assert_eq!(command(ControlProfile::Wheel), (0.5, -0.25));
+        // Forward on the stick, scaled by the lever.
+        assert_eq!(command(ControlProfile::Joystick), (0.25, 0.5));
+    }
 }
@@ -0,0 +1,260 @@
+use super::detection::{scan_for_gamepad_devices, GamepadDeviceRules};
+use crate::application_state::ApplicationState;
+use crate::event_bus::{Event, EventSubscriber};
+use std::fs;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+// Shows the application state on the gamepads' own lights, so that the driver can tell whether the vehicle is armed
+// without looking at it:
+// - the lightbar (DualShock 4, DualSense) is green while armed, red on a failsafe or error, and blue otherwise,
+// - the first player indicator (DualSense, Xbox 360) is lit while armed, all of them on a failsafe or error.
+//
+// The kernel drivers expose these lights as LEDs in sysfs, below the HID device that the gamepad's evdev device
+// belongs to. They are looked up again on every state change, as gamepads come and go. Gamepads without any lights
+// we know about (such as Xbox controllers over Bluetooth) are left alone.
+//
+// ⚠️ The LEDs' `brightness` files are only writable by root by default, so a udev rule is needed to make them
+// writable by the service's user.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Indication {
+    // Red, green and blue, from 0.0 to 1.0.
+    color: [f64; 3],
+    // How many player indicators to light.
+    players: u8,
+}
+
+impl Indication {
+    fn for_state(state: ApplicationState) -> Indication {
+        const GREEN: [f64; 3] = [0.0, 1.0, 0.0];
+        const RED: [f64; 3] = [1.0, 0.0, 0.0];
+        const BLUE: [f64; 3] = [0.0, 0.0, 1.0];
+        const DIM_BLUE: [f64; 3] = [0.0, 0.0, 0.1];
+
+        match state {
+            ApplicationState::Armed | ApplicationState::Driving => Indication {
+                color: GREEN,
+                players: 1,
+            },
+            ApplicationState::Failsafe | ApplicationState::Fault => Indication {
+                color: RED,
+                players: u8::MAX,
+            },
+            ApplicationState::Asleep => Indication {
+                color: DIM_BLUE,
+                players: 0,
+            },
+            ApplicationState::WaitingForInput | ApplicationState::Disarmed => Indication {
+                color: BLUE,
+                players: 0,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Light {
+    // An RGB LED with an intensity per color, such as the DualSense lightbar. The order of the colors is read from
+    // its `multi_index` file.
+    Multicolor { path: PathBuf, colors: Vec<usize> },
+    // One color of an RGB LED whose colors are separate LEDs, such as the DualShock 4 lightbar.
+    Color { path: PathBuf, color: usize },
+    // A player indicator, e.g. `input5:white:player-1`.
+    Player { path: PathBuf, number: u8 },
+    // The ring of an Xbox 360 controller, which takes an animation rather than a brightness.
+    XpadRing { path: PathBuf },
+}
+
+impl Light {
+    fn path(&self) -> &Path {
+        match self {
+            Light::Multicolor { path, .. } => path,
+            Light::Color { path, .. } => path,
+            Light::Player { path, .. } => path,
+            Light::XpadRing { path } => path,
+        }
+    }
+}
+
+pub struct GamepadLights {
+    device_rules: GamepadDeviceRules,
+    lights: Vec<Light>,
+    shown: Option<Indication>,
+    // Writing to the lights is only warned about once, as it is likely to keep failing (e.g. for lack of permission).
+    warned: bool,
+}
+
+impl GamepadLights {
+    pub fn new(device_rules: &GamepadDeviceRules) -> GamepadLights {
+        GamepadLights {
+            device_rules: device_rules.clone(),
+            lights: Vec::new(),
+            shown: None,
+            warned: false,
+        }
+    }
+
+    fn show(&mut self, indication: Indication) {
+        let lights = find_lights(&self.device_rules);
+        if lights != self.lights {
+            self.lights = lights;
+            self.shown = None;
+        }
+        if self.shown == Some(indication) {
+            return;
+        }
+
+        let mut result = Ok(());
+        for light in &self.lights {
+            result = result.and(set_light(light, indication));
+        }
+
+        match result {
+            Ok(_) => self.shown = Some(indication),
+            Err(error) => {
+                if !self.warned {
+                    log::warn!(
+                        "Could not set gamepad lights (is there a udev rule making them writable?). - Cause: {}",
+                        error
+                    );
+                    self.warned = true;
+                }
+            }
+        }
+    }
+}
+
+impl EventSubscriber for GamepadLights {
+    fn handle_event(&mut self, event: Event) {
+        if let Event::StateChanged { state, .. } = event {
+            self.show(Indication::for_state(state));
+        }
+    }
+}
+
+// Gamepads that are not accessible (e.g. still being set up by udev) are skipped.
+fn find_lights(device_rules: &GamepadDeviceRules) -> Vec<Light> {
+    let Ok(device_files) = scan_for_gamepad_devices(device_rules) else {
+        return Vec::new();
+    };
+
+    let mut lights = Vec::new();
+    for device_file in device_files {
+        // 💁‍♂️ The LEDs are found by device number rather than by resolving the device file's link, as resolving
+        // links is not among the system calls the seccomp filter allows.
+        let Ok(metadata) = fs::metadata(&device_file) else {
+            continue;
+        };
+        let rdev = metadata.rdev();
+        let (major, minor) = unsafe { (libc::major(rdev), libc::minor(rdev)) };
+        let led_folder = PathBuf::from(format!(
+            "/sys/dev/char/{}:{}/device/device/leds",
+            major, minor
+        ));
+
+        let Ok(entries) = fs::read_dir(&led_folder) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if let Some(light) = identify_light(&entry.path()) {
+                lights.push(light);
+            }
+        }
+    }
+    lights.sort_by(|a, b| a.path().cmp(b.path()));
+
+    lights
+}
+
+fn identify_light(path: &Path) -> Option<Light> {
+    let name = path.file_name()?.to_str()?;
+    let function = name.rsplit(':').next()?;
+    let path = path.to_path_buf();
+
+    if let Ok(multi_index) = fs::read_to_string(path.join("multi_index")) {
+        let colors = multi_index
+            .split_whitespace()
+            .map(color_index)
+            .collect::<Option<Vec<_>>>()?;
+        return Some(Light::Multicolor { path, colors });
+    }
+
+    if let Some(number) = function.strip_prefix("player-") {
+        return Some(Light::Player {
+            path,
+            number: number.parse().ok()?,
+        });
+    }
+
+    if name.starts_with("xpad") {
+        return Some(Light::XpadRing { path });
+    }
+
+    Some(Light::Color {
+        path,
+        color: color_index(function)?,
+    })
+}
+
+fn color_index(name: &str) -> Option<usize> {
+    match name {
+        "red" => Some(0),
+        "green" => Some(1),
+        "blue" => Some(2),
+        _ => None,
+    }
+}
+
+fn set_light(light: &Light, indication: Indication) -> Result<(), IoError> {
+    match light {
+        Light::Multicolor { path, colors } => {
+            let max_brightness = max_brightness(path)?;
+            let intensities = colors
+                .iter()
+                .map(|&color| scale(indication.color[color], max_brightness).to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            fs::write(path.join("multi_intensity"), intensities)?;
+            set_brightness(path, 1.0)
+        }
+        Light::Color { path, color } => set_brightness(path, indication.color[*color]),
+        Light::Player { path, number } => set_brightness(
+            path,
+            if *number <= indication.players {
+                1.0
+            } else {
+                0.0
+            },
+        ),
+        Light::XpadRing { path } => {
+            // 0 turns the ring off, 1 blinks all quadrants, 6 lights the first quadrant.
+            let animation = match indication.players {
+                0 => 0,
+                1 => 6,
+                _ => 1,
+            };
+            fs::write(path.join("brightness"), animation.to_string())
+        }
+    }
+}
+
+fn set_brightness(path: &Path, brightness: f64) -> Result<(), IoError> {
+    fs::write(
+        path.join("brightness"),
+        scale(brightness, max_brightness(path)?).to_string(),
+    )
+}
+
+fn max_brightness(path: &Path) -> Result<u32, IoError> {
+    fs::read_to_string(path.join("max_brightness"))?
+        .trim()
+        .parse()
+        .map_err(|_| IoError::from(ErrorKind::InvalidData))
+}
+
+fn scale(value: f64, maximum: u32) -> u32 {
+    (value.clamp(0.0, 1.0) * maximum as f64).round() as u32
+}
@@ -0,0 +1,508 @@
+use super::gamepad::{
+    AxisRange, GamepadIdentity, ABS_BRAKE, ABS_GAS, ABS_HAT0X, ABS_HAT0Y, ABS_RX, ABS_RY, ABS_RZ,
+    ABS_THROTTLE, ABS_WHEEL, ABS_X, ABS_Y, ABS_Z, BTN_A, BTN_B, BTN_MODE, BTN_SELECT, BTN_START,
+    BTN_THUMBL, BTN_THUMBR, BTN_TL, BTN_TR, BTN_X, BTN_Y,
+};
+use super::{Button, DpadAxis, Pedal, Stick, StickAxis, Trigger};
+use crate::folder_monitor::{FolderEvent, FolderMonitor, ProcessingError};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+
+// Which evdev codes a controller reports its buttons and axes with, so that controllers other than the one this was
+// written for (see `gamepad.rs`) can be used by adding a line to a mapping file (`gamepad.mapping_file`), rather
+// than by changing code. The file is modelled on SDL's gamecontrollerdb.txt, one controller per line:
+//
+//     # vendor:product[:version],name,control:code,...
+//     054c:09cc,DualShock 4,a:b130,b:b131,x:b134,y:b133,leftx:a00,lefty:a01,righty:a04~,lefttrigger:a02@0..255
+//
+// Controllers are told apart by their vendor and product IDs in hexadecimal, as `lsusb` shows them, and optionally by
+// their version, which some vendors bump when a firmware update changes the layout. A line for the exact version
+// takes precedence over one without a version. Controllers without a line are decoded as xpadneo reports an Xbox
+// controller.
+//
+// The controls are named as in SDL (`a`, `b`, `x`, `y`, `leftshoulder`, `rightshoulder`, `back`, `start`, `guide`,
+// `leftstick`, `rightstick`, `leftx`, `lefty`, `rightx`, `righty`, `lefttrigger`, `righttrigger`), with `dpadx`,
+// `dpady`, `wheel`, `gas`, `brake` and `throttle` for the axes SDL does not know about. Unlike in SDL, they are
+// assigned evdev codes in hexadecimal, as in input-event-codes.h: `b<code>` for a button (`BTN_*`) and `a<code>` for
+// an axis (`ABS_*`). An axis can be inverted with a trailing `~`, and its range given with `@<minimum>..<maximum>`
+// for devices that report a wrong one. A line replaces the built-in mapping as a whole: controls it leaves out are
+// ignored.
+//
+// 💁‍♂️ The file is watched for changes, and also reloaded on SIGHUP. A connected controller switches to its new
+// mapping right away. A file that fails to load is reported and leaves the previous mappings in place.
+
+// What an axis is decoded as.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AxisTarget {
+    Stick(Stick, StickAxis),
+    Trigger(Trigger),
+    Dpad(DpadAxis),
+    Wheel,
+    Pedal(Pedal),
+    ThrottleLever,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AxisAssignment {
+    pub target: AxisTarget,
+    pub inverted: bool,
+    // Overrides the range the device reports.
+    pub range: Option<AxisRange>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControllerMapping {
+    buttons: HashMap<libc::__u16, Button>,
+    axes: HashMap<libc::__u16, AxisAssignment>,
+}
+
+impl ControllerMapping {
+    // The codes of the xpadneo driver.
+    pub fn builtin() -> ControllerMapping {
+        let axis = |target| AxisAssignment {
+            target,
+            inverted: false,
+            range: None,
+        };
+
+        ControllerMapping {
+            buttons: HashMap::from([
+                (BTN_A, Button::A),
+                (BTN_B, Button::B),
+                (BTN_X, Button::X),
+                (BTN_Y, Button::Y),
+                (BTN_TL, Button::TL),
+                (BTN_TR, Button::TR),
+                (BTN_SELECT, Button::SELECT),
+                (BTN_START, Button::START),
+                (BTN_MODE, Button::MODE),
+                (BTN_THUMBL, Button::THUMBL),
+                (BTN_THUMBR, Button::THUMBR),
+            ]),
+            axes: HashMap::from([
+                (
+                    ABS_X,
+                    axis(AxisTarget::Stick(Stick::Left, StickAxis::Horizontal)),
+                ),
+                (
+                    ABS_Y,
+                    axis(AxisTarget::Stick(Stick::Left, StickAxis::Vertical)),
+                ),
+                (
+                    ABS_RX,
+                    axis(AxisTarget::Stick(Stick::Right, StickAxis::Horizontal)),
+                ),
+                (
+                    ABS_RY,
+                    axis(AxisTarget::Stick(Stick::Right, StickAxis::Vertical)),
+                ),
+                (ABS_Z, axis(AxisTarget::Trigger(Trigger::Left))),
+                (ABS_RZ, axis(AxisTarget::Trigger(Trigger::Right))),
+                (ABS_HAT0X, axis(AxisTarget::Dpad(DpadAxis::Horizontal))),
+                (ABS_HAT0Y, axis(AxisTarget::Dpad(DpadAxis::Vertical))),
+                (ABS_WHEEL, axis(AxisTarget::Wheel)),
+                (ABS_GAS, axis(AxisTarget::Pedal(Pedal::Gas))),
+                (ABS_BRAKE, axis(AxisTarget::Pedal(Pedal::Brake))),
+                (ABS_THROTTLE, axis(AxisTarget::ThrottleLever)),
+            ]),
+        }
+    }
+
+    pub fn button(&self, code: libc::__u16) -> Option<Button> {
+        self.buttons.get(&code).copied()
+    }
+
+    pub fn axis(&self, code: libc::__u16) -> Option<AxisAssignment> {
+        self.axes.get(&code).copied()
+    }
+
+    // Parses the assignments of a line, e.g. `a:b130,leftx:a00`. Returns the offending assignment if one is invalid.
+    fn parse(assignments: &[&str]) -> Result<ControllerMapping, String> {
+        let mut mapping = ControllerMapping {
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+        };
+
+        for assignment in assignments {
+            let invalid = || assignment.to_string();
+            let (control, code) = assignment.split_once(':').ok_or_else(invalid)?;
+
+            if let Some(code) = code.strip_prefix('b') {
+                let button = parse_button(control).ok_or_else(invalid)?;
+                let code = libc::__u16::from_str_radix(code, 16).map_err(|_| invalid())?;
+                mapping.buttons.insert(code, button);
+            } else if let Some(code) = code.strip_prefix('a') {
+                let target = parse_axis_target(control).ok_or_else(invalid)?;
+                let (code, range) = match code.split_once('@') {
+                    Some((code, range)) => (code, Some(parse_range(range).ok_or_else(invalid)?)),
+                    None => (code, None),
+                };
+                let (code, inverted) = match code.strip_suffix('~') {
+                    Some(code) => (code, true),
+                    None => (code, false),
+                };
+                let code = libc::__u16::from_str_radix(code, 16).map_err(|_| invalid())?;
+                mapping.axes.insert(
+                    code,
+                    AxisAssignment {
+                        target,
+                        inverted,
+                        range,
+                    },
+                );
+            } else {
+                return Err(invalid());
+            }
+        }
+
+        Ok(mapping)
+    }
+}
+
+fn parse_button(control: &str) -> Option<Button> {
+    match control {
+        "a" => Some(Button::A),
+        "b" => Some(Button::B),
+        "x" => Some(Button::X),
+        "y" => Some(Button::Y),
+        "leftshoulder" => Some(Button::TL),
+        "rightshoulder" => Some(Button::TR),
+        "back" => Some(Button::SELECT),
+        "start" => Some(Button::START),
+        "guide" => Some(Button::MODE),
+        "leftstick" => Some(Button::THUMBL),
+        "rightstick" => Some(Button::THUMBR),
+        _ => None,
+    }
+}
+
+fn parse_axis_target(control: &str) -> Option<AxisTarget> {
+    match control {
+        "leftx" => Some(AxisTarget::Stick(Stick::Left, StickAxis::Horizontal)),
+        "lefty" => Some(AxisTarget::Stick(Stick::Left, StickAxis::Vertical)),
+        "rightx" => Some(AxisTarget::Stick(Stick::Right, StickAxis::Horizontal)),
+        "righty" => Some(AxisTarget::Stick(Stick::Right, StickAxis::Vertical)),
+        "lefttrigger" => Some(AxisTarget::Trigger(Trigger::Left)),
+        "righttrigger" => Some(AxisTarget::Trigger(Trigger::Right)),
+        "dpadx" => Some(AxisTarget::Dpad(DpadAxis::Horizontal)),
+        "dpady" => Some(AxisTarget::Dpad(DpadAxis::Vertical)),
+        "wheel" => Some(AxisTarget::Wheel),
+        "gas" => Some(AxisTarget::Pedal(Pedal::Gas)),
+        "brake" => Some(AxisTarget::Pedal(Pedal::Brake)),
+        "throttle" => Some(AxisTarget::ThrottleLever),
+        _ => None,
+    }
+}
+
+// E.g. `0..255` or `-512..511`.
+fn parse_range(text: &str) -> Option<AxisRange> {
+    let (minimum, maximum) = text.split_once("..")?;
+    let range = AxisRange {
+        minimum: minimum.parse().ok()?,
+        maximum: maximum.parse().ok()?,
+    };
+
+    (range.maximum > range.minimum).then_some(range)
+}
+
+struct MappingEntry {
+    vendor: u16,
+    product: u16,
+    version: Option<u16>,
+    name: String,
+    mapping: ControllerMapping,
+}
+
+#[derive(Default)]
+pub struct MappingDatabase {
+    entries: Vec<MappingEntry>,
+}
+
+impl MappingDatabase {
+    pub fn load(path: &Path) -> Result<MappingDatabase, LoadError> {
+        let text = fs::read_to_string(path).map_err(|source| LoadError::CouldNotReadFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        MappingDatabase::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<MappingDatabase, LoadError> {
+        let mut entries = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // SDL's files end every line with a comma.
+            let fields: Vec<&str> = line
+                .trim_end_matches(',')
+                .split(',')
+                .map(str::trim)
+                .collect();
+            let [id, name, assignments @ ..] = fields.as_slice() else {
+                return Err(LoadError::InvalidSyntax { line: line_number });
+            };
+
+            let numbers: Vec<Option<u16>> = id
+                .split(':')
+                .map(|number| u16::from_str_radix(number, 16).ok())
+                .collect();
+            let (vendor, product, version) = match numbers.as_slice() {
+                [Some(vendor), Some(product)] => (*vendor, *product, None),
+                [Some(vendor), Some(product), Some(version)] => (*vendor, *product, Some(*version)),
+                _ => {
+                    return Err(LoadError::InvalidControllerId {
+                        line: line_number,
+                        id: id.to_string(),
+                    })
+                }
+            };
+
+            let mapping = ControllerMapping::parse(assignments).map_err(|assignment| {
+                LoadError::InvalidAssignment {
+                    line: line_number,
+                    assignment,
+                }
+            })?;
+
+            entries.push(MappingEntry {
+                vendor,
+                product,
+                version,
+                name: name.to_string(),
+                mapping,
+            });
+        }
+
+        Ok(MappingDatabase { entries })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // The name and mapping of the line for the controller, if there is one. Of several matching lines, the last one
+    // counts, so that a line added to the end of the file overrides those before it.
+    pub fn find(&self, identity: &GamepadIdentity) -> Option<(&str, &ControllerMapping)> {
+        let matching = |version: Option<u16>| {
+            self.entries.iter().rev().find(|entry| {
+                entry.vendor == identity.vendor
+                    && entry.product == identity.product
+                    && entry.version == version
+            })
+        };
+
+        matching(Some(identity.version))
+            .or_else(|| matching(None))
+            .map(|entry| (entry.name.as_str(), &entry.mapping))
+    }
+}
+
+// The mapping file, if one is configured, loaded and kept up to date.
+pub struct ControllerMappings {
+    file: Option<PathBuf>,
+    database: MappingDatabase,
+    folder_monitor: Option<FolderMonitor>,
+}
+
+impl ControllerMappings {
+    // A configured file has to load, like the configuration itself. Not being able to watch it is no reason not to
+    // drive, though.
+    pub fn new(file: Option<&Path>) -> Result<ControllerMappings, LoadError> {
+        let Some(file) = file else {
+            return Ok(ControllerMappings {
+                file: None,
+                database: MappingDatabase::default(),
+                folder_monitor: None,
+            });
+        };
+
+        let database = MappingDatabase::load(file)?;
+        log::info!(
+            "Loaded {} controller mappings from {}.",
+            database.len(),
+            file.display()
+        );
+
+        let folder = match file.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let folder_monitor = match FolderMonitor::new(folder) {
+            Ok(folder_monitor) => Some(folder_monitor),
+            Err(error) => {
+                log::warn!(
+                    "Not watching {} for changes, controller mappings will only be reloaded on SIGHUP. - Cause: {}",
+                    file.display(),
+                    error
+                );
+                None
+            }
+        };
+
+        Ok(ControllerMappings {
+            file: Some(file.to_path_buf()),
+            database,
+            folder_monitor,
+        })
+    }
+
+    pub fn mapping_for(&self, identity: &GamepadIdentity) -> ControllerMapping {
+        match self.database.find(identity) {
+            Some((name, mapping)) => {
+                log::info!("Using the controller mapping for {}.", name);
+                mapping.clone()
+            }
+            None => ControllerMapping::builtin(),
+        }
+    }
+
+    // Returns whether the file was written since the last call.
+    pub fn file_changed(&self) -> Result<bool, ProcessingError> {
+        let (Some(file), Some(folder_monitor)) = (&self.file, &self.folder_monitor) else {
+            return Ok(false);
+        };
+
+        let mut changed = false;
+        folder_monitor.process_filesystem_events(|event| match event {
+            FolderEvent::Added(path) | FolderEvent::Modified(path) => {
+                changed |= path.file_name() == file.file_name();
+            }
+            FolderEvent::EventQueueOverflowed => changed = true,
+            _ => (),
+        })?;
+
+        Ok(changed)
+    }
+
+    // Returns whether the mappings changed, i.e. the file was loaded.
+    pub fn reload(&mut self) -> bool {
+        let Some(file) = &self.file else {
+            return false;
+        };
+
+        match MappingDatabase::load(file) {
+            Ok(database) => {
+                log::info!(
+                    "Reloaded {} controller mappings from {}.",
+                    database.len(),
+                    file.display()
+                );
+                self.database = database;
+                true
+            }
+            Err(error) => {
+                log::error!(
+                    "Keeping the current controller mappings, as the changed ones could not be loaded. - Cause: {}",
+                    error
+                );
+                false
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    CouldNotReadFile { path: PathBuf, source: IoError },
+    InvalidSyntax { line: usize },
+    InvalidControllerId { line: usize, id: String },
+    InvalidAssignment { line: usize, assignment: String },
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::CouldNotReadFile { path: _, source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            LoadError::CouldNotReadFile { path, source: _ } => {
+                format!(
+                    "Could not read controller mapping file at {}.",
+                    path.display()
+                )
+            }
+            LoadError::InvalidSyntax { line } => {
+                format!(
+                    "Invalid syntax in controller mapping file on line {}.",
+                    line
+                )
+            }
+            LoadError::InvalidControllerId { line, id } => format!(
+                "Invalid controller ID '{}' in controller mapping file on line {}.",
+                id, line
+            ),
+            LoadError::InvalidAssignment { line, assignment } => format!(
+                "Invalid assignment '{}' in controller mapping file on line {}.",
+                assignment, line
+            ),
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_and_finding_mappings() {
+        let database = MappingDatabase::parse(
+            "# A comment.\n\
+             \n\
+             054c:09cc,DualShock 4,a:b130,leftx:a00,righty:a04~,lefttrigger:a02@0..255,\n\
+             054c:09cc:8111,DualShock 4 (new firmware),a:b131\n",
+        )
+        .unwrap();
+        assert_eq!(database.len(), 2);
+
+        let mut identity = GamepadIdentity {
+            name: "Wireless Controller".to_string(),
+            bus_type: 0x05,
+            vendor: 0x054c,
+            product: 0x09cc,
+            version: 0x8100,
+            physical_location: String::new(),
+        };
+        let (name, mapping) = database.find(&identity).unwrap();
+        assert_eq!(name, "DualShock 4");
+        assert_eq!(mapping.button(BTN_A), Some(Button::A));
+        assert_eq!(mapping.button(BTN_B), None);
+        let trigger = mapping.axis(ABS_Z).unwrap();
+        assert_eq!(trigger.target, AxisTarget::Trigger(Trigger::Left));
+        assert_eq!(trigger.range.unwrap().maximum, 255);
+        assert!(mapping.axis(ABS_RY).unwrap().inverted);
+
+        // The exact version takes precedence.
+        identity.version = 0x8111;
+        let (_, mapping) = database.find(&identity).unwrap();
+        assert_eq!(mapping.button(BTN_B), Some(Button::A));
+
+        identity.product = 0x05c4;
+        assert!(database.find(&identity).is_none());
+
+        assert!(matches!(
+            MappingDatabase::parse("054c:09cc,DualShock 4,a:b130,touchpad:b14a"),
+            Err(LoadError::InvalidAssignment { line: 1, .. })
+        ));
+        assert!(matches!(
+            MappingDatabase::parse("\n054c,DualShock 4,a:b130"),
+            Err(LoadError::InvalidControllerId { line: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+use super::detection::{find_companion_device, INPUT_PROP_ACCELEROMETER};
+use super::gamepad::{
+    apply_deadzone, open_gamepad_device, ABS_X, ABS_Y, ABS_Z, EV_ABS, EV_SYN, SYN_REPORT,
+};
+use crate::control_values::NormalizedAxis;
+use std::io::Error as IoError;
+use std::mem;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+
+// The motion sensors of a DualShock 4 or DualSense, which the kernel exposes as an input device of their own next to
+// the gamepad's, for steering by tilting the controller.
+//
+// The tilt is taken from the accelerometer alone: it is the angle by which the controller's left-to-right axis
+// leaves the horizontal, no matter whether the controller is held flat or upright like a steering wheel. Turning the
+// controller clockwise steers to the right. Accelerating the controller (e.g. when shaking it) shows up as tilt, which
+// the smoothing below only partly hides.
+
+// How much of a new reading goes into the smoothed one.
+const SMOOTHING_FACTOR: f64 = 0.2;
+
+pub struct MotionSensors {
+    device_fd: OwnedFd,
+    raw: [f64; 3],
+    smoothed: Option<[f64; 3]>,
+}
+
+impl MotionSensors {
+    // Finds the motion sensors belonging to the gamepad at `gamepad_device_file`, i.e. the input device with the
+    // accelerometer property below the same HID device. Returns `None` for gamepads without any.
+    pub fn find(gamepad_device_file: &Path) -> Result<Option<MotionSensors>, IoError> {
+        let Some(device_file) =
+            find_companion_device(gamepad_device_file, INPUT_PROP_ACCELEROMETER)?
+        else {
+            return Ok(None);
+        };
+
+        let device_fd = open_gamepad_device(&device_file)?;
+        log::info!("Using motion sensors at {}.", device_file.display());
+
+        Ok(Some(MotionSensors {
+            device_fd,
+            raw: [0.0; 3],
+            smoothed: None,
+        }))
+    }
+
+    // Returns the tilt at the end of the events read, if any, with `full_tilt_degrees` of tilt mapped to the extremes.
+    pub fn read_tilt(&mut self, full_tilt_degrees: f64) -> Result<Option<NormalizedAxis>, IoError> {
+        const NUMBER_OF_EVENTS_IN_BUFFER: usize = 64;
+        const INPUT_EVENT_SIZE: usize = mem::size_of::<libc::input_event>();
+
+        let mut buffer = [MaybeUninit::<libc::input_event>::uninit(); NUMBER_OF_EVENTS_IN_BUFFER];
+        let mut updated = false;
+
+        // The sensors report far more often than the runloop runs, so keep reading until caught up.
+        loop {
+            let bytes_read = unsafe {
+                libc::read(
+                    self.device_fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    NUMBER_OF_EVENTS_IN_BUFFER * INPUT_EVENT_SIZE,
+                )
+            };
+
+            if bytes_read < 0 {
+                let error = IoError::last_os_error();
+                if error.raw_os_error() == Some(libc::EAGAIN) {
+                    break;
+                }
+                return Err(error);
+            }
+
+            let events_read = bytes_read as usize / INPUT_EVENT_SIZE;
+            for event in &buffer[..events_read] {
+                let event = unsafe { event.assume_init() };
+                match (event.type_, event.code) {
+                    (EV_ABS, ABS_X) => self.raw[0] = event.value as f64,
+                    (EV_ABS, ABS_Y) => self.raw[1] = event.value as f64,
+                    (EV_ABS, ABS_Z) => self.raw[2] = event.value as f64,
+                    // Readings are only complete at the end of a report.
+                    (EV_SYN, SYN_REPORT) => {
+                        let smoothed = self.smoothed.get_or_insert(self.raw);
+                        for (smoothed, raw) in smoothed.iter_mut().zip(self.raw) {
+                            *smoothed += SMOOTHING_FACTOR * (raw - *smoothed);
+                        }
+                        updated = true;
+                    }
+                    _ => (),
+                }
+            }
+
+            if events_read < NUMBER_OF_EVENTS_IN_BUFFER {
+                break;
+            }
+        }
+
+        if !updated {
+            return Ok(None);
+        }
+
+        Ok(self.smoothed.map(|[x, y, z]| {
+            // At rest, the accelerometer measures the opposite of gravity, so the right side going down makes x
+            // negative.
+            let tilt_degrees = (-x).atan2(y.hypot(z)).to_degrees();
+            apply_deadzone(tilt_degrees / full_tilt_degrees)
+        }))
+    }
+}
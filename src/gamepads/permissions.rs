@@ -0,0 +1,98 @@
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+// Gamepads that are there but cannot be opened for lack of permissions, for long enough that udev cannot be blamed for
+// still fixing them up, point at how the service is deployed: it runs as a user that is not in the group owning the
+// event devices (usually `input`), or no udev rule gives that group access. This is reported once, with what was
+// observed, so that the status API shows what is wrong without anyone having to dig through the log.
+
+// What was observed about the service's user and a device it was refused access to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PermissionDiagnostic {
+    pub uid: u32,
+    pub gid: u32,
+    // `None` when the device could not even be looked at.
+    pub device_uid: Option<u32>,
+    pub device_gid: Option<u32>,
+    pub device_mode: Option<u32>,
+}
+
+impl PermissionDiagnostic {
+    pub fn observe(device_file_path: &Path) -> PermissionDiagnostic {
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let status = CString::new(device_file_path.as_os_str().as_bytes())
+            .ok()
+            .and_then(|path| {
+                let mut status: libc::stat = unsafe { std::mem::zeroed() };
+                let result = unsafe { libc::stat(path.as_ptr(), &mut status) };
+                (result == 0).then_some(status)
+            });
+
+        PermissionDiagnostic {
+            uid,
+            gid,
+            device_uid: status.map(|status| status.st_uid),
+            device_gid: status.map(|status| status.st_gid),
+            device_mode: status.map(|status| status.st_mode & 0o7777),
+        }
+    }
+
+    // What to do about it, to go with the observations in the log.
+    pub fn remediation(&self) -> String {
+        match self.device_gid {
+            Some(device_gid) => format!(
+                "Add the service's user to group {} (e.g. `usermod -aG input <user>`), or add a udev rule such as \
+                 `SUBSYSTEM==\"input\", KERNEL==\"event*\", GROUP=\"input\", MODE=\"0660\"`, then reconnect the \
+                 gamepad.",
+                device_gid
+            ),
+            None => "Check that the service's user can get to /dev/input at all.".to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for PermissionDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "running as uid {} gid {}", self.uid, self.gid)?;
+        if let (Some(device_uid), Some(device_gid), Some(device_mode)) =
+            (self.device_uid, self.device_gid, self.device_mode)
+        {
+            write!(
+                f,
+                ", device owned by uid {} gid {} with mode {:04o}",
+                device_uid, device_gid, device_mode
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describing_what_was_observed() {
+        let missing = PermissionDiagnostic::observe(Path::new("/dev/input/no-such-device"));
+        assert_eq!(missing.device_mode, None);
+        assert_eq!(
+            missing.to_string(),
+            format!("running as uid {} gid {}", missing.uid, missing.gid)
+        );
+
+        let observed = PermissionDiagnostic {
+            uid: 1000,
+            gid: 1000,
+            device_uid: Some(0),
+            device_gid: Some(106),
+            device_mode: Some(0o660),
+        };
+        assert_eq!(
+            observed.to_string(),
+            "running as uid 1000 gid 1000, device owned by uid 0 gid 106 with mode 0660"
+        );
+        assert!(observed.remediation().contains("group 106"));
+    }
+}
@@ -0,0 +1,169 @@
+use super::detection::{scan_for_gamepad_devices, GamepadDeviceRules};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::io::Error as IoError;
+use std::mem;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::prelude::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Rumbles the gamepads, to get the driver's attention without them having to look away from the vehicle. This uses
+// the force feedback interface of their evdev devices: a rumble effect is uploaded once per device and updated for
+// every rumble, after which writing an `EV_FF` event plays it.
+//
+// The devices are opened separately from the ones input is read from, for writing, and looked up again on every
+// rumble, as gamepads come and go. Gamepads without force feedback (or without a driver supporting it) are left
+// alone.
+//
+// 💁‍♂️ Event devices are readable and writable by the `input` group alike, so reading input from a gamepad is all it
+// takes permission-wise.
+
+// input-event-codes.h
+const EV_FF: libc::__u16 = 0x15;
+// input.h
+const FF_RUMBLE: libc::__u16 = 0x50;
+
+struct RumbleDevice {
+    device_fd: OwnedFd,
+    effect_id: libc::__s16,
+}
+
+pub struct GamepadRumble {
+    device_rules: GamepadDeviceRules,
+    devices: HashMap<PathBuf, RumbleDevice>,
+    // Rumbling is only warned about once, as it is likely to keep failing (e.g. for lack of permission).
+    warned: bool,
+}
+
+impl GamepadRumble {
+    pub fn new(device_rules: &GamepadDeviceRules) -> GamepadRumble {
+        GamepadRumble {
+            device_rules: device_rules.clone(),
+            devices: HashMap::new(),
+            warned: false,
+        }
+    }
+
+    // `strength` goes from 0.0 to 1.0.
+    pub fn rumble(&mut self, strength: f64, duration: Duration) {
+        let device_files = scan_for_gamepad_devices(&self.device_rules).unwrap_or_default();
+        self.devices.retain(|path, _| device_files.contains(path));
+
+        let mut result = Ok(());
+        for path in device_files {
+            result = result.and(self.rumble_device(&path, strength, duration));
+        }
+
+        if let Err(error) = result {
+            if !self.warned {
+                log::warn!("Could not rumble gamepad. - Cause: {}", error);
+                self.warned = true;
+            }
+        }
+    }
+
+    fn rumble_device(
+        &mut self,
+        path: &Path,
+        strength: f64,
+        duration: Duration,
+    ) -> Result<(), IoError> {
+        let device = match self.devices.remove(path) {
+            Some(device) => device,
+            None => RumbleDevice {
+                device_fd: open_for_writing(path)?,
+                effect_id: -1,
+            },
+        };
+
+        let magnitude = (strength.clamp(0.0, 1.0) * u16::MAX as f64).round() as u16;
+        let effect_id =
+            match upload_effect(&device.device_fd, device.effect_id, magnitude, duration) {
+                Ok(effect_id) => effect_id,
+                // Devices without force feedback, or without rumble effects.
+                Err(error) if matches!(error.raw_os_error(), Some(libc::ENOSYS | libc::EINVAL)) => {
+                    return Ok(())
+                }
+                Err(error) => return Err(error),
+            };
+        let device = RumbleDevice {
+            effect_id,
+            ..device
+        };
+
+        play_effect(&device.device_fd, effect_id)?;
+        self.devices.insert(path.to_path_buf(), device);
+
+        Ok(())
+    }
+}
+
+fn open_for_writing(device_file_path: &Path) -> Result<OwnedFd, IoError> {
+    let device_file_path = CString::new(device_file_path.as_os_str().as_bytes()).unwrap();
+
+    let fd = unsafe {
+        libc::open(
+            device_file_path.as_ptr(),
+            libc::O_RDWR | libc::O_NONBLOCK | libc::O_CLOEXEC,
+        )
+    };
+
+    if fd == -1 {
+        Err(IoError::last_os_error())
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+// Uploads a new effect (with an ID of -1) or replaces an existing one, and returns its ID.
+fn upload_effect(
+    device_fd: &OwnedFd,
+    effect_id: libc::__s16,
+    magnitude: u16,
+    duration: Duration,
+) -> Result<libc::__s16, IoError> {
+    // _IOW('E', 0x80, struct ff_effect)
+    const EVIOCSFF: libc::c_ulong =
+        0x40000000 | ((mem::size_of::<libc::ff_effect>() as libc::c_ulong) << 16) | 0x4580;
+
+    let mut effect: libc::ff_effect = unsafe { mem::zeroed() };
+    effect.type_ = FF_RUMBLE;
+    effect.id = effect_id;
+    effect.replay.length = duration.as_millis().min(u16::MAX as u128) as u16;
+    // The union holds a `struct ff_rumble_effect` for rumble effects: the strong motor first, then the weak one.
+    let rumble = libc::ff_rumble_effect {
+        strong_magnitude: magnitude,
+        weak_magnitude: magnitude,
+    };
+    unsafe {
+        (effect.u.as_mut_ptr() as *mut libc::ff_rumble_effect).write_unaligned(rumble);
+    }
+
+    let result = unsafe { libc::ioctl(device_fd.as_raw_fd(), EVIOCSFF as _, &mut effect) };
+    if result < 0 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(effect.id)
+}
+
+fn play_effect(device_fd: &OwnedFd, effect_id: libc::__s16) -> Result<(), IoError> {
+    let mut event: libc::input_event = unsafe { mem::zeroed() };
+    event.type_ = EV_FF;
+    event.code = effect_id as libc::__u16;
+    event.value = 1;
+
+    let bytes_written = unsafe {
+        libc::write(
+            device_fd.as_raw_fd(),
+            &event as *const libc::input_event as *const libc::c_void,
+            mem::size_of::<libc::input_event>(),
+        )
+    };
+    if bytes_written < 0 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(())
+}
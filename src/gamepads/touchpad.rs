@@ -0,0 +1,369 @@
+use super::detection::{find_companion_device, INPUT_PROP_BUTTONPAD};
+use super::gamepad::{
+    event_timestamp, open_gamepad_device, query_axis_range, use_monotonic_event_timestamps,
+    AxisRange, EV_ABS, EV_SYN, SYN_DROPPED, SYN_REPORT,
+};
+use std::io::Error as IoError;
+use std::mem;
+use std::mem::MaybeUninit;
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+// The touchpad of a DualShock 4 or DualSense, which the kernel exposes as an input device of its own next to the
+// gamepad's, as an auxiliary input: swiping across it or tapping its left or right half triggers a configurable
+// action.
+//
+// The touchpad speaks the multitouch protocol (type B): every finger gets a slot, and the events of a report update
+// the slot selected by the last ABS_MT_SLOT event. ABS_MT_TRACKING_ID starts a contact in a slot, or ends it when -1.
+// Gestures are made with one finger: a contact that had company at any time is ignored, so that resting a second
+// finger on the touchpad cancels a gesture.
+
+// input-event-codes.h: multitouch axes.
+const ABS_MT_SLOT: libc::__u16 = 0x2f;
+const ABS_MT_POSITION_X: libc::__u16 = 0x35;
+const ABS_MT_POSITION_Y: libc::__u16 = 0x36;
+const ABS_MT_TRACKING_ID: libc::__u16 = 0x39;
+
+// The DualShock 4 and DualSense track two fingers.
+const MAX_SLOTS: usize = 2;
+
+// How far a finger has to travel, as a fraction of the touchpad's size, and how quickly, to swipe.
+const SWIPE_DISTANCE: f64 = 0.3;
+const MAXIMUM_SWIPE_DURATION: Duration = Duration::from_secs(1);
+// How far a finger may wander, and how long it may rest, to still tap.
+const TAP_DISTANCE: f64 = 0.05;
+const MAXIMUM_TAP_DURATION: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Gesture {
+    SwipeUp,
+    SwipeDown,
+    SwipeLeft,
+    SwipeRight,
+    TapLeft,
+    TapRight,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TouchpadAction {
+    IncreaseSpeedLimit,
+    DecreaseSpeedLimit,
+    ToggleChoreography,
+}
+
+impl FromStr for TouchpadAction {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "speed_limit_up" => Ok(TouchpadAction::IncreaseSpeedLimit),
+            "speed_limit_down" => Ok(TouchpadAction::DecreaseSpeedLimit),
+            "choreography" => Ok(TouchpadAction::ToggleChoreography),
+            _ => Err(()),
+        }
+    }
+}
+
+// What each gesture does, if anything.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct TouchpadActions {
+    pub swipe_up: Option<TouchpadAction>,
+    pub swipe_down: Option<TouchpadAction>,
+    pub swipe_left: Option<TouchpadAction>,
+    pub swipe_right: Option<TouchpadAction>,
+    pub tap_left: Option<TouchpadAction>,
+    pub tap_right: Option<TouchpadAction>,
+}
+
+impl TouchpadActions {
+    pub fn action(&self, gesture: Gesture) -> Option<TouchpadAction> {
+        match gesture {
+            Gesture::SwipeUp => self.swipe_up,
+            Gesture::SwipeDown => self.swipe_down,
+            Gesture::SwipeLeft => self.swipe_left,
+            Gesture::SwipeRight => self.swipe_right,
+            Gesture::TapLeft => self.tap_left,
+            Gesture::TapRight => self.tap_right,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == TouchpadActions::default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Contact {
+    // The state as of the last report, and what the events since then changed.
+    touching: bool,
+    will_touch: bool,
+    // Positions as raw values, starting with where the finger went down.
+    start: [libc::__s32; 2],
+    position: [libc::__s32; 2],
+    started_at: Duration,
+    // Whether another finger was on the touchpad at some point during the contact.
+    crowded: bool,
+}
+
+struct MultitouchDecoder {
+    ranges: [AxisRange; 2],
+    slot: usize,
+    contacts: [Contact; MAX_SLOTS],
+}
+
+impl MultitouchDecoder {
+    fn new(ranges: [AxisRange; 2]) -> MultitouchDecoder {
+        MultitouchDecoder {
+            ranges,
+            slot: 0,
+            contacts: [Contact::default(); MAX_SLOTS],
+        }
+    }
+
+    fn process_event(&mut self, event: &libc::input_event, mut handler: impl FnMut(Gesture)) {
+        match (event.type_, event.code) {
+            (EV_ABS, ABS_MT_SLOT) => self.slot = event.value.max(0) as usize,
+            (EV_ABS, ABS_MT_TRACKING_ID) => {
+                if let Some(contact) = self.contacts.get_mut(self.slot) {
+                    contact.will_touch = event.value >= 0;
+                }
+            }
+            (EV_ABS, ABS_MT_POSITION_X) => {
+                if let Some(contact) = self.contacts.get_mut(self.slot) {
+                    contact.position[0] = event.value;
+                }
+            }
+            (EV_ABS, ABS_MT_POSITION_Y) => {
+                if let Some(contact) = self.contacts.get_mut(self.slot) {
+                    contact.position[1] = event.value;
+                }
+            }
+            (EV_SYN, SYN_REPORT) => self.end_report(event_timestamp(event), &mut handler),
+            // Contacts that started or ended among the dropped events cannot be told apart from the ones that did
+            // not, so any gesture in progress is cancelled.
+            (EV_SYN, SYN_DROPPED) => {
+                for contact in &mut self.contacts {
+                    contact.crowded = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn end_report(&mut self, timestamp: Duration, handler: &mut impl FnMut(Gesture)) {
+        let fingers = self
+            .contacts
+            .iter()
+            .filter(|contact| contact.will_touch)
+            .count();
+
+        for contact in &mut self.contacts {
+            match (contact.touching, contact.will_touch) {
+                (false, true) => {
+                    contact.start = contact.position;
+                    contact.started_at = timestamp;
+                    contact.crowded = fingers > 1;
+                }
+                (true, true) => contact.crowded |= fingers > 1,
+                (true, false) => {
+                    if !contact.crowded {
+                        let gesture = recognize_gesture(
+                            contact,
+                            self.ranges,
+                            timestamp.saturating_sub(contact.started_at),
+                        );
+                        if let Some(gesture) = gesture {
+                            handler(gesture);
+                        }
+                    }
+                }
+                (false, false) => (),
+            }
+            contact.touching = contact.will_touch;
+        }
+    }
+}
+
+fn recognize_gesture(
+    contact: &Contact,
+    ranges: [AxisRange; 2],
+    duration: Duration,
+) -> Option<Gesture> {
+    let start = [0, 1].map(|axis| ranges[axis].normalize(contact.start[axis]));
+    let end = [0, 1].map(|axis| ranges[axis].normalize(contact.position[axis]));
+    let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+
+    if dx.abs().max(dy.abs()) >= SWIPE_DISTANCE && duration <= MAXIMUM_SWIPE_DURATION {
+        // The y axis points down.
+        return Some(match (dx.abs() > dy.abs(), dx > 0.0, dy > 0.0) {
+            (true, true, _) => Gesture::SwipeRight,
+            (true, false, _) => Gesture::SwipeLeft,
+            (false, _, true) => Gesture::SwipeDown,
+            (false, _, false) => Gesture::SwipeUp,
+        });
+    }
+
+    if dx.hypot(dy) <= TAP_DISTANCE && duration <= MAXIMUM_TAP_DURATION {
+        return Some(if start[0] < 0.5 {
+            Gesture::TapLeft
+        } else {
+            Gesture::TapRight
+        });
+    }
+
+    None
+}
+
+pub struct Touchpad {
+    device_fd: OwnedFd,
+    decoder: MultitouchDecoder,
+}
+
+impl Touchpad {
+    // Finds the touchpad belonging to the gamepad at `gamepad_device_file`, i.e. the input device with the buttonpad
+    // property below the same HID device. Returns `None` for gamepads without one.
+    pub fn find(gamepad_device_file: &Path) -> Result<Option<Touchpad>, IoError> {
+        let Some(device_file) = find_companion_device(gamepad_device_file, INPUT_PROP_BUTTONPAD)?
+        else {
+            return Ok(None);
+        };
+
+        let device_fd = open_gamepad_device(&device_file)?;
+        use_monotonic_event_timestamps(&device_fd)?;
+        let (Some(x_range), Some(y_range)) = (
+            query_axis_range(&device_fd, ABS_MT_POSITION_X),
+            query_axis_range(&device_fd, ABS_MT_POSITION_Y),
+        ) else {
+            return Ok(None);
+        };
+        log::info!("Using touchpad at {}.", device_file.display());
+
+        Ok(Some(Touchpad {
+            device_fd,
+            decoder: MultitouchDecoder::new([x_range, y_range]),
+        }))
+    }
+
+    // Passes the gestures completed since the last call to `handler`.
+    pub fn read_gestures(&mut self, mut handler: impl FnMut(Gesture)) -> Result<(), IoError> {
+        const NUMBER_OF_EVENTS_IN_BUFFER: usize = 64;
+        const INPUT_EVENT_SIZE: usize = mem::size_of::<libc::input_event>();
+
+        let mut buffer = [MaybeUninit::<libc::input_event>::uninit(); NUMBER_OF_EVENTS_IN_BUFFER];
+
+        loop {
+            let bytes_read = unsafe {
+                libc::read(
+                    self.device_fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    NUMBER_OF_EVENTS_IN_BUFFER * INPUT_EVENT_SIZE,
+                )
+            };
+
+            if bytes_read < 0 {
+                let error = IoError::last_os_error();
+                if error.raw_os_error() == Some(libc::EAGAIN) {
+                    return Ok(());
+                }
+                return Err(error);
+            }
+
+            let events_read = bytes_read as usize / INPUT_EVENT_SIZE;
+            for event in &buffer[..events_read] {
+                let event = unsafe { event.assume_init() };
+                self.decoder.process_event(&event, &mut handler);
+            }
+
+            if events_read < NUMBER_OF_EVENTS_IN_BUFFER {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(
+        milliseconds: u64,
+        type_: libc::__u16,
+        code: libc::__u16,
+        value: i32,
+    ) -> libc::input_event {
+        libc::input_event {
+            time: libc::timeval {
+                tv_sec: (milliseconds / 1000) as libc::time_t,
+                tv_usec: (milliseconds % 1000 * 1000) as libc::suseconds_t,
+            },
+            type_,
+            code,
+            value,
+        }
+    }
+
+    fn gestures(events: &[(u64, libc::__u16, i32)]) -> Vec<Gesture> {
+        let range = AxisRange {
+            minimum: 0,
+            maximum: 1000,
+        };
+        let mut decoder = MultitouchDecoder::new([range, range]);
+        let mut gestures = Vec::new();
+        for &(milliseconds, code, value) in events {
+            let event = match code {
+                SYN_REPORT => event(milliseconds, EV_SYN, SYN_REPORT, 0),
+                _ => event(milliseconds, EV_ABS, code, value),
+            };
+            decoder.process_event(&event, |gesture| gestures.push(gesture));
+        }
+
+        gestures
+    }
+
+    #[test]
+    fn multitouch_gestures() {
+        let tap_right = [
+            (0, ABS_MT_TRACKING_ID, 7),
+            (0, ABS_MT_POSITION_X, 800),
+            (0, ABS_MT_POSITION_Y, 500),
+            (0, SYN_REPORT, 0),
+            (100, ABS_MT_POSITION_X, 810),
+            (100, SYN_REPORT, 0),
+            (150, ABS_MT_TRACKING_ID, -1),
+            (150, SYN_REPORT, 0),
+        ];
+        assert_eq!(gestures(&tap_right), [Gesture::TapRight]);
+
+        let swipe_up = [
+            (0, ABS_MT_TRACKING_ID, 8),
+            (0, ABS_MT_POSITION_X, 500),
+            (0, ABS_MT_POSITION_Y, 900),
+            (0, SYN_REPORT, 0),
+            (200, ABS_MT_POSITION_Y, 300),
+            (200, SYN_REPORT, 0),
+            (300, ABS_MT_TRACKING_ID, -1),
+            (300, SYN_REPORT, 0),
+        ];
+        assert_eq!(gestures(&swipe_up), [Gesture::SwipeUp]);
+
+        // A second finger in slot 1 cancels the swipe of the first.
+        let two_fingers = [
+            (0, ABS_MT_TRACKING_ID, 9),
+            (0, ABS_MT_POSITION_X, 100),
+            (0, SYN_REPORT, 0),
+            (100, ABS_MT_SLOT, 1),
+            (100, ABS_MT_TRACKING_ID, 10),
+            (100, ABS_MT_POSITION_X, 900),
+            (100, SYN_REPORT, 0),
+            (200, ABS_MT_SLOT, 0),
+            (200, ABS_MT_POSITION_X, 700),
+            (200, ABS_MT_TRACKING_ID, -1),
+            (200, ABS_MT_SLOT, 1),
+            (200, ABS_MT_TRACKING_ID, -1),
+            (200, SYN_REPORT, 0),
+        ];
+        assert_eq!(gestures(&two_fingers), []);
+    }
+}
@@ -0,0 +1,189 @@
+use crate::gps::{Fix, Position, EARTH_RADIUS_M};
+use crate::runloop;
+use std::time::Duration;
+
+// Keeps the vehicle from running off across an open field, e.g. when it drives out of radio range with the throttle
+// stuck open, by capping the throttle once the GPS puts it outside of a boundary: a circle around a point or a polygon.
+// The cap goes from full throttle at the boundary down to `speed_outside` at `margin` beyond it, in a straight line.
+// It is not cut altogether, so that the vehicle can still be driven back in. While outside, the driver is alerted by
+// rumbling the gamepad, harder the further out the vehicle is.
+//
+// ⚠️ Without a fix, the cap stays where it was: losing the fix inside does not restrict driving, and losing it outside
+// does not lift the restriction.
+
+const ALERT_INTERVAL: Duration = Duration::from_secs(1);
+// How long an alert rumbles for.
+pub const ALERT_DURATION: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Boundary {
+    // The radius is in meters.
+    Circle { center: Position, radius: f64 },
+    // The corners, in order. The last one connects back to the first one.
+    Polygon(Vec<Position>),
+}
+
+impl Boundary {
+    // In meters, 0.0 when inside.
+    fn distance_outside(&self, position: &Position) -> f64 {
+        match self {
+            Boundary::Circle { center, radius } => (center.distance_to(position) - radius).max(0.0),
+            Boundary::Polygon(corners) => {
+                // Geofences are small enough for the earth to be taken as flat, which makes this a matter of 2D
+                // geometry in meters relative to the first corner.
+                let origin = corners[0];
+                let to_meters = |position: &Position| {
+                    (
+                        (position.longitude - origin.longitude).to_radians()
+                            * EARTH_RADIUS_M
+                            * origin.latitude.to_radians().cos(),
+                        (position.latitude - origin.latitude).to_radians() * EARTH_RADIUS_M,
+                    )
+                };
+                let point = to_meters(position);
+                let corners: Vec<(f64, f64)> = corners.iter().map(to_meters).collect();
+                let edges = corners
+                    .iter()
+                    .zip(corners.iter().cycle().skip(1))
+                    .map(|(&start, &end)| (start, end));
+
+                let mut inside = false;
+                let mut distance = f64::INFINITY;
+                for (start, end) in edges {
+                    // Casting a ray from the point to the right: it crosses the edges an odd number of times from
+                    // inside.
+                    if (start.1 > point.1) != (end.1 > point.1)
+                        && point.0
+                            < start.0 + (point.1 - start.1) / (end.1 - start.1) * (end.0 - start.0)
+                    {
+                        inside = !inside;
+                    }
+                    distance = distance.min(distance_to_segment(point, start, end));
+                }
+
+                if inside {
+                    0.0
+                } else {
+                    distance
+                }
+            }
+        }
+    }
+}
+
+fn distance_to_segment(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared > 0.0 {
+        (((point.0 - start.0) * dx + (point.1 - start.1) * dy) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    (point.0 - (start.0 + t * dx)).hypot(point.1 - (start.1 + t * dy))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GeofenceSettings {
+    // In meters.
+    pub margin: f64,
+    // The throttle cap from `margin` outwards, from 0.0 to 1.0.
+    pub speed_outside: f64,
+}
+
+pub struct Geofence {
+    boundary: Boundary,
+    settings: GeofenceSettings,
+    // How far into the margin the vehicle is, from 0.0 at the boundary to 1.0, if outside.
+    outside: Option<f64>,
+    last_alert: Option<Duration>,
+}
+
+impl Geofence {
+    pub fn new(boundary: Boundary, settings: GeofenceSettings) -> Self {
+        Self {
+            boundary,
+            settings,
+            outside: None,
+            last_alert: None,
+        }
+    }
+
+    // Expected to be called with every new fix. Returns whether the vehicle has just left the geofence.
+    pub fn handle_fix(&mut self, fix: Fix) -> bool {
+        let distance = self.boundary.distance_outside(&fix.position);
+        let outside = (distance > 0.0).then(|| (distance / self.settings.margin).min(1.0));
+
+        match (self.outside, outside) {
+            (None, Some(_)) => log::warn!(
+                "Left the geofence, capping the throttle as it gets further out ({:.0} m).",
+                distance
+            ),
+            (Some(_), None) => log::info!("Back inside the geofence."),
+            _ => (),
+        }
+        let left = self.outside.is_none() && outside.is_some();
+        self.outside = outside;
+
+        left
+    }
+
+    // From 0.0 to 1.0, to scale the throttle by.
+    pub fn throttle_cap(&self) -> f64 {
+        match self.outside {
+            Some(fraction) => 1.0 - fraction * (1.0 - self.settings.speed_outside),
+            None => 1.0,
+        }
+    }
+
+    // Whether to alert the driver now, and how hard, from 0.0 to 1.0. While outside, this comes up every
+    // `ALERT_INTERVAL`.
+    pub fn alert(&mut self) -> Option<f64> {
+        let fraction = self.outside?;
+        let now = runloop::now();
+        if self
+            .last_alert
+            .is_some_and(|last_alert| now - last_alert < ALERT_INTERVAL)
+        {
+            return None;
+        }
+        self.last_alert = Some(now);
+
+        Some(0.5 + fraction / 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_outside() {
+        let position = |latitude: f64, longitude: f64| Position {
+            latitude,
+            longitude,
+        };
+        // 0.001° of latitude is about 111 m.
+        let circle = Boundary::Circle {
+            center: position(52.0, 5.0),
+            radius: 100.0,
+        };
+        assert_eq!(circle.distance_outside(&position(52.0005, 5.0)), 0.0);
+        assert!((circle.distance_outside(&position(52.002, 5.0)) - 122.4).abs() < 0.5);
+
+        // An L shape.
+        let polygon = Boundary::Polygon(vec![
+            position(52.0, 5.0),
+            position(52.002, 5.0),
+            position(52.002, 5.001),
+            position(52.001, 5.001),
+            position(52.001, 5.002),
+            position(52.0, 5.002),
+        ]);
+        assert_eq!(polygon.distance_outside(&position(52.0015, 5.0005)), 0.0);
+        assert_eq!(polygon.distance_outside(&position(52.0005, 5.0015)), 0.0);
+        // In the notch of the L, and south of it.
+        assert!((polygon.distance_outside(&position(52.0015, 5.0015)) - 34.2).abs() < 0.5);
+        assert!((polygon.distance_outside(&position(51.999, 5.001)) - 111.2).abs() < 0.5);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::locomotion::{self, PCA9685Driver, PulseWidths, SetPWMError};
+use std::rc::Rc;
+use std::time::Duration;
+
+// A pan/tilt gimbal for a camera: two servos on spare PCA9685 channels, aimed with the right stick. Pressing the gimbal
+// button switches the right stick over to the gimbal and back, as it may be in use for an auxiliary channel otherwise.
+//
+// Each servo has its own endpoints, as the mechanics of a gimbal rarely allow the full servo range. The servos follow
+// the stick no faster than `full_deflection_time` from center to an endpoint, so that the picture does not jerk
+// around. With `center_on_release`, the stick position is where the camera points, which centers it when the stick is
+// let go of. Otherwise, pushing the stick moves the camera and it stays pointed wherever it was left.
+//
+// 💁‍♂️ Switching the gimbal off lets go of the stick as far as the gimbal is concerned, so it centers or stays put the
+// same way.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GimbalAxisSettings {
+    pub channel: u8,
+    pub pulse_widths: PulseWidths,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GimbalSettings {
+    pub pan: GimbalAxisSettings,
+    pub tilt: GimbalAxisSettings,
+    pub full_deflection_time: Duration,
+    pub center_on_release: bool,
+}
+
+struct GimbalAxis {
+    settings: GimbalAxisSettings,
+    // From -1.0 to 1.0.
+    position: f64,
+    // `None` while it is not known what the output was last set to.
+    written_count: Option<u16>,
+}
+
+impl GimbalAxis {
+    fn new(settings: GimbalAxisSettings) -> GimbalAxis {
+        GimbalAxis {
+            settings,
+            position: 0.0,
+            written_count: None,
+        }
+    }
+
+    fn write(&mut self, pca9685_driver: &PCA9685Driver) -> Result<(), SetPWMError> {
+        let count =
+            locomotion::duty_cycle_count(locomotion::locomotion_value_to_pwm_on_percentage(
+                self.position,
+                &self.settings.pulse_widths,
+                pca9685_driver.pwm_frequency(),
+            ))?;
+        if self.written_count != Some(count) {
+            self.written_count = None;
+            pca9685_driver.set_pwm_on_count(self.settings.channel, count)?;
+            self.written_count = Some(count);
+        }
+
+        Ok(())
+    }
+}
+
+// Where an axis goes next from `position`, with the stick at `input` and moving no further than `max_step`.
+fn next_position(position: f64, input: f64, max_step: f64, center_on_release: bool) -> f64 {
+    let step = if center_on_release {
+        (input - position).clamp(-max_step, max_step)
+    } else {
+        input * max_step
+    };
+
+    (position + step).clamp(-1.0, 1.0)
+}
+
+pub struct Gimbal {
+    pca9685_driver: Rc<PCA9685Driver>,
+    settings: GimbalSettings,
+    active: bool,
+    pan: GimbalAxis,
+    tilt: GimbalAxis,
+    last_update: Option<Duration>,
+}
+
+impl Gimbal {
+    pub fn new(pca9685_driver: Rc<PCA9685Driver>, settings: GimbalSettings) -> Gimbal {
+        Gimbal {
+            pca9685_driver,
+            settings,
+            active: false,
+            pan: GimbalAxis::new(settings.pan),
+            tilt: GimbalAxis::new(settings.tilt),
+            last_update: None,
+        }
+    }
+
+    // Whether the right stick aims the gimbal.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn toggle(&mut self) {
+        self.active = !self.active;
+        if self.active {
+            log::info!("The right stick now aims the gimbal.");
+        } else {
+            log::info!("The right stick no longer aims the gimbal.");
+        }
+    }
+
+    // Expected to be called every runloop iteration, with the positions of the right stick's axes if the input source
+    // has them, up and right positive.
+    pub fn update(
+        &mut self,
+        pan_input: Option<f64>,
+        tilt_input: Option<f64>,
+        now: Duration,
+    ) -> Result<(), SetPWMError> {
+        let elapsed = self.last_update.map_or(Duration::ZERO, |last_update| {
+            now.saturating_sub(last_update)
+        });
+        self.last_update = Some(now);
+        let max_step = elapsed.as_secs_f64() / self.settings.full_deflection_time.as_secs_f64();
+
+        for (axis, input) in [(&mut self.pan, pan_input), (&mut self.tilt, tilt_input)] {
+            let input = if self.active {
+                input.unwrap_or(0.0)
+            } else {
+                0.0
+            };
+            axis.position = next_position(
+                axis.position,
+                input,
+                max_step,
+                self.settings.center_on_release,
+            );
+            axis.write(&self.pca9685_driver)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn speed_limited_movement() {
+        // Following the stick, no faster than the step, and back to center once it is let go of.
+        assert_eq!(next_position(0.0, 1.0, 0.1, true), 0.1);
+        assert_eq!(next_position(0.95, 1.0, 0.1, true), 1.0);
+        assert_eq!(next_position(0.5, 0.0, 0.1, true), 0.4);
+        assert_eq!(next_position(0.05, 0.0, 0.1, true), 0.0);
+
+        // Moving while pushed, slower when pushed less, and staying put otherwise.
+        assert_eq!(next_position(0.5, 0.5, 0.1, false), 0.55);
+        assert_eq!(next_position(0.5, 0.0, 0.1, false), 0.5);
+        assert_eq!(next_position(-0.95, -1.0, 0.1, false), -1.0);
+    }
+}
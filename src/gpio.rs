@@ -0,0 +1,496 @@
+use std::error::Error;
+use std::io::Error as IoError;
+use std::os::fd::{AsFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// GPIO lines are requested through the GPIO character device (/dev/gpiochipN), using version 2 of its ABI. Unlike
+// the deprecated sysfs interface, lines are released automatically when the process goes away, and edge events come
+// with kernel timestamps.
+//
+// 💁‍♂️ On a Raspberry Pi, the 40 pin header is on /dev/gpiochip0 (or /dev/gpiochip4 on a Pi 5), and line offsets
+// are the BCM GPIO numbers.
+
+// The name the lines are requested under, as shown by `gpioinfo`.
+const CONSUMER: &str = "roestbak";
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bias {
+    Disabled,
+    PullUp,
+    PullDown,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+// Which edges an input line reports events for.
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Edges {
+    Rising,
+    Falling,
+    Both,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EdgeEvent {
+    pub edge: Edge,
+    // When the edge was detected, on the clock of `runloop::now()`.
+    pub timestamp: Duration,
+}
+
+// A line driven by us. `active_low` inverts the electrical level, so that `true` always means "active".
+pub struct OutputLine {
+    request_fd: OwnedFd,
+    offset: u32,
+}
+
+impl OutputLine {
+    pub fn request(
+        chip_path: &Path,
+        offset: u32,
+        active_low: bool,
+        initial_value: bool,
+    ) -> Result<OutputLine, SetupError> {
+        let mut flags = ffi::LINE_FLAG_OUTPUT;
+        if active_low {
+            flags |= ffi::LINE_FLAG_ACTIVE_LOW;
+        }
+
+        let request_fd = request_line(chip_path, offset, flags, Some(initial_value))?;
+
+        Ok(OutputLine { request_fd, offset })
+    }
+
+    pub fn set_value(&self, value: bool) -> Result<(), WriteError> {
+        ffi::set_value(self.request_fd.as_fd(), value).map_err(|source| {
+            WriteError::CouldNotSetValue {
+                offset: self.offset,
+                source,
+            }
+        })
+    }
+}
+
+// A line read by us, optionally reporting edges.
+#[allow(dead_code)]
+pub struct InputLine {
+    request_fd: OwnedFd,
+    offset: u32,
+}
+
+#[allow(dead_code)]
+impl InputLine {
+    pub fn request(
+        chip_path: &Path,
+        offset: u32,
+        active_low: bool,
+        bias: Bias,
+        edges: Option<Edges>,
+    ) -> Result<InputLine, SetupError> {
+        let mut flags = ffi::LINE_FLAG_INPUT;
+        if active_low {
+            flags |= ffi::LINE_FLAG_ACTIVE_LOW;
+        }
+        flags |= match bias {
+            Bias::Disabled => ffi::LINE_FLAG_BIAS_DISABLED,
+            Bias::PullUp => ffi::LINE_FLAG_BIAS_PULL_UP,
+            Bias::PullDown => ffi::LINE_FLAG_BIAS_PULL_DOWN,
+        };
+        flags |= match edges {
+            None => 0,
+            Some(Edges::Rising) => ffi::LINE_FLAG_EDGE_RISING,
+            Some(Edges::Falling) => ffi::LINE_FLAG_EDGE_FALLING,
+            Some(Edges::Both) => ffi::LINE_FLAG_EDGE_RISING | ffi::LINE_FLAG_EDGE_FALLING,
+        };
+
+        let request_fd = request_line(chip_path, offset, flags, None)?;
+
+        Ok(InputLine { request_fd, offset })
+    }
+
+    pub fn value(&self) -> Result<bool, ReadError> {
+        ffi::get_value(self.request_fd.as_fd()).map_err(|source| ReadError::CouldNotGetValue {
+            offset: self.offset,
+            source,
+        })
+    }
+
+    // Passes any pending edge events to `handler`, without blocking.
+    pub fn read_events(&self, mut handler: impl FnMut(EdgeEvent)) -> Result<(), ReadError> {
+        loop {
+            let event = ffi::read_event(self.request_fd.as_fd()).map_err(|source| {
+                ReadError::CouldNotReadEvents {
+                    offset: self.offset,
+                    source,
+                }
+            })?;
+
+            match event {
+                Some(event) => handler(event),
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+fn request_line(
+    chip_path: &Path,
+    offset: u32,
+    flags: u64,
+    output_value: Option<bool>,
+) -> Result<OwnedFd, SetupError> {
+    let chip_fd = ffi::open_chip(chip_path).map_err(|source| SetupError::CouldNotOpenChip {
+        path: chip_path.to_path_buf(),
+        source,
+    })?;
+
+    // The chip itself is no longer needed once the line has been requested.
+    ffi::request_line(chip_fd.as_fd(), offset, CONSUMER, flags, output_value).map_err(|source| {
+        SetupError::CouldNotRequestLine {
+            path: chip_path.to_path_buf(),
+            offset,
+            source,
+        }
+    })
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    CouldNotOpenChip {
+        path: PathBuf,
+        source: IoError,
+    },
+    CouldNotRequestLine {
+        path: PathBuf,
+        offset: u32,
+        source: IoError,
+    },
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            SetupError::CouldNotOpenChip { path: _, source } => source,
+            SetupError::CouldNotRequestLine {
+                path: _,
+                offset: _,
+                source,
+            } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::CouldNotOpenChip { path, source: _ } => {
+                format!("Could not open GPIO chip at {}.", path.display())
+            }
+            SetupError::CouldNotRequestLine {
+                path,
+                offset,
+                source: _,
+            } => {
+                format!(
+                    "Could not request line {} of GPIO chip at {}.",
+                    offset,
+                    path.display()
+                )
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    CouldNotGetValue { offset: u32, source: IoError },
+    CouldNotReadEvents { offset: u32, source: IoError },
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            ReadError::CouldNotGetValue { offset: _, source } => source,
+            ReadError::CouldNotReadEvents { offset: _, source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            ReadError::CouldNotGetValue { offset, source: _ } => {
+                format!("Could not get the value of GPIO line {}.", offset)
+            }
+            ReadError::CouldNotReadEvents { offset, source: _ } => {
+                format!("Could not read edge events of GPIO line {}.", offset)
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug)]
+pub enum WriteError {
+    CouldNotSetValue { offset: u32, source: IoError },
+}
+
+impl Error for WriteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            WriteError::CouldNotSetValue { offset: _, source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            WriteError::CouldNotSetValue { offset, source: _ } => {
+                format!("Could not set the value of GPIO line {}.", offset)
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+mod ffi {
+    use super::{Edge, EdgeEvent};
+    use std::ffi::CString;
+    use std::io::Error as IoError;
+    use std::mem::{self, MaybeUninit};
+    use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+    use std::os::unix::prelude::OsStrExt;
+    use std::path::Path;
+    use std::time::Duration;
+
+    pub const LINE_FLAG_ACTIVE_LOW: u64 = 1 << 1;
+    pub const LINE_FLAG_INPUT: u64 = 1 << 2;
+    pub const LINE_FLAG_OUTPUT: u64 = 1 << 3;
+    pub const LINE_FLAG_EDGE_RISING: u64 = 1 << 4;
+    pub const LINE_FLAG_EDGE_FALLING: u64 = 1 << 5;
+    pub const LINE_FLAG_BIAS_PULL_UP: u64 = 1 << 8;
+    pub const LINE_FLAG_BIAS_PULL_DOWN: u64 = 1 << 9;
+    pub const LINE_FLAG_BIAS_DISABLED: u64 = 1 << 10;
+
+    const LINE_ATTR_ID_OUTPUT_VALUES: u32 = 2;
+
+    const LINE_EVENT_RISING_EDGE: u32 = 1;
+
+    const GPIO_MAX_NAME_SIZE: usize = 32;
+    const GPIO_V2_LINES_MAX: usize = 64;
+    const GPIO_V2_LINE_NUM_ATTRS_MAX: usize = 10;
+
+    // These match the kernel's `gpio_v2_*` structs from `linux/gpio.h`. The unions in the attributes are represented
+    // by their largest member, a u64.
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct LineAttribute {
+        id: u32,
+        padding: u32,
+        value: u64,
+    }
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct LineConfigAttribute {
+        attribute: LineAttribute,
+        mask: u64,
+    }
+
+    #[repr(C)]
+    struct LineConfig {
+        flags: u64,
+        num_attrs: u32,
+        padding: [u32; 5],
+        attrs: [LineConfigAttribute; GPIO_V2_LINE_NUM_ATTRS_MAX],
+    }
+
+    #[repr(C)]
+    struct LineRequest {
+        offsets: [u32; GPIO_V2_LINES_MAX],
+        consumer: [u8; GPIO_MAX_NAME_SIZE],
+        config: LineConfig,
+        num_lines: u32,
+        event_buffer_size: u32,
+        padding: [u32; 5],
+        fd: i32,
+    }
+
+    #[repr(C)]
+    struct LineValues {
+        bits: u64,
+        mask: u64,
+    }
+
+    #[repr(C)]
+    struct LineEvent {
+        timestamp_ns: u64,
+        id: u32,
+        offset: u32,
+        seqno: u32,
+        line_seqno: u32,
+        padding: [u32; 6],
+    }
+
+    // The sizes are part of the ioctl request codes below, so they had better be right.
+    const _: () = assert!(mem::size_of::<LineRequest>() == 592);
+    const _: () = assert!(mem::size_of::<LineValues>() == 16);
+    const _: () = assert!(mem::size_of::<LineEvent>() == 48);
+
+    // _IOWR(0xB4, 0x07, struct gpio_v2_line_request)
+    const GPIO_V2_GET_LINE_IOCTL: libc::c_ulong = 0xC250B407;
+    // _IOWR(0xB4, 0x0E, struct gpio_v2_line_values)
+    const GPIO_V2_LINE_GET_VALUES_IOCTL: libc::c_ulong = 0xC010B40E;
+    // _IOWR(0xB4, 0x0F, struct gpio_v2_line_values)
+    const GPIO_V2_LINE_SET_VALUES_IOCTL: libc::c_ulong = 0xC010B40F;
+
+    pub fn open_chip(chip_path: &Path) -> Result<OwnedFd, IoError> {
+        let chip_path = CString::new(chip_path.as_os_str().as_bytes()).unwrap();
+
+        let fd = unsafe { libc::open(chip_path.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC) };
+
+        if fd == -1 {
+            Err(IoError::last_os_error())
+        } else {
+            Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+        }
+    }
+
+    pub fn request_line(
+        chip_fd: BorrowedFd<'_>,
+        offset: u32,
+        consumer: &str,
+        flags: u64,
+        output_value: Option<bool>,
+    ) -> Result<OwnedFd, IoError> {
+        let mut request: LineRequest = unsafe { MaybeUninit::zeroed().assume_init() };
+        request.offsets[0] = offset;
+        request.num_lines = 1;
+        request.config.flags = flags;
+
+        let consumer = consumer.as_bytes();
+        let length = consumer.len().min(GPIO_MAX_NAME_SIZE - 1);
+        request.consumer[..length].copy_from_slice(&consumer[..length]);
+
+        // Without this, an output line would briefly be driven low before the first value is set.
+        if let Some(output_value) = output_value {
+            request.config.num_attrs = 1;
+            request.config.attrs[0] = LineConfigAttribute {
+                attribute: LineAttribute {
+                    id: LINE_ATTR_ID_OUTPUT_VALUES,
+                    padding: 0,
+                    value: output_value as u64,
+                },
+                mask: 1,
+            };
+        }
+
+        let result = unsafe {
+            libc::ioctl(
+                chip_fd.as_raw_fd(),
+                GPIO_V2_GET_LINE_IOCTL as _,
+                &mut request,
+            )
+        };
+        if result < 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        let request_fd = unsafe { OwnedFd::from_raw_fd(request.fd) };
+
+        // Reading edge events must never block the runloop.
+        let result =
+            unsafe { libc::fcntl(request_fd.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK) };
+        if result < 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        Ok(request_fd)
+    }
+
+    pub fn get_value(request_fd: BorrowedFd<'_>) -> Result<bool, IoError> {
+        let mut values = LineValues { bits: 0, mask: 1 };
+
+        let result = unsafe {
+            libc::ioctl(
+                request_fd.as_raw_fd(),
+                GPIO_V2_LINE_GET_VALUES_IOCTL as _,
+                &mut values,
+            )
+        };
+
+        if result < 0 {
+            Err(IoError::last_os_error())
+        } else {
+            Ok(values.bits & 1 != 0)
+        }
+    }
+
+    pub fn set_value(request_fd: BorrowedFd<'_>, value: bool) -> Result<(), IoError> {
+        let mut values = LineValues {
+            bits: value as u64,
+            mask: 1,
+        };
+
+        let result = unsafe {
+            libc::ioctl(
+                request_fd.as_raw_fd(),
+                GPIO_V2_LINE_SET_VALUES_IOCTL as _,
+                &mut values,
+            )
+        };
+
+        if result < 0 {
+            Err(IoError::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    // Returns `None` if no event is pending.
+    pub fn read_event(request_fd: BorrowedFd<'_>) -> Result<Option<EdgeEvent>, IoError> {
+        let mut event = MaybeUninit::<LineEvent>::uninit();
+
+        let bytes_read = unsafe {
+            libc::read(
+                request_fd.as_raw_fd(),
+                event.as_mut_ptr() as *mut libc::c_void,
+                mem::size_of::<LineEvent>(),
+            )
+        };
+
+        if bytes_read < 0 {
+            let error = IoError::last_os_error();
+            if error.raw_os_error() == Some(libc::EAGAIN) {
+                return Ok(None);
+            }
+            return Err(error);
+        }
+
+        // The kernel only ever hands out whole events.
+        assert_eq!(bytes_read as usize, mem::size_of::<LineEvent>());
+        let event = unsafe { event.assume_init() };
+
+        // Events are timestamped using CLOCK_MONOTONIC by default, which is what `runloop::now()` uses as well.
+        Ok(Some(EdgeEvent {
+            edge: if event.id == LINE_EVENT_RISING_EDGE {
+                Edge::Rising
+            } else {
+                Edge::Falling
+            },
+            timestamp: Duration::from_nanos(event.timestamp_ns),
+        }))
+    }
+}
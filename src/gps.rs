@@ -0,0 +1,196 @@
+mod nmea;
+
+use crate::runloop;
+use crate::serial::{self, Parity, SerialPort, SerialSettings};
+use nmea::Sentence;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+// A GPS module on a UART, for the speed and position that go into the telemetry and the session summary. Modules send
+// a burst of NMEA sentences (see `nmea.rs`) once a second, usually at 9600 baud.
+//
+// 💁‍♂️ The GPS is only along for the record: nothing about driving depends on it.
+
+// Modules report once a second, so missing a couple of reports in a row means the fix is gone.
+const FIX_TIMEOUT: Duration = Duration::from_millis(2500);
+
+// Longer than any NMEA sentence. A line that grows beyond it is not NMEA, e.g. because of a wrong baud rate.
+const MAXIMUM_LINE_LENGTH: usize = 128;
+
+pub const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+// In degrees, south and west negative.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Position {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Position {
+    // In meters, along the great circle.
+    pub fn distance_to(&self, other: &Position) -> f64 {
+        let (latitude, other_latitude) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let half_chord = ((other_latitude - latitude) / 2.0).sin().powi(2)
+            + latitude.cos()
+                * other_latitude.cos()
+                * ((other.longitude - self.longitude).to_radians() / 2.0)
+                    .sin()
+                    .powi(2);
+
+        2.0 * EARTH_RADIUS_M * half_chord.sqrt().asin()
+    }
+}
+
+// Written as latitude and longitude separated by a space, e.g. `52.0907 5.1214`.
+impl FromStr for Position {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts = text.split_whitespace();
+        let mut parse_next = |range: f64| {
+            parts
+                .next()
+                .and_then(|part| part.parse::<f64>().ok())
+                .filter(|value| (-range..=range).contains(value))
+                .ok_or(())
+        };
+        let position = Position {
+            latitude: parse_next(90.0)?,
+            longitude: parse_next(180.0)?,
+        };
+
+        match parts.next() {
+            Some(_) => Err(()),
+            None => Ok(position),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Fix {
+    pub position: Position,
+    // Over ground, in m/s.
+    pub speed: f64,
+}
+
+pub struct GpsReceiver {
+    serial_port: SerialPort,
+    device_file_path: PathBuf,
+    received: Vec<u8>,
+    fix: Option<Fix>,
+    fix_received_at: Option<Duration>,
+    // Whether the previous read failed, so that only the first failure of a series is logged.
+    failing: bool,
+}
+
+impl GpsReceiver {
+    pub fn new(device_file_path: &Path, baud_rate: u32) -> Result<GpsReceiver, serial::SetupError> {
+        let serial_port = SerialPort::open(
+            device_file_path,
+            SerialSettings {
+                baud_rate,
+                parity: Parity::None,
+                two_stop_bits: false,
+            },
+        )?;
+        log::info!(
+            "Listening for GPS reports on {} at {} baud.",
+            device_file_path.display(),
+            baud_rate
+        );
+
+        Ok(GpsReceiver {
+            serial_port,
+            device_file_path: device_file_path.to_path_buf(),
+            received: Vec::with_capacity(MAXIMUM_LINE_LENGTH * 2),
+            fix: None,
+            fix_received_at: None,
+            failing: false,
+        })
+    }
+
+    // Expected to be called every runloop iteration. Returns a fix if one was received since the previous call.
+    pub fn update(&mut self) -> Option<Fix> {
+        let mut latest_sentence = None;
+        let mut buffer = [0u8; 512];
+
+        loop {
+            match self.serial_port.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes_read) => {
+                    self.failing = false;
+                    for &byte in &buffer[..bytes_read] {
+                        if let Some(sentence) = self.handle_byte(byte) {
+                            latest_sentence = Some(sentence);
+                        }
+                    }
+                }
+                Err(error) => {
+                    if !self.failing {
+                        log::warn!(
+                            "Could not read from GPS module on {}. - Cause: {}",
+                            self.device_file_path.display(),
+                            error
+                        );
+                        self.failing = true;
+                    }
+                    break;
+                }
+            }
+        }
+
+        let now = runloop::now();
+        match latest_sentence {
+            Some(Sentence::Fix(fix)) => {
+                if self.fix.is_none() {
+                    log::info!("GPS fix acquired.");
+                }
+                self.fix = Some(fix);
+                self.fix_received_at = Some(now);
+
+                return Some(fix);
+            }
+            Some(Sentence::NoFix) => self.lose_fix(),
+            None => {
+                if self
+                    .fix_received_at
+                    .is_some_and(|received_at| now - received_at >= FIX_TIMEOUT)
+                {
+                    self.lose_fix();
+                }
+            }
+        }
+
+        None
+    }
+
+    // The current fix, if there is one.
+    pub fn fix(&self) -> Option<Fix> {
+        self.fix
+    }
+
+    // Collects bytes into lines, and returns the sentence a line holds once it is complete.
+    fn handle_byte(&mut self, byte: u8) -> Option<Sentence> {
+        if byte != b'\n' {
+            if self.received.len() < MAXIMUM_LINE_LENGTH {
+                self.received.push(byte);
+            }
+            return None;
+        }
+
+        let sentence = std::str::from_utf8(&self.received)
+            .ok()
+            .and_then(nmea::parse);
+        self.received.clear();
+
+        sentence
+    }
+
+    fn lose_fix(&mut self) {
+        if self.fix.take().is_some() {
+            log::warn!("GPS fix lost.");
+        }
+        self.fix_received_at = None;
+    }
+}
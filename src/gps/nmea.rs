@@ -0,0 +1,96 @@
+use super::{Fix, Position};
+
+// GPS modules report in NMEA 0183 sentences: lines of ASCII such as
+//
+//     $GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A
+//
+// with comma separated fields and a checksum (the XOR of everything between `$` and `*`, in hex). Of the many
+// sentences a module sends every second, RMC ("recommended minimum") has all that is needed here: whether there is a
+// fix, the position and the speed over ground. The first two letters say which satellite system(s) the fix is from
+// (`GP` for GPS, `GN` for a combination), which does not matter here.
+
+const KNOTS_TO_METERS_PER_SECOND: f64 = 1852.0 / 3600.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sentence {
+    Fix(Fix),
+    // The module is running but does not have a fix (yet).
+    NoFix,
+}
+
+// Returns `None` for lines that are not an RMC sentence, or a corrupted one.
+pub fn parse(line: &str) -> Option<Sentence> {
+    let (body, checksum) = line.trim().strip_prefix('$')?.split_once('*')?;
+    if u8::from_str_radix(checksum, 16).ok()?
+        != body.bytes().fold(0, |checksum, byte| checksum ^ byte)
+    {
+        return None;
+    }
+
+    let fields: Vec<&str> = body.split(',').collect();
+    if fields.len() < 8 || fields[0].len() != 5 || !fields[0].ends_with("RMC") {
+        return None;
+    }
+
+    if fields[2] != "A" {
+        return Some(Sentence::NoFix);
+    }
+
+    Some(Sentence::Fix(Fix {
+        position: Position {
+            latitude: parse_coordinate(fields[3], fields[4], 'N', 'S')?,
+            longitude: parse_coordinate(fields[5], fields[6], 'E', 'W')?,
+        },
+        speed: fields[7].parse::<f64>().ok()? * KNOTS_TO_METERS_PER_SECOND,
+    }))
+}
+
+// Coordinates are written as degrees and decimal minutes, e.g. `4807.038` for 48° 7.038'. South and west are negative.
+fn parse_coordinate(value: &str, hemisphere: &str, positive: char, negative: char) -> Option<f64> {
+    let (whole, _) = value.split_once('.').unwrap_or((value, ""));
+    let degree_digits = whole.len().checked_sub(2)?;
+    let degrees: f64 = value.get(..degree_digits)?.parse().ok()?;
+    let minutes: f64 = value.get(degree_digits..)?.parse().ok()?;
+    let coordinate = degrees + minutes / 60.0;
+
+    match hemisphere.chars().next()? {
+        hemisphere if hemisphere == positive => Some(coordinate),
+        hemisphere if hemisphere == negative => Some(-coordinate),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rmc_sentences() {
+        let Some(Sentence::Fix(fix)) =
+            parse("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A\r\n")
+        else {
+            panic!("Expected a fix.");
+        };
+        assert!((fix.position.latitude - 48.1173).abs() < 1e-9);
+        assert!((fix.position.longitude - 11.516_666_666).abs() < 1e-9);
+        assert!((fix.speed - 11.523_555_555).abs() < 1e-6);
+
+        let Some(Sentence::Fix(fix)) =
+            parse("$GNRMC,083559.00,A,3356.4356,S,15112.3457,W,0.004,77.52,091202,,,A*42")
+        else {
+            panic!("Expected a fix.");
+        };
+        assert!(fix.position.latitude < -33.9 && fix.position.longitude < -151.2);
+
+        assert_eq!(parse("$GPRMC,,V,,,,,,,,,,N*53"), Some(Sentence::NoFix));
+        // A wrong checksum, and a sentence of another type.
+        assert_eq!(
+            parse("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6B"),
+            None
+        );
+        assert_eq!(
+            parse("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47"),
+            None
+        );
+    }
+}
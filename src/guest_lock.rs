@@ -0,0 +1,119 @@
+use crate::gamepads::Button;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Makes it safe to hand the controller to a child or a guest: while the lock is engaged, the throttle is capped at
+// `guest_lock.max_speed_percent` and the controller cannot raise it (no boost, no changing the speed limit from the
+// gamepad). The lock is engaged through the control socket (`roestbak ctl guest-lock`) or from the start
+// (`guest_lock.engaged`), and disengaged either through the control socket or by entering the unlock sequence on the
+// controller, e.g. `guest_lock.unlock_sequence = x, x, y, tl`.
+//
+// The sequence works like a PIN: the lock opens once the last buttons pressed are the sequence, and pausing for longer
+// than `ENTRY_TIMEOUT` between two buttons starts over. Buttons that also do something else (e.g. Y starting a
+// choreography) still do that.
+//
+// 💁‍♂️ A sequence that is easy to enter by accident, such as pressing the same button a few times, is no lock at
+// all. Four or more different buttons make a decent one.
+
+const ENTRY_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub struct GuestLock {
+    unlock_sequence: Vec<Button>,
+    max_speed: f64,
+    engaged: bool,
+    // The buttons pressed last, as many as the unlock sequence has, and when the last one was.
+    entered: VecDeque<Button>,
+    last_entry_at: Duration,
+}
+
+impl GuestLock {
+    pub fn new(unlock_sequence: &[Button], max_speed_percent: u8, engaged: bool) -> GuestLock {
+        GuestLock {
+            unlock_sequence: unlock_sequence.to_vec(),
+            max_speed: max_speed_percent as f64 / 100.0,
+            engaged,
+            entered: VecDeque::new(),
+            last_entry_at: Duration::ZERO,
+        }
+    }
+
+    pub fn is_engaged(&self) -> bool {
+        self.engaged
+    }
+
+    pub fn engage(&mut self) {
+        self.engaged = true;
+        self.entered.clear();
+    }
+
+    pub fn disengage(&mut self) {
+        self.engaged = false;
+    }
+
+    // The share of the throttle the lock allows, from 0.0 to 1.0.
+    pub fn throttle_cap(&self) -> f64 {
+        if self.engaged {
+            self.max_speed
+        } else {
+            1.0
+        }
+    }
+
+    // Returns whether the button completed the unlock sequence, which disengages the lock.
+    pub fn button_pressed(&mut self, button: Button, now: Duration) -> bool {
+        if !self.engaged || self.unlock_sequence.is_empty() {
+            return false;
+        }
+
+        if now.saturating_sub(self.last_entry_at) > ENTRY_TIMEOUT {
+            self.entered.clear();
+        }
+        self.last_entry_at = now;
+
+        if self.entered.len() == self.unlock_sequence.len() {
+            self.entered.pop_front();
+        }
+        self.entered.push_back(button);
+
+        if self.entered.iter().eq(&self.unlock_sequence) {
+            self.entered.clear();
+            self.engaged = false;
+            return true;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unlocking_with_the_sequence() {
+        let at = Duration::from_millis;
+        let mut lock = GuestLock::new(&[Button::X, Button::X, Button::Y], 30, true);
+        assert_eq!(lock.throttle_cap(), 0.3);
+
+        // A wrong button spoils the attempt.
+        assert!(!lock.button_pressed(Button::X, at(0)));
+        assert!(!lock.button_pressed(Button::A, at(100)));
+        assert!(!lock.button_pressed(Button::X, at(200)));
+        assert!(!lock.button_pressed(Button::Y, at(300)));
+        assert!(lock.is_engaged());
+
+        // Too long a pause starts over.
+        assert!(!lock.button_pressed(Button::X, at(1_000)));
+        assert!(!lock.button_pressed(Button::X, at(5_000)));
+        assert!(!lock.button_pressed(Button::Y, at(5_100)));
+        assert!(lock.is_engaged());
+
+        // Whatever was pressed before the sequence does not matter.
+        assert!(!lock.button_pressed(Button::X, at(6_000)));
+        assert!(!lock.button_pressed(Button::X, at(6_100)));
+        assert!(!lock.button_pressed(Button::X, at(6_200)));
+        assert!(lock.button_pressed(Button::Y, at(6_300)));
+        assert!(!lock.is_engaged());
+        assert_eq!(lock.throttle_cap(), 1.0);
+    }
+}
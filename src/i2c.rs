@@ -1,69 +1,316 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
 use std::error::Error;
 use std::io::Error as IoError;
-use std::os::fd::{AsFd, OwnedFd};
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
 
+// Cloning gives another handle on the same open device file, with the same slave address. Handles can be used from
+// any thread.
+#[derive(Clone)]
 pub struct I2CDevice {
-    device_fd: OwnedFd,
+    device_fd: Arc<OwnedFd>,
+    bus_lock: Arc<Mutex<()>>,
+}
+
+// The handles given out by `I2CDevice::shared`, by bus and slave address. They stay open until the process exits.
+static SHARED_DEVICES: Lazy<Mutex<HashMap<(PathBuf, i32), I2CDevice>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// The kernel makes every single transfer atomic, but knows nothing of sequences of them that belong together, such as
+// the four registers of a PWM channel. Every transfer through an `I2CDevice` holds the lock of its bus, and sequences
+// hold it throughout, so that those of devices used from different threads do not interleave. Buses are told apart
+// by the path of their device file, as given.
+static BUS_LOCKS: Lazy<Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The SMBus operations needed by device drivers. Abstracting over these allows drivers to be exercised without
+/// actual hardware.
+pub trait I2CTransport {
+    fn write_byte_data(&self, command: u8, value: u8) -> Result<(), WriteError>;
+    fn read_byte_data(&self, command: u8) -> Result<u8, ReadError>;
+
+    /// Reads two bytes in one transfer. SMBus sends the low byte first, which devices that send the high byte first
+    /// (such as most temperature sensors) need swapped.
+    fn read_word_data(&self, command: u8) -> Result<u16, ReadError>;
+
+    /// Writes the registers in order, without transfers to other devices on the bus in between. Stops at the first
+    /// write that fails.
+    fn write_byte_data_sequence(&self, writes: &[(u8, u8)]) -> Result<(), WriteError> {
+        for &(command, value) in writes {
+            self.write_byte_data(command, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn bus_lock(device_file_path: &Path) -> Arc<Mutex<()>> {
+    let mut bus_locks = lock(&BUS_LOCKS);
+
+    Arc::clone(bus_locks.entry(device_file_path.to_path_buf()).or_default())
+}
+
+// A thread panicking while holding the lock leaves nothing half-done that others would trip over, apart from maybe
+// an incomplete sequence on the bus, which the next sequence overwrites.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+// How the slave address is to be used.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct AddressingOptions {
+    // Whether the address is a 10-bit one (up to 0x3FF) rather than a 7-bit one (up to 0x7F).
+    pub ten_bit: bool,
+
+    // The kernel refuses addresses that an in-kernel driver claims (shown as `UU` by `i2cdetect`). Forcing the
+    // address shares the device with that driver instead.
+    // ⚠️ Nothing coordinates the two: only force the address when the kernel driver leaves the registers used here
+    // alone, e.g. a driver that only reads while this only writes.
+    pub force: bool,
 }
 
 impl I2CDevice {
-    pub fn new(device_file_path: &Path, slave_address: i32) -> Result<Self, SetupError> {
+    pub fn new(
+        device_file_path: &Path,
+        slave_address: i32,
+        options: AddressingOptions,
+    ) -> Result<Self, SetupError> {
+        let maximum_address = if options.ten_bit { 0x3FF } else { 0x7F };
+        if !(0..=maximum_address).contains(&slave_address) {
+            return Err(SetupError::InvalidSlaveAddress {
+                address: slave_address,
+                ten_bit: options.ten_bit,
+            });
+        }
+
         let device_fd = ffi::open_i2c_device(device_file_path).map_err(|source| {
             SetupError::CouldNotOpenI2CDevice {
                 path: device_file_path.to_path_buf(),
                 source,
             }
         })?;
-        ffi::set_slave_address(device_fd.as_fd(), slave_address).map_err(|source| {
-            SetupError::CouldNotSetSlaveAddress {
+        // The address is interpreted according to this, so it has to be set first. It is also left unset otherwise, as
+        // not every adapter supports it.
+        if options.ten_bit {
+            ffi::set_ten_bit_addressing(device_fd.as_fd(), true).map_err(|source| {
+                SetupError::CouldNotEnableTenBitAddressing {
+                    path: device_file_path.to_path_buf(),
+                    source,
+                }
+            })?;
+        }
+        ffi::set_slave_address(device_fd.as_fd(), slave_address, options.force).map_err(
+            |source| SetupError::CouldNotSetSlaveAddress {
                 address: slave_address,
                 source,
-            }
-        })?;
+            },
+        )?;
 
-        Ok(Self { device_fd })
+        Ok(Self {
+            device_fd: Arc::new(device_fd),
+            bus_lock: bus_lock(device_file_path),
+        })
     }
 
-    pub fn write_byte_data(&self, command: u8, value: u8) -> Result<(), WriteError> {
-        ffi::i2c_smbus_write_byte_data(self.device_fd.as_fd(), command, value).map_err(|source| {
-            WriteError::CouldNotWriteByteData {
-                command,
-                value,
-                source,
-            }
+    // Like `new`, but every peripheral asking for the same slave address on the same bus gets a handle on the same
+    // open device file, rather than each opening its own. The options only take effect for the first to ask.
+    pub fn shared(
+        device_file_path: &Path,
+        slave_address: i32,
+        options: AddressingOptions,
+    ) -> Result<Self, SetupError> {
+        let mut shared_devices = SHARED_DEVICES
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let key = (device_file_path.to_path_buf(), slave_address);
+        if let Some(device) = shared_devices.get(&key) {
+            return Ok(device.clone());
+        }
+
+        let device = Self::new(device_file_path, slave_address, options)?;
+        shared_devices.insert(key, device.clone());
+
+        Ok(device)
+    }
+
+    // A write without a register, e.g. for commands sent to the general call address.
+    pub fn write_byte(&self, value: u8) -> Result<(), WriteError> {
+        let _bus = lock(&self.bus_lock);
+        ffi::i2c_smbus_write_byte(self.device_fd.as_fd(), value)
+            .map_err(|source| WriteError::CouldNotWriteByte { value, source })
+    }
+
+    // A second handle on the same device, with the same slave address, but its own file descriptor.
+    pub fn try_clone(&self) -> Result<Self, SetupError> {
+        let device_fd = self
+            .device_fd
+            .try_clone()
+            .map_err(|source| SetupError::CouldNotCloneI2CDevice { source })?;
+
+        Ok(Self {
+            device_fd: Arc::new(device_fd),
+            bus_lock: Arc::clone(&self.bus_lock),
         })
     }
 
-    pub fn read_byte_data(&self, command: u8) -> Result<u8, ReadError> {
+    // Bypasses the bus lock, for when the current thread may be holding it, e.g. in a panic hook. Should another
+    // thread be halfway through a sequence, this may land in the middle of it.
+    pub fn write_byte_data_without_lock(&self, command: u8, value: u8) -> Result<(), WriteError> {
+        write_byte_data(self.device_fd.as_fd(), command, value)
+    }
+}
+
+impl I2CTransport for I2CDevice {
+    fn write_byte_data(&self, command: u8, value: u8) -> Result<(), WriteError> {
+        let _bus = lock(&self.bus_lock);
+        write_byte_data(self.device_fd.as_fd(), command, value)
+    }
+
+    fn read_byte_data(&self, command: u8) -> Result<u8, ReadError> {
+        let _bus = lock(&self.bus_lock);
         ffi::i2c_smbus_read_byte_data(self.device_fd.as_fd(), command)
             .map_err(|source| ReadError::CouldNotReadByteData { command, source })
     }
+
+    fn read_word_data(&self, command: u8) -> Result<u16, ReadError> {
+        let _bus = lock(&self.bus_lock);
+        ffi::i2c_smbus_read_word_data(self.device_fd.as_fd(), command)
+            .map_err(|source| ReadError::CouldNotReadWordData { command, source })
+    }
+
+    fn write_byte_data_sequence(&self, writes: &[(u8, u8)]) -> Result<(), WriteError> {
+        let _bus = lock(&self.bus_lock);
+        for &(command, value) in writes {
+            write_byte_data(self.device_fd.as_fd(), command, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_byte_data(device_fd: BorrowedFd<'_>, command: u8, value: u8) -> Result<(), WriteError> {
+    ffi::i2c_smbus_write_byte_data(device_fd, command, value).map_err(|source| {
+        WriteError::CouldNotWriteByteData {
+            command,
+            value,
+            source,
+        }
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ProbeResult {
+    Responded,
+    // A kernel driver claims the address, so it was not probed.
+    Claimed,
+    NoResponse,
+}
+
+// The addresses that are not reserved for special purposes, as probed by `i2cdetect`.
+pub const SCANNABLE_ADDRESSES: std::ops::RangeInclusive<i32> = 0x03..=0x77;
+
+// Finds out which slave addresses on a bus respond, the way `i2cdetect` does: with an SMBus quick write, except for
+// address ranges of EEPROMs, which may take that for a write command, where a byte is read instead.
+pub fn scan_bus(device_file_path: &Path) -> Result<Vec<(i32, ProbeResult)>, SetupError> {
+    let device_fd = ffi::open_i2c_device(device_file_path).map_err(|source| {
+        SetupError::CouldNotOpenI2CDevice {
+            path: device_file_path.to_path_buf(),
+            source,
+        }
+    })?;
+
+    let mut results = Vec::new();
+    for address in SCANNABLE_ADDRESSES {
+        if let Err(source) = ffi::set_slave_address(device_fd.as_fd(), address, false) {
+            if source.raw_os_error() == Some(libc::EBUSY) {
+                results.push((address, ProbeResult::Claimed));
+                continue;
+            }
+            return Err(SetupError::CouldNotSetSlaveAddress { address, source });
+        }
+
+        let response = if (0x30..=0x37).contains(&address) || (0x50..=0x5F).contains(&address) {
+            ffi::i2c_smbus_read_byte(device_fd.as_fd()).map(|_| ())
+        } else {
+            ffi::i2c_smbus_write_quick(device_fd.as_fd())
+        };
+        let result = match response {
+            Ok(()) => ProbeResult::Responded,
+            Err(_) => ProbeResult::NoResponse,
+        };
+        results.push((address, result));
+    }
+
+    Ok(results)
+}
+
+// Whether an I2C error is what a locked-up bus looks like: the controller timing out waiting for the bus, or giving
+// up on the transfer altogether. A slave merely not acknowledging (`EREMOTEIO`) is not.
+fn indicates_stuck_bus(source: &IoError) -> bool {
+    matches!(
+        source.raw_os_error(),
+        Some(libc::ETIMEDOUT) | Some(libc::EIO)
+    )
 }
 
 #[derive(Debug)]
 pub enum SetupError {
+    InvalidSlaveAddress { address: i32, ten_bit: bool },
     CouldNotOpenI2CDevice { path: PathBuf, source: IoError },
+    CouldNotEnableTenBitAddressing { path: PathBuf, source: IoError },
     CouldNotSetSlaveAddress { address: i32, source: IoError },
+    CouldNotCloneI2CDevice { source: IoError },
 }
 
 impl Error for SetupError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(match self {
-            SetupError::CouldNotOpenI2CDevice { path: _, source } => source,
-            SetupError::CouldNotSetSlaveAddress { address: _, source } => source,
-        })
+        match self {
+            SetupError::InvalidSlaveAddress {
+                address: _,
+                ten_bit: _,
+            } => None,
+            SetupError::CouldNotOpenI2CDevice { path: _, source } => Some(source),
+            SetupError::CouldNotEnableTenBitAddressing { path: _, source } => Some(source),
+            SetupError::CouldNotSetSlaveAddress { address: _, source } => Some(source),
+            SetupError::CouldNotCloneI2CDevice { source } => Some(source),
+        }
     }
 }
 
 impl std::fmt::Display for SetupError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let description = match self {
+            SetupError::InvalidSlaveAddress { address, ten_bit } => {
+                format!(
+                    "{:x} is not a valid {} I2C slave address.",
+                    address,
+                    if *ten_bit { "10-bit" } else { "7-bit" }
+                )
+            }
             SetupError::CouldNotOpenI2CDevice { path, source: _ } => {
                 format!("Could not open I2C device at {}.", path.display())
             }
-            SetupError::CouldNotSetSlaveAddress { address, source: _ } => {
-                format!("Could not set I2C slave address {:x}.", address)
+            SetupError::CouldNotEnableTenBitAddressing { path, source: _ } => {
+                format!(
+                    "Could not enable 10-bit addressing on I2C device at {}.",
+                    path.display()
+                )
+            }
+            SetupError::CouldNotSetSlaveAddress { address, source } => {
+                // The kernel refuses addresses claimed by one of its drivers with EBUSY.
+                if source.raw_os_error() == Some(libc::EBUSY) {
+                    format!(
+                        "Could not set I2C slave address {:x}, as a kernel driver claims it.",
+                        address
+                    )
+                } else {
+                    format!("Could not set I2C slave address {:x}.", address)
+                }
+            }
+            SetupError::CouldNotCloneI2CDevice { source: _ } => {
+                "Could not clone I2C device.".to_string()
             }
         };
 
@@ -74,12 +321,23 @@ impl std::fmt::Display for SetupError {
 #[derive(Debug)]
 pub enum ReadError {
     CouldNotReadByteData { command: u8, source: IoError },
+    CouldNotReadWordData { command: u8, source: IoError },
+}
+
+impl ReadError {
+    pub fn indicates_stuck_bus(&self) -> bool {
+        match self {
+            ReadError::CouldNotReadByteData { command: _, source } => indicates_stuck_bus(source),
+            ReadError::CouldNotReadWordData { command: _, source } => indicates_stuck_bus(source),
+        }
+    }
 }
 
 impl Error for ReadError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         Some(match self {
             ReadError::CouldNotReadByteData { command: _, source } => source,
+            ReadError::CouldNotReadWordData { command: _, source } => source,
         })
     }
 }
@@ -90,6 +348,9 @@ impl std::fmt::Display for ReadError {
             ReadError::CouldNotReadByteData { command, source: _ } => {
                 format!("Could not read byte data using command {:x}.", command)
             }
+            ReadError::CouldNotReadWordData { command, source: _ } => {
+                format!("Could not read word data using command {:x}.", command)
+            }
         };
 
         write!(f, "{}", description)
@@ -103,6 +364,23 @@ pub enum WriteError {
         value: u8,
         source: IoError,
     },
+    CouldNotWriteByte {
+        value: u8,
+        source: IoError,
+    },
+}
+
+impl WriteError {
+    pub fn indicates_stuck_bus(&self) -> bool {
+        match self {
+            WriteError::CouldNotWriteByteData {
+                command: _,
+                value: _,
+                source,
+            } => indicates_stuck_bus(source),
+            WriteError::CouldNotWriteByte { value: _, source } => indicates_stuck_bus(source),
+        }
+    }
 }
 
 impl Error for WriteError {
@@ -113,6 +391,7 @@ impl Error for WriteError {
                 value: _,
                 source,
             } => source,
+            WriteError::CouldNotWriteByte { value: _, source } => source,
         })
     }
 }
@@ -127,6 +406,9 @@ impl std::fmt::Display for WriteError {
             } => {
                 format!("Could not write {:x} using command {:x}.", value, command)
             }
+            WriteError::CouldNotWriteByte { value, source: _ } => {
+                format!("Could not write {:x}.", value)
+            }
         };
 
         write!(f, "{}", description)
@@ -152,11 +434,38 @@ mod ffi {
         }
     }
 
-    pub fn set_slave_address(device_fd: BorrowedFd<'_>, address: i32) -> Result<(), IoError> {
+    pub fn set_slave_address(
+        device_fd: BorrowedFd<'_>,
+        address: i32,
+        force: bool,
+    ) -> Result<(), IoError> {
         const I2C_SLAVE_IOCTL_REQUEST: u64 = 0x0703;
+        const I2C_SLAVE_FORCE_IOCTL_REQUEST: u64 = 0x0706;
 
-        let result =
-            unsafe { libc::ioctl(device_fd.as_raw_fd(), I2C_SLAVE_IOCTL_REQUEST, address) };
+        let request = if force {
+            I2C_SLAVE_FORCE_IOCTL_REQUEST
+        } else {
+            I2C_SLAVE_IOCTL_REQUEST
+        };
+        let result = unsafe { libc::ioctl(device_fd.as_raw_fd(), request, address) };
+
+        if result < 0 {
+            Err(IoError::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn set_ten_bit_addressing(device_fd: BorrowedFd<'_>, enabled: bool) -> Result<(), IoError> {
+        const I2C_TENBIT_IOCTL_REQUEST: u64 = 0x0704;
+
+        let result = unsafe {
+            libc::ioctl(
+                device_fd.as_raw_fd(),
+                I2C_TENBIT_IOCTL_REQUEST,
+                libc::c_ulong::from(enabled),
+            )
+        };
 
         if result < 0 {
             Err(IoError::last_os_error())
@@ -205,7 +514,10 @@ mod ffi {
 
     #[repr(u32)]
     enum I2CSMBusDataSize {
+        Quick = 0,
+        Byte = 1,
         ByteData = 2,
+        WordData = 3,
     }
 
     impl I2CSMBusDataSize {
@@ -233,6 +545,42 @@ mod ffi {
         Ok(())
     }
 
+    // 💁‍♂️ A single byte is sent in place of the command, without any data.
+    pub fn i2c_smbus_write_byte(device_fd: BorrowedFd<'_>, value: u8) -> Result<(), IoError> {
+        i2c_smbus_access(
+            device_fd,
+            I2CSMBusReadWrite::Write,
+            value,
+            I2CSMBusDataSize::Byte,
+            std::ptr::null_mut(),
+        )
+    }
+
+    // Only addresses the slave, without sending it any data.
+    pub fn i2c_smbus_write_quick(device_fd: BorrowedFd<'_>) -> Result<(), IoError> {
+        i2c_smbus_access(
+            device_fd,
+            I2CSMBusReadWrite::Write,
+            0,
+            I2CSMBusDataSize::Quick,
+            std::ptr::null_mut(),
+        )
+    }
+
+    pub fn i2c_smbus_read_byte(device_fd: BorrowedFd<'_>) -> Result<u8, IoError> {
+        let mut data = I2CSMBusData::new();
+
+        i2c_smbus_access(
+            device_fd,
+            I2CSMBusReadWrite::Read,
+            0,
+            I2CSMBusDataSize::Byte,
+            &mut data,
+        )?;
+
+        Ok(data.block[0])
+    }
+
     pub fn i2c_smbus_read_byte_data(device_fd: BorrowedFd<'_>, command: u8) -> Result<u8, IoError> {
         let mut data = I2CSMBusData::new();
 
@@ -247,6 +595,23 @@ mod ffi {
         Ok(data.block[0])
     }
 
+    pub fn i2c_smbus_read_word_data(
+        device_fd: BorrowedFd<'_>,
+        command: u8,
+    ) -> Result<u16, IoError> {
+        let mut data = I2CSMBusData::new();
+
+        i2c_smbus_access(
+            device_fd,
+            I2CSMBusReadWrite::Read,
+            command,
+            I2CSMBusDataSize::WordData,
+            &mut data,
+        )?;
+
+        Ok(u16::from_le_bytes([data.block[0], data.block[1]]))
+    }
+
     // This is based on `i2c_smbus_access` in `i2c-tools`.
     fn i2c_smbus_access(
         device_fd: BorrowedFd<'_>,
@@ -279,3 +644,61 @@ mod ffi {
         }
     }
 }
+
+#[cfg(test)]
+pub mod mock {
+    use super::{I2CTransport, ReadError, WriteError};
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    /// An in-memory stand-in for an I2C device. Every write is recorded in order, and reads return the value most
+    /// recently written to a register (or 0 if it was never written). Clones share the recorded writes and registers,
+    /// so that a test can keep one while a driver owns another.
+    #[derive(Clone)]
+    pub struct MockI2CTransport {
+        writes: Rc<RefCell<Vec<(u8, u8)>>>,
+        registers: Rc<RefCell<HashMap<u8, u8>>>,
+    }
+
+    impl MockI2CTransport {
+        pub fn new() -> Self {
+            Self {
+                writes: Rc::new(RefCell::new(Vec::new())),
+                registers: Rc::new(RefCell::new(HashMap::new())),
+            }
+        }
+
+        pub fn writes(&self) -> Vec<(u8, u8)> {
+            self.writes.borrow().clone()
+        }
+
+        pub fn clear_writes(&self) {
+            self.writes.borrow_mut().clear();
+        }
+
+        pub fn register(&self, command: u8) -> u8 {
+            self.registers.borrow().get(&command).copied().unwrap_or(0)
+        }
+    }
+
+    impl I2CTransport for MockI2CTransport {
+        fn write_byte_data(&self, command: u8, value: u8) -> Result<(), WriteError> {
+            self.writes.borrow_mut().push((command, value));
+            self.registers.borrow_mut().insert(command, value);
+            Ok(())
+        }
+
+        fn read_byte_data(&self, command: u8) -> Result<u8, ReadError> {
+            Ok(self.register(command))
+        }
+
+        // The low byte comes from `command`, the high byte from the register after it.
+        fn read_word_data(&self, command: u8) -> Result<u16, ReadError> {
+            Ok(u16::from_le_bytes([
+                self.register(command),
+                self.register(command.wrapping_add(1)),
+            ]))
+        }
+    }
+}
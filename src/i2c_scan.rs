@@ -0,0 +1,53 @@
+use crate::i2c::{self, ProbeResult};
+use std::path::Path;
+
+// Prints which slave addresses respond on a bus, laid out like `i2cdetect` does, to check the wiring before driving.
+// `--` is an address without response, `UU` one that a kernel driver claims.
+
+// What is likely to be found at an address, as far as this service is concerned.
+const KNOWN_DEVICES: &[(i32, &str)] = &[
+    (0x40, "PCA9685"),
+    // Every PCA9685 on the bus also answers to this, while its ALLCALL bit is set.
+    (0x70, "PCA9685 (all call address)"),
+];
+
+// Returns whether any device responded.
+pub fn run(bus: &Path) -> Result<bool, i2c::SetupError> {
+    let results = i2c::scan_bus(bus)?;
+
+    println!("Scanning {}:", bus.display());
+    println!("     0  1  2  3  4  5  6  7  8  9  a  b  c  d  e  f");
+    for row in (0..0x80).step_by(0x10) {
+        let cells: Vec<String> = (row..row + 0x10)
+            .map(
+                |address| match results.iter().find(|(probed, _)| *probed == address) {
+                    Some((_, ProbeResult::Responded)) => format!("{:02x}", address),
+                    Some((_, ProbeResult::Claimed)) => "UU".to_string(),
+                    Some((_, ProbeResult::NoResponse)) => "--".to_string(),
+                    None => "  ".to_string(),
+                },
+            )
+            .collect();
+        println!("{:02x}: {}", row, cells.join(" "));
+    }
+
+    let responding: Vec<i32> = results
+        .iter()
+        .filter(|(_, result)| *result != ProbeResult::NoResponse)
+        .map(|(address, _)| *address)
+        .collect();
+    if responding.is_empty() {
+        println!("No devices found. Check the wiring and power, and that this is the right bus.");
+        return Ok(false);
+    }
+
+    for address in responding {
+        let description = KNOWN_DEVICES
+            .iter()
+            .find(|(known_address, _)| *known_address == address)
+            .map_or("unknown device", |(_, description)| description);
+        println!("0x{:02x}: {}", address, description);
+    }
+
+    Ok(true)
+}
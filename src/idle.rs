@@ -0,0 +1,90 @@
+use crate::clock::{Clock, MonotonicClock};
+use crate::locomotion::LocomotionCommand;
+use std::time::Duration;
+
+// An armed vehicle left standing still keeps drawing current: the steering servo holds its position (often buzzing
+// audibly while doing so) and the ESC stays ready to go. After `timeout` without any input, the vehicle is disarmed
+// and the PCA9685 put to sleep, or only its ESC and servo channels turned off when other outputs share the chip (see
+// `LocomotionController::sleep`). The next input wakes it up and arms it again.
+//
+// ⚠️ The input that wakes the vehicle up is not executed: the ESC first gets to see neutral again after having had no
+// pulses at all, as many ESCs refuse to drive until they have.
+
+pub struct IdleMonitor<C: Clock = MonotonicClock> {
+    clock: C,
+    timeout: Duration,
+    idle_since: Option<Duration>,
+}
+
+impl IdleMonitor {
+    pub fn new(timeout: Duration) -> IdleMonitor {
+        IdleMonitor::with_clock(timeout, MonotonicClock)
+    }
+}
+
+impl<C: Clock> IdleMonitor<C> {
+    pub fn with_clock(timeout: Duration, clock: C) -> IdleMonitor<C> {
+        IdleMonitor {
+            clock,
+            timeout,
+            idle_since: None,
+        }
+    }
+
+    // Returns whether the vehicle has been idle for long enough to be put to sleep.
+    pub fn update(&mut self, idle: bool) -> bool {
+        if !idle {
+            self.idle_since = None;
+            return false;
+        }
+
+        let now = self.clock.now();
+        let idle_since = *self.idle_since.get_or_insert(now);
+
+        if now - idle_since >= self.timeout {
+            self.idle_since = None;
+            return true;
+        }
+
+        false
+    }
+}
+
+// Locks an armed vehicle whose gamepad was not touched for `timeout`, see `inactivity_lock.after_seconds`. A trigger
+// held steady and a cruise throttle do not make the gamepad send anything, so the timeout only runs while the vehicle
+// is not being driven: locking is about keeping a vehicle at rest from setting off, not about stopping one.
+pub struct InactivityLock<C: Clock = MonotonicClock> {
+    monitor: IdleMonitor<C>,
+    // As last reported by the input source, see `InputSource::last_activity`.
+    last_activity: Option<Duration>,
+}
+
+impl InactivityLock {
+    pub fn new(timeout: Duration) -> InactivityLock {
+        InactivityLock::with_clock(timeout, MonotonicClock)
+    }
+}
+
+impl<C: Clock> InactivityLock<C> {
+    pub fn with_clock(timeout: Duration, clock: C) -> InactivityLock<C> {
+        InactivityLock {
+            monitor: IdleMonitor::with_clock(timeout, clock),
+            last_activity: None,
+        }
+    }
+
+    // Returns whether to lock the vehicle now. `lockable` tells whether the vehicle is armed (or would be on the next
+    // input) with nothing but the driver in control of it, and `command` is what is about to be executed.
+    pub fn update(
+        &mut self,
+        lockable: bool,
+        last_activity: Option<Duration>,
+        command: &LocomotionCommand,
+    ) -> bool {
+        let touched = last_activity != self.last_activity;
+        self.last_activity = last_activity;
+
+        self.monitor
+            .update(lockable && !touched && command.is_neutral())
+    }
+}
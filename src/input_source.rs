@@ -0,0 +1,166 @@
+use crate::control_values::{Steering, Throttle};
+use crate::gamepads::{Button, PermissionDiagnostic};
+use crate::locomotion::LocomotionCommand;
+use crate::subsystems::Subsystem;
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
+
+// Noteworthy changes reported while processing input, for the benefit of statistics, notifications and the like.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputNotification {
+    // The source started delivering input, e.g. a gamepad was connected or a radio link was established.
+    Connected,
+    Disconnected,
+    // The source went away while the vehicle was being driven, causing it to be brought to a stop.
+    FailsafeEngaged,
+    // The button for starting or stopping the choreography was pressed.
+    ChoreographyToggled,
+    // The driver asked for the speed limit to be raised or lowered by this many percentage points.
+    SpeedLimitAdjusted(i8),
+    // The button for switching the right stick over to the camera gimbal and back was pressed.
+    GimbalToggled,
+    // Input devices have kept refusing to be opened for lack of permissions, which points at a deployment problem.
+    PermissionDenied(PermissionDiagnostic),
+    // The button for arming again after the inactivity lock disarmed the vehicle was pressed.
+    ArmRequested,
+    // Any button was pressed, whatever it does otherwise, e.g. for entering the guest lock's unlock sequence.
+    ButtonPressed(Button),
+}
+
+/// Something the vehicle can be driven with. Sources are polled once per runloop iteration and must never block.
+pub trait InputSource {
+    fn is_connected(&self) -> bool;
+
+    // Processes any pending input and returns the command the driver currently wants to have executed. A source that
+    // is not connected is expected to return a neutral command.
+    fn process_input(
+        &mut self,
+        notify: &mut dyn FnMut(InputNotification),
+    ) -> Result<LocomotionCommand, Box<dyn Error>>;
+
+    // Drops any input the source holds on to by itself, such as a cruise control setting, so that it does not take
+    // effect again later on (e.g. after re-arming).
+    fn release_latched_input(&mut self) {}
+
+    // Called when the runloop was held up for a long while (e.g. the system was suspended), which the clock need not
+    // reflect. Whatever was received before that is to be considered stale rather than recent, so that a source that
+    // times out its input does so right away if nothing new comes in.
+    fn expire_received_input(&mut self) {}
+
+    // Which device is delivering input, for the status API, e.g. the name and ID of a gamepad.
+    fn device_description(&self) -> Option<String> {
+        None
+    }
+
+    // The position of an axis that does not drive, from -1.0 to 1.0 with up (or right) positive, if the source has
+    // it. See `auxiliary_channel.rs`.
+    fn auxiliary_axis(&self, _axis: AuxiliaryAxis) -> Option<f64> {
+        None
+    }
+
+    // Which way the driver wants the winch to run: 1.0 for pulling in, -1.0 for letting out and 0.0 for neither. See
+    // `winch.rs`.
+    fn winch_direction(&self) -> f64 {
+        0.0
+    }
+
+    // When the driver last touched a control, whether or not that changed the command, on the clock of
+    // `runloop::now()`. `None` for sources that cannot tell, or before the first touch.
+    fn last_activity(&self) -> Option<Duration> {
+        None
+    }
+
+    // The part of the source that receives input over the network, if any, so that it can be turned off and on while
+    // running. See `subsystems.rs`.
+    fn network_input(&mut self) -> Option<&mut dyn Subsystem> {
+        None
+    }
+
+    // Loads the controller mapping file again, for sources that decode controllers by it. See
+    // `gamepads/mapping.rs`.
+    fn reload_controller_mappings(&mut self) {}
+}
+
+// Axes left over by the driving controls, for controlling an auxiliary channel.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AuxiliaryAxis {
+    RightStickHorizontal,
+    RightStickVertical,
+}
+
+impl FromStr for AuxiliaryAxis {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "right_stick_horizontal" => Ok(AuxiliaryAxis::RightStickHorizontal),
+            "right_stick_vertical" => Ok(AuxiliaryAxis::RightStickVertical),
+            _ => Err(()),
+        }
+    }
+}
+
+// Which channels of a radio-style source control the vehicle.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChannelMapping {
+    // 1-based channel numbers, as printed on the radio.
+    pub throttle_channel: u8,
+    pub steering_channel: u8,
+    pub throttle_reversed: bool,
+    pub steering_reversed: bool,
+}
+
+impl ChannelMapping {
+    // Turns the values of the mapped channels, from -1.0 to 1.0, into a command.
+    pub fn to_command(self, channel_value: impl Fn(u8) -> f64) -> LocomotionCommand {
+        let mut throttle = channel_value(self.throttle_channel);
+        let mut steering = channel_value(self.steering_channel);
+        if self.throttle_reversed {
+            throttle = -throttle;
+        }
+        if self.steering_reversed {
+            steering = -steering;
+        }
+
+        LocomotionCommand::new(Throttle::new(throttle), Steering::new(steering))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputSourceKind {
+    Gamepad,
+    Sbus,
+    Mavlink,
+    Keyboard,
+}
+
+impl InputSourceKind {
+    pub const ALL: [InputSourceKind; 4] = [
+        InputSourceKind::Gamepad,
+        InputSourceKind::Sbus,
+        InputSourceKind::Mavlink,
+        InputSourceKind::Keyboard,
+    ];
+
+    // As in `input.source = <name>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            InputSourceKind::Gamepad => "gamepad",
+            InputSourceKind::Sbus => "sbus",
+            InputSourceKind::Mavlink => "mavlink",
+            InputSourceKind::Keyboard => "keyboard",
+        }
+    }
+}
+
+impl FromStr for InputSourceKind {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        InputSourceKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == text)
+            .ok_or(())
+    }
+}
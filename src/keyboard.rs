@@ -0,0 +1,413 @@
+use crate::control_values::{Steering, Throttle};
+use crate::gamepads::{
+    event_timestamp, use_monotonic_event_timestamps, EV_SYN, SYN_DROPPED, SYN_REPORT,
+};
+use crate::input_source::{InputNotification, InputSource};
+use crate::locomotion::LocomotionCommand;
+use crate::runloop;
+use std::error::Error;
+use std::ffi::CString;
+use std::io::Error as IoError;
+use std::mem::{self, MaybeUninit};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::prelude::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Driving with a keyboard, for exercising the locomotion chain on the bench without pairing a controller:
+// - the up and down arrows (or W and S) drive forward and backward,
+// - the left and right arrows (or A and D) steer,
+// - space stops the vehicle at once,
+// - C starts or stops the choreography.
+//
+// Keys are either pressed or not, so the throttle and steering ramp towards where the pressed keys point, and back to
+// neutral once the keys are released, rather than jumping between the extremes.
+//
+// ⚠️ The keyboard is grabbed while it is connected, so that key presses meant for the vehicle do not end up on a
+// console as well.
+
+// How fast the throttle and steering change, per second.
+const THROTTLE_RAMP_RATE: f64 = 1.0;
+const STEERING_RAMP_RATE: f64 = 4.0;
+
+// How long to wait before trying to open the keyboard again after it went away, or could not be opened.
+const REOPEN_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct KeyboardInputSource {
+    device_file_path: PathBuf,
+    device_fd: Option<OwnedFd>,
+    next_open_attempt_at: Duration,
+    keys: PressedKeys,
+    // Events are dropped from a SYN_DROPPED up to the next SYN_REPORT, see `read_events`.
+    recovering_from_dropped: bool,
+    throttle: f64,
+    steering: f64,
+    updated_at: Duration,
+}
+
+#[derive(Default)]
+struct PressedKeys {
+    forward: bool,
+    backward: bool,
+    left: bool,
+    right: bool,
+}
+
+impl PressedKeys {
+    fn throttle_target(&self) -> f64 {
+        axis_target(self.forward, self.backward)
+    }
+
+    // Steering to the right is positive, as with a gamepad stick pushed to the right.
+    fn steering_target(&self) -> f64 {
+        axis_target(self.right, self.left)
+    }
+}
+
+fn axis_target(positive: bool, negative: bool) -> f64 {
+    match (positive, negative) {
+        (true, false) => 1.0,
+        (false, true) => -1.0,
+        _ => 0.0,
+    }
+}
+
+impl KeyboardInputSource {
+    pub fn new(device_file_path: &Path) -> KeyboardInputSource {
+        log::info!(
+            "Driving with the keyboard {} (arrow keys or WASD, space to stop).",
+            device_file_path.display()
+        );
+
+        KeyboardInputSource {
+            device_file_path: device_file_path.to_path_buf(),
+            device_fd: None,
+            next_open_attempt_at: Duration::ZERO,
+            keys: PressedKeys::default(),
+            recovering_from_dropped: false,
+            throttle: 0.0,
+            steering: 0.0,
+            updated_at: runloop::now(),
+        }
+    }
+
+    fn open(&mut self, now: Duration, notify: &mut dyn FnMut(InputNotification)) {
+        if now < self.next_open_attempt_at {
+            return;
+        }
+        self.next_open_attempt_at = now + REOPEN_INTERVAL;
+
+        match open_keyboard_device(&self.device_file_path) {
+            Ok(device_fd) => {
+                log::info!("Keyboard {} connected.", self.device_file_path.display());
+                self.device_fd = Some(device_fd);
+                self.recovering_from_dropped = false;
+                notify(InputNotification::Connected);
+            }
+            Err(error) => {
+                log::debug!(
+                    "Could not open keyboard {}. - Cause: {}",
+                    self.device_file_path.display(),
+                    error
+                );
+            }
+        }
+    }
+
+    fn disconnect(&mut self, now: Duration, notify: &mut dyn FnMut(InputNotification)) {
+        log::warn!("Keyboard {} disconnected.", self.device_file_path.display());
+        self.device_fd = None;
+        self.next_open_attempt_at = now + REOPEN_INTERVAL;
+        notify(InputNotification::Disconnected);
+
+        if self.throttle != 0.0 || self.steering != 0.0 {
+            notify(InputNotification::FailsafeEngaged);
+        }
+
+        self.keys = PressedKeys::default();
+        self.throttle = 0.0;
+        self.steering = 0.0;
+    }
+
+    // Returns the time of the most recent key event affecting locomotion.
+    //
+    // When the kernel's event buffer overflows, it drops what was in it and queues a SYN_DROPPED. A key released in
+    // the events that were dropped would otherwise stay pressed, and keep driving, until pressed again. So the events
+    // up to the next SYN_REPORT are dropped as well, and which keys are pressed is asked of the device again.
+    fn read_events(
+        &mut self,
+        device_fd: &OwnedFd,
+        notify: &mut dyn FnMut(InputNotification),
+    ) -> Result<Option<Duration>, IoError> {
+        const NUMBER_OF_EVENTS_IN_BUFFER: usize = 64;
+        const INPUT_EVENT_SIZE: usize = mem::size_of::<libc::input_event>();
+
+        let mut input_timestamp = None;
+
+        loop {
+            let mut buffer =
+                [MaybeUninit::<libc::input_event>::uninit(); NUMBER_OF_EVENTS_IN_BUFFER];
+
+            let bytes_read = unsafe {
+                libc::read(
+                    device_fd.as_raw_fd(),
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    NUMBER_OF_EVENTS_IN_BUFFER * INPUT_EVENT_SIZE,
+                )
+            };
+
+            if bytes_read < 0 {
+                let error = IoError::last_os_error();
+                if error.raw_os_error() == Some(libc::EAGAIN) {
+                    return Ok(input_timestamp);
+                }
+                return Err(error);
+            }
+
+            let events_read = bytes_read as usize / INPUT_EVENT_SIZE;
+            if events_read == 0 {
+                return Ok(input_timestamp);
+            }
+
+            for event in &buffer[0..events_read] {
+                let event = unsafe { event.assume_init() };
+
+                if event.type_ == EV_SYN && event.code == SYN_DROPPED {
+                    log::error!("Keyboard event buffer overflow. Events may have been dropped.");
+                    self.recovering_from_dropped = true;
+                    continue;
+                }
+                if self.recovering_from_dropped {
+                    if event.type_ == EV_SYN && event.code == SYN_REPORT {
+                        self.recovering_from_dropped = false;
+                        self.keys = query_pressed_keys(device_fd).unwrap_or_else(|error| {
+                            log::warn!(
+                                "Could not find out which keys are pressed, so they are taken to be released. - Cause: {}",
+                                error
+                            );
+                            PressedKeys::default()
+                        });
+                        input_timestamp = Some(event_timestamp(&event));
+                    }
+                    continue;
+                }
+
+                // Key repeats (a value of 2) are of no interest.
+                if event.type_ != EV_KEY || !(event.value == 0 || event.value == 1) {
+                    continue;
+                }
+                let pressed = event.value == 1;
+
+                let key = match event.code {
+                    KEY_UP | KEY_W => &mut self.keys.forward,
+                    KEY_DOWN | KEY_S => &mut self.keys.backward,
+                    KEY_LEFT | KEY_A => &mut self.keys.left,
+                    KEY_RIGHT | KEY_D => &mut self.keys.right,
+                    KEY_SPACE => {
+                        if pressed {
+                            self.keys = PressedKeys::default();
+                            self.throttle = 0.0;
+                            self.steering = 0.0;
+                            input_timestamp = Some(event_timestamp(&event));
+                        }
+                        continue;
+                    }
+                    KEY_C => {
+                        if pressed {
+                            notify(InputNotification::ChoreographyToggled);
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                };
+
+                *key = pressed;
+                input_timestamp = Some(event_timestamp(&event));
+            }
+        }
+    }
+}
+
+impl InputSource for KeyboardInputSource {
+    fn is_connected(&self) -> bool {
+        self.device_fd.is_some()
+    }
+
+    fn process_input(
+        &mut self,
+        notify: &mut dyn FnMut(InputNotification),
+    ) -> Result<LocomotionCommand, Box<dyn Error>> {
+        let now = runloop::now();
+        let elapsed = (now - self.updated_at).as_secs_f64();
+        self.updated_at = now;
+
+        if self.device_fd.is_none() {
+            self.open(now, notify);
+        }
+
+        let mut input_timestamp = None;
+        if let Some(device_fd) = self.device_fd.take() {
+            match self.read_events(&device_fd, notify) {
+                Ok(timestamp) => {
+                    input_timestamp = timestamp;
+                    self.device_fd = Some(device_fd);
+                }
+                Err(error) if error.raw_os_error() == Some(libc::ENODEV) => {
+                    self.disconnect(now, notify);
+                }
+                Err(error) => {
+                    self.device_fd = Some(device_fd);
+                    return Err(error.into());
+                }
+            }
+        }
+
+        self.throttle = ramp(
+            self.throttle,
+            self.keys.throttle_target(),
+            THROTTLE_RAMP_RATE * elapsed,
+        );
+        self.steering = ramp(
+            self.steering,
+            self.keys.steering_target(),
+            STEERING_RAMP_RATE * elapsed,
+        );
+
+        Ok(
+            LocomotionCommand::new(Throttle::new(self.throttle), Steering::new(self.steering))
+                .with_input_timestamp(input_timestamp),
+        )
+    }
+}
+
+fn ramp(value: f64, target: f64, maximum_step: f64) -> f64 {
+    value + (target - value).clamp(-maximum_step, maximum_step)
+}
+
+// Event types of interest.
+const EV_KEY: libc::__u16 = 0x01;
+
+// EV_KEY event codes of interest.
+const KEY_W: libc::__u16 = 17;
+const KEY_A: libc::__u16 = 30;
+const KEY_S: libc::__u16 = 31;
+const KEY_D: libc::__u16 = 32;
+const KEY_C: libc::__u16 = 46;
+const KEY_SPACE: libc::__u16 = 57;
+const KEY_UP: libc::__u16 = 103;
+const KEY_LEFT: libc::__u16 = 105;
+const KEY_RIGHT: libc::__u16 = 106;
+const KEY_DOWN: libc::__u16 = 108;
+
+// The key state bitmap covers every code up to KEY_MAX.
+const KEY_MAX: usize = 0x2ff;
+
+fn query_pressed_keys(device_fd: &OwnedFd) -> Result<PressedKeys, IoError> {
+    const KEY_STATE_SIZE: usize = KEY_MAX / 8 + 1;
+    // _IOC(_IOC_READ, 'E', 0x18, KEY_STATE_SIZE)
+    const EVIOCGKEY: libc::c_ulong = 0x80004518 | (KEY_STATE_SIZE as libc::c_ulong) << 16;
+
+    let mut key_state = [0u8; KEY_STATE_SIZE];
+    let result = unsafe {
+        libc::ioctl(
+            device_fd.as_raw_fd(),
+            EVIOCGKEY as _,
+            key_state.as_mut_ptr(),
+        )
+    };
+    if result < 0 {
+        return Err(IoError::last_os_error());
+    }
+
+    let pressed = |code: libc::__u16| key_state[code as usize / 8] & (1 << (code % 8)) != 0;
+    Ok(PressedKeys {
+        forward: pressed(KEY_UP) || pressed(KEY_W),
+        backward: pressed(KEY_DOWN) || pressed(KEY_S),
+        left: pressed(KEY_LEFT) || pressed(KEY_A),
+        right: pressed(KEY_RIGHT) || pressed(KEY_D),
+    })
+}
+
+fn open_keyboard_device(device_file_path: &Path) -> Result<OwnedFd, IoError> {
+    // _IOW('E', 0x90, int)
+    const EVIOCGRAB: libc::c_ulong = 0x40044590;
+
+    let device_file_path = CString::new(device_file_path.as_os_str().as_bytes()).unwrap();
+
+    let fd = unsafe {
+        libc::open(
+            device_file_path.as_ptr(),
+            libc::O_RDONLY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+        )
+    };
+    if fd == -1 {
+        return Err(IoError::last_os_error());
+    }
+    let device_fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // Event timestamps are compared with `runloop::now()` for latency measurements.
+    use_monotonic_event_timestamps(&device_fd)?;
+
+    let grab: libc::c_int = 1;
+    let result = unsafe { libc::ioctl(device_fd.as_raw_fd(), EVIOCGRAB as _, grab) };
+    if result == -1 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(device_fd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_bytes(type_: libc::__u16, code: libc::__u16, value: i32) -> Vec<u8> {
+        let event = libc::input_event {
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        };
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const libc::input_event as *const u8,
+                mem::size_of::<libc::input_event>(),
+            )
+        };
+
+        bytes.to_vec()
+    }
+
+    #[test]
+    fn dropped_events_do_not_leave_keys_pressed() {
+        let mut fds = [0; 2];
+        let result = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+        assert_eq!(result, 0);
+        let (read_end, write_end) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        // W is released in the events that were dropped, and pressed again in those up to the next SYN_REPORT, which
+        // are dropped as well. A pipe cannot be asked which keys are pressed, so they are all taken to be released.
+        let mut events = event_bytes(EV_KEY, KEY_W, 1);
+        events.extend(event_bytes(EV_SYN, SYN_REPORT, 0));
+        events.extend(event_bytes(EV_SYN, SYN_DROPPED, 0));
+        events.extend(event_bytes(EV_KEY, KEY_W, 1));
+        events.extend(event_bytes(EV_SYN, SYN_REPORT, 0));
+        let written = unsafe {
+            libc::write(
+                write_end.as_raw_fd(),
+                events.as_ptr() as *const libc::c_void,
+                events.len(),
+            )
+        };
+        assert_eq!(written, events.len() as isize);
+
+        let mut keyboard = KeyboardInputSource::new(Path::new("/dev/input/event0"));
+        keyboard.read_events(&read_end, &mut |_| ()).unwrap();
+        assert!(!keyboard.keys.forward);
+        assert!(!keyboard.recovering_from_dropped);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::application_state::ApplicationState;
+use crate::event_bus::{Event, EventSubscriber};
+use crate::gpio::{self, OutputLine};
+use crate::runloop;
+use std::path::Path;
+use std::time::Duration;
+
+// A relay (or MOSFET) on a GPIO line that switches the ESC's power, so that drive power is physically removed
+// whenever the service is not in a position to control the vehicle. The relay is only closed while:
+// - the vehicle is armed. The relay closes once the vehicle is armed and input is connected, and stays closed through
+//   the failsafe, so that the brake ramp can still bring the vehicle to a stop. It opens when the vehicle is disarmed
+//   or the service stops because of a fault, and only closes again after the vehicle was armed anew, never just
+//   because input came back,
+// - the runloop keeps to its schedule. An iteration that starts more than `max_gap` after the previous one opens
+//   the relay until the vehicle is next disarmed and armed again, as whatever held up the runloop might do so again.
+//
+// ⚠️ A line keeps its level when the process hangs, so a steady level only removes power when the service notices
+// that something is wrong. With `heartbeat`, the line is instead toggled on every iteration while healthy, for
+// driving the relay through a charge pump or retriggerable monostable that drops out as soon as the toggling stops.
+
+pub struct KillRelaySettings {
+    pub max_gap: Duration,
+    pub heartbeat: bool,
+}
+
+pub struct KillRelay {
+    line: OutputLine,
+    settings: KillRelaySettings,
+    armed: bool,
+    // Whether the vehicle was armed since the relay last opened, as only that allows closing it again. The service
+    // starts out armed.
+    rearmed: bool,
+    schedule_fault: bool,
+    last_update: Option<Duration>,
+    level: bool,
+}
+
+impl KillRelay {
+    // The relay starts out open.
+    pub fn new(
+        chip_path: &Path,
+        offset: u32,
+        active_low: bool,
+        settings: KillRelaySettings,
+    ) -> Result<KillRelay, gpio::SetupError> {
+        let line = OutputLine::request(chip_path, offset, active_low, false)?;
+        log::info!(
+            "Kill relay on line {} of {}{}.",
+            offset,
+            chip_path.display(),
+            if settings.heartbeat {
+                ", driven by a heartbeat"
+            } else {
+                ""
+            }
+        );
+
+        Ok(KillRelay {
+            line,
+            settings,
+            armed: false,
+            rearmed: true,
+            schedule_fault: false,
+            last_update: None,
+            level: false,
+        })
+    }
+
+    // Expected to be called every runloop iteration.
+    pub fn update(&mut self) -> Result<(), gpio::WriteError> {
+        let now = runloop::now();
+        if let Some(last_update) = self.last_update {
+            let gap = now - last_update;
+            if gap > self.settings.max_gap && self.armed && !self.schedule_fault {
+                log::error!(
+                    "Opening kill relay, as the runloop was held up for {:?}. Disarm and arm again to close it.",
+                    gap
+                );
+                self.schedule_fault = true;
+            }
+        }
+        self.last_update = Some(now);
+
+        let healthy = self.armed && !self.schedule_fault;
+        let level = match (healthy, self.settings.heartbeat) {
+            (false, _) => false,
+            (true, false) => true,
+            (true, true) => !self.level,
+        };
+
+        if level != self.level {
+            self.line.set_value(level)?;
+            self.level = level;
+        }
+
+        Ok(())
+    }
+
+    // Opens the relay for good, e.g. when the service stops.
+    pub fn open(&mut self) -> Result<(), gpio::WriteError> {
+        self.armed = false;
+        self.line.set_value(false)?;
+        self.level = false;
+
+        Ok(())
+    }
+}
+
+impl EventSubscriber for KillRelay {
+    fn handle_event(&mut self, event: Event) {
+        match event {
+            Event::StateChanged { state, .. } => match state {
+                ApplicationState::Armed | ApplicationState::Driving if self.rearmed => {
+                    self.armed = true
+                }
+                ApplicationState::Fault => {
+                    self.armed = false;
+                    self.rearmed = false;
+                }
+                _ => (),
+            },
+            Event::Armed => self.rearmed = true,
+            Event::Disarmed => {
+                self.armed = false;
+                self.rearmed = false;
+                if self.schedule_fault {
+                    log::info!("Kill relay will close again once armed.");
+                    self.schedule_fault = false;
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use crate::application_state::ApplicationState;
+use crate::control_values::{Steering, Throttle};
+use crate::event_bus::{Event, EventSubscriber};
+use crate::locomotion::{PCA9685Driver, SetPWMError};
+use crate::subsystems::Subsystem;
+use std::error::Error;
+use std::rc::Rc;
+use std::time::Duration;
+
+// Brake lights and turn signals on spare PCA9685 channels, like a full-size vehicle has, worked out from what the
+// vehicle is executing rather than switched by the driver:
+// - the brake lights come on while the throttle is coming down (or reversing), and stay on for `BRAKE_HOLD` after,
+//   so that a short tap is still visible,
+// - a turn signal blinks once the steering has been held to its side beyond the threshold for the delay, so that
+//   the small corrections of driving straight do not set it off, and stops once the steering comes back,
+// - both turn signals blink as hazard flashers while the failsafe is engaged, or the service ran into an error.
+//
+// Each light is optional; whichever channels are configured are driven. Lighting can be turned off while running, see
+// `subsystems.rs`, which switches all lights off until it is turned on again.
+
+// The rate, in full throttle per second, at which the throttle has to come down to count as braking.
+const BRAKING_RATE: f64 = 1.0;
+const BRAKE_HOLD: Duration = Duration::from_millis(500);
+// About 90 flashes a minute, as turn signals usually go.
+const BLINK_HALF_PERIOD: Duration = Duration::from_millis(333);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LightingSettings {
+    pub brake_channel: Option<u8>,
+    pub left_turn_channel: Option<u8>,
+    pub right_turn_channel: Option<u8>,
+    // How far the steering has to be turned, from 0.0 to 1.0, and for how long, to set off a turn signal.
+    pub turn_signal_threshold: f64,
+    pub turn_signal_delay: Duration,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+struct LitLights {
+    brake: bool,
+    left_turn: bool,
+    right_turn: bool,
+}
+
+// Which lights are lit, apart from the outputs so that it can be tested without them.
+struct Lights {
+    settings: LightingSettings,
+    hazards: bool,
+    last_throttle: Throttle,
+    last_update: Option<Duration>,
+    braking_until: Option<Duration>,
+    // The side the steering is turned to beyond the threshold (-1.0 for left, 1.0 for right) and since when.
+    turning: Option<(f64, Duration)>,
+}
+
+impl Lights {
+    fn new(settings: LightingSettings) -> Lights {
+        Lights {
+            settings,
+            hazards: false,
+            last_throttle: Throttle::NEUTRAL,
+            last_update: None,
+            braking_until: None,
+            turning: None,
+        }
+    }
+
+    fn advance(&mut self, throttle: Throttle, steering: Steering, now: Duration) -> LitLights {
+        let elapsed = self.last_update.map_or(Duration::ZERO, |last_update| {
+            now.saturating_sub(last_update)
+        });
+        self.last_update = Some(now);
+
+        // Coming down from the throttle, or going from one direction to the other, is braking.
+        let previous = self.last_throttle.value();
+        let current = throttle.value();
+        let reversing = previous * current < 0.0;
+        let slowing_down = !elapsed.is_zero()
+            && (previous.abs() - current.abs()) / elapsed.as_secs_f64() >= BRAKING_RATE;
+        if reversing || slowing_down {
+            self.braking_until = Some(now + BRAKE_HOLD);
+        }
+        self.last_throttle = throttle;
+        let brake = self.braking_until.is_some_and(|until| now < until);
+
+        let side = steering.value();
+        self.turning = if side.abs() < self.settings.turn_signal_threshold {
+            None
+        } else {
+            match self.turning {
+                Some((turning_side, since)) if turning_side == side.signum() => {
+                    Some((turning_side, since))
+                }
+                _ => Some((side.signum(), now)),
+            }
+        };
+        let signalled_side = self
+            .turning
+            .filter(|(_, since)| now.saturating_sub(*since) >= self.settings.turn_signal_delay)
+            .map(|(side, _)| side);
+
+        let blink_on = (now.as_millis() / BLINK_HALF_PERIOD.as_millis()).is_multiple_of(2);
+        let (left_turn, right_turn) = if self.hazards {
+            (blink_on, blink_on)
+        } else {
+            match signalled_side {
+                Some(side) if side < 0.0 => (blink_on, false),
+                Some(_) => (false, blink_on),
+                None => (false, false),
+            }
+        };
+
+        LitLights {
+            brake,
+            left_turn,
+            right_turn,
+        }
+    }
+}
+
+pub struct Lighting {
+    pca9685_driver: Rc<PCA9685Driver>,
+    lights: Lights,
+    // `None` while it is not known what the outputs were last set to.
+    lit: Option<LitLights>,
+    running: bool,
+}
+
+impl Lighting {
+    pub fn new(pca9685_driver: Rc<PCA9685Driver>, settings: LightingSettings) -> Lighting {
+        Lighting {
+            pca9685_driver,
+            lights: Lights::new(settings),
+            lit: None,
+            running: true,
+        }
+    }
+
+    // Expected to be called every runloop iteration, with the throttle and steering being executed.
+    pub fn update(
+        &mut self,
+        throttle: Throttle,
+        steering: Steering,
+        now: Duration,
+    ) -> Result<(), SetPWMError> {
+        let lit = self.lights.advance(throttle, steering, now);
+        if !self.running {
+            return Ok(());
+        }
+        let previous = self.lit.take();
+        let settings = self.lights.settings;
+
+        for (channel, is_lit, was_lit) in [
+            (
+                settings.brake_channel,
+                lit.brake,
+                previous.map(|previous| previous.brake),
+            ),
+            (
+                settings.left_turn_channel,
+                lit.left_turn,
+                previous.map(|previous| previous.left_turn),
+            ),
+            (
+                settings.right_turn_channel,
+                lit.right_turn,
+                previous.map(|previous| previous.right_turn),
+            ),
+        ] {
+            let Some(channel) = channel else {
+                continue;
+            };
+            if was_lit == Some(is_lit) {
+                continue;
+            }
+            if is_lit {
+                self.pca9685_driver.set_full_on(channel)?;
+            } else {
+                self.pca9685_driver.set_full_off(channel)?;
+            }
+        }
+        self.lit = Some(lit);
+
+        Ok(())
+    }
+}
+
+impl Subsystem for Lighting {
+    fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // The lights are kept track of while stopped, so whatever should be lit comes on with the next update.
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        self.lit = None;
+        self.running = true;
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        let settings = self.lights.settings;
+        for channel in [
+            settings.brake_channel,
+            settings.left_turn_channel,
+            settings.right_turn_channel,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            self.pca9685_driver.set_full_off(channel)?;
+        }
+        self.lit = None;
+        self.running = false;
+
+        Ok(())
+    }
+}
+
+impl EventSubscriber for Lighting {
+    fn handle_event(&mut self, event: Event) {
+        if let Event::StateChanged { state, .. } = event {
+            self.lights.hazards =
+                matches!(state, ApplicationState::Failsafe | ApplicationState::Fault);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn brake_lights_and_turn_signals() {
+        let mut lights = Lights::new(LightingSettings {
+            brake_channel: Some(2),
+            left_turn_channel: Some(3),
+            right_turn_channel: Some(4),
+            turn_signal_threshold: 0.5,
+            turn_signal_delay: Duration::from_millis(400),
+        });
+        let at = Duration::from_millis;
+
+        // Speeding up is not braking, but letting go of the throttle quickly is, for a while.
+        assert!(
+            !lights
+                .advance(Throttle::new(0.8), Steering::CENTER, at(0))
+                .brake
+        );
+        assert!(
+            !lights
+                .advance(Throttle::new(1.0), Steering::CENTER, at(100))
+                .brake
+        );
+        assert!(
+            lights
+                .advance(Throttle::NEUTRAL, Steering::CENTER, at(200))
+                .brake
+        );
+        assert!(
+            lights
+                .advance(Throttle::NEUTRAL, Steering::CENTER, at(600))
+                .brake
+        );
+        assert!(
+            !lights
+                .advance(Throttle::NEUTRAL, Steering::CENTER, at(800))
+                .brake
+        );
+
+        // A turn signal takes the steering being held, and blinks on that side only.
+        let left = Steering::new(-0.8);
+        assert_eq!(
+            lights.advance(Throttle::NEUTRAL, left, at(1000)),
+            LitLights::default()
+        );
+        let lit = lights.advance(Throttle::NEUTRAL, left, at(1400));
+        assert!(lit.left_turn && !lit.right_turn);
+        assert!(!lights.advance(Throttle::NEUTRAL, left, at(1700)).left_turn);
+        assert!(
+            !lights
+                .advance(Throttle::NEUTRAL, Steering::CENTER, at(2000))
+                .left_turn
+        );
+
+        // Hazards blink both, whatever the steering.
+        lights.hazards = true;
+        let lit = lights.advance(Throttle::NEUTRAL, Steering::CENTER, at(2000));
+        assert!(lit.left_turn && lit.right_turn);
+    }
+}
@@ -1,4 +1,22 @@
+mod arming;
+mod bus_recovery;
+mod channels;
 mod controller;
+mod latency;
 mod pca9685;
+mod pulse_widths;
+mod pwm_table;
 
-pub use controller::{LocomotionCommand, LocomotionController};
+pub use arming::ArmingStep;
+pub use bus_recovery::BusRecovery;
+pub use channels::{Output, OutputChannels};
+pub use controller::{
+    locomotion_value_to_pwm_on_percentage, ExecuteCommandError, LocomotionCommand,
+    LocomotionController, DEFAULT_WRITE_LATENCY_THRESHOLD, I2C_DEVICE_FILE, PWM_FREQUENCY,
+};
+pub use latency::LatencyPercentiles;
+pub use pca9685::{
+    duty_cycle_count, emergency_stop, is_supported_pwm_frequency, software_reset, PCA9685Driver,
+    PCA9685Settings, SetPWMError,
+};
+pub use pulse_widths::{PulseWidths, MAXIMUM_PULSE_WIDTH_US, MINIMUM_PULSE_WIDTH_US};
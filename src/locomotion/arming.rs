@@ -0,0 +1,46 @@
+use super::pulse_widths::{MAXIMUM_PULSE_WIDTH_US, MINIMUM_PULSE_WIDTH_US};
+use std::str::FromStr;
+use std::time::Duration;
+
+// ESCs only start driving once they have seen a signal they recognize as the transmitter being on. Most are happy
+// with neutral, but others want e.g. minimum throttle for a couple of seconds first. The arming sequence is what the
+// ESC is sent when the locomotion controller is created, one step after the other, before neutral. Without any steps,
+// the ESC gets neutral right away.
+
+// Anything an ESC could want to see takes a few seconds at most. A longer step is more likely a typo.
+const MAXIMUM_STEP_DURATION: Duration = Duration::from_secs(10);
+
+// A pulse width to send for a while, written as `<pulse width in μs>:<duration in ms>`, e.g. `1000:2000`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ArmingStep {
+    pub pulse_width_us: u32,
+    pub duration: Duration,
+}
+
+impl FromStr for ArmingStep {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (pulse_width_us, duration_ms) = text.split_once(':').ok_or(())?;
+        let pulse_width_us = pulse_width_us
+            .trim()
+            .parse()
+            .ok()
+            .filter(|pulse_width_us| {
+                (MINIMUM_PULSE_WIDTH_US..=MAXIMUM_PULSE_WIDTH_US).contains(pulse_width_us)
+            })
+            .ok_or(())?;
+        let duration = duration_ms
+            .trim()
+            .parse()
+            .map(Duration::from_millis)
+            .ok()
+            .filter(|duration| *duration <= MAXIMUM_STEP_DURATION)
+            .ok_or(())?;
+
+        Ok(ArmingStep {
+            pulse_width_us,
+            duration,
+        })
+    }
+}
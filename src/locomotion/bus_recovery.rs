@@ -0,0 +1,71 @@
+use super::controller::{ExecuteCommandError, LocomotionController};
+
+// Rather than giving up on the first I2C error, errors that look like a locked-up bus are tolerated for a few
+// commands, after which the PCA9685 is reset and set up again. Meanwhile, the outputs keep whatever they were last set
+// to, and every following command is an attempt to get through again.
+//
+// ⚠️ Freeing the bus itself is left to the kernel: I2C adapter drivers that support bus recovery clock SCL when a
+// transfer times out, provided the device tree gives them the pins to do so (`scl-gpios` and a `gpio` pinctrl state).
+// The pins can only be switched away from their I2C function and back again from there. Without that, a slave
+// holding SDA low keeps the bus locked up, and the service gives up after `MAX_RECOVERIES`.
+
+// After this many recoveries without a command getting through in between, the bus is given up on.
+const MAX_RECOVERIES: u32 = 3;
+
+pub struct BusRecovery {
+    errors_before_recovery: u32,
+    consecutive_errors: u32,
+    recoveries: u32,
+}
+
+impl BusRecovery {
+    pub fn new(errors_before_recovery: u32) -> Self {
+        Self {
+            errors_before_recovery,
+            consecutive_errors: 0,
+            recoveries: 0,
+        }
+    }
+
+    pub fn command_succeeded(&mut self) {
+        if self.recoveries > 0 {
+            log::info!("I2C bus is working again.");
+        }
+        self.consecutive_errors = 0;
+        self.recoveries = 0;
+    }
+
+    // Returns the error when it is not one recovery helps with, or when recovering did not help.
+    pub fn handle_error(
+        &mut self,
+        error: ExecuteCommandError,
+        locomotion_controller: &mut LocomotionController,
+    ) -> Result<(), ExecuteCommandError> {
+        if !error.indicates_stuck_bus() || self.recoveries == MAX_RECOVERIES {
+            return Err(error);
+        }
+
+        self.consecutive_errors += 1;
+        if self.consecutive_errors < self.errors_before_recovery {
+            log::warn!("I2C bus may be locked up. - Cause: {}", error);
+            return Ok(());
+        }
+
+        self.consecutive_errors = 0;
+        self.recoveries += 1;
+        log::warn!(
+            "Resetting the PCA9685 after I2C errors, attempt {} of {}.",
+            self.recoveries,
+            MAX_RECOVERIES
+        );
+        // Should the bus still be stuck, this fails as well, which the next attempt may fix.
+        if let Err(setup_error) = locomotion_controller.reinitialize() {
+            log::warn!(
+                "Could not set the PCA9685 up again. - Cause: {}",
+                setup_error
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+// Which PCA9685 channel each output is connected to, so that the code driving an output asks for it by name rather
+// than hard-coding a channel number. The throttle and steering default to channels 0 and 1, as they always were, and
+// can be moved like anything else. All other outputs are optional.
+//
+// Outputs are configured as `channels.<name> = <channel>`, e.g. `channels.winch = 7`. The `<subsystem>.channel` style
+// settings that came before, e.g. `winch.channel`, configure the same thing.
+//
+// 💁‍♂️ Every output is on the one PCA9685 there is. Should a second one be added, this is where an output would say
+// which of the two it is on.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Output {
+    Throttle,
+    Steering,
+    StatusLed,
+    Buzzer,
+    BrakeLight,
+    LeftTurnSignal,
+    RightTurnSignal,
+    Fan,
+    Auxiliary,
+    GimbalPan,
+    GimbalTilt,
+    Winch,
+}
+
+impl Output {
+    pub const ALL: [Output; 12] = [
+        Output::Throttle,
+        Output::Steering,
+        Output::StatusLed,
+        Output::Buzzer,
+        Output::BrakeLight,
+        Output::LeftTurnSignal,
+        Output::RightTurnSignal,
+        Output::Fan,
+        Output::Auxiliary,
+        Output::GimbalPan,
+        Output::GimbalTilt,
+        Output::Winch,
+    ];
+
+    // As in `channels.<name>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Output::Throttle => "throttle",
+            Output::Steering => "steering",
+            Output::StatusLed => "status_led",
+            Output::Buzzer => "buzzer",
+            Output::BrakeLight => "brake_light",
+            Output::LeftTurnSignal => "left_turn_signal",
+            Output::RightTurnSignal => "right_turn_signal",
+            Output::Fan => "fan",
+            Output::Auxiliary => "aux",
+            Output::GimbalPan => "gimbal_pan",
+            Output::GimbalTilt => "gimbal_tilt",
+            Output::Winch => "winch",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl std::fmt::Display for Output {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            Output::Throttle => "ESC",
+            Output::Steering => "steering servo",
+            Output::StatusLed => "status LED",
+            Output::Buzzer => "buzzer",
+            Output::BrakeLight => "brake light",
+            Output::LeftTurnSignal => "left turn signal",
+            Output::RightTurnSignal => "right turn signal",
+            Output::Fan => "fan",
+            Output::Auxiliary => "auxiliary channel",
+            Output::GimbalPan => "gimbal pan servo",
+            Output::GimbalTilt => "gimbal tilt servo",
+            Output::Winch => "winch",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+impl FromStr for Output {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Output::ALL
+            .into_iter()
+            .find(|output| output.name() == text)
+            .ok_or(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputChannels {
+    channels: [Option<u8>; Output::ALL.len()],
+}
+
+impl Default for OutputChannels {
+    fn default() -> Self {
+        let mut channels = OutputChannels {
+            channels: [None; Output::ALL.len()],
+        };
+        channels.assign(Output::Throttle, 0);
+        channels.assign(Output::Steering, 1);
+
+        channels
+    }
+}
+
+impl OutputChannels {
+    pub fn assign(&mut self, output: Output, channel: u8) {
+        self.channels[output.index()] = Some(channel);
+    }
+
+    pub fn get(&self, output: Output) -> Option<u8> {
+        self.channels[output.index()]
+    }
+
+    // The throttle and steering always have a channel, there being no way to unassign one.
+    pub fn throttle(&self) -> u8 {
+        self.get(Output::Throttle).unwrap_or(0)
+    }
+
+    pub fn steering(&self) -> u8 {
+        self.get(Output::Steering).unwrap_or(1)
+    }
+
+    // Whether anything besides the throttle and steering is connected to the PCA9685.
+    pub fn has_other_outputs(&self) -> bool {
+        Output::ALL
+            .into_iter()
+            .filter(|output| !matches!(output, Output::Throttle | Output::Steering))
+            .any(|output| self.get(output).is_some())
+    }
+
+    // Pairs of outputs assigned the same channel.
+    pub fn clashes(&self) -> Vec<(Output, Output, u8)> {
+        let assigned: Vec<(Output, u8)> = Output::ALL
+            .into_iter()
+            .filter_map(|output| self.get(output).map(|channel| (output, channel)))
+            .collect();
+
+        let mut clashes = Vec::new();
+        for (index, (first, channel)) in assigned.iter().enumerate() {
+            for (second, other_channel) in &assigned[index + 1..] {
+                if channel == other_channel {
+                    clashes.push((*first, *second, *channel));
+                }
+            }
+        }
+
+        clashes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outputs_by_name_and_clashes() {
+        let mut channels = OutputChannels::default();
+        assert_eq!((channels.throttle(), channels.steering()), (0, 1));
+        assert!(!channels.has_other_outputs());
+        assert_eq!("winch".parse(), Ok(Output::Winch));
+        assert_eq!("headlights".parse::<Output>(), Err(()));
+
+        channels.assign("winch".parse().unwrap(), 7);
+        assert_eq!(channels.get(Output::Winch), Some(7));
+        assert!(channels.clashes().is_empty());
+        assert!(channels.has_other_outputs());
+
+        // Moving the throttle onto the winch's channel.
+        channels.assign(Output::Throttle, 7);
+        assert_eq!(
+            channels.clashes(),
+            vec![(Output::Throttle, Output::Winch, 7)]
+        );
+    }
+}
@@ -1,71 +1,615 @@
-use super::pca9685::{self, PCA9685Driver};
-use std::{error::Error, path::Path};
+use super::arming::ArmingStep;
+use super::channels::OutputChannels;
+use super::latency::{
+    LatencyPercentiles, LatencyStatistics, WatchdogVerdict, WriteLatencyWatchdog,
+};
+use super::pca9685::{self, PCA9685Driver, PCA9685Settings, SetPWMError};
+use super::pulse_widths::{PulseWidths, DEFAULT_PULSE_WIDTHS};
+use super::pwm_table::PwmTable;
+use crate::clock::{Clock, MonotonicClock};
+use crate::control_values::{Steering, Throttle};
+use crate::event_bus::{Event, EventBus};
+use crate::i2c::{I2CDevice, I2CTransport};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
 
 #[derive(Debug, Copy, Clone)]
 pub struct LocomotionCommand {
-    // -1.0 for full reverse to 1.0 for full speed forward.
-    throttle: f64,
+    throttle: Throttle,
+    direction: Steering,
+
+    // When the input that led to this command occurred, on the clock the controller runs on (normally that of
+    // `runloop::now()`). Only set on the command that first reflects a given input, so that every input is measured
+    // once.
+    input_timestamp: Option<Duration>,
 
-    // -1.0 for steering maximally to the left to 1.0 for steering maximally to the right.
-    direction: f64,
+    // Whether the driver asked for full power, lifting the configured speed cap for the moment.
+    boosted: bool,
 }
 
 impl LocomotionCommand {
-    pub fn new(throttle: f64, direction: f64) -> Self {
-        assert!(throttle >= -1.0);
-        assert!(throttle <= 1.0);
-        assert!(direction >= -1.0);
-        assert!(direction <= 1.0);
+    pub fn new(throttle: Throttle, direction: Steering) -> Self {
+        Self {
+            throttle,
+            direction,
+            input_timestamp: None,
+            boosted: false,
+        }
+    }
+
+    pub fn neutral() -> Self {
+        Self::new(Throttle::NEUTRAL, Steering::CENTER)
+    }
+
+    // Scales the throttle so that full throttle corresponds to `limit`.
+    pub fn with_speed_limit(self, limit: f64) -> Self {
+        Self {
+            throttle: self.throttle.scaled(limit),
+            ..self
+        }
+    }
 
+    // Replaces the throttle and direction, keeping track of the input they derive from.
+    pub fn with_values(self, throttle: Throttle, direction: Steering) -> Self {
         Self {
             throttle,
             direction,
+            ..self
+        }
+    }
+
+    pub fn with_boost(self, boosted: bool) -> Self {
+        Self { boosted, ..self }
+    }
+
+    pub fn with_input_timestamp(self, input_timestamp: Option<Duration>) -> Self {
+        Self {
+            input_timestamp,
+            ..self
         }
     }
 
-    pub fn get_throttle(&self) -> f64 {
+    pub fn get_throttle(&self) -> Throttle {
         self.throttle
     }
 
-    pub fn get_direction(&self) -> f64 {
+    pub fn get_direction(&self) -> Steering {
         self.direction
     }
+
+    pub fn is_neutral(&self) -> bool {
+        self.throttle.is_neutral() && self.direction.is_centered()
+    }
+
+    pub fn is_boosted(&self) -> bool {
+        self.boosted
+    }
+}
+
+// PWM values are only written when they change, which leaves the I2C bus mostly idle while a command is held.
+// Unchanged values are still rewritten every `refresh_interval`, so that a PCA9685 that lost its state (e.g. due to
+// a brown-out) does not keep driving stale or no pulses for long.
+pub struct LocomotionController<T: I2CTransport = I2CDevice, C: Clock = MonotonicClock> {
+    clock: C,
+    pca9685_driver: Rc<PCA9685Driver<T>>,
+    // The bus the PCA9685 is on, for resetting it. Unknown for drivers not created by `new`.
+    i2c_device_file: Option<PathBuf>,
+    throttle_channel: u8,
+    steering_channel: u8,
+    refresh_interval: Duration,
+    steering_pulse_widths: PulseWidths,
+    // See `use_pwm_tables`.
+    pwm_tables: Option<PwmTables>,
+    // As duty cycle counts, see `pca9685::duty_cycle_count`.
+    written_throttle_pwm: Option<u16>,
+    written_steering_pwm: Option<u16>,
+    last_refresh: Duration,
+    input_latency: LatencyStatistics,
+    write_latency: WriteLatencyWatchdog,
+    executed_throttle: f64,
+    brake_ramp: Option<BrakeRamp>,
+    active_brake: Option<ActiveBrake>,
+    asleep: bool,
+    // Whether other outputs (the status LED, buzzer, lights, …) are on the PCA9685 as well, see `sleep`.
+    shares_pca9685: bool,
+    event_bus: Option<EventBus>,
+}
+
+// Cutting the throttle at speed makes the ESC brake as hard as it can, which can flip the vehicle over. When input
+// is lost, the throttle is instead brought down gradually: from full throttle to neutral over `duration`, and
+// proportionally quicker from lower throttle.
+struct BrakeRamp {
+    started_at: Duration,
+    initial_throttle: f64,
+    duration: Duration,
+}
+
+// Stopping quicker than coasting down does: the throttle is reversed for `until`, which the ESC takes as braking.
+//
+// ⚠️ This relies on the ESC treating reverse throttle while going forward as braking, as car ESCs with a
+// forward/brake/reverse mode do: they only reverse once the throttle went back to neutral after braking. ESCs without a
+// brake (forward/reverse crawler ESCs, for one) drive backwards instead, so the `brake` failsafe response is not for
+// them. Going backwards, reversing the throttle would always mean driving forward, so there is no active braking then.
+struct ActiveBrake {
+    until: Duration,
+    throttle: f64,
 }
 
-pub struct LocomotionController {
-    pca9685_driver: PCA9685Driver,
+// Firm enough to stop in a short distance, without locking up the wheels on most surfaces.
+const ACTIVE_BRAKE_THROTTLE: f64 = 0.5;
+
+struct PwmTables {
+    throttle: PwmTable,
+    steering: PwmTable,
 }
 
-impl LocomotionController {
-    pub fn new() -> Result<Self, SetupError> {
-        let pca9685_driver = PCA9685Driver::new(Path::new(I2C_DEVICE_FILE), PWM_FREQUENCY)
+impl LocomotionController<I2CDevice> {
+    pub fn new(
+        channels: &OutputChannels,
+        refresh_interval: Duration,
+        steering_pulse_widths: PulseWidths,
+        arming_sequence: &[ArmingStep],
+        i2c_device_file: &Path,
+        pca9685_settings: &PCA9685Settings,
+    ) -> Result<Self, SetupError> {
+        let pca9685_driver = PCA9685Driver::new(i2c_device_file, PWM_FREQUENCY, pca9685_settings)
             .map_err(|source| SetupError::PCA9685SetupError { source })?;
 
-        // This will initialize the ESC.
-        pca9685_driver
-            .set_pwm_on_percentage(PCA9685_THROTTLE_CHANNEL, PWM_CENTER_ON_PCT)
-            .map_err(|source| SetupError::CouldNotInitializeESC { source })?;
+        let mut controller = Self::with_driver(
+            pca9685_driver,
+            MonotonicClock,
+            channels,
+            refresh_interval,
+            steering_pulse_widths,
+            arming_sequence,
+        )?;
+        controller.i2c_device_file = Some(i2c_device_file.to_path_buf());
+
+        controller
+            .pca9685_driver
+            .install_emergency_stop_on_panic()
+            .map_err(|source| SetupError::PCA9685SetupError { source })?;
 
-        Ok(Self { pca9685_driver })
+        Ok(controller)
     }
+}
+
+impl<T: I2CTransport, C: Clock> LocomotionController<T, C> {
+    pub fn with_driver(
+        pca9685_driver: PCA9685Driver<T>,
+        clock: C,
+        channels: &OutputChannels,
+        refresh_interval: Duration,
+        steering_pulse_widths: PulseWidths,
+        arming_sequence: &[ArmingStep],
+    ) -> Result<Self, SetupError> {
+        arm_esc(
+            &pca9685_driver,
+            channels.throttle(),
+            &clock,
+            arming_sequence,
+        )
+        .map_err(|source| SetupError::CouldNotInitializeESC { source })?;
+
+        let now = clock.now();
+        Ok(Self {
+            clock,
+            pca9685_driver: Rc::new(pca9685_driver),
+            i2c_device_file: None,
+            throttle_channel: channels.throttle(),
+            steering_channel: channels.steering(),
+            refresh_interval,
+            steering_pulse_widths,
+            pwm_tables: None,
+            written_throttle_pwm: None,
+            written_steering_pwm: None,
+            last_refresh: now,
+            input_latency: LatencyStatistics::new(),
+            write_latency: WriteLatencyWatchdog::new(DEFAULT_WRITE_LATENCY_THRESHOLD, now),
+            executed_throttle: 0.0,
+            brake_ramp: None,
+            active_brake: None,
+            asleep: false,
+            shares_pca9685: channels.has_other_outputs(),
+            event_bus: None,
+        })
+    }
+
+    pub fn publish_events_to(&mut self, event_bus: EventBus) {
+        self.event_bus = Some(event_bus);
+    }
+
+    // A warning is logged and published when the p99 of writing a channel goes over `threshold`.
+    pub fn set_write_latency_threshold(&mut self, threshold: Duration) {
+        self.write_latency.set_threshold(threshold);
+    }
+
+    // Looks the duty cycles up in tables worked out once now, rather than working them out for every command, which
+    // takes measurably longer on the slowest boards. Values are quantized for this, see `pwm_table.rs`.
+    pub fn use_pwm_tables(&mut self) -> Result<(), SetupError> {
+        let pwm_frequency = self.pca9685_driver.pwm_frequency();
+        let throttle = PwmTable::new(&THROTTLE_PULSE_WIDTHS, pwm_frequency)
+            .map_err(|source| SetupError::CouldNotCreatePWMTables { source })?;
+        let steering = PwmTable::new(&self.steering_pulse_widths, pwm_frequency)
+            .map_err(|source| SetupError::CouldNotCreatePWMTables { source })?;
+        self.pwm_tables = Some(PwmTables { throttle, steering });
+
+        Ok(())
+    }
+
+    pub fn execute_command(
+        &mut self,
+        command: LocomotionCommand,
+    ) -> Result<(), ExecuteCommandError> {
+        if self.asleep {
+            return Ok(());
+        }
+
+        let now = self.clock.now();
+        if now - self.last_refresh >= self.refresh_interval {
+            self.written_throttle_pwm = None;
+            self.written_steering_pwm = None;
+            self.last_refresh = now;
+        }
+
+        let throttle = self.ramped_throttle(command.get_throttle().value(), now);
+        let throttle_pwm = self.throttle_count(throttle)?;
+        if self.written_throttle_pwm != Some(throttle_pwm) {
+            // Forget what was written until the write is known to have succeeded, so that a failed write is retried.
+            self.written_throttle_pwm = None;
+            self.timed_write(self.throttle_channel, throttle_pwm)?;
+            self.written_throttle_pwm = Some(throttle_pwm);
+        }
+        self.executed_throttle = throttle;
+
+        let steering_pwm = self.steering_count(command.get_direction().value())?;
+        if self.written_steering_pwm != Some(steering_pwm) {
+            self.written_steering_pwm = None;
+            self.timed_write(self.steering_channel, steering_pwm)?;
+            self.written_steering_pwm = Some(steering_pwm);
+        }
+
+        // The time from the kernel receiving an input event to the corresponding PWM values having been written.
+        if let Some(input_timestamp) = command.input_timestamp {
+            self.input_latency
+                .record(self.clock.now().saturating_sub(input_timestamp));
+        }
+
+        self.check_write_latency(now);
+
+        Ok(())
+    }
+
+    fn throttle_count(&self, throttle: f64) -> Result<u16, SetPWMError> {
+        match &self.pwm_tables {
+            Some(pwm_tables) => Ok(pwm_tables.throttle.count(throttle)),
+            None => pca9685::duty_cycle_count(locomotion_value_to_pwm_on_percentage(
+                throttle,
+                &THROTTLE_PULSE_WIDTHS,
+                self.pca9685_driver.pwm_frequency(),
+            )),
+        }
+    }
+
+    fn steering_count(&self, direction: f64) -> Result<u16, SetPWMError> {
+        match &self.pwm_tables {
+            Some(pwm_tables) => Ok(pwm_tables.steering.count(direction)),
+            None => pca9685::duty_cycle_count(locomotion_value_to_pwm_on_percentage(
+                direction,
+                &self.steering_pulse_widths,
+                self.pca9685_driver.pwm_frequency(),
+            )),
+        }
+    }
+
+    fn timed_write(&mut self, channel: u8, count: u16) -> Result<(), ExecuteCommandError> {
+        let start = self.clock.now();
+        self.pca9685_driver.set_pwm_on_count(channel, count)?;
+        self.write_latency
+            .record(self.clock.now().saturating_sub(start));
+
+        Ok(())
+    }
+
+    fn check_write_latency(&mut self, now: Duration) {
+        let Some(verdict) = self.write_latency.check(now) else {
+            return;
+        };
+
+        let event = match verdict {
+            WatchdogVerdict::Exceeded { p99 } => {
+                log::warn!(
+                    "Writing to the PCA9685 has become slow (p99 {:?}), check the I2C wiring.",
+                    p99
+                );
+                Event::I2CLatencyExceeded { p99 }
+            }
+            WatchdogVerdict::Recovered { p99 } => {
+                log::info!("Writing to the PCA9685 is back to normal (p99 {:?}).", p99);
+                Event::I2CLatencyRecovered
+            }
+        };
+        if let Some(event_bus) = &self.event_bus {
+            event_bus.publish(event);
+        }
+    }
+
+    // Brings the throttle down gradually over the next commands, as long as they are neutral. A command with any
+    // other throttle ends the ramp, as the driver is back in control.
+    pub fn start_brake_ramp(&mut self, duration: Duration) {
+        if self.executed_throttle == 0.0 || duration.is_zero() {
+            return;
+        }
+
+        log::info!(
+            "Bringing throttle down from {:.0}% over {:?}.",
+            self.executed_throttle * 100.0,
+            duration.mul_f64(self.executed_throttle.abs())
+        );
+        self.active_brake = None;
+        self.brake_ramp = Some(BrakeRamp {
+            started_at: self.clock.now(),
+            initial_throttle: self.executed_throttle,
+            duration,
+        });
+        if let Some(event_bus) = &self.event_bus {
+            event_bus.publish(Event::BrakeRampStarted {
+                throttle: self.executed_throttle,
+            });
+        }
+    }
+
+    // Brakes for `duration` over the next commands, as long as they are neutral, after which the throttle is neutral.
+    // As with a brake ramp, a command with any other throttle ends it. Only going forward can be braked for, see
+    // `ActiveBrake`: going backwards, the throttle comes down as with a brake ramp instead.
+    pub fn start_active_brake(&mut self, duration: Duration) {
+        if self.executed_throttle < 0.0 {
+            self.start_brake_ramp(duration);
+            return;
+        }
+        if self.executed_throttle == 0.0 || duration.is_zero() {
+            return;
+        }
+
+        log::info!(
+            "Braking from {:.0}% for {:?}.",
+            self.executed_throttle * 100.0,
+            duration
+        );
+        self.brake_ramp = None;
+        self.active_brake = Some(ActiveBrake {
+            until: self.clock.now() + duration,
+            throttle: -ACTIVE_BRAKE_THROTTLE,
+        });
+    }
+
+    fn ramped_throttle(&mut self, throttle: f64, now: Duration) -> f64 {
+        if let Some(active_brake) = &self.active_brake {
+            if throttle != 0.0 || now >= active_brake.until {
+                self.active_brake = None;
+            } else {
+                return active_brake.throttle;
+            }
+        }
+
+        let Some(brake_ramp) = &self.brake_ramp else {
+            return throttle;
+        };
+
+        if throttle != 0.0 {
+            self.brake_ramp = None;
+            return throttle;
+        }
+
+        let elapsed_fraction = now.saturating_sub(brake_ramp.started_at).as_secs_f64()
+            / brake_ramp.duration.as_secs_f64();
+        let remaining_throttle = brake_ramp.initial_throttle.abs() - elapsed_fraction;
+        if remaining_throttle <= 0.0 {
+            self.brake_ramp = None;
+            return 0.0;
+        }
+
+        remaining_throttle.copysign(brake_ramp.initial_throttle)
+    }
+
+    // Brings the vehicle to a stop before the service exits, as the failsafe would: the throttle comes down over (up
+    // to) `brake_ramp`, with a neutral command every `interval`. Both outputs are neutral afterwards. Should `urgent`
+    // return true on the way, the ramp is skipped and neutral written right away.
+    pub fn come_to_stop(
+        &mut self,
+        brake_ramp: Duration,
+        interval: Duration,
+        urgent: impl Fn() -> bool,
+    ) -> Result<(), ExecuteCommandError> {
+        if self.asleep {
+            return Ok(());
+        }
+
+        self.start_brake_ramp(brake_ramp);
+        let mut next_command_at = self.clock.now();
+        loop {
+            if urgent() {
+                self.brake_ramp = None;
+                self.active_brake = None;
+            }
+            self.execute_command(LocomotionCommand::neutral())?;
+            if self.brake_ramp.is_none() {
+                return Ok(());
+            }
+
+            next_command_at += interval;
+            self.clock.sleep_until(next_command_at);
+        }
+    }
+
+    // Measures how long executing a command takes by repeatedly executing a neutral one. This is dominated by the I2C
+    // writes, and the slowest attempt is returned to leave some margin.
+    pub fn measure_command_duration(&mut self) -> Result<Duration, ExecuteCommandError> {
+        const ATTEMPTS: usize = 5;
+
+        let mut slowest = Duration::ZERO;
+        for _ in 0..ATTEMPTS {
+            // Make sure the values are actually written.
+            self.written_throttle_pwm = None;
+            self.written_steering_pwm = None;
+
+            let start = self.clock.now();
+            self.execute_command(LocomotionCommand::neutral())?;
+            slowest = slowest.max(self.clock.now() - start);
+        }
+
+        Ok(slowest)
+    }
+
+    // Sends the steering servo a given pulse width, bypassing its calibration, so that the calibration can be found.
+    pub fn set_steering_pulse_width(
+        &mut self,
+        pulse_width_us: u32,
+    ) -> Result<(), ExecuteCommandError> {
+        self.written_steering_pwm = None;
+        let steering_pwm = pca9685::duty_cycle_count(pulse_width_to_pwm_on_percentage(
+            pulse_width_us as f64,
+            self.pca9685_driver.pwm_frequency(),
+        ))?;
+        self.pca9685_driver
+            .set_pwm_on_count(self.steering_channel, steering_pwm)?;
+        self.written_steering_pwm = Some(steering_pwm);
+
+        Ok(())
+    }
+
+    // Puts the PCA9685 to sleep, so that the servo stops holding its position (and buzzing while doing so) and the
+    // ESC sees no pulses at all. Commands are ignored until `wake` is called.
+    //
+    // 💁‍♂️ Sleeping stops the oscillator, and with it every channel. When other outputs share the chip, only the ESC
+    // and servo channels are turned off instead, which saves nearly as much, while the status LED, buzzer and lights
+    // carry on.
+    pub fn sleep(&mut self) -> Result<(), ExecuteCommandError> {
+        if self.shares_pca9685 {
+            self.pca9685_driver.set_full_off(self.throttle_channel)?;
+            self.pca9685_driver.set_full_off(self.steering_channel)?;
+        } else {
+            // Waking up resumes the outputs with the values they had, which should not include any throttle.
+            self.pca9685_driver.set_pwm_on_percentage(
+                self.throttle_channel,
+                locomotion_value_to_pwm_on_percentage(
+                    0.0,
+                    &THROTTLE_PULSE_WIDTHS,
+                    self.pca9685_driver.pwm_frequency(),
+                ),
+            )?;
+            self.pca9685_driver.sleep()?;
+        }
+        self.written_throttle_pwm = None;
+        self.written_steering_pwm = None;
+        self.asleep = true;
+        self.brake_ramp = None;
+        self.active_brake = None;
+        self.executed_throttle = 0.0;
+
+        Ok(())
+    }
+
+    // Wakes the PCA9685 up again and makes sure that the next command is written out in full, which also ends the
+    // ESC and servo channels being turned off.
+    pub fn wake(&mut self) -> Result<(), ExecuteCommandError> {
+        if !self.shares_pca9685 {
+            self.pca9685_driver.wake()?;
+        }
+        self.asleep = false;
+        self.written_throttle_pwm = None;
+        self.written_steering_pwm = None;
+
+        Ok(())
+    }
+
+    // Resets the PCA9685 and sets it up from scratch, should it have lost its state. The next command is written out
+    // in full, but other users of the remaining channels have to rewrite theirs. While asleep, the outputs stay off as
+    // they were, though the oscillator keeps running.
+    pub fn reinitialize(&mut self) -> Result<(), SetupError> {
+        self.written_throttle_pwm = None;
+        self.written_steering_pwm = None;
+        if let Some(i2c_device_file) = &self.i2c_device_file {
+            pca9685::software_reset(i2c_device_file)
+                .map_err(|source| SetupError::PCA9685SetupError { source })?;
+        }
+        self.pca9685_driver
+            .reinitialize()
+            .map_err(|source| SetupError::PCA9685SetupError { source })
+    }
+
+    // Picks up again after the runloop was held up for a long while (e.g. the system was suspended): a brake ramp
+    // underway is over, and the next command is written out in full. Should the PCA9685 have lost power meanwhile, it
+    // is set up again, which is what the result tells.
+    pub fn resynchronize(&mut self) -> Result<bool, SetupError> {
+        self.brake_ramp = None;
+        self.active_brake = None;
+        self.written_throttle_pwm = None;
+        self.written_steering_pwm = None;
+        self.last_refresh = self.clock.now();
+
+        let lost_configuration = self
+            .pca9685_driver
+            .has_lost_configuration(self.asleep && !self.shares_pca9685)
+            .map_err(|source| SetupError::CouldNotCheckConfiguration { source })?;
+        if lost_configuration {
+            self.reinitialize()?;
+        }
+
+        Ok(lost_configuration)
+    }
+
+    // Turns every output of the PCA9685 fully off at once, including those driven by others (such as the status LED).
+    // The ESC and servo see no pulses at all, which they take as a signal loss. The next command turns them back on.
+    pub fn turn_off_all_outputs(&mut self) -> Result<(), ExecuteCommandError> {
+        self.written_throttle_pwm = None;
+        self.written_steering_pwm = None;
+        self.pca9685_driver.set_all_full_off()?;
 
-    pub fn execute_command(&self, command: LocomotionCommand) -> Result<(), ExecuteCommandError> {
-        self.pca9685_driver.set_pwm_on_percentage(
-            PCA9685_THROTTLE_CHANNEL,
-            locomotion_value_to_pwm_on_percentage(command.get_throttle()),
-        )?;
-        self.pca9685_driver.set_pwm_on_percentage(
-            PCA9685_STEERING_CHANNEL,
-            locomotion_value_to_pwm_on_percentage(command.get_direction()),
-        )?;
         Ok(())
     }
+
+    // The pulse widths last written to the ESC and steering servo, as far as they are known to have been written.
+    pub fn pulse_widths_us(&self) -> (Option<f64>, Option<f64>) {
+        let pulse_width_us = |count: Option<u16>| {
+            count.map(|count| {
+                count as f64 / 4095.0 / self.pca9685_driver.pwm_frequency() * 1_000_000.0
+            })
+        };
+
+        (
+            pulse_width_us(self.written_throttle_pwm),
+            pulse_width_us(self.written_steering_pwm),
+        )
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.asleep
+    }
+
+    // The remaining channels of the PCA9685 are free for other outputs to use.
+    pub fn pca9685_driver(&self) -> Rc<PCA9685Driver<T>> {
+        Rc::clone(&self.pca9685_driver)
+    }
+
+    pub fn input_latency_percentiles(&self) -> Option<LatencyPercentiles> {
+        self.input_latency.percentiles()
+    }
+
+    // How long writing a PWM channel takes.
+    pub fn write_latency_percentiles(&self) -> Option<LatencyPercentiles> {
+        self.write_latency.percentiles()
+    }
 }
 
 #[derive(Debug)]
 pub enum SetupError {
     PCA9685SetupError { source: pca9685::SetupError },
     CouldNotInitializeESC { source: pca9685::SetPWMError },
+    CouldNotCheckConfiguration { source: pca9685::SetPWMError },
+    CouldNotCreatePWMTables { source: pca9685::SetPWMError },
 }
 
 impl Error for SetupError {
@@ -73,6 +617,8 @@ impl Error for SetupError {
         Some(match self {
             SetupError::PCA9685SetupError { source } => source,
             SetupError::CouldNotInitializeESC { source } => source,
+            SetupError::CouldNotCheckConfiguration { source } => source,
+            SetupError::CouldNotCreatePWMTables { source } => source,
         })
     }
 }
@@ -81,10 +627,16 @@ impl std::fmt::Display for SetupError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let description = match self {
             SetupError::PCA9685SetupError { source: _ } => {
-                format!("Locomotion controller initialization error.")
+                "Locomotion controller initialization error."
             }
             SetupError::CouldNotInitializeESC { source: _ } => {
-                format!("Locomotion controller initialization error: Could not send initialization signal to ESC.")
+                "Locomotion controller initialization error: Could not send initialization signal to ESC."
+            }
+            SetupError::CouldNotCheckConfiguration { source: _ } => {
+                "Could not check whether the PCA9685 is still configured."
+            }
+            SetupError::CouldNotCreatePWMTables { source: _ } => {
+                "Could not work out the PWM lookup tables for the configured pulse widths."
             }
         };
 
@@ -97,6 +649,14 @@ pub enum ExecuteCommandError {
     SetPWMError { source: pca9685::SetPWMError },
 }
 
+impl ExecuteCommandError {
+    pub fn indicates_stuck_bus(&self) -> bool {
+        match self {
+            ExecuteCommandError::SetPWMError { source } => source.indicates_stuck_bus(),
+        }
+    }
+}
+
 impl Error for ExecuteCommandError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         Some(match self {
@@ -117,24 +677,212 @@ impl From<pca9685::SetPWMError> for ExecuteCommandError {
     }
 }
 
-const I2C_DEVICE_FILE: &str = "/dev/i2c-1";
+// The default for `i2c.device_file`.
+pub const I2C_DEVICE_FILE: &str = "/dev/i2c-1";
+
+pub const PWM_FREQUENCY: u32 = 50;
+
+// Writing a channel takes four single-byte writes, which is about 1.5ms on a healthy bus at 100kHz.
+pub const DEFAULT_WRITE_LATENCY_THRESHOLD: Duration = Duration::from_millis(5);
+
+// Sends the ESC the arming sequence, if any, followed by neutral. See `arming.rs`.
+fn arm_esc<T: I2CTransport>(
+    pca9685_driver: &PCA9685Driver<T>,
+    throttle_channel: u8,
+    clock: &impl Clock,
+    arming_sequence: &[ArmingStep],
+) -> Result<(), SetPWMError> {
+    let pwm_frequency = pca9685_driver.pwm_frequency();
+
+    if !arming_sequence.is_empty() {
+        log::info!(
+            "Arming the ESC, which takes {:?}.",
+            arming_sequence
+                .iter()
+                .map(|step| step.duration)
+                .sum::<Duration>()
+        );
+    }
+    for step in arming_sequence {
+        pca9685_driver.set_pwm_on_percentage(
+            throttle_channel,
+            pulse_width_to_pwm_on_percentage(step.pulse_width_us as f64, pwm_frequency),
+        )?;
+        clock.sleep_until(clock.now() + step.duration);
+    }
+
+    pca9685_driver.set_pwm_on_percentage(
+        throttle_channel,
+        locomotion_value_to_pwm_on_percentage(0.0, &THROTTLE_PULSE_WIDTHS, pwm_frequency),
+    )
+}
+
+// The ESC is not calibrated: ESCs calibrate themselves to the transmitter instead.
+const THROTTLE_PULSE_WIDTHS: PulseWidths = DEFAULT_PULSE_WIDTHS;
+
+pub fn locomotion_value_to_pwm_on_percentage(
+    value: f64,
+    pulse_widths: &PulseWidths,
+    pwm_frequency: f64,
+) -> f64 {
+    pulse_width_to_pwm_on_percentage(pulse_widths.pulse_width_us(value), pwm_frequency)
+}
+
+// Going by the frequency the PCA9685 actually runs at rather than `PWM_FREQUENCY`, so that neither the rounding of the
+// prescale nor a known oscillator error shifts the pulse widths.
+fn pulse_width_to_pwm_on_percentage(pulse_width_us: f64, pwm_frequency: f64) -> f64 {
+    pulse_width_us * pwm_frequency / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::i2c::mock::MockI2CTransport;
+    use crate::locomotion::channels::Output;
+
+    #[test]
+    fn command_values_are_clamped() {
+        let values = |command: LocomotionCommand| {
+            (
+                command.get_throttle().value(),
+                command.get_direction().value(),
+            )
+        };
+
+        let command = LocomotionCommand::new(Throttle::new(-1.0), Steering::new(1.0));
+        assert_eq!(values(command), (-1.0, 1.0));
+
+        let command = LocomotionCommand::new(Throttle::new(1.0000001), Steering::new(-1.0000001));
+        assert_eq!(values(command), (1.0, -1.0));
+
+        let command = LocomotionCommand::new(
+            Throttle::new(f64::INFINITY),
+            Steering::new(f64::NEG_INFINITY),
+        );
+        assert_eq!(values(command), (1.0, -1.0));
+
+        let command = LocomotionCommand::new(Throttle::new(f64::NAN), Steering::new(f64::NAN));
+        assert_eq!(values(command), (0.0, 0.0));
+
+        let command =
+            LocomotionCommand::new(Throttle::new(0.8), Steering::CENTER).with_speed_limit(1.5);
+        assert_eq!(values(command), (1.0, 0.0));
+    }
+
+    #[test]
+    fn active_braking_never_drives_the_other_way() {
+        let clock = ManualClock::new(Duration::from_secs(1000));
+        let pca9685_driver = PCA9685Driver::with_transport(
+            MockI2CTransport::new(),
+            PWM_FREQUENCY,
+            &PCA9685Settings::default(),
+        )
+        .unwrap();
+        let mut controller = LocomotionController::with_driver(
+            pca9685_driver,
+            clock.clone(),
+            &OutputChannels::default(),
+            Duration::from_millis(100),
+            PulseWidths::default(),
+            &[],
+        )
+        .unwrap();
+        let drive = |controller: &mut LocomotionController<_, _>, throttle| {
+            controller
+                .execute_command(LocomotionCommand::new(
+                    Throttle::new(throttle),
+                    Steering::CENTER,
+                ))
+                .unwrap();
+        };
+
+        drive(&mut controller, 0.8);
+        controller.start_active_brake(Duration::from_millis(300));
+        assert_eq!(
+            controller.ramped_throttle(0.0, clock.now()),
+            -ACTIVE_BRAKE_THROTTLE
+        );
+
+        // Reversing is brought down to neutral instead.
+        drive(&mut controller, -0.8);
+        controller.start_active_brake(Duration::from_millis(300));
+        let throttle = controller.ramped_throttle(0.0, clock.now());
+        assert!((-0.8..=0.0).contains(&throttle));
+    }
+
+    #[test]
+    fn sleeping_leaves_other_outputs_running() {
+        let i2c = MockI2CTransport::new();
+        let pca9685_driver =
+            PCA9685Driver::with_transport(i2c.clone(), PWM_FREQUENCY, &PCA9685Settings::default())
+                .unwrap();
+        let mut channels = OutputChannels::default();
+        channels.assign(Output::StatusLed, 4);
+        let mut controller = LocomotionController::with_driver(
+            pca9685_driver,
+            ManualClock::new(Duration::from_secs(1000)),
+            &channels,
+            Duration::from_millis(100),
+            PulseWidths::default(),
+            &[],
+        )
+        .unwrap();
 
-const PCA9685_THROTTLE_CHANNEL: u8 = 0;
-const PCA9685_STEERING_CHANNEL: u8 = 1;
+        // MODE1 keeps the SLEEP bit clear, while the LED0_OFF_H registers of the throttle and steering channels get
+        // their full OFF bit set.
+        controller.sleep().unwrap();
+        assert_eq!(i2c.register(0x00) & 0x10, 0);
+        assert_ne!(i2c.register(0x09) & 0x10, 0);
+        assert_ne!(i2c.register(0x09 + 4) & 0x10, 0);
 
-const PWM_FREQUENCY: u32 = 50;
+        controller.wake().unwrap();
+        controller
+            .execute_command(LocomotionCommand::neutral())
+            .unwrap();
+        assert_eq!(i2c.register(0x09) & 0x10, 0);
+        assert_eq!(i2c.register(0x09 + 4) & 0x10, 0);
+    }
+
+    // Executing commands that differ every time, so that both channels are written, against a transport that takes
+    // no time. What is left is what the controller itself adds to the I2C writes.
+    fn benchmark_command_execution(name: &str, use_pwm_tables: bool) {
+        let clock = ManualClock::new(Duration::from_secs(1000));
+        let i2c = MockI2CTransport::new();
+        let pca9685_driver =
+            PCA9685Driver::with_transport(i2c.clone(), PWM_FREQUENCY, &PCA9685Settings::default())
+                .unwrap();
+        let mut controller = LocomotionController::with_driver(
+            pca9685_driver,
+            clock.clone(),
+            &OutputChannels::default(),
+            Duration::from_millis(100),
+            PulseWidths::default(),
+            &[],
+        )
+        .unwrap();
+        if use_pwm_tables {
+            controller.use_pwm_tables().unwrap();
+        }
 
-// 1ms, 1.5ms and 2ms per cycle.
-const PWM_MIN_ON_PCT: f64 = 1.0 * (PWM_FREQUENCY as f64) / 1000.0;
-const PWM_CENTER_ON_PCT: f64 = 1.5 * (PWM_FREQUENCY as f64) / 1000.0;
-const PWM_MAX_ON_PCT: f64 = 2.0 * (PWM_FREQUENCY as f64) / 1000.0;
+        crate::benchmark::measure(name, Duration::from_micros(100), |iteration| {
+            let value = (iteration % 2001) as f64 / 1000.0 - 1.0;
+            let command = LocomotionCommand::new(Throttle::new(value), Steering::new(-value))
+                .with_input_timestamp(Some(clock.now()));
+            controller.execute_command(command).unwrap();
+            i2c.clear_writes();
+        });
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_command_execution_working_out_pwm() {
+        benchmark_command_execution("Executing a command", false);
+    }
 
-fn locomotion_value_to_pwm_on_percentage(value: f64) -> f64 {
-    if value == 0.0 {
-        PWM_CENTER_ON_PCT
-    } else if value > 0.0 {
-        PWM_CENTER_ON_PCT - ((PWM_CENTER_ON_PCT - PWM_MIN_ON_PCT) * value)
-    } else {
-        PWM_CENTER_ON_PCT + ((PWM_MAX_ON_PCT - PWM_CENTER_ON_PCT) * value.abs())
+    #[test]
+    #[ignore]
+    fn benchmark_command_execution_looking_up_pwm() {
+        benchmark_command_execution("Executing a command with PWM tables", true);
     }
 }
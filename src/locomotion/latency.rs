@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// Only recent samples are kept, so that the percentiles reflect current behaviour rather than being dominated by
+// whatever happened early on in a long session.
+const MAX_SAMPLES: usize = 1000;
+
+#[derive(Debug, Copy, Clone)]
+pub struct LatencyPercentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+pub struct LatencyStatistics {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyStatistics {
+    pub fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    pub fn percentiles(&self) -> Option<LatencyPercentiles> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted: Vec<Duration> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+
+        // Nearest-rank percentiles.
+        let percentile = |p: usize| sorted[((sorted.len() * p).div_ceil(100)).max(1) - 1];
+
+        Some(LatencyPercentiles {
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+// A degrading I2C bus (clock stretching, noise on long wires) makes every write take a little longer, which silently
+// eats into the runloop's budget until iterations overrun. This keeps an eye on how long writing a PWM channel takes,
+// so that is noticed while driving still works.
+//
+// 💁‍♂️ The p99 is only looked at every `CHECK_INTERVAL`, as sorting the samples every iteration would add to the very
+// latency being watched.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct WriteLatencyWatchdog {
+    statistics: LatencyStatistics,
+    threshold: Duration,
+    last_check: Duration,
+    exceeded: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WatchdogVerdict {
+    Exceeded { p99: Duration },
+    Recovered { p99: Duration },
+}
+
+impl WriteLatencyWatchdog {
+    pub fn new(threshold: Duration, now: Duration) -> Self {
+        Self {
+            statistics: LatencyStatistics::new(),
+            threshold,
+            last_check: now,
+            exceeded: false,
+        }
+    }
+
+    pub fn set_threshold(&mut self, threshold: Duration) {
+        self.threshold = threshold;
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        self.statistics.record(latency);
+    }
+
+    pub fn percentiles(&self) -> Option<LatencyPercentiles> {
+        self.statistics.percentiles()
+    }
+
+    // Only reports changes: once when the p99 goes over the threshold, and once when it is back under it.
+    pub fn check(&mut self, now: Duration) -> Option<WatchdogVerdict> {
+        if now.saturating_sub(self.last_check) < CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = now;
+
+        let p99 = self.statistics.percentiles()?.p99;
+        match (self.exceeded, p99 > self.threshold) {
+            (false, true) => {
+                self.exceeded = true;
+                Some(WatchdogVerdict::Exceeded { p99 })
+            }
+            (true, false) => {
+                self.exceeded = false;
+                Some(WatchdogVerdict::Recovered { p99 })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_reports_changes_only() {
+        let millisecond = Duration::from_millis(1);
+        let mut watchdog = WriteLatencyWatchdog::new(2 * millisecond, Duration::ZERO);
+
+        watchdog.record(millisecond);
+        assert_eq!(watchdog.check(CHECK_INTERVAL), None);
+
+        // Slow writes are only noticed once the interval has passed, and only once.
+        for _ in 0..100 {
+            watchdog.record(5 * millisecond);
+        }
+        assert_eq!(watchdog.check(CHECK_INTERVAL + millisecond), None);
+        assert_eq!(
+            watchdog.check(2 * CHECK_INTERVAL),
+            Some(WatchdogVerdict::Exceeded {
+                p99: 5 * millisecond
+            })
+        );
+        assert_eq!(watchdog.check(3 * CHECK_INTERVAL), None);
+
+        for _ in 0..1000 {
+            watchdog.record(millisecond);
+        }
+        assert_eq!(
+            watchdog.check(4 * CHECK_INTERVAL),
+            Some(WatchdogVerdict::Recovered { p99: millisecond })
+        );
+    }
+}
@@ -1,54 +1,286 @@
-use std::{error::Error, path::Path, time::Duration};
+use std::{error::Error, panic, path::Path, time::Duration};
 
-use crate::i2c::{self, I2CDevice};
+use crate::i2c::{self, AddressingOptions, I2CDevice, I2CTransport};
+use once_cell::sync::OnceCell;
 
 // The datasheet is available at: https://cdn-shop.adafruit.com/datasheets/PCA9685.pdf.
 
-pub struct PCA9685Driver {
-    i2c_device: I2CDevice,
+// How the device is set up. These take effect when the driver is created.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PCA9685Settings {
+    // Whether to start the pulses of the channels at different points in the period, rather than all at once, to
+    // avoid the current spikes of all servos moving together. See `stagger_outputs`.
+    pub stagger_outputs: bool,
+
+    // The frequency of a clock on the EXTCLK pin, for boards that have one. The internal oscillator is only accurate
+    // to a few percent, which shifts the pulse widths the ESC sees by as much.
+    pub external_clock_frequency: Option<u32>,
+
+    // The PWM frequency measured on an output divided by the expected one, e.g. 1.02 when a 50 Hz output measures
+    // 51 Hz. This corrects for the oscillator being off, so that the pulse widths come out as intended.
+    pub frequency_correction: f64,
+
+    // Whether to use the device even when a kernel driver (e.g. `pwm-pca9685`) claims its address. See
+    // `i2c::AddressingOptions`.
+    pub force_address: bool,
+}
+
+impl Default for PCA9685Settings {
+    fn default() -> Self {
+        Self {
+            stagger_outputs: false,
+            external_clock_frequency: None,
+            frequency_correction: 1.0,
+            force_address: false,
+        }
+    }
 }
 
-impl PCA9685Driver {
-    pub fn new(i2c_device_file_path: &Path, pwm_frequency: u32) -> Result<Self, SetupError> {
-        let i2c_device = I2CDevice::new(i2c_device_file_path, I2C_BUS_ADDRESS)?;
+impl PCA9685Settings {
+    fn oscillator_frequency(&self) -> f64 {
+        let nominal_frequency = match self.external_clock_frequency {
+            Some(frequency) => frequency as f64,
+            None => INTERNAL_OSCILLATOR_FREQUENCY,
+        };
 
-        // This resets MODE1 and MODE2 to their default values. Setting the SLEEP bit will stop all PWM output.
-        i2c_device.write_byte_data(REGISTER_MODE1, MODE1_ALLCALL_FLAG | MODE1_SLEEP_FLAG)?;
-        i2c_device.write_byte_data(REGISTER_MODE2, MODE2_OUTDRV_FLAG)?;
+        nominal_frequency * self.frequency_correction
+    }
+}
 
-        // The prescale can only be set while the SLEEP bit is set.
-        let prescale = prescale_value_for_frequency(pwm_frequency);
-        i2c_device.write_byte_data(REGISTER_PRESCALE, prescale)?;
+pub struct PCA9685Driver<T: I2CTransport = I2CDevice> {
+    i2c_device: T,
+    // What MODE1 is set to while awake.
+    mode1: u8,
+    prescale: u8,
+    // See `pwm_frequency`.
+    pwm_frequency: f64,
+    // The count at which each channel's pulse starts. See `stagger_outputs`.
+    phase_offsets: [u16; 16],
+}
 
-        // After wake-up, a 500μs delay is required before configuring PWM outputs.
-        i2c_device.write_byte_data(REGISTER_MODE1, MODE1_ALLCALL_FLAG)?;
-        std::thread::sleep(Duration::from_micros(500));
+// A separate handle on the device, for cutting all PWM output when things go wrong. It is kept separately so that it
+// can be used from a panic hook, wherever the panic happened.
+static EMERGENCY_STOP_DEVICE: OnceCell<I2CDevice> = OnceCell::new();
 
-        // The PWM outputs will remain reset after the sleep cycle, so the device should be in fresh start-up
-        // state now. (While unneeded here, note for future reference that there is a RESTART functionality
-        // that allows for restarting the PWM outputs after a sleep cycle.)
+impl PCA9685Driver<I2CDevice> {
+    pub fn new(
+        i2c_device_file_path: &Path,
+        pwm_frequency: u32,
+        settings: &PCA9685Settings,
+    ) -> Result<Self, SetupError> {
+        let addressing_options = AddressingOptions {
+            ten_bit: false,
+            force: settings.force_address,
+        };
+        let i2c_device =
+            I2CDevice::shared(i2c_device_file_path, I2C_BUS_ADDRESS, addressing_options)?;
 
-        Ok(Self { i2c_device })
+        Self::with_transport(i2c_device, pwm_frequency, settings)
     }
 
-    pub fn set_pwm_on_percentage(&self, channel: u8, percentage: f64) -> Result<(), SetPWMError> {
-        assert!(percentage >= 0.0);
-        assert!(percentage <= 1.0);
+    // Without this, a panic leaves the PCA9685 generating whatever pulses it was last told to, i.e. the ESC keeps
+    // running at the last commanded throttle. The hook puts the device to sleep first, then defers to the previous
+    // hook. The same happens for an abort due to a panic, as the hook runs before aborting.
+    pub fn install_emergency_stop_on_panic(&self) -> Result<(), SetupError> {
+        let emergency_stop_device = self.i2c_device.try_clone()?;
+        if EMERGENCY_STOP_DEVICE.set(emergency_stop_device).is_err() {
+            // Already installed.
+            return Ok(());
+        }
+
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            emergency_stop();
+            previous_hook(info);
+        }));
 
-        self.set_pwm(channel, 0, (percentage * 4095.0).round() as u16)
+        Ok(())
     }
+}
 
-    fn set_pwm(&self, channel: u8, on: u16, off: u16) -> Result<(), SetPWMError> {
-        assert!(channel < 16);
+// Returns every PCA9685 on the bus to its power-on state, using the general call software reset: all outputs off,
+// asleep, on the internal oscillator. Unlike setting the device up again, this also clears the EXTCLK bit, which
+// otherwise sticks until the device is power cycled.
+pub fn software_reset(i2c_device_file_path: &Path) -> Result<(), SetupError> {
+    let general_call_device = I2CDevice::new(
+        i2c_device_file_path,
+        I2C_GENERAL_CALL_ADDRESS,
+        AddressingOptions::default(),
+    )?;
+    general_call_device.write_byte(SWRST_COMMAND)?;
 
+    Ok(())
+}
+
+// Turns all outputs fully off and puts the device to sleep, which stops all PWM output. The outputs stay off should
+// the device be woken up again, until a channel is written to. This is a last-ditch effort: errors are ignored, as
+// there is nothing left to do about them. Does nothing if no emergency stop was installed.
+pub fn emergency_stop() {
+    if let Some(device) = EMERGENCY_STOP_DEVICE.get() {
+        // The panicking thread may be holding the bus lock.
+        let _ = device.write_byte_data_without_lock(REGISTER_ALL_LED_OFF_H, LED_FULL_FLAG);
+        let _ = device
+            .write_byte_data_without_lock(REGISTER_MODE1, MODE1_ALLCALL_FLAG | MODE1_SLEEP_FLAG);
+    }
+}
+
+impl<T: I2CTransport> PCA9685Driver<T> {
+    pub fn with_transport(
+        i2c_device: T,
+        pwm_frequency: u32,
+        settings: &PCA9685Settings,
+    ) -> Result<Self, SetupError> {
+        let mut mode1 = MODE1_ALLCALL_FLAG;
+        if settings.external_clock_frequency.is_some() {
+            mode1 |= MODE1_EXTCLK_FLAG;
+        }
+        let oscillator_frequency = settings.oscillator_frequency();
+        let prescale = prescale_value_for_frequency(pwm_frequency, oscillator_frequency)?;
+        configure(&i2c_device, mode1, prescale)?;
+
+        // The PWM outputs will remain reset after the sleep cycle, as RESTART is not written, so the device should be
+        // in fresh start-up state now.
+
+        let mut driver = Self {
+            i2c_device,
+            mode1,
+            prescale,
+            pwm_frequency: oscillator_frequency / (4096.0 * (prescale as f64 + 1.0)),
+            phase_offsets: [0; 16],
+        };
+        if settings.stagger_outputs {
+            driver.stagger_outputs();
+        }
+
+        Ok(driver)
+    }
+
+    // Sets the device up again as it was when the driver was created, e.g. after it may have lost its state while
+    // the I2C bus was locked up. All outputs are off until written to again.
+    pub fn reinitialize(&self) -> Result<(), SetupError> {
+        configure(&self.i2c_device, self.mode1, self.prescale)
+    }
+
+    // Whether the registers still read back as the driver set them up, which they do not when the device lost power
+    // at some point (and with it its configuration). `asleep` tells whether the device was put to sleep.
+    pub fn has_lost_configuration(&self, asleep: bool) -> Result<bool, SetPWMError> {
+        let prescale = self.i2c_device.read_byte_data(REGISTER_PRESCALE)?;
+        // The RESTART bit comes and goes with sleeping, and EXTCLK sticks anyway.
+        let mode1 = self.i2c_device.read_byte_data(REGISTER_MODE1)? & !MODE1_RESTART_FLAG;
+        let expected_mode1 = if asleep {
+            self.mode1 | MODE1_SLEEP_FLAG
+        } else {
+            self.mode1
+        };
+
+        Ok(prescale != self.prescale || mode1 != expected_mode1)
+    }
+
+    // The frequency the outputs actually run at, as far as the oscillator frequency is known. This differs slightly
+    // from the requested frequency, as the prescale is a whole number.
+    pub fn pwm_frequency(&self) -> f64 {
+        self.pwm_frequency
+    }
+
+    // By default, every channel's pulse starts at count 0, so that all servos draw their current at the same moment,
+    // which can brown out the supply. This starts each channel's pulse at `staggered_phase_offset` instead, spreading
+    // the load across the period. The offsets take effect with the next value written to each channel.
+    pub fn stagger_outputs(&mut self) {
+        self.phase_offsets = std::array::from_fn(|channel| staggered_phase_offset(channel as u8));
+    }
+
+    // Stops all PWM output and the oscillator, which is the lowest power state the device has. The PWM registers keep
+    // their values, and can be written while asleep.
+    pub fn sleep(&self) -> Result<(), SetPWMError> {
         self.i2c_device
-            .write_byte_data(REGISTER_LED0_ON_L + 4 * channel, (on & 0xFF) as u8)?;
+            .write_byte_data(REGISTER_MODE1, self.mode1 | MODE1_SLEEP_FLAG)?;
+
+        Ok(())
+    }
+
+    // Starts the oscillator again. When the device was put to sleep with outputs active, it sets the RESTART bit, and
+    // writing that bit back once the oscillator is stable resumes all of them with the values they had. Otherwise,
+    // output only resumes for channels that are written to afterwards.
+    pub fn wake(&self) -> Result<(), SetPWMError> {
+        let restart = self.i2c_device.read_byte_data(REGISTER_MODE1)? & MODE1_RESTART_FLAG != 0;
+
         self.i2c_device
-            .write_byte_data(REGISTER_LED0_ON_H + 4 * channel, (on >> 8) as u8)?;
+            .write_byte_data(REGISTER_MODE1, self.mode1)?;
+        std::thread::sleep(Duration::from_micros(500));
+
+        if restart {
+            self.i2c_device
+                .write_byte_data(REGISTER_MODE1, self.mode1 | MODE1_RESTART_FLAG)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_pwm_on_percentage(&self, channel: u8, percentage: f64) -> Result<(), SetPWMError> {
+        check_channel(channel)?;
+
+        self.set_pwm_on_count(channel, duty_cycle_count(percentage)?)
+    }
+
+    // Like `set_pwm_on_percentage`, with the duty cycle already worked out with `duty_cycle_count`.
+    pub fn set_pwm_on_count(&self, channel: u8, count: u16) -> Result<(), SetPWMError> {
+        check_channel(channel)?;
+        if count > 4095 {
+            return Err(SetPWMError::InvalidDutyCycle {
+                percentage: count as f64 / 4095.0,
+            });
+        }
+
+        // A pulse may wrap around the end of the period. A duty cycle of 0 results in equal ON and OFF counts, which
+        // the device takes as being off all the time, as it did without an offset.
+        let on = self.phase_offsets[channel as usize];
+        let off = (on + count) % 4096;
+
+        self.set_pwm(channel, on, off)
+    }
+
+    // Keeps the output high, without any pulses. Writing a PWM value to the channel ends this.
+    pub fn set_full_on(&self, channel: u8) -> Result<(), SetPWMError> {
+        check_channel(channel)?;
+
+        // Full OFF takes precedence over full ON, so it has to be cleared as well.
+        self.i2c_device.write_byte_data_sequence(&[
+            (REGISTER_LED0_ON_H + 4 * channel, LED_FULL_FLAG),
+            (REGISTER_LED0_OFF_H + 4 * channel, 0),
+        ])?;
+
+        Ok(())
+    }
+
+    // Keeps the output low, without any pulses. Writing a PWM value to the channel ends this.
+    pub fn set_full_off(&self, channel: u8) -> Result<(), SetPWMError> {
+        check_channel(channel)?;
+
         self.i2c_device
-            .write_byte_data(REGISTER_LED0_OFF_L + 4 * channel, (off & 0xFF) as u8)?;
+            .write_byte_data(REGISTER_LED0_OFF_H + 4 * channel, LED_FULL_FLAG)?;
+
+        Ok(())
+    }
+
+    // Turns every output fully off at once, in a single write to the ALL_LED registers. Each channel stays off until
+    // a PWM value is written to it.
+    pub fn set_all_full_off(&self) -> Result<(), SetPWMError> {
         self.i2c_device
-            .write_byte_data(REGISTER_LED0_OFF_H + 4 * channel, (off >> 8) as u8)?;
+            .write_byte_data(REGISTER_ALL_LED_OFF_H, LED_FULL_FLAG)?;
+
+        Ok(())
+    }
+
+    fn set_pwm(&self, channel: u8, on: u16, off: u16) -> Result<(), SetPWMError> {
+        check_channel(channel)?;
+
+        // Whoever else uses the bus does not get to see (or cause) a half-written channel.
+        self.i2c_device.write_byte_data_sequence(&[
+            (REGISTER_LED0_ON_L + 4 * channel, (on & 0xFF) as u8),
+            (REGISTER_LED0_ON_H + 4 * channel, (on >> 8) as u8),
+            (REGISTER_LED0_OFF_L + 4 * channel, (off & 0xFF) as u8),
+            (REGISTER_LED0_OFF_H + 4 * channel, (off >> 8) as u8),
+        ])?;
 
         Ok(())
     }
@@ -58,20 +290,34 @@ impl PCA9685Driver {
 pub enum SetupError {
     I2CWriteError { source: i2c::WriteError },
     I2CSetupError { source: i2c::SetupError },
+    UnsupportedPWMFrequency { pwm_frequency: u32 },
 }
 
 impl Error for SetupError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(match self {
-            SetupError::I2CWriteError { source } => source,
-            SetupError::I2CSetupError { source } => source,
-        })
+        match self {
+            SetupError::I2CWriteError { source } => Some(source),
+            SetupError::I2CSetupError { source } => Some(source),
+            SetupError::UnsupportedPWMFrequency { pwm_frequency: _ } => None,
+        }
     }
 }
 
 impl std::fmt::Display for SetupError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Could not set up PCA9685 device.")
+        let description = match self {
+            SetupError::I2CWriteError { source: _ } | SetupError::I2CSetupError { source: _ } => {
+                "Could not set up PCA9685 device.".to_string()
+            }
+            SetupError::UnsupportedPWMFrequency { pwm_frequency } => {
+                format!(
+                    "A PWM frequency of {} Hz is outside of what the PCA9685 prescaler supports.",
+                    pwm_frequency
+                )
+            }
+        };
+
+        write!(f, "{}", description)
     }
 }
 
@@ -87,22 +333,93 @@ impl From<i2c::SetupError> for SetupError {
     }
 }
 
+// Leaves the device awake with all outputs reset, running at `prescale`.
+fn configure<T: I2CTransport>(i2c_device: &T, mode1: u8, prescale: u8) -> Result<(), SetupError> {
+    // This resets MODE1 and MODE2 to their default values. Setting the SLEEP bit will stop all PWM output.
+    i2c_device.write_byte_data(REGISTER_MODE1, MODE1_ALLCALL_FLAG | MODE1_SLEEP_FLAG)?;
+    i2c_device.write_byte_data(REGISTER_MODE2, MODE2_OUTDRV_FLAG)?;
+
+    // Switching to the external clock also requires the SLEEP bit to be set, in a separate write. The EXTCLK bit then
+    // sticks until the device is reset, whatever is written to MODE1 later on.
+    if mode1 & MODE1_EXTCLK_FLAG != 0 {
+        i2c_device.write_byte_data(REGISTER_MODE1, mode1 | MODE1_SLEEP_FLAG)?;
+    }
+
+    // The prescale can only be set while the SLEEP bit is set.
+    i2c_device.write_byte_data(REGISTER_PRESCALE, prescale)?;
+
+    // After wake-up, a 500μs delay is required before configuring PWM outputs.
+    i2c_device.write_byte_data(REGISTER_MODE1, mode1)?;
+    std::thread::sleep(Duration::from_micros(500));
+
+    Ok(())
+}
+
+fn check_channel(channel: u8) -> Result<(), SetPWMError> {
+    if channel >= 16 {
+        return Err(SetPWMError::InvalidChannel { channel });
+    }
+
+    Ok(())
+}
+
+// How many of the 4096 counts of a period the output is to be on for a duty cycle from 0.0 to 1.0.
+pub fn duty_cycle_count(percentage: f64) -> Result<u16, SetPWMError> {
+    if !(0.0..=1.0).contains(&percentage) {
+        return Err(SetPWMError::InvalidDutyCycle { percentage });
+    }
+
+    Ok((percentage * 4095.0).round() as u16)
+}
+
 #[derive(Debug)]
 pub enum SetPWMError {
     I2CWriteError { source: i2c::WriteError },
+    I2CReadError { source: i2c::ReadError },
+    InvalidChannel { channel: u8 },
+    InvalidDutyCycle { percentage: f64 },
+}
+
+impl SetPWMError {
+    pub fn indicates_stuck_bus(&self) -> bool {
+        match self {
+            SetPWMError::I2CWriteError { source } => source.indicates_stuck_bus(),
+            SetPWMError::I2CReadError { source } => source.indicates_stuck_bus(),
+            SetPWMError::InvalidChannel { channel: _ } => false,
+            SetPWMError::InvalidDutyCycle { percentage: _ } => false,
+        }
+    }
 }
 
 impl Error for SetPWMError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        Some(match self {
-            SetPWMError::I2CWriteError { source } => source,
-        })
+        match self {
+            SetPWMError::I2CWriteError { source } => Some(source),
+            SetPWMError::I2CReadError { source } => Some(source),
+            SetPWMError::InvalidChannel { channel: _ } => None,
+            SetPWMError::InvalidDutyCycle { percentage: _ } => None,
+        }
     }
 }
 
 impl std::fmt::Display for SetPWMError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Could not set PWM value on PCA9685 device.")
+        let description = match self {
+            SetPWMError::I2CWriteError { source: _ } | SetPWMError::I2CReadError { source: _ } => {
+                "Could not set PWM value on PCA9685 device.".to_string()
+            }
+            SetPWMError::InvalidChannel { channel } => {
+                format!("The PCA9685 has no channel {}.", channel)
+            }
+            SetPWMError::InvalidDutyCycle { percentage } => {
+                format!(
+                    "Invalid PWM duty cycle {} (expected 0.0 to 1.0).",
+                    percentage
+                )
+            }
+        };
+
+        write!(f, "{}", description)
     }
 }
 
@@ -112,7 +429,15 @@ impl From<i2c::WriteError> for SetPWMError {
     }
 }
 
+impl From<i2c::ReadError> for SetPWMError {
+    fn from(value: i2c::ReadError) -> Self {
+        SetPWMError::I2CReadError { source: value }
+    }
+}
+
 const I2C_BUS_ADDRESS: i32 = 0x40;
+const I2C_GENERAL_CALL_ADDRESS: i32 = 0x00;
+const SWRST_COMMAND: u8 = 0x06;
 
 const REGISTER_MODE1: u8 = 0x00;
 const REGISTER_MODE2: u8 = 0x01;
@@ -120,21 +445,296 @@ const REGISTER_LED0_ON_L: u8 = 0x06;
 const REGISTER_LED0_ON_H: u8 = 0x07;
 const REGISTER_LED0_OFF_L: u8 = 0x08;
 const REGISTER_LED0_OFF_H: u8 = 0x09;
+const REGISTER_ALL_LED_OFF_H: u8 = 0xFD;
 const REGISTER_PRESCALE: u8 = 0xFE;
 
+// Bit 4 of the LEDn_ON_H and LEDn_OFF_H registers (and their ALL_LED counterparts) keeps the output fully on or off.
+const LED_FULL_FLAG: u8 = 0x10;
+
 const MODE2_OUTDRV_FLAG: u8 = 0x04;
 
 const MODE1_ALLCALL_FLAG: u8 = 0x01;
 const MODE1_SLEEP_FLAG: u8 = 0x10;
+const MODE1_EXTCLK_FLAG: u8 = 0x40;
+const MODE1_RESTART_FLAG: u8 = 0x80;
+
+const INTERNAL_OSCILLATOR_FREQUENCY: f64 = 25_000_000.0;
+
+// Channels sorted by their bit-reversed number get evenly spaced offsets, so that however few of the lowest channels are
+// in use, their pulses start as far apart as possible: 0 and 1 (throttle and steering) half a period apart, 2 and 3 a
+// quarter period from those, and so on.
+fn staggered_phase_offset(channel: u8) -> u16 {
+    ((channel.reverse_bits() >> 4) as u16) * (4096 / 16)
+}
+
+// The prescaler only accepts values from 3 upwards, which limits the PWM frequency to roughly 24 to 1526 Hz with the
+// internal oscillator.
+pub fn is_supported_pwm_frequency(pwm_frequency: u32, settings: &PCA9685Settings) -> bool {
+    prescale_value_for_frequency(pwm_frequency, settings.oscillator_frequency()).is_ok()
+}
+
+fn prescale_value_for_frequency(
+    pwm_frequency: u32,
+    oscillator_frequency: f64,
+) -> Result<u8, SetupError> {
+    let prescale_value = (oscillator_frequency / (4096.0 * pwm_frequency as f64)).round() - 1.0;
+
+    if !(0x03 as f64..=0xFF as f64).contains(&prescale_value) {
+        return Err(SetupError::UnsupportedPWMFrequency { pwm_frequency });
+    }
+
+    Ok(prescale_value as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::mock::MockI2CTransport;
+
+    fn create_driver() -> PCA9685Driver<MockI2CTransport> {
+        PCA9685Driver::with_transport(MockI2CTransport::new(), 50, &PCA9685Settings::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn initialization_sequence() {
+        let driver = create_driver();
+
+        assert_eq!(
+            driver.i2c_device.writes(),
+            vec![
+                (REGISTER_MODE1, MODE1_ALLCALL_FLAG | MODE1_SLEEP_FLAG),
+                (REGISTER_MODE2, MODE2_OUTDRV_FLAG),
+                (REGISTER_PRESCALE, 121),
+                (REGISTER_MODE1, MODE1_ALLCALL_FLAG),
+            ]
+        );
+    }
 
-fn prescale_value_for_frequency(pwm_frequency: u32) -> u8 {
-    let internal_oscillator_frequency: f64 = 25000000.0;
-    let pwm_frequency = pwm_frequency as f64;
+    #[test]
+    fn prescale_values() {
+        let internal = INTERNAL_OSCILLATOR_FREQUENCY;
+        assert_eq!(prescale_value_for_frequency(50, internal).unwrap(), 121);
+        assert_eq!(prescale_value_for_frequency(200, internal).unwrap(), 30);
+        assert_eq!(prescale_value_for_frequency(1526, internal).unwrap(), 0x03);
+        assert_eq!(prescale_value_for_frequency(24, internal).unwrap(), 253);
+        assert_eq!(prescale_value_for_frequency(50, 16_000_000.0).unwrap(), 77);
 
-    let prescale_value = (internal_oscillator_frequency / (4096.0 * pwm_frequency)).round() - 1.0;
+        assert!(prescale_value_for_frequency(23, internal).is_err());
+        assert!(prescale_value_for_frequency(2000, internal).is_err());
+        assert!(prescale_value_for_frequency(0, internal).is_err());
+    }
+
+    #[test]
+    fn frequency_correction() {
+        // An oscillator running 3% fast needs a larger prescale for the same frequency, and what remains of the
+        // error is known.
+        let settings = PCA9685Settings {
+            frequency_correction: 1.03,
+            ..PCA9685Settings::default()
+        };
+        let driver = PCA9685Driver::with_transport(MockI2CTransport::new(), 50, &settings).unwrap();
+
+        assert_eq!(driver.i2c_device.register(REGISTER_PRESCALE), 125);
+        assert!((driver.pwm_frequency() - 49.89).abs() < 0.01);
+    }
+
+    #[test]
+    fn external_clock() {
+        let settings = PCA9685Settings {
+            external_clock_frequency: Some(16_000_000),
+            ..PCA9685Settings::default()
+        };
+        let driver = PCA9685Driver::with_transport(MockI2CTransport::new(), 50, &settings).unwrap();
+
+        assert_eq!(
+            driver.i2c_device.writes(),
+            vec![
+                (REGISTER_MODE1, MODE1_ALLCALL_FLAG | MODE1_SLEEP_FLAG),
+                (REGISTER_MODE2, MODE2_OUTDRV_FLAG),
+                (
+                    REGISTER_MODE1,
+                    MODE1_ALLCALL_FLAG | MODE1_EXTCLK_FLAG | MODE1_SLEEP_FLAG
+                ),
+                (REGISTER_PRESCALE, 77),
+                (REGISTER_MODE1, MODE1_ALLCALL_FLAG | MODE1_EXTCLK_FLAG),
+            ]
+        );
+    }
+
+    #[test]
+    fn restart_after_sleep() {
+        let driver = create_driver();
+        driver.sleep().unwrap();
+
+        // Asleep without outputs having been active, there is nothing to restart.
+        driver.i2c_device.clear_writes();
+        driver.wake().unwrap();
+        assert_eq!(
+            driver.i2c_device.writes(),
+            vec![(REGISTER_MODE1, MODE1_ALLCALL_FLAG)]
+        );
+
+        // The device sets the RESTART bit itself when put to sleep with outputs active.
+        driver
+            .i2c_device
+            .write_byte_data(
+                REGISTER_MODE1,
+                MODE1_RESTART_FLAG | MODE1_SLEEP_FLAG | MODE1_ALLCALL_FLAG,
+            )
+            .unwrap();
+        driver.i2c_device.clear_writes();
+        driver.wake().unwrap();
+        assert_eq!(
+            driver.i2c_device.writes(),
+            vec![
+                (REGISTER_MODE1, MODE1_ALLCALL_FLAG),
+                (REGISTER_MODE1, MODE1_ALLCALL_FLAG | MODE1_RESTART_FLAG),
+            ]
+        );
+    }
+
+    #[test]
+    fn lost_configuration() {
+        let driver = create_driver();
+        assert!(!driver.has_lost_configuration(false).unwrap());
+        assert!(driver.has_lost_configuration(true).unwrap());
+
+        driver.sleep().unwrap();
+        assert!(!driver.has_lost_configuration(true).unwrap());
+        driver.wake().unwrap();
+
+        // What the device comes up with after power-on.
+        driver
+            .i2c_device
+            .write_byte_data(REGISTER_MODE1, MODE1_SLEEP_FLAG | MODE1_ALLCALL_FLAG)
+            .unwrap();
+        driver
+            .i2c_device
+            .write_byte_data(REGISTER_PRESCALE, 0x1E)
+            .unwrap();
+        assert!(driver.has_lost_configuration(false).unwrap());
+
+        driver.reinitialize().unwrap();
+        assert!(!driver.has_lost_configuration(false).unwrap());
+    }
+
+    #[test]
+    fn channel_register_layout() {
+        let driver = create_driver();
+        driver.i2c_device.clear_writes();
+
+        driver.set_pwm(0, 0x0123, 0x0456).unwrap();
+        driver.set_pwm(15, 0x0789, 0x0ABC).unwrap();
 
-    assert!(prescale_value >= 0x03 as f64);
-    assert!(prescale_value <= 0xFF as f64);
+        assert_eq!(
+            driver.i2c_device.writes(),
+            vec![
+                (0x06, 0x23),
+                (0x07, 0x01),
+                (0x08, 0x56),
+                (0x09, 0x04),
+                (0x42, 0x89),
+                (0x43, 0x07),
+                (0x44, 0xBC),
+                (0x45, 0x0A),
+            ]
+        );
+    }
+
+    #[test]
+    fn pwm_on_percentage_to_off_count() {
+        let driver = create_driver();
+
+        driver.set_pwm_on_percentage(2, 0.0).unwrap();
+        assert_eq!(driver.i2c_device.register(0x0E), 0x00);
+        assert_eq!(driver.i2c_device.register(0x0F), 0x00);
+
+        driver.set_pwm_on_percentage(2, 0.5).unwrap();
+        assert_eq!(driver.i2c_device.register(0x10), 0x00);
+        assert_eq!(driver.i2c_device.register(0x11), 0x08);
+
+        driver.set_pwm_on_percentage(2, 1.0).unwrap();
+        assert_eq!(driver.i2c_device.register(0x10), 0xFF);
+        assert_eq!(driver.i2c_device.register(0x11), 0x0F);
+    }
+
+    #[test]
+    fn invalid_channels_and_duty_cycles() {
+        let driver = create_driver();
+        driver.i2c_device.clear_writes();
+
+        assert!(driver.set_pwm_on_percentage(15, 0.0).is_ok());
+        assert!(driver.set_pwm_on_percentage(15, 1.0).is_ok());
+        assert!(matches!(
+            driver.set_pwm_on_percentage(16, 0.5),
+            Err(SetPWMError::InvalidChannel { channel: 16 })
+        ));
+        assert!(matches!(
+            driver.set_full_on(16),
+            Err(SetPWMError::InvalidChannel { channel: 16 })
+        ));
+        for percentage in [-0.0001, 1.0001, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                driver.set_pwm_on_percentage(2, percentage),
+                Err(SetPWMError::InvalidDutyCycle { .. })
+            ));
+        }
+
+        // Nothing invalid made it to the device.
+        assert_eq!(driver.i2c_device.writes().len(), 8);
+    }
+
+    #[test]
+    fn full_on_and_off() {
+        let driver = create_driver();
+        driver.i2c_device.clear_writes();
+
+        driver.set_full_on(3).unwrap();
+        driver.set_full_off(3).unwrap();
+        driver.set_pwm(3, 0, 0x0100).unwrap();
+        driver.set_all_full_off().unwrap();
+
+        assert_eq!(
+            driver.i2c_device.writes(),
+            vec![
+                (0x13, 0x10),
+                (0x15, 0x00),
+                (0x15, 0x10),
+                (0x12, 0x00),
+                (0x13, 0x00),
+                (0x14, 0x00),
+                (0x15, 0x01),
+                (0xFD, 0x10),
+            ]
+        );
+    }
 
-    prescale_value as u8
+    #[test]
+    fn phase_offsets() {
+        let mut driver = create_driver();
+        driver.stagger_outputs();
+
+        // Channel 1 starts half a period in, and its pulse ends within the period.
+        driver.set_pwm_on_percentage(1, 0.1).unwrap();
+        assert_eq!(driver.i2c_device.register(0x0A), 0x00);
+        assert_eq!(driver.i2c_device.register(0x0B), 0x08);
+        assert_eq!(driver.i2c_device.register(0x0C), 0x9A);
+        assert_eq!(driver.i2c_device.register(0x0D), 0x09);
+
+        // Channel 3 starts three quarters in, and its pulse wraps around the end of the period.
+        driver.set_pwm_on_percentage(3, 0.5).unwrap();
+        assert_eq!(driver.i2c_device.register(0x12), 0x00);
+        assert_eq!(driver.i2c_device.register(0x13), 0x0C);
+        assert_eq!(driver.i2c_device.register(0x14), 0x00);
+        assert_eq!(driver.i2c_device.register(0x15), 0x04);
+
+        assert_eq!(
+            (0..16).map(staggered_phase_offset).collect::<Vec<_>>(),
+            vec![
+                0, 2048, 1024, 3072, 512, 2560, 1536, 3584, 256, 2304, 1280, 3328, 768, 2816, 1792,
+                3840
+            ]
+        );
+    }
 }
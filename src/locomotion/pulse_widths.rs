@@ -0,0 +1,154 @@
+use std::fs::{self, File};
+use std::io::Error as IoError;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// What a servo or ESC can be sent: the pulse width in microseconds corresponding to locomotion values of -1.0, 0.0
+// and 1.0. Values in between are interpolated linearly on either side of the center.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PulseWidths {
+    pub negative_us: u32,
+    pub center_us: u32,
+    pub positive_us: u32,
+}
+
+// Standard RC pulses: 1.5ms is neutral, and the extremes are 0.5ms to either side of it.
+pub const DEFAULT_PULSE_WIDTHS: PulseWidths = PulseWidths {
+    negative_us: 2000,
+    center_us: 1500,
+    positive_us: 1000,
+};
+
+// Anything outside of this is beyond what any servo is meant to be sent.
+pub const MINIMUM_PULSE_WIDTH_US: u32 = 500;
+pub const MAXIMUM_PULSE_WIDTH_US: u32 = 2500;
+
+impl Default for PulseWidths {
+    fn default() -> Self {
+        DEFAULT_PULSE_WIDTHS
+    }
+}
+
+impl PulseWidths {
+    pub fn pulse_width_us(&self, value: f64) -> f64 {
+        let center = self.center_us as f64;
+        let extreme = if value >= 0.0 {
+            self.positive_us as f64
+        } else {
+            self.negative_us as f64
+        };
+
+        center + (extreme - center) * value.abs()
+    }
+
+    // Whether the center lies between the extremes, which in turn lie on different sides of it.
+    pub fn is_consistent(&self) -> bool {
+        let (low, high) = if self.negative_us < self.positive_us {
+            (self.negative_us, self.positive_us)
+        } else {
+            (self.positive_us, self.negative_us)
+        };
+
+        low < self.center_us && self.center_us < high
+    }
+
+    // Loads pulse widths saved with `save`. A missing file means nothing was saved, which is no error.
+    pub fn load(path: &Path) -> Result<Option<PulseWidths>, IoError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        let mut pulse_widths = DEFAULT_PULSE_WIDTHS;
+
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| invalid_data(line))?;
+
+            let value = value
+                .parse::<u32>()
+                .ok()
+                .filter(|value| (MINIMUM_PULSE_WIDTH_US..=MAXIMUM_PULSE_WIDTH_US).contains(value))
+                .ok_or_else(|| invalid_data(line))?;
+
+            match key {
+                "negative_us" => pulse_widths.negative_us = value,
+                "center_us" => pulse_widths.center_us = value,
+                "positive_us" => pulse_widths.positive_us = value,
+                _ => return Err(invalid_data(line)),
+            }
+        }
+
+        if !pulse_widths.is_consistent() {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                "The center pulse width does not lie between the extremes.",
+            ));
+        }
+
+        Ok(Some(pulse_widths))
+    }
+
+    // Like the odometer state, the pulse widths are written to a temporary file first, which then atomically replaces
+    // the previous file.
+    pub fn save(&self, path: &Path) -> Result<(), IoError> {
+        let mut temporary_path = path.as_os_str().to_owned();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+
+        let mut file = File::create(&temporary_path)?;
+        write!(
+            file,
+            "negative_us = {}\ncenter_us = {}\npositive_us = {}\n",
+            self.negative_us, self.center_us, self.positive_us
+        )?;
+        file.sync_all()?;
+
+        fs::rename(&temporary_path, path)
+    }
+}
+
+// Written as the pulse widths for -1.0, 0.0 and 1.0 separated by spaces, e.g. `1100 1500 1900`, for servos that are
+// configured rather than calibrated.
+impl FromStr for PulseWidths {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let values = text
+            .split_whitespace()
+            .map(|part| {
+                part.parse::<u32>()
+                    .ok()
+                    .filter(|value| {
+                        (MINIMUM_PULSE_WIDTH_US..=MAXIMUM_PULSE_WIDTH_US).contains(value)
+                    })
+                    .ok_or(())
+            })
+            .collect::<Result<Vec<u32>, ()>>()?;
+        let [negative_us, center_us, positive_us] = values[..] else {
+            return Err(());
+        };
+
+        let pulse_widths = PulseWidths {
+            negative_us,
+            center_us,
+            positive_us,
+        };
+        if !pulse_widths.is_consistent() {
+            return Err(());
+        }
+
+        Ok(pulse_widths)
+    }
+}
+
+fn invalid_data(line: &str) -> IoError {
+    IoError::new(
+        ErrorKind::InvalidData,
+        format!("Invalid line in pulse widths file: '{}'.", line),
+    )
+}
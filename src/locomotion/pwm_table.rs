@@ -0,0 +1,103 @@
+use super::controller::locomotion_value_to_pwm_on_percentage;
+use super::pca9685::{self, SetPWMError};
+use super::pulse_widths::PulseWidths;
+
+// Locomotion values are quantized to this many steps on either side of neutral. With the default pulse widths, a step
+// is 0.5μs, well below the about 5μs a duty cycle count amounts to at 50 Hz. A value therefore gets at most one count
+// off from what working it out exactly would give, and only when it lies close to halfway between two counts.
+const STEPS: usize = 1000;
+
+// The duty cycle count for every quantized locomotion value from -1.0 to 1.0, worked out for given pulse widths and
+// PWM frequency.
+pub struct PwmTable {
+    counts: Box<[u16]>,
+}
+
+impl PwmTable {
+    pub fn new(pulse_widths: &PulseWidths, pwm_frequency: f64) -> Result<PwmTable, SetPWMError> {
+        let counts = (0..=2 * STEPS)
+            .map(|index| {
+                let value = index as f64 / STEPS as f64 - 1.0;
+                pca9685::duty_cycle_count(locomotion_value_to_pwm_on_percentage(
+                    value,
+                    pulse_widths,
+                    pwm_frequency,
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(PwmTable { counts })
+    }
+
+    // Values outside of -1.0 to 1.0 (which `Throttle` and `Steering` never are) get the count of the nearest extreme.
+    pub fn count(&self, value: f64) -> u16 {
+        let index = ((value + 1.0) * STEPS as f64).round() as usize;
+
+        self.counts[index.min(2 * STEPS)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::locomotion::PWM_FREQUENCY;
+    use std::hint::black_box;
+    use std::time::Duration;
+
+    fn exact_count(value: f64, pulse_widths: &PulseWidths) -> u16 {
+        pca9685::duty_cycle_count(locomotion_value_to_pwm_on_percentage(
+            value,
+            pulse_widths,
+            PWM_FREQUENCY as f64,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn lookup_matches_working_out() {
+        let pulse_widths = PulseWidths {
+            negative_us: 1100,
+            center_us: 1520,
+            positive_us: 1950,
+        };
+        let table = PwmTable::new(&pulse_widths, PWM_FREQUENCY as f64).unwrap();
+
+        for value in [-1.0, -0.5, 0.0, 0.25, 1.0] {
+            assert_eq!(table.count(value), exact_count(value, &pulse_widths));
+        }
+        for index in 0..=20_000 {
+            let value = index as f64 / 10_000.0 - 1.0;
+            assert!(
+                table
+                    .count(value)
+                    .abs_diff(exact_count(value, &pulse_widths))
+                    <= 1
+            );
+        }
+        assert_eq!(table.count(-7.0), table.count(-1.0));
+        assert_eq!(table.count(f64::INFINITY), table.count(1.0));
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark_pwm_lookup() {
+        let pulse_widths = PulseWidths::default();
+        let table = PwmTable::new(&pulse_widths, PWM_FREQUENCY as f64).unwrap();
+        let value = |iteration: u32| (iteration % 2001) as f64 / 1000.0 - 1.0;
+
+        crate::benchmark::measure(
+            "Working out a duty cycle",
+            Duration::from_micros(1),
+            |iteration| {
+                black_box(exact_count(black_box(value(iteration)), &pulse_widths));
+            },
+        );
+        crate::benchmark::measure(
+            "Looking up a duty cycle",
+            Duration::from_micros(1),
+            |iteration| {
+                black_box(table.count(black_box(value(iteration))));
+            },
+        );
+    }
+}
@@ -1,4 +1,7 @@
+use crate::clock::{Clock, MonotonicClock};
 use log::{Level, Log, Metadata, Record, SetLoggerError};
+use std::sync::Mutex;
+use std::time::Duration;
 
 pub struct SimpleLogger;
 
@@ -23,6 +26,16 @@ impl Log for SimpleLogger {
                 record.target(),
                 record.args()
             );
+
+            if record.level() <= Level::Warn {
+                let logged_error = LoggedError {
+                    message: record.args().to_string(),
+                    timestamp: MonotonicClock.now(),
+                };
+                if let Ok(mut last_error) = LAST_ERROR.lock() {
+                    *last_error = Some(logged_error);
+                }
+            }
         }
     }
 
@@ -30,3 +43,32 @@ impl Log for SimpleLogger {
 }
 
 const HARDCODED_MAX_LEVEL: Level = Level::Info;
+
+// Most problems while running are dealt with and only logged, so the last one of those is kept around for reporting
+// on the service's health. Warnings count as well, as that is how most of them are logged.
+static LAST_ERROR: Mutex<Option<LoggedError>> = Mutex::new(None);
+
+#[derive(Debug, Clone)]
+pub struct LoggedError {
+    pub message: String,
+    // On the clock of `runloop::now()`.
+    pub timestamp: Duration,
+}
+
+pub fn last_error() -> Option<LoggedError> {
+    LAST_ERROR
+        .lock()
+        .ok()
+        .and_then(|last_error| last_error.clone())
+}
+
+// Durations in log messages and reports are formatted as e.g. `2h05m09s` rather than `7509.123456s`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}h{:02}m{:02}s",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
@@ -1,63 +1,1537 @@
-use crate::gamepads::GamepadInputInterpreter;
-use crate::locomotion::LocomotionController;
+// Error variants are consistently named after what could not be done (`CouldNot...`).
+#![allow(clippy::enum_variant_names)]
+
+use crate::application_state::{ApplicationState, ApplicationStateMachine, Conditions};
+use crate::arbitration::{Arbiter, CommandSource, SafetyLimit};
+use crate::arguments::{Arguments, Mode};
+use crate::auxiliary_channel::{AuxiliaryChannel, AuxiliarySettings};
+use crate::buzzer::{Alert, Buzzer};
+use crate::choreography::{Choreography, ChoreographyPlayer};
+use crate::configuration::Configuration;
+use crate::configuration_check::Severity;
+use crate::configuration_reloader::ConfigurationReloader;
+use crate::control::{ControlSocket, Request, Response};
+use crate::daemon::PidFile;
+#[cfg(feature = "dbus")]
+use crate::dbus::DBusService;
+use crate::engine_sound::{EngineSound, EngineSoundSettings};
+use crate::event_bus::{Event, EventBus, EventSubscriber};
+use crate::exit_codes::FatalErrorKind;
+use crate::failsafe_policy::FailureSource;
+use crate::fan::{Fan, FanOutput, FanSettings};
+use crate::gamepads::{GamepadHaptics, GamepadLights, GamepadRumble, PermissionDiagnostic};
+use crate::geofence::{Boundary, Geofence, GeofenceSettings};
+use crate::gimbal::{Gimbal, GimbalAxisSettings, GimbalSettings};
+use crate::gps::GpsReceiver;
+use crate::guest_lock::GuestLock;
+use crate::idle::{IdleMonitor, InactivityLock};
+use crate::input_source::{AuxiliaryAxis, InputNotification, InputSource, InputSourceKind};
+use crate::kill_relay::{KillRelay, KillRelaySettings};
+use crate::lighting::{Lighting, LightingSettings};
+use crate::locomotion::{BusRecovery, LocomotionCommand, LocomotionController, Output};
 use crate::logging::SimpleLogger;
-use crate::runloop::IterationOutcome;
+#[cfg(feature = "mdns")]
+use crate::mdns::{Endpoint, MdnsAdvertiser};
+use crate::network::{NetworkRuntime, NetworkServices};
+use crate::odometer::Odometer;
+use crate::runloop::{Iteration, IterationOutcome, Runloop};
+use crate::session_statistics::SessionStatistics;
 use crate::signals::{SignalIntention, SignalManager};
+use crate::status_file::{Status, StatusFile};
+use crate::status_led::StatusLed;
+use crate::subsystems::{Subsystem, SubsystemKind};
+use crate::telemetry::{CsvTelemetryLog, UdpTelemetrySender};
+use crate::thermal::{DeratingSettings, ThermalDerating, Tmp102};
+use crate::winch::Winch;
 use std::error::Error;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process::{self, ExitCode};
 use std::time::Duration;
 
+mod application_state;
+mod arbitration;
+mod arguments;
+mod auxiliary_channel;
+#[cfg(test)]
+mod benchmark;
+mod build_info;
+mod buzzer;
+mod choreography;
+mod clock;
+mod configuration;
+mod configuration_check;
+mod configuration_reloader;
+mod control;
+mod control_values;
+mod copilot;
+mod daemon;
+mod dbus;
+mod diagnostic_dump;
+mod driving;
+mod engine_sound;
+mod event_bus;
+mod exit_codes;
+mod failsafe_policy;
+mod fan;
 mod folder_monitor;
 mod gamepads;
+mod geofence;
+mod gimbal;
+mod gpio;
+mod gps;
+mod guest_lock;
 mod i2c;
+mod i2c_scan;
+mod idle;
+mod input_source;
+mod keyboard;
+mod kill_relay;
+mod lighting;
 mod locomotion;
 mod logging;
+#[cfg(feature = "mavlink")]
+mod mavlink;
+#[cfg(feature = "mdns")]
+mod mdns;
+mod network;
+mod odometer;
+mod registry;
 mod runloop;
+mod sbus;
+mod seccomp;
+mod self_test;
+mod serial;
+mod session_statistics;
 mod signals;
-
-const RUNLOOP_INTERVAL: Duration = Duration::from_millis(20);
+#[cfg(test)]
+mod simulation;
+mod status_file;
+mod status_led;
+mod steering_calibration;
+mod subsystems;
+mod telemetry;
+mod thermal;
+mod winch;
 
 fn main() -> ExitCode {
-    match run_application() {
-        Ok(_) => ExitCode::SUCCESS,
+    let arguments = match Arguments::parse() {
+        Ok(arguments) => arguments,
         Err(error) => {
-            log::error!("{}", FatalErrorFormatter { error: &error });
-            ExitCode::FAILURE
+            eprintln!("{}", FatalErrorFormatter { error: &error });
+            eprintln!("Try --help.");
+            return FatalErrorKind::InvalidArguments.into();
+        }
+    };
+
+    let configuration_file = arguments.configuration_file.as_deref();
+    let vehicle = arguments.vehicle.as_deref();
+
+    match arguments.mode {
+        Mode::Service { daemonize } => match run_service(configuration_file, vehicle, daemonize) {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    FatalErrorFormatter {
+                        error: error.as_ref()
+                    }
+                );
+                FatalErrorKind::classify(error.as_ref()).into()
+            }
+        },
+        Mode::CheckConfiguration => match run_configuration_check(configuration_file, vehicle) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => FatalErrorKind::InvalidConfiguration.into(),
+            Err(error) => {
+                eprintln!(
+                    "{}",
+                    FatalErrorFormatter {
+                        error: error.as_ref()
+                    }
+                );
+                FatalErrorKind::classify(error.as_ref()).into()
+            }
+        },
+        Mode::CalibrateSteering => match run_steering_calibration(configuration_file, vehicle) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::FAILURE,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    FatalErrorFormatter {
+                        error: error.as_ref()
+                    }
+                );
+                FatalErrorKind::classify(error.as_ref()).into()
+            }
+        },
+        Mode::ScanI2C { bus } => match run_i2c_scan(configuration_file, vehicle, bus) {
+            Ok(true) => ExitCode::SUCCESS,
+            Ok(false) => ExitCode::FAILURE,
+            Err(error) => {
+                eprintln!(
+                    "{}",
+                    FatalErrorFormatter {
+                        error: error.as_ref()
+                    }
+                );
+                FatalErrorKind::classify(error.as_ref()).into()
+            }
+        },
+        Mode::RecordRaw {
+            device_file,
+            capture_file,
+        } => match run_raw_recording(&device_file, &capture_file) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!(
+                    "{}",
+                    FatalErrorFormatter {
+                        error: error.as_ref()
+                    }
+                );
+                FatalErrorKind::classify(error.as_ref()).into()
+            }
+        },
+        Mode::PrintHelp => {
+            print!("{}", arguments::usage());
+            ExitCode::SUCCESS
+        }
+        Mode::PrintVersion => {
+            println!("{}", build_info::describe());
+            ExitCode::SUCCESS
+        }
+        Mode::ListenTelemetry { port } => {
+            match telemetry::listen(SocketAddr::from(([0, 0, 0, 0], port))) {
+                Ok(_) => ExitCode::SUCCESS,
+                Err(error) => {
+                    eprintln!("Could not listen for telemetry. - Cause: {}", error);
+                    FatalErrorKind::RuntimeFault.into()
+                }
+            }
+        }
+        Mode::ControlClient(request) => {
+            match run_control_client(configuration_file, vehicle, request) {
+                Ok(true) => ExitCode::SUCCESS,
+                Ok(false) => ExitCode::FAILURE,
+                Err(error) => {
+                    eprintln!(
+                        "{}",
+                        FatalErrorFormatter {
+                            error: error.as_ref()
+                        }
+                    );
+                    FatalErrorKind::classify(error.as_ref()).into()
+                }
+            }
         }
     }
 }
 
-fn run_application() -> Result<(), Box<dyn std::error::Error>> {
+fn run_service(
+    configuration_file: Option<&Path>,
+    vehicle: Option<&str>,
+    daemonize: bool,
+) -> Result<(), Box<dyn Error>> {
     SimpleLogger::install()?;
 
+    let mut configuration = Configuration::load(configuration_file, vehicle)?;
+
+    // Problems with the configuration are still reported on the terminal. The configuration file is watched by its
+    // absolute path, as the working directory changes.
+    let absolute_configuration_file = configuration_file.map(std::path::absolute).transpose()?;
+    let configuration_file = absolute_configuration_file.as_deref();
+    let _pid_file = if daemonize {
+        daemon::daemonize(&configuration.daemon_log_file)?;
+        Some(PidFile::create(&configuration.daemon_pid_file)?)
+    } else {
+        None
+    };
+
     log::info!("Starting roestbak service with PID {}.", process::id());
+    log::info!("This is {}.", build_info::describe());
+
+    let mut configuration_reloader = ConfigurationReloader::new(configuration_file, vehicle);
 
     let signal_manager = SignalManager::install()?;
-    let mut gamepad_input_interpreter = GamepadInputInterpreter::new()?;
-    let locomotion_controller = LocomotionController::new()?;
+    let control_socket = ControlSocket::bind(&configuration.control_socket_file)?;
+    #[cfg(feature = "dbus")]
+    let mut dbus_service = if configuration.dbus_enabled {
+        Some(DBusService::connect()?)
+    } else {
+        None
+    };
+    let mut input_source = registry::create_driving_input(&configuration)?;
+    let mut kill_relay = match configuration.kill_relay_line {
+        Some(line) => Some(KillRelay::new(
+            &configuration.kill_relay_gpio_chip,
+            line,
+            configuration.kill_relay_active_low,
+            KillRelaySettings {
+                max_gap: configuration.kill_relay_max_gap,
+                heartbeat: configuration.kill_relay_heartbeat,
+            },
+        )?),
+        None => None,
+    };
+    let mut thermal_derating = match configuration.thermal_sensor_address {
+        Some(address) => Some(ThermalDerating::new(
+            Tmp102::new(&configuration.i2c_device_file, address)?,
+            DeratingSettings {
+                derate_from: configuration.thermal_derate_from,
+                limit: configuration.thermal_limit,
+                max_speed_at_limit: configuration.thermal_max_speed_at_limit_percent as f64 / 100.0,
+            },
+        )),
+        None => None,
+    };
+    let mut gps_receiver = match &configuration.gps_device_file {
+        Some(path) => Some(GpsReceiver::new(path, configuration.gps_baud_rate)?),
+        None => None,
+    };
+    let geofence_boundary = match configuration.geofence_center {
+        Some(center) => Some(Boundary::Circle {
+            center,
+            radius: configuration.geofence_radius_m,
+        }),
+        None => (!configuration.geofence_polygon.is_empty())
+            .then(|| Boundary::Polygon(configuration.geofence_polygon.clone())),
+    };
+    let mut geofence = geofence_boundary.map(|boundary| {
+        Geofence::new(
+            boundary,
+            GeofenceSettings {
+                margin: configuration.geofence_margin_m,
+                speed_outside: configuration.geofence_speed_outside_percent as f64 / 100.0,
+            },
+        )
+    });
+    if geofence.is_some() && gps_receiver.is_none() {
+        log::warn!("Not enforcing the geofence, as there is no GPS module to go by.");
+        geofence = None;
+    }
+    // The driver is alerted to leaving the geofence, and whatever has a rumble pattern, by rumbling the gamepad.
+    let mut gamepad_haptics = ((geofence.is_some()
+        || !configuration.gamepad_rumble_patterns.is_empty())
+        && configuration.input_source == InputSourceKind::Gamepad)
+        .then(|| {
+            GamepadHaptics::new(
+                GamepadRumble::new(&configuration.gamepad_devices),
+                &configuration.gamepad_rumble_patterns,
+            )
+        });
+    let choreography = match &configuration.choreography_file {
+        Some(path) => Some(Choreography::load(path)?),
+        None => None,
+    };
+    let event_bus = EventBus::new();
+    let mut locomotion_controller = LocomotionController::new(
+        &configuration.channels,
+        configuration.locomotion_refresh_interval,
+        steering_calibration::load(&configuration.steering_calibration_file),
+        &configuration.esc_arming_sequence,
+        configuration.pca9685_bus(),
+        &configuration.pca9685,
+    )?;
+    locomotion_controller.publish_events_to(event_bus.clone());
+    locomotion_controller.set_write_latency_threshold(configuration.i2c_latency_warning_threshold);
+    if configuration.locomotion_pwm_tables_enabled {
+        locomotion_controller.use_pwm_tables()?;
+    }
+    let mut status_led = configuration
+        .channels
+        .get(Output::StatusLed)
+        .map(|channel| StatusLed::new(locomotion_controller.pca9685_driver(), channel));
+    let lighting_settings = LightingSettings {
+        brake_channel: configuration.channels.get(Output::BrakeLight),
+        left_turn_channel: configuration.channels.get(Output::LeftTurnSignal),
+        right_turn_channel: configuration.channels.get(Output::RightTurnSignal),
+        turn_signal_threshold: configuration.lighting_turn_signal_threshold_percent as f64 / 100.0,
+        turn_signal_delay: configuration.lighting_turn_signal_delay,
+    };
+    let mut lighting = (lighting_settings.brake_channel.is_some()
+        || lighting_settings.left_turn_channel.is_some()
+        || lighting_settings.right_turn_channel.is_some())
+    .then(|| Lighting::new(locomotion_controller.pca9685_driver(), lighting_settings));
+    let mut gamepad_lights = configuration
+        .gamepad_lights_enabled
+        .then(|| GamepadLights::new(&configuration.gamepad_devices));
+    let mut buzzer = configuration
+        .channels
+        .get(Output::Buzzer)
+        .map(|channel| Buzzer::new(locomotion_controller.pca9685_driver(), channel));
+    let mut auxiliary_channel = configuration
+        .channels
+        .get(Output::Auxiliary)
+        .map(|channel| {
+            AuxiliaryChannel::new(
+                locomotion_controller.pca9685_driver(),
+                channel,
+                AuxiliarySettings {
+                    axis: configuration.auxiliary_axis,
+                    output: configuration.auxiliary_output,
+                    mode: configuration.auxiliary_mode,
+                    full_range_time: configuration.auxiliary_full_range_time,
+                    smoothing: configuration.auxiliary_smoothing,
+                },
+            )
+        });
+    let mut gimbal = match (
+        configuration.channels.get(Output::GimbalPan),
+        configuration.channels.get(Output::GimbalTilt),
+    ) {
+        (Some(pan_channel), Some(tilt_channel)) => Some(Gimbal::new(
+            locomotion_controller.pca9685_driver(),
+            GimbalSettings {
+                pan: GimbalAxisSettings {
+                    channel: pan_channel,
+                    pulse_widths: configuration.gimbal_pan_pulse_widths,
+                },
+                tilt: GimbalAxisSettings {
+                    channel: tilt_channel,
+                    pulse_widths: configuration.gimbal_tilt_pulse_widths,
+                },
+                full_deflection_time: configuration.gimbal_full_deflection_time,
+                center_on_release: configuration.gimbal_center_on_release,
+            },
+        )),
+        (None, None) => None,
+        _ => {
+            log::warn!("Not controlling the gimbal, as it needs both a pan and a tilt channel.");
+            None
+        }
+    };
+    let mut winch = configuration.channels.get(Output::Winch).map(|channel| {
+        Winch::new(
+            locomotion_controller.pca9685_driver(),
+            channel,
+            configuration.winch_pulse_widths,
+            configuration.winch_speed_percent as f64 / 100.0,
+        )
+    });
+    // Only for show, so it is not worth failing over.
+    let mut engine_sound = configuration
+        .engine_sound_device_file
+        .as_ref()
+        .and_then(|path| {
+            let settings = EngineSoundSettings {
+                idle_frequency: configuration.engine_sound_idle_frequency,
+                max_frequency: configuration.engine_sound_max_frequency,
+                volume: configuration.engine_sound_volume_percent as f64 / 100.0,
+            };
+            EngineSound::new(path, settings)
+                .map_err(|error| {
+                    log::warn!(
+                        "Not playing engine sound, as {} could not be opened. - Cause: {}",
+                        path.display(),
+                        error
+                    )
+                })
+                .ok()
+        });
+    let fan_output = match (
+        configuration.channels.get(Output::Fan),
+        configuration.fan_gpio_line,
+    ) {
+        (Some(channel), _) => Some(FanOutput::PCA9685Channel {
+            pca9685_driver: locomotion_controller.pca9685_driver(),
+            channel,
+        }),
+        (None, Some(line)) => Some(FanOutput::gpio(&configuration.fan_gpio_chip, line)?),
+        (None, None) => None,
+    };
+    // Without a temperature, there is nothing to go by.
+    let mut fan = match (fan_output, &thermal_derating) {
+        (Some(output), Some(_)) => Some(Fan::new(
+            output,
+            FanSettings {
+                on_above: configuration.fan_on_above,
+                off_below: configuration.fan_off_below,
+            },
+        )),
+        (Some(_), None) => {
+            log::warn!("Not controlling the fan, as there is no temperature sensor to go by.");
+            None
+        }
+        (None, _) => None,
+    };
+    let mut bus_recovery = configuration
+        .i2c_recovery_after_errors
+        .map(BusRecovery::new);
+    let mut choreography_player = ChoreographyPlayer::new();
+    let mut arbiter = Arbiter::new(configuration.arbitration_takeover_ramp);
+
+    let mut odometer = Odometer::load(&configuration.odometer_state_file);
+    odometer.log_totals();
+
+    let mut statistics = SessionStatistics::new();
+    let mut status_file = configuration
+        .status_file_enabled
+        .then(|| StatusFile::new(&configuration.status_file));
+    // Telemetry is nice to have, but no reason not to drive.
+    let mut telemetry_log = configuration.telemetry_csv_file.as_ref().and_then(|path| {
+        CsvTelemetryLog::open(path, configuration.telemetry_csv_max_size_mb * 1024 * 1024)
+            .map_err(|error| {
+                log::warn!(
+                    "Could not open telemetry log {}. - Cause: {}",
+                    path.display(),
+                    error
+                )
+            })
+            .ok()
+    });
+    let telemetry_sender = configuration
+        .telemetry_udp_destination
+        .and_then(|destination| {
+            UdpTelemetrySender::new(destination, configuration.telemetry_udp_rate_hz)
+                .map_err(|error| {
+                    log::warn!(
+                        "Could not send telemetry to {}. - Cause: {}",
+                        destination,
+                        FatalErrorFormatter { error: &error }
+                    )
+                })
+                .ok()
+        });
+    // Like telemetry, being found on the network is no reason not to drive.
+    #[cfg(feature = "mdns")]
+    let mdns_advertiser = if configuration.mdns_enabled {
+        MdnsAdvertiser::new(
+            configuration.mdns_instance_name.as_deref(),
+            network_endpoints(&configuration),
+        )
+        .map_err(|error| {
+            log::warn!(
+                "Not advertising via mDNS. - Cause: {}",
+                FatalErrorFormatter { error: &error }
+            )
+        })
+        .ok()
+    } else {
+        None
+    };
+    let network_services = NetworkServices {
+        telemetry_sender,
+        #[cfg(feature = "mdns")]
+        mdns_advertiser,
+    };
+    let mut runloop = Runloop::new(configuration.runloop_interval);
 
-    runloop::start_runloop(RUNLOOP_INTERVAL, || {
-        if let Some(signal) = signal_manager.next_signal()? {
+    let mut armed = true;
+    let mut speed_limit_percentage: u8 = 100;
+    let mut last_locomotion_command = LocomotionCommand::neutral();
+    // Set when the failsafe brings the vehicle to a stop, until input is restored.
+    let mut failsafe_engaged = false;
+    // Set when the throttle is held at neutral after the failsafe responded to leaving the geofence, until the driver
+    // lets go of the throttle.
+    let mut throttle_held = false;
+    // Set when input devices keep refusing to be opened for lack of permissions, until one is opened.
+    let mut input_permission_problem: Option<PermissionDiagnostic> = None;
+    let mut idle_monitor = configuration.idle_timeout.map(IdleMonitor::new);
+    let mut state_machine = ApplicationStateMachine::new(event_bus.clone());
+    // Set when disarmed for being idle, as opposed to by request, which means that input arms the vehicle again.
+    let mut idle_disarmed = false;
+    // Only gamepads tell when the driver touches them, see `InputSource::last_activity`.
+    let mut inactivity_lock = configuration.inactivity_lock_timeout.and_then(|timeout| {
+        if configuration.input_source != InputSourceKind::Gamepad {
+            log::warn!("Not locking on inactivity, as that needs a gamepad as the input source.");
+            return None;
+        }
+        Some(InactivityLock::new(timeout))
+    });
+    // Set when disarmed by the inactivity lock, until the START button arms the vehicle again.
+    let mut inactivity_locked = false;
+    let mut guest_lock = GuestLock::new(
+        &configuration.guest_lock_unlock_sequence,
+        configuration.guest_lock_max_speed_percent,
+        configuration.guest_lock_engaged,
+    );
+    if guest_lock.is_engaged() {
+        log::info!("Starting with the guest lock engaged.");
+    }
+
+    // From here on, every way out goes through the shutdown sequence below, as the PCA9685 would otherwise keep
+    // sending whatever it was sent last.
+    let mut network_runtime = None;
+    let setup_result = complete_setup(
+        &configuration,
+        &mut locomotion_controller,
+        &runloop,
+        network_services,
+    )
+    .map(|started_network_runtime| network_runtime = started_network_runtime);
+
+    let run_iteration = |iteration: Iteration| -> Result<IterationOutcome, Box<dyn Error>> {
+        // The dump is made further down, once the status can be put together.
+        let mut dump_requested = false;
+        for signal in signal_manager.pending_signals()? {
             match signal {
                 SignalIntention::Terminate => {
                     log::info!("Received termination signal.");
                     return Ok(IterationOutcome::Conclude);
                 }
+                SignalIntention::DumpAndTerminate => {
+                    log::info!("Received quit signal.");
+                    dump_requested = true;
+                }
                 SignalIntention::ReloadConfiguration => {
-                    log::info!("Ignoring configuration reload signal.");
+                    log::info!("Reloading configuration on request.");
+                    configuration_reloader.reload(&mut configuration);
+                    input_source.reload_controller_mappings();
+                }
+            }
+        }
+        // Not being able to watch the file any more is no reason to stop driving either.
+        match configuration_reloader.file_changed() {
+            Ok(true) => {
+                log::info!("Reloading configuration, as the file changed.");
+                configuration_reloader.reload(&mut configuration);
+            }
+            Ok(false) => (),
+            Err(error) => {
+                log::warn!(
+                    "Not watching {} for changes any more, the configuration will only be reloaded on SIGHUP. - Cause: {}",
+                    configuration_reloader.watched_file().display(),
+                    error
+                );
+                configuration_reloader.stop_watching();
+            }
+        }
+
+        if iteration.held_up_for.is_some() {
+            input_source.expire_received_input();
+            // Should the PCA9685 not be reachable, executing the next command fails as well, which is dealt with below.
+            match locomotion_controller.resynchronize() {
+                Ok(true) => log::warn!("PCA9685 had lost its configuration, set it up again."),
+                Ok(false) => (),
+                Err(error) => log::warn!(
+                    "Could not check the PCA9685 after the runloop was held up. - Cause: {}",
+                    error
+                ),
+            }
+        }
+
+        let network_dropped_message_count = network_runtime
+            .as_ref()
+            .map(NetworkRuntime::dropped_message_count);
+        let state = state_machine.state();
+        let thermal_throttle_cap = thermal_derating.as_ref().map(ThermalDerating::throttle_cap);
+        let temperature = thermal_derating
+            .as_ref()
+            .and_then(ThermalDerating::temperature);
+        let fan_running = fan.as_ref().map(Fan::is_running);
+        let winch_running = winch.as_ref().map(Winch::is_running);
+        let configuration_error = configuration_reloader.last_error();
+        let mut handle_request = |request| match request {
+            Request::Status => {
+                let response = Response::ok()
+                    .with_field("armed", armed)
+                    .with_field("inactivity_locked", inactivity_locked)
+                    .with_field("guest_locked", guest_lock.is_engaged())
+                    .with_field("state", state)
+                    .with_field("speed_limit_percent", speed_limit_percentage)
+                    .with_field("input_connected", input_source.is_connected())
+                    .with_field(
+                        "throttle_percent",
+                        (last_locomotion_command.get_throttle().value() * 100.0).round(),
+                    )
+                    .with_field(
+                        "steering_percent",
+                        (last_locomotion_command.get_direction().value() * 100.0).round(),
+                    )
+                    .with_field("uptime_seconds", statistics.run_duration().as_secs());
+                let response = match input_source.device_description() {
+                    Some(description) => response.with_field("input_device", description),
+                    None => response,
+                };
+
+                // Working out the percentiles sorts the samples, so this is only done when asked for. See `latency.rs`.
+                let response = match locomotion_controller.input_latency_percentiles() {
+                    Some(latency) => response
+                        .with_field("input_latency_p50_ms", format_milliseconds(latency.p50))
+                        .with_field("input_latency_p95_ms", format_milliseconds(latency.p95))
+                        .with_field("input_latency_p99_ms", format_milliseconds(latency.p99)),
+                    None => response,
+                };
+                let response = match locomotion_controller.write_latency_percentiles() {
+                    Some(latency) => response
+                        .with_field("i2c_write_latency_p99_ms", format_milliseconds(latency.p99))
+                        .with_field("i2c_write_latency_max_ms", format_milliseconds(latency.max)),
+                    None => response,
+                };
+                let response = match thermal_throttle_cap {
+                    Some(cap) => {
+                        response.with_field("thermal_throttle_cap_percent", (cap * 100.0).round())
+                    }
+                    None => response,
+                };
+                let response = match temperature {
+                    Some(temperature) => {
+                        response.with_field("temperature_celsius", format!("{:.1}", temperature))
+                    }
+                    None => response,
+                };
+                let response = match fan_running {
+                    Some(running) => response.with_field("fan_running", running),
+                    None => response,
+                };
+                let response = match winch_running {
+                    Some(running) => response.with_field("winch_running", running),
+                    None => response,
+                };
+                let response = match configuration_error {
+                    Some(error) => response.with_field("configuration_error", error),
+                    None => response,
+                };
+                let response = match input_permission_problem {
+                    Some(diagnostic) => {
+                        let response = response
+                            .with_field("input_permission_denied", true)
+                            .with_field("service_uid", diagnostic.uid)
+                            .with_field("service_gid", diagnostic.gid);
+                        match (
+                            diagnostic.device_uid,
+                            diagnostic.device_gid,
+                            diagnostic.device_mode,
+                        ) {
+                            (Some(uid), Some(gid), Some(mode)) => response
+                                .with_field("input_device_uid", uid)
+                                .with_field("input_device_gid", gid)
+                                .with_field("input_device_mode", format!("{:04o}", mode)),
+                            _ => response,
+                        }
+                    }
+                    None => response,
+                };
+                let response = match network_dropped_message_count {
+                    Some(count) => response.with_field("network_dropped_messages", count),
+                    None => response,
+                };
+                SubsystemKind::ALL.into_iter().fold(
+                    response,
+                    |response, kind| match find_subsystem(
+                        kind,
+                        network_runtime.as_mut(),
+                        input_source.as_mut(),
+                        lighting.as_mut(),
+                    ) {
+                        Some(subsystem) => {
+                            response.with_field(kind.status_key(), subsystem.is_running())
+                        }
+                        None => response,
+                    },
+                )
+            }
+            Request::Arm => {
+                log::info!("Armed by remote request.");
+                armed = true;
+                inactivity_locked = false;
+                event_bus.publish(Event::Armed);
+                Response::ok()
+            }
+            Request::Disarm => {
+                log::info!("Disarmed by remote request.");
+                armed = false;
+                idle_disarmed = false;
+                inactivity_locked = false;
+                input_source.release_latched_input();
+                choreography_player.stop();
+                event_bus.publish(Event::Disarmed);
+                Response::ok()
+            }
+            Request::EngageGuestLock => {
+                log::info!("Guest lock engaged by remote request.");
+                guest_lock.engage();
+                Response::ok()
+            }
+            Request::DisengageGuestLock => {
+                log::info!("Guest lock disengaged by remote request.");
+                guest_lock.disengage();
+                Response::ok()
+            }
+            Request::SetSpeedLimit(percentage) => {
+                log::info!("Speed limit set to {}% by remote request.", percentage);
+                speed_limit_percentage = percentage;
+                Response::ok().with_field("speed_limit_percent", percentage)
+            }
+            Request::EnableSubsystem(kind) | Request::DisableSubsystem(kind) => {
+                let enable = matches!(request, Request::EnableSubsystem(_));
+                let Some(subsystem) = find_subsystem(
+                    kind,
+                    network_runtime.as_mut(),
+                    input_source.as_mut(),
+                    lighting.as_mut(),
+                ) else {
+                    return Response::Error(format!(
+                        "There is no {} to {}, as it is not configured or could not be set up.",
+                        kind,
+                        if enable { "enable" } else { "disable" }
+                    ));
+                };
+
+                match subsystems::set_running(subsystem, enable) {
+                    Ok(changed) => {
+                        if changed {
+                            log::info!(
+                                "{} the {} by remote request.",
+                                if enable { "Enabled" } else { "Disabled" },
+                                kind
+                            );
+                        }
+                        Response::ok().with_field(kind.status_key(), enable)
+                    }
+                    Err(error) => Response::Error(format!(
+                        "Could not {} the {}. - Cause: {}",
+                        if enable { "enable" } else { "disable" },
+                        kind,
+                        error
+                    )),
+                }
+            }
+        };
+
+        if dump_requested {
+            diagnostic_dump::log(&handle_request(Request::Status));
+            return Ok(IterationOutcome::Conclude);
+        }
+
+        control_socket.process_requests(&mut handle_request)?;
+
+        #[cfg(feature = "dbus")]
+        if let Some(service) = &mut dbus_service {
+            if let Err(error) = service.process_messages(&mut handle_request) {
+                log::error!("Disabling D-Bus interface. - Cause: {}", error);
+                dbus_service = None;
+            }
+        }
+
+        let mut input_failure = None;
+        let locomotion_command = input_source.process_input(&mut |notification| {
+            let signal = match notification {
+                InputNotification::Connected => {
+                    statistics.record_gamepad_connected();
+                    input_permission_problem = None;
+                    event_bus.publish(Event::InputConnected);
+                    failsafe_engaged = false;
+                    Some(dbus::Signal::GamepadConnected)
+                }
+                InputNotification::Disconnected => {
+                    statistics.record_gamepad_disconnected();
+                    event_bus.publish(Event::InputDisconnected);
+                    input_failure =
+                        Some(if configuration.input_source == InputSourceKind::Mavlink {
+                            FailureSource::LinkLost
+                        } else {
+                            FailureSource::InputLost
+                        });
+                    if choreography_player.is_playing() {
+                        log::info!("Choreography stopped because the gamepad went away.");
+                        choreography_player.stop();
+                    }
+                    None
+                }
+                InputNotification::FailsafeEngaged => {
+                    statistics.record_failsafe_activation();
+                    event_bus.publish(Event::FailsafeEngaged);
+                    failsafe_engaged = true;
+                    Some(dbus::Signal::FailsafeEngaged)
+                }
+                InputNotification::ChoreographyToggled => {
+                    if choreography.is_none() {
+                        log::info!("Ignoring choreography button, no choreography is configured.");
+                    } else if choreography_player.is_playing() {
+                        log::info!("Choreography stopped.");
+                        choreography_player.stop();
+                    } else if !armed {
+                        log::info!("Ignoring choreography button while disarmed.");
+                    } else {
+                        log::info!("Choreography started.");
+                        choreography_player.start();
+                    }
+                    None
+                }
+                InputNotification::SpeedLimitAdjusted(_) if guest_lock.is_engaged() => {
+                    log::info!("Ignoring speed limit change from the gamepad, as the guest lock is engaged.");
+                    None
+                }
+                InputNotification::SpeedLimitAdjusted(delta) => {
+                    speed_limit_percentage =
+                        (speed_limit_percentage as i16 + delta as i16).clamp(0, 100) as u8;
+                    log::info!(
+                        "Speed limit set to {}% from the gamepad.",
+                        speed_limit_percentage
+                    );
+                    None
+                }
+                InputNotification::PermissionDenied(diagnostic) => {
+                    event_bus.publish(Event::InputPermissionDenied(diagnostic));
+                    input_permission_problem = Some(diagnostic);
+                    None
+                }
+                InputNotification::ArmRequested => {
+                    if inactivity_locked {
+                        log::info!("Armed by the START button.");
+                        armed = true;
+                        inactivity_locked = false;
+                        event_bus.publish(Event::Armed);
+                    }
+                    None
+                }
+                InputNotification::ButtonPressed(button) => {
+                    if guest_lock.button_pressed(button, runloop::now()) {
+                        log::info!("Guest lock disengaged from the gamepad.");
+                    }
+                    None
+                }
+                InputNotification::GimbalToggled => {
+                    match &mut gimbal {
+                        Some(gimbal) => gimbal.toggle(),
+                        None => log::info!("Ignoring gimbal button, no gimbal is configured."),
+                    }
+                    None
+                }
+            };
+
+            #[cfg(feature = "dbus")]
+            if let (Some(service), Some(signal)) = (&mut dbus_service, signal) {
+                if let Err(error) = service.emit(signal) {
+                    log::error!("Disabling D-Bus interface. - Cause: {}", error);
+                    dbus_service = None;
+                }
+            }
+            #[cfg(not(feature = "dbus"))]
+            let _ = signal;
+        })?;
+
+        let now = runloop::now();
+        arbiter.propose(
+            CommandSource::Driver,
+            configuration.driving.shape(locomotion_command),
+            now,
+        );
+        match choreography
+            .as_ref()
+            .and_then(|choreography| choreography_player.current_command(choreography))
+        {
+            Some(command) => arbiter.propose(CommandSource::Choreography, command, now),
+            None => arbiter.withdraw(CommandSource::Choreography),
+        }
+        let decision = arbiter.arbitrate(now);
+        // Once the driver takes over, the choreography is over rather than paused.
+        if choreography_player.is_playing() && decision.source == Some(CommandSource::Driver) {
+            log::info!("Choreography interrupted by gamepad input.");
+            choreography_player.stop();
+        }
+        let locomotion_command = decision.command;
+
+        // Waking up from being idle is locked out as well, so that the lock holds whatever the idle timeout.
+        if let Some(inactivity_lock) = &mut inactivity_lock {
+            if inactivity_lock.update(
+                (armed || idle_disarmed) && !choreography_player.is_playing(),
+                input_source.last_activity(),
+                &locomotion_command,
+            ) {
+                log::info!(
+                    "Disarming, as the gamepad was not touched for a while. Press START to arm again."
+                );
+                if armed {
+                    event_bus.publish(Event::Disarmed);
+                }
+                armed = false;
+                idle_disarmed = false;
+                inactivity_locked = true;
+                input_source.release_latched_input();
+            }
+        }
+
+        let input_is_neutral = locomotion_command.is_neutral();
+        let mut woke_up = false;
+        if locomotion_controller.is_asleep() {
+            if armed || (idle_disarmed && !input_is_neutral) {
+                log::info!("Waking up and arming.");
+                if let Err(error) = locomotion_controller.wake() {
+                    statistics.record_i2c_error();
+                    return Err(error.into());
+                }
+                armed = true;
+                idle_disarmed = false;
+                woke_up = true;
+                event_bus.publish(Event::Armed);
+            }
+        } else if let Some(idle_monitor) = &mut idle_monitor {
+            if idle_monitor.update(armed && input_is_neutral && !choreography_player.is_playing()) {
+                log::info!("Disarming and going to sleep, as there was no input for a while.");
+                armed = false;
+                idle_disarmed = true;
+                input_source.release_latched_input();
+                event_bus.publish(Event::Disarmed);
+                if let Err(error) = locomotion_controller.sleep() {
+                    statistics.record_i2c_error();
+                    return Err(error.into());
+                }
+            }
+        }
+        let locomotion_command = if woke_up {
+            LocomotionCommand::neutral()
+        } else {
+            locomotion_command
+        };
+
+        if let Some(thermal_derating) = &mut thermal_derating {
+            thermal_derating.update();
+        }
+        let mut geofence_breached = false;
+        if let Some(fix) = gps_receiver.as_mut().and_then(GpsReceiver::update) {
+            statistics.record_gps_fix(fix);
+            if let Some(geofence) = &mut geofence {
+                geofence_breached = geofence.handle_fix(fix);
+            }
+        }
+        if let (Some(haptics), Some(strength)) = (
+            &mut gamepad_haptics,
+            geofence.as_mut().and_then(Geofence::alert),
+        ) {
+            haptics.rumble(strength, geofence::ALERT_DURATION);
+        }
+        let failures = input_failure
+            .into_iter()
+            .chain(geofence_breached.then_some(FailureSource::GeofenceBreach));
+        for source in failures {
+            let Some(response) = configuration
+                .failsafe_policy
+                .response_to(source, configuration.failsafe_brake_ramp)
+            else {
+                continue;
+            };
+            response.stop(&mut locomotion_controller);
+            if response.disarm && armed {
+                log::warn!("Disarmed by the failsafe ({}).", source);
+                armed = false;
+                idle_disarmed = false;
+                input_source.release_latched_input();
+                choreography_player.stop();
+                event_bus.publish(Event::Disarmed);
+            } else if source == FailureSource::GeofenceBreach {
+                throttle_held = true;
+            }
+        }
+        if throttle_held && locomotion_command.get_throttle().is_neutral() {
+            throttle_held = false;
+        }
+
+        arbiter.limit(
+            SafetyLimit::ThermalDerating,
+            thermal_derating
+                .as_ref()
+                .map_or(1.0, ThermalDerating::throttle_cap),
+        );
+        arbiter.limit(
+            SafetyLimit::Geofence,
+            geofence.as_ref().map_or(1.0, Geofence::throttle_cap),
+        );
+        arbiter.limit(
+            SafetyLimit::GeofenceHold,
+            if throttle_held { 0.0 } else { 1.0 },
+        );
+        arbiter.limit(SafetyLimit::GuestLock, guest_lock.throttle_cap());
+        let locomotion_command = if armed {
+            let max_speed = if locomotion_command.is_boosted() && !guest_lock.is_engaged() {
+                1.0
+            } else {
+                configuration.driving.max_speed()
+            };
+            // Boosting does not get around the safety limits (e.g. the thermal cap or the geofence), as those keep
+            // the ESC from burning out and the vehicle from getting lost.
+            arbiter.clamp(
+                locomotion_command
+                    .with_speed_limit(speed_limit_percentage as f64 / 100.0 * max_speed),
+            )
+        } else {
+            LocomotionCommand::neutral()
+        };
+
+        statistics.record_commanded_throttle(locomotion_command.get_throttle());
+        odometer.update(!locomotion_command.get_throttle().is_neutral());
+        last_locomotion_command = locomotion_command;
+
+        match locomotion_controller.execute_command(
+            configuration
+                .driving
+                .assist_low_speed(locomotion_command, runloop::now()),
+        ) {
+            Ok(()) => {
+                if let Some(bus_recovery) = &mut bus_recovery {
+                    bus_recovery.command_succeeded();
+                }
+            }
+            Err(error) => {
+                statistics.record_i2c_error();
+                match &mut bus_recovery {
+                    Some(bus_recovery) => {
+                        bus_recovery.handle_error(error, &mut locomotion_controller)?
+                    }
+                    None => return Err(error.into()),
                 }
             }
         }
 
-        let locomotion_command = gamepad_input_interpreter.process_input()?;
-        locomotion_controller.execute_command(locomotion_command)?;
+        let state = ApplicationState::determine(Conditions {
+            failsafe_engaged,
+            asleep: locomotion_controller.is_asleep(),
+            input_connected: input_source.is_connected(),
+            armed,
+            throttle: locomotion_command.get_throttle(),
+        });
+        state_machine.update(state);
+
+        if let Some(status_file) = status_file
+            .as_mut()
+            .filter(|status_file| status_file.is_due())
+        {
+            status_file.write(&Status {
+                running: true,
+                state,
+                input_connected: input_source.is_connected(),
+                input_device: input_source.device_description(),
+                uptime: statistics.run_duration(),
+                last_error: logging::last_error(),
+            });
+        }
+
+        if telemetry_log.is_some() || network_runtime.is_some() {
+            let (throttle_pulse_width_us, steering_pulse_width_us) =
+                locomotion_controller.pulse_widths_us();
+            let sample = telemetry::Sample {
+                timestamp: runloop::now(),
+                state,
+                input_connected: input_source.is_connected(),
+                armed,
+                throttle: locomotion_command.get_throttle(),
+                steering: locomotion_command.get_direction(),
+                throttle_pulse_width_us,
+                steering_pulse_width_us,
+                i2c_errors: statistics.i2c_error_count(),
+                temperature_celsius: thermal_derating
+                    .as_ref()
+                    .and_then(ThermalDerating::temperature),
+                fan_running: fan.as_ref().map(Fan::is_running),
+                gps_fix: gps_receiver.as_ref().and_then(GpsReceiver::fix),
+            };
+            if let Some(telemetry_log) = &mut telemetry_log {
+                telemetry_log.record(&sample);
+            }
+            if let Some(network_runtime) = &mut network_runtime {
+                network_runtime.send_telemetry(sample);
+            }
+        }
+
+        let mut subscribers: Vec<&mut dyn EventSubscriber> = vec![&mut statistics];
+        if let Some(led) = &mut status_led {
+            subscribers.push(led);
+        }
+        if let Some(lighting) = &mut lighting {
+            subscribers.push(lighting);
+        }
+        if let Some(lights) = &mut gamepad_lights {
+            subscribers.push(lights);
+        }
+        if let Some(buzzer) = &mut buzzer {
+            subscribers.push(buzzer);
+        }
+        if let Some(haptics) = &mut gamepad_haptics {
+            subscribers.push(haptics);
+        }
+        if let Some(kill_relay) = &mut kill_relay {
+            subscribers.push(kill_relay);
+        }
+        event_bus.dispatch(&mut subscribers);
+
+        if let Some(kill_relay) = &mut kill_relay {
+            kill_relay.update()?;
+        }
+
+        if let Some(led) = &mut status_led {
+            if let Err(error) = led.update() {
+                statistics.record_i2c_error();
+                log::warn!("Could not update status LED. - Cause: {}", error);
+            }
+        }
+
+        if let Some(lighting) = &mut lighting {
+            let result = lighting.update(
+                last_locomotion_command.get_throttle(),
+                last_locomotion_command.get_direction(),
+                runloop::now(),
+            );
+            if let Err(error) = result {
+                statistics.record_i2c_error();
+                log::warn!("Could not update lights. - Cause: {}", error);
+            }
+        }
+
+        if let Some(buzzer) = &mut buzzer {
+            if let Err(error) = buzzer.update() {
+                statistics.record_i2c_error();
+                log::warn!("Could not update buzzer. - Cause: {}", error);
+            }
+        }
+
+        if let Some(haptics) = &mut gamepad_haptics {
+            haptics.update();
+        }
+
+        if let Some(winch) = &mut winch {
+            let may_run = armed && last_locomotion_command.get_throttle().is_neutral();
+            if let Err(error) = winch.update(input_source.winch_direction(), may_run) {
+                statistics.record_i2c_error();
+                log::warn!("Could not update winch. - Cause: {}", error);
+            }
+        }
+
+        if let Some(engine_sound) = &mut engine_sound {
+            engine_sound.update(last_locomotion_command.get_throttle().value(), armed);
+        }
+
+        // While the right stick aims the gimbal, it leaves the auxiliary channel alone.
+        let gimbal_active = gimbal.as_ref().is_some_and(Gimbal::is_active);
+        if let Some(gimbal) = &mut gimbal {
+            let result = gimbal.update(
+                input_source.auxiliary_axis(AuxiliaryAxis::RightStickHorizontal),
+                input_source.auxiliary_axis(AuxiliaryAxis::RightStickVertical),
+                runloop::now(),
+            );
+            if let Err(error) = result {
+                statistics.record_i2c_error();
+                log::warn!("Could not update gimbal. - Cause: {}", error);
+            }
+        }
+
+        if let Some(auxiliary_channel) = &mut auxiliary_channel {
+            let input = if gimbal_active {
+                None
+            } else {
+                input_source.auxiliary_axis(auxiliary_channel.axis())
+            };
+            if let Err(error) = auxiliary_channel.update(input, runloop::now()) {
+                statistics.record_i2c_error();
+                log::warn!("Could not update auxiliary channel. - Cause: {}", error);
+            }
+        }
+
+        if let Some(fan) = &mut fan {
+            let temperature = thermal_derating
+                .as_ref()
+                .and_then(ThermalDerating::temperature);
+            if let Err(error) = fan.update(temperature) {
+                log::warn!(
+                    "Could not switch fan. - Cause: {}",
+                    FatalErrorFormatter { error: &error }
+                );
+            }
+        }
 
         Ok(IterationOutcome::KeepGoing)
-    })
+    };
+    let result = setup_result.and_then(|()| runloop.run(run_iteration));
+
+    // Shutting down, in this order:
+    // 1. Input is no longer accepted, so that nothing can get the vehicle going again.
+    // 2. The vehicle comes to a stop.
+    // 3. Drive power is cut and PWM output stopped.
+    // 4. Telemetry and the other records of the session are written out.
+    // 5. Whatever is left is closed.
+    drop(input_source);
+    drop(control_socket);
+    #[cfg(feature = "dbus")]
+    drop(dbus_service);
+
+    // Going to neutral keeps the other outputs (such as the buzzer) working for now. If even that fails, all PWM
+    // output is cut right away. Asking for termination again (e.g. a second Ctrl-C) skips the brake ramp, and
+    // whatever else takes a while below.
+    if let Err(error) = locomotion_controller.come_to_stop(
+        configuration.failsafe_brake_ramp,
+        configuration.runloop_interval,
+        || signal_manager.is_urgent(),
+    ) {
+        log::error!("Stopping all PWM output. - Cause: {}", error);
+        locomotion::emergency_stop();
+    }
+    let urgent = signal_manager.is_urgent();
+    if urgent {
+        log::warn!("Termination was asked for again, shutting down without waiting on anything.");
+    }
+    if let Some(winch) = &mut winch {
+        if let Err(error) = winch.stop() {
+            log::error!("Could not stop winch. - Cause: {}", error);
+        }
+    }
+
+    if result.is_err() {
+        state_machine.update(ApplicationState::Fault);
+        let mut subscribers: Vec<&mut dyn EventSubscriber> = vec![&mut statistics];
+        if let Some(led) = &mut status_led {
+            subscribers.push(led);
+        }
+        if let Some(lighting) = &mut lighting {
+            subscribers.push(lighting);
+        }
+        if let Some(lights) = &mut gamepad_lights {
+            subscribers.push(lights);
+        }
+        event_bus.dispatch(&mut subscribers);
+    }
+
+    if let Some(kill_relay) = &mut kill_relay {
+        if let Err(error) = kill_relay.open() {
+            log::error!("Could not open kill relay. - Cause: {}", error);
+        }
+    }
+
+    if let Some(fan) = &mut fan {
+        if let Err(error) = fan.turn_off() {
+            log::warn!(
+                "Could not turn off fan. - Cause: {}",
+                FatalErrorFormatter { error: &error }
+            );
+        }
+    }
+
+    if let (false, Some(buzzer)) = (urgent, &mut buzzer) {
+        let alert = if result.is_err() {
+            Alert::FatalError
+        } else {
+            Alert::Disarmed
+        };
+        if let Err(error) = buzzer.play_to_completion(alert) {
+            log::warn!("Could not sound buzzer. - Cause: {}", error);
+        }
+    }
+
+    // Leave every output (including the status LED) off rather than with whatever pulses it was last sent. After an
+    // error, the PCA9685 may not be reachable in the regular way anymore.
+    if result.is_err() {
+        locomotion::emergency_stop();
+    } else if let Err(error) = locomotion_controller.turn_off_all_outputs() {
+        log::warn!("Could not turn off PWM outputs. - Cause: {}", error);
+    }
+
+    if let Some(telemetry_log) = &mut telemetry_log {
+        telemetry_log.flush();
+    }
+    if let Some(status_file) = &mut status_file {
+        status_file.write(&Status {
+            running: false,
+            state: state_machine.state(),
+            input_connected: false,
+            input_device: None,
+            uptime: statistics.run_duration(),
+            last_error: logging::last_error(),
+        });
+    }
+    // Not waiting for the network thread leaves mDNS records to expire by themselves.
+    if let (false, Some(network_runtime)) = (urgent, network_runtime.take()) {
+        network_runtime.stop();
+    }
+    statistics.record_runloop_overruns(runloop.overrun_count());
+    statistics.record_input_latency(locomotion_controller.input_latency_percentiles());
+    statistics.log_summary();
+
+    odometer.save();
+    odometer.log_totals();
+
+    if let Some(path) = &configuration.session_summary_file {
+        if let Err(error) = statistics.write_json(path) {
+            log::warn!(
+                "Could not write session summary to {}. - Cause: {}",
+                path.display(),
+                error
+            );
+        }
+    }
+
+    drop(kill_relay);
+    drop(buzzer);
+    drop(fan);
+    drop(gamepad_haptics);
+    drop(gps_receiver);
+    drop(status_led);
+    drop(lighting);
+    drop(gamepad_lights);
+    drop(locomotion_controller);
+
+    result
+}
+
+// What is left to do after the locomotion controller has been created, before the runloop can start.
+fn complete_setup(
+    configuration: &Configuration,
+    locomotion_controller: &mut LocomotionController,
+    runloop: &Runloop,
+    network_services: NetworkServices,
+) -> Result<Option<NetworkRuntime>, Box<dyn Error>> {
+    if configuration.self_test_enabled {
+        self_test::run(locomotion_controller)?;
+    }
+    runloop.check_interval(locomotion_controller.measure_command_duration()?)?;
+
+    // Started before the seccomp filter is installed, see `network.rs`.
+    let network_runtime = if network_services.is_empty() {
+        None
+    } else {
+        Some(NetworkRuntime::start(network_services)?)
+    };
+
+    // Setup is done at this point, so whatever was needed only for that can be locked away.
+    if configuration.seccomp_enabled {
+        seccomp::install_filter()?;
+    }
+
+    Ok(network_runtime)
+}
+
+fn run_steering_calibration(
+    configuration_file: Option<&Path>,
+    vehicle: Option<&str>,
+) -> Result<bool, Box<dyn Error>> {
+    SimpleLogger::install()?;
+
+    let configuration = Configuration::load(configuration_file, vehicle)?;
+    // Calibrating from a known state, whatever an earlier run left the PCA9685 in.
+    locomotion::software_reset(configuration.pca9685_bus())?;
+    let steering_calibration_file = &configuration.steering_calibration_file;
+    let mut locomotion_controller = LocomotionController::new(
+        &configuration.channels,
+        configuration.locomotion_refresh_interval,
+        steering_calibration::load(steering_calibration_file),
+        &configuration.esc_arming_sequence,
+        configuration.pca9685_bus(),
+        &configuration.pca9685,
+    )?;
+
+    steering_calibration::run(
+        &mut locomotion_controller,
+        steering_calibration_file,
+        &configuration.gamepad_devices,
+        configuration.gamepad_mapping_file.as_deref(),
+    )
+}
+
+fn run_i2c_scan(
+    configuration_file: Option<&Path>,
+    vehicle: Option<&str>,
+    bus: Option<PathBuf>,
+) -> Result<bool, Box<dyn Error>> {
+    let bus = match bus {
+        Some(bus) => bus,
+        None => Configuration::load(configuration_file, vehicle)?
+            .pca9685_bus()
+            .to_path_buf(),
+    };
+
+    Ok(i2c_scan::run(&bus)?)
+}
+
+fn run_raw_recording(device_file: &Path, capture_file: &Path) -> Result<(), Box<dyn Error>> {
+    let signal_manager = SignalManager::install()?;
+    let event_count = gamepads::record_capture(device_file, capture_file, &signal_manager)?;
+    println!(
+        "Recorded {} events to {}.",
+        event_count,
+        capture_file.display()
+    );
+
+    Ok(())
+}
+
+fn run_control_client(
+    configuration_file: Option<&Path>,
+    vehicle: Option<&str>,
+    request: Request,
+) -> Result<bool, Box<dyn Error>> {
+    let configuration = Configuration::load(configuration_file, vehicle)?;
+    Ok(control::run_client(
+        &configuration.control_socket_file,
+        request,
+    )?)
+}
+
+// The subsystem of the given kind, if it was configured and could be set up. See `subsystems.rs`.
+fn find_subsystem<'a>(
+    kind: SubsystemKind,
+    network_runtime: Option<&'a mut NetworkRuntime>,
+    input_source: &'a mut dyn InputSource,
+    lighting: Option<&'a mut Lighting>,
+) -> Option<&'a mut dyn Subsystem> {
+    match kind {
+        SubsystemKind::Telemetry => network_runtime
+            .and_then(NetworkRuntime::telemetry_stream)
+            .map(|telemetry_stream| telemetry_stream as &mut dyn Subsystem),
+        SubsystemKind::NetworkInput => input_source.network_input(),
+        SubsystemKind::Lighting => lighting.map(|lighting| lighting as &mut dyn Subsystem),
+    }
+}
+
+// The endpoints a ground station may want to find: where to send MAVLink to, and where telemetry is sent to.
+#[cfg(feature = "mdns")]
+fn network_endpoints(configuration: &Configuration) -> Vec<Endpoint> {
+    let mut endpoints = Vec::new();
+    if configuration.input_source == InputSourceKind::Mavlink
+        || configuration.copilot_source == Some(InputSourceKind::Mavlink)
+    {
+        endpoints.push(Endpoint {
+            role: "control",
+            protocol: "mavlink",
+            port: configuration.mavlink_listen_address.port(),
+        });
+    }
+    if let Some(destination) = configuration.telemetry_udp_destination {
+        endpoints.push(Endpoint {
+            role: "telemetry",
+            protocol: "roestbak-telemetry",
+            port: destination.port(),
+        });
+    }
+
+    endpoints
+}
+
+// Returns whether the configuration is fit for running the service. Warnings are reported, but do not fail the check.
+fn run_configuration_check(
+    configuration_file: Option<&Path>,
+    vehicle: Option<&str>,
+) -> Result<bool, Box<dyn Error>> {
+    let configuration = Configuration::load(configuration_file, vehicle)?;
+    let findings = configuration_check::check(&configuration);
+
+    for finding in &findings {
+        eprintln!("{}", finding);
+    }
+
+    let error_count = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    let warning_count = findings.len() - error_count;
+
+    if error_count > 0 {
+        eprintln!(
+            "Configuration check failed with {} error(s) and {} warning(s).",
+            error_count, warning_count
+        );
+        Ok(false)
+    } else {
+        println!("Configuration is valid ({} warning(s)).", warning_count);
+        Ok(true)
+    }
+}
+
+fn format_milliseconds(duration: Duration) -> String {
+    format!("{:.1}", duration.as_secs_f64() * 1000.0)
 }
 
 struct FatalErrorFormatter<'a> {
-    error: &'a Box<dyn Error>,
+    error: &'a dyn Error,
 }
 
 impl<'a> std::fmt::Display for FatalErrorFormatter<'a> {
@@ -0,0 +1,5 @@
+mod frame;
+mod signing;
+mod source;
+
+pub use source::MavlinkInputSource;
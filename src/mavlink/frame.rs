@@ -0,0 +1,301 @@
+use super::signing::{self, MessageSigning};
+
+// Just enough of the MAVLink wire format to be driven by a ground control station: decoding of v1 and v2 frames
+// carrying the few messages of interest, and encoding of our own heartbeat.
+//
+// Verifying and adding signatures is left to `signing.rs`.
+
+const MAGIC_V1: u8 = 0xFE;
+const MAGIC_V2: u8 = 0xFD;
+const HEADER_SIZE_V1: usize = 6;
+const HEADER_SIZE_V2: usize = 10;
+const CHECKSUM_SIZE: usize = 2;
+const SIGNATURE_SIZE: usize = signing::SIGNATURE_BLOCK_SIZE;
+const INCOMPAT_FLAG_SIGNED: u8 = 0x01;
+
+const MESSAGE_ID_HEARTBEAT: u32 = 0;
+const MESSAGE_ID_MANUAL_CONTROL: u32 = 69;
+const MESSAGE_ID_RC_CHANNELS_OVERRIDE: u32 = 70;
+
+// Every message type has its own "extra" byte mixed into the checksum, derived from its definition. This guards
+// against the two sides disagreeing about a message's layout.
+const CRC_EXTRA_HEARTBEAT: u8 = 50;
+const CRC_EXTRA_MANUAL_CONTROL: u8 = 243;
+const CRC_EXTRA_RC_CHANNELS_OVERRIDE: u8 = 124;
+
+// Payload sizes, including v2 extension fields. v2 frames may be shorter, since trailing zero bytes are truncated.
+const PAYLOAD_SIZE_HEARTBEAT: usize = 9;
+const PAYLOAD_SIZE_MANUAL_CONTROL: usize = 30;
+const PAYLOAD_SIZE_RC_CHANNELS_OVERRIDE: usize = 38;
+
+pub const RC_CHANNELS_OVERRIDE_CHANNELS: usize = 18;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Header {
+    pub system_id: u8,
+    pub component_id: u8,
+}
+
+pub struct Frame<'a> {
+    pub header: Header,
+    pub message: Message,
+    pub is_signed: bool,
+    // The complete frame, for verifying its signature.
+    pub bytes: &'a [u8],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Message {
+    Heartbeat {
+        vehicle_type: u8,
+    },
+    // Axes range from -1000 to 1000.
+    ManualControl {
+        target_system: u8,
+        x: i16,
+        y: i16,
+        z: i16,
+        r: i16,
+    },
+    // Channel values are pulse widths in microseconds. 0 and 65535 mean "do not override this channel".
+    RcChannelsOverride {
+        target_system: u8,
+        channels: [u16; RC_CHANNELS_OVERRIDE_CHANNELS],
+    },
+}
+
+// Decodes all frames in a datagram. Frames carrying other messages, and anything that fails its checksum, are
+// skipped.
+pub fn decode_frames(mut datagram: &[u8]) -> Vec<Frame<'_>> {
+    let mut frames = Vec::new();
+
+    while let Some(start) = datagram
+        .iter()
+        .position(|&byte| byte == MAGIC_V1 || byte == MAGIC_V2)
+    {
+        datagram = &datagram[start..];
+
+        match decode_frame(datagram) {
+            Some((frame_size, decoded)) => {
+                if let Some((header, message)) = decoded {
+                    frames.push(Frame {
+                        header,
+                        message,
+                        is_signed: datagram[0] == MAGIC_V2
+                            && datagram[2] & INCOMPAT_FLAG_SIGNED != 0,
+                        bytes: &datagram[..frame_size],
+                    });
+                }
+                datagram = &datagram[frame_size..];
+            }
+            None => datagram = &datagram[1..],
+        }
+    }
+
+    frames
+}
+
+// Returns the size of the frame at the start of `bytes` and its message, if it is one we care about. Returns `None`
+// if there is no valid frame at the start of `bytes`.
+fn decode_frame(bytes: &[u8]) -> Option<(usize, Option<(Header, Message)>)> {
+    let (header_size, payload_size, message_id, header, trailer_size) = match bytes[0] {
+        MAGIC_V1 => {
+            if bytes.len() < HEADER_SIZE_V1 {
+                return None;
+            }
+            let header = Header {
+                system_id: bytes[3],
+                component_id: bytes[4],
+            };
+            (
+                HEADER_SIZE_V1,
+                bytes[1] as usize,
+                bytes[5] as u32,
+                header,
+                0,
+            )
+        }
+        MAGIC_V2 => {
+            if bytes.len() < HEADER_SIZE_V2 {
+                return None;
+            }
+            let header = Header {
+                system_id: bytes[5],
+                component_id: bytes[6],
+            };
+            let message_id = u32::from_le_bytes([bytes[7], bytes[8], bytes[9], 0]);
+            let trailer_size = if bytes[2] & INCOMPAT_FLAG_SIGNED != 0 {
+                SIGNATURE_SIZE
+            } else {
+                0
+            };
+            (
+                HEADER_SIZE_V2,
+                bytes[1] as usize,
+                message_id,
+                header,
+                trailer_size,
+            )
+        }
+        _ => return None,
+    };
+
+    let checksum_start = header_size + payload_size;
+    let frame_size = checksum_start + CHECKSUM_SIZE + trailer_size;
+    if bytes.len() < frame_size {
+        return None;
+    }
+
+    let (crc_extra, full_payload_size) = match message_id {
+        MESSAGE_ID_HEARTBEAT => (CRC_EXTRA_HEARTBEAT, PAYLOAD_SIZE_HEARTBEAT),
+        MESSAGE_ID_MANUAL_CONTROL => (CRC_EXTRA_MANUAL_CONTROL, PAYLOAD_SIZE_MANUAL_CONTROL),
+        MESSAGE_ID_RC_CHANNELS_OVERRIDE => (
+            CRC_EXTRA_RC_CHANNELS_OVERRIDE,
+            PAYLOAD_SIZE_RC_CHANNELS_OVERRIDE,
+        ),
+        // The checksum of messages we do not know cannot be verified, so trust the length to skip them.
+        _ => return Some((frame_size, None)),
+    };
+
+    let expected_checksum = u16::from_le_bytes([bytes[checksum_start], bytes[checksum_start + 1]]);
+    if checksum(&bytes[1..checksum_start], crc_extra) != expected_checksum {
+        return None;
+    }
+
+    let mut payload = [0u8; PAYLOAD_SIZE_RC_CHANNELS_OVERRIDE];
+    let received_size = payload_size.min(full_payload_size);
+    payload[..received_size].copy_from_slice(&bytes[header_size..header_size + received_size]);
+
+    let i16_at = |offset: usize| i16::from_le_bytes([payload[offset], payload[offset + 1]]);
+    let u16_at = |offset: usize| u16::from_le_bytes([payload[offset], payload[offset + 1]]);
+
+    // Fields are laid out ordered by size rather than in the order of the message definition.
+    let message = match message_id {
+        MESSAGE_ID_HEARTBEAT => Message::Heartbeat {
+            vehicle_type: payload[4],
+        },
+        MESSAGE_ID_MANUAL_CONTROL => Message::ManualControl {
+            x: i16_at(0),
+            y: i16_at(2),
+            z: i16_at(4),
+            r: i16_at(6),
+            target_system: payload[10],
+        },
+        _ => {
+            let mut channels = [0u16; RC_CHANNELS_OVERRIDE_CHANNELS];
+            for (index, channel) in channels.iter_mut().enumerate().take(8) {
+                *channel = u16_at(index * 2);
+            }
+            // Channels 9 to 18 are an extension that comes after the target fields.
+            for (index, channel) in channels.iter_mut().enumerate().skip(8) {
+                *channel = u16_at(18 + (index - 8) * 2);
+            }
+
+            Message::RcChannelsOverride {
+                target_system: payload[16],
+                channels,
+            }
+        }
+    };
+
+    Some((frame_size, Some((header, message))))
+}
+
+pub fn encode_heartbeat(
+    header: Header,
+    sequence: u8,
+    vehicle_type: u8,
+    base_mode: u8,
+    system_status: u8,
+    signing: Option<&mut MessageSigning>,
+) -> Vec<u8> {
+    const MAV_AUTOPILOT_GENERIC: u8 = 0;
+    const MAVLINK_VERSION: u8 = 3;
+
+    let mut payload = [0u8; PAYLOAD_SIZE_HEARTBEAT];
+    payload[4] = vehicle_type;
+    payload[5] = MAV_AUTOPILOT_GENERIC;
+    payload[6] = base_mode;
+    payload[7] = system_status;
+    payload[8] = MAVLINK_VERSION;
+
+    encode_frame(
+        header,
+        sequence,
+        MESSAGE_ID_HEARTBEAT,
+        CRC_EXTRA_HEARTBEAT,
+        &payload,
+        signing,
+    )
+}
+
+// What a ground control station sends, for testing.
+#[cfg(test)]
+pub fn encode_manual_control(
+    header: Header,
+    target_system: u8,
+    y: i16,
+    z: i16,
+    signing: Option<&mut MessageSigning>,
+) -> Vec<u8> {
+    let mut payload = [0u8; PAYLOAD_SIZE_MANUAL_CONTROL];
+    payload[2..4].copy_from_slice(&y.to_le_bytes());
+    payload[4..6].copy_from_slice(&z.to_le_bytes());
+    payload[10] = target_system;
+
+    encode_frame(
+        header,
+        0,
+        MESSAGE_ID_MANUAL_CONTROL,
+        CRC_EXTRA_MANUAL_CONTROL,
+        &payload,
+        signing,
+    )
+}
+
+fn encode_frame(
+    header: Header,
+    sequence: u8,
+    message_id: u32,
+    crc_extra: u8,
+    payload: &[u8],
+    signing: Option<&mut MessageSigning>,
+) -> Vec<u8> {
+    let incompat_flags = if signing.is_some() {
+        INCOMPAT_FLAG_SIGNED
+    } else {
+        0
+    };
+    let mut frame = vec![
+        MAGIC_V2,
+        payload.len() as u8,
+        incompat_flags,
+        0,
+        sequence,
+        header.system_id,
+        header.component_id,
+    ];
+    frame.extend_from_slice(&message_id.to_le_bytes()[..3]);
+    frame.extend_from_slice(payload);
+
+    let checksum = checksum(&frame[1..], crc_extra);
+    frame.extend_from_slice(&checksum.to_le_bytes());
+    if let Some(signing) = signing {
+        signing.sign(&mut frame);
+    }
+
+    frame
+}
+
+// CRC-16/MCRF4XX, as used by MAVLink.
+fn checksum(bytes: &[u8], crc_extra: u8) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+
+    for &byte in bytes.iter().chain(std::iter::once(&crc_extra)) {
+        let mut tmp = byte ^ (crc & 0xFF) as u8;
+        tmp ^= tmp << 4;
+        crc = (crc >> 8) ^ ((tmp as u16) << 8) ^ ((tmp as u16) << 3) ^ ((tmp as u16) >> 4);
+    }
+
+    crc
+}
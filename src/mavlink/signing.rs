@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// MAVLink 2 message signing, which keeps anyone on the network without the key from driving the vehicle. Ground
+// control stations such as QGroundControl and MAVProxy support it, and derive the 32-byte key from a passphrase the
+// same way as we do: as its SHA-256 hash.
+//
+// A signed frame ends in a link ID, a timestamp and the first 6 bytes of SHA-256(key + frame + link ID + timestamp).
+// The timestamp counts 10 μs units since 2015-01-01 and serves as a sequence number: per stream (system, component and
+// link ID), it has to increase with every frame, so that recorded frames cannot be replayed. A stream we have not seen
+// before may not start more than a minute in the past.
+//
+// 💁‍♂️ Streams are only remembered once a frame has been verified, so without the key the table cannot be filled up.
+
+const KEY_SIZE: usize = 32;
+pub const SIGNATURE_BLOCK_SIZE: usize = 13;
+const SIGNATURE_SIZE: usize = 6;
+const TIMESTAMP_SIZE: usize = 6;
+
+// 2015-01-01T00:00:00Z
+const TIMESTAMP_EPOCH: Duration = Duration::from_secs(1_420_070_400);
+const TIMESTAMP_UNITS_PER_SECOND: u64 = 100_000;
+const MAX_NEW_STREAM_AGE: u64 = 60 * TIMESTAMP_UNITS_PER_SECOND;
+const MAX_STREAMS: usize = 64;
+
+// The link ID of the frames we sign.
+const OUR_LINK_ID: u8 = 0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct Stream {
+    system_id: u8,
+    component_id: u8,
+    link_id: u8,
+}
+
+pub struct MessageSigning {
+    key: [u8; KEY_SIZE],
+    // The newest timestamp seen or sent, which is our notion of the current time should the clock be behind.
+    timestamp: u64,
+    streams: HashMap<Stream, u64>,
+}
+
+impl MessageSigning {
+    pub fn with_passphrase(passphrase: &str) -> MessageSigning {
+        MessageSigning {
+            key: sha256(&[passphrase.as_bytes()]),
+            timestamp: 0,
+            streams: HashMap::new(),
+        }
+    }
+
+    // Expects a complete v2 frame, including its signature block.
+    pub fn verify(&mut self, frame: &[u8], system_id: u8, component_id: u8) -> bool {
+        let Some(signed_size) = frame.len().checked_sub(SIGNATURE_BLOCK_SIZE) else {
+            return false;
+        };
+        let (signed_part, signature_block) = frame.split_at(signed_size);
+        let link_id = signature_block[0];
+        let timestamp = timestamp_from_bytes(&signature_block[1..1 + TIMESTAMP_SIZE]);
+
+        let hash = sha256(&[
+            &self.key,
+            signed_part,
+            &signature_block[..1 + TIMESTAMP_SIZE],
+        ]);
+        if hash[..SIGNATURE_SIZE] != signature_block[1 + TIMESTAMP_SIZE..] {
+            return false;
+        }
+
+        let stream = Stream {
+            system_id,
+            component_id,
+            link_id,
+        };
+        let is_fresh = match self.streams.get(&stream) {
+            Some(&last_timestamp) => timestamp > last_timestamp,
+            None => timestamp + MAX_NEW_STREAM_AGE >= self.current_timestamp(),
+        };
+        if !is_fresh {
+            return false;
+        }
+
+        if !self.streams.contains_key(&stream) && self.streams.len() >= MAX_STREAMS {
+            let oldest_stream = self
+                .streams
+                .iter()
+                .min_by_key(|(_, &last_timestamp)| last_timestamp)
+                .map(|(stream, _)| *stream);
+            if let Some(oldest_stream) = oldest_stream {
+                self.streams.remove(&oldest_stream);
+            }
+        }
+        self.streams.insert(stream, timestamp);
+        self.timestamp = self.timestamp.max(timestamp);
+
+        true
+    }
+
+    // Appends the signature block to `frame`, which must already have the signed flag set and its checksum appended.
+    pub fn sign(&mut self, frame: &mut Vec<u8>) {
+        self.timestamp = self.current_timestamp().max(self.timestamp + 1);
+
+        let mut link_id_and_timestamp = [0u8; 1 + TIMESTAMP_SIZE];
+        link_id_and_timestamp[0] = OUR_LINK_ID;
+        link_id_and_timestamp[1..].copy_from_slice(&self.timestamp.to_le_bytes()[..TIMESTAMP_SIZE]);
+
+        let hash = sha256(&[&self.key, frame, &link_id_and_timestamp]);
+        frame.extend_from_slice(&link_id_and_timestamp);
+        frame.extend_from_slice(&hash[..SIGNATURE_SIZE]);
+    }
+
+    fn current_timestamp(&self) -> u64 {
+        // A clock that has not been set yet (no RTC and no network time) is somewhere in 1970.
+        let clock_timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .and_then(|since_unix_epoch| since_unix_epoch.checked_sub(TIMESTAMP_EPOCH))
+            .map_or(0, |since_epoch| {
+                since_epoch.as_micros() as u64 / (1_000_000 / TIMESTAMP_UNITS_PER_SECOND)
+            });
+
+        clock_timestamp.max(self.timestamp)
+    }
+}
+
+fn timestamp_from_bytes(bytes: &[u8]) -> u64 {
+    let mut timestamp = [0u8; 8];
+    timestamp[..TIMESTAMP_SIZE].copy_from_slice(bytes);
+
+    u64::from_le_bytes(timestamp)
+}
+
+// SHA-256 (FIPS 180-4) of the concatenation of `parts`.
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let length: usize = parts.iter().map(|part| part.len()).sum();
+    let mut message: Vec<u8> = Vec::with_capacity(length + 72);
+    for part in parts {
+        message.extend_from_slice(part);
+    }
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((length as u64) * 8).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (index, word) in block.chunks_exact(4).enumerate() {
+            w[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..64 {
+            let s0 = w[index - 15].rotate_right(7)
+                ^ w[index - 15].rotate_right(18)
+                ^ (w[index - 15] >> 3);
+            let s1 = w[index - 2].rotate_right(17)
+                ^ w[index - 2].rotate_right(19)
+                ^ (w[index - 2] >> 10);
+            w[index] = w[index - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[index - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for index in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(K[index])
+                .wrapping_add(w[index]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+
+        for (value, addition) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(addition);
+        }
+    }
+
+    let mut hash = [0u8; 32];
+    for (bytes, value) in hash.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_test_vectors() {
+        let hex = |hash: [u8; 32]| {
+            hash.iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<String>()
+        };
+
+        assert_eq!(
+            hex(sha256(&[b""])),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(&[b"ab", b"c"])),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(sha256(&[
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            ])),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn signed_frames_are_verified_once() {
+        let mut sender = MessageSigning::with_passphrase("roestbak");
+        let mut receiver = MessageSigning::with_passphrase("roestbak");
+        let mut stranger = MessageSigning::with_passphrase("something else");
+
+        let mut frame = vec![0xFD, 1, 0x01, 0, 0, 255, 190, 0, 0, 0, 42, 0x12, 0x34];
+        sender.sign(&mut frame);
+        assert_eq!(frame.len(), 13 + SIGNATURE_BLOCK_SIZE);
+
+        assert!(!stranger.verify(&frame, 255, 190));
+        assert!(receiver.verify(&frame, 255, 190));
+        // Replayed.
+        assert!(!receiver.verify(&frame, 255, 190));
+
+        let mut tampered = frame.clone();
+        tampered[10] = 43;
+        assert!(!receiver.verify(&tampered, 255, 190));
+
+        frame.truncate(13);
+        sender.sign(&mut frame);
+        assert!(receiver.verify(&frame, 255, 190));
+    }
+}
@@ -0,0 +1,581 @@
+use super::frame::{self, Frame, Header, Message};
+use super::signing::MessageSigning;
+use crate::control_values::{Steering, Throttle};
+use crate::input_source::{ChannelMapping, InputNotification, InputSource};
+use crate::locomotion::LocomotionCommand;
+use crate::subsystems::Subsystem;
+use std::error::Error;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+// A ground control station is expected to send a heartbeat every second. Missing a few in a row means the link is
+// gone, which stops the vehicle.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Control messages are sent continuously while a joystick is in use. If they stop while heartbeats keep coming, the
+// operator has most likely disabled their joystick, which should not leave the vehicle driving.
+const CONTROL_TIMEOUT: Duration = Duration::from_millis(500);
+
+// Handling datagrams happens on the runloop, so a flood should not be able to stall it.
+const MAX_DATAGRAMS_PER_ITERATION: usize = 16;
+const MAX_DATAGRAM_SIZE: usize = 2048;
+
+const OUR_HEADER: Header = Header {
+    system_id: 1,
+    component_id: 1,
+};
+const MAV_TYPE_GCS: u8 = 6;
+const MAV_TYPE_GROUND_ROVER: u8 = 10;
+const MAV_MODE_FLAG_MANUAL_INPUT_ENABLED: u8 = 64;
+const MAV_STATE_ACTIVE: u8 = 4;
+
+// RC_CHANNELS_OVERRIDE pulse widths for the extreme and center positions.
+const PULSE_WIDTH_MINIMUM: f64 = 1000.0;
+const PULSE_WIDTH_CENTER: f64 = 1500.0;
+const PULSE_WIDTH_MAXIMUM: f64 = 2000.0;
+
+// Drives the vehicle from a ground control station (e.g. QGroundControl) or a companion computer speaking MAVLink
+// over UDP. Both MANUAL_CONTROL and RC_CHANNELS_OVERRIDE are understood:
+// - MANUAL_CONTROL is interpreted like ArduPilot's Rover does: `z` is the throttle and `y` the steering,
+// - RC_CHANNELS_OVERRIDE uses the configured channel mapping.
+//
+// We announce ourselves with a heartbeat to the configured ground control station address, if any, and to whoever
+// we have received heartbeats from.
+//
+// Only the ground control station whose heartbeat established the link gets to drive: control messages from any other
+// address are ignored, and so are heartbeats from others until the link is lost.
+//
+// With a signing passphrase, signed frames are only accepted with a valid signature, and our heartbeats are signed.
+// Unsigned frames are accepted as well unless signing is required, but control messages always have to be signed
+// then, which is what keeps others on the network from driving the vehicle. See `signing.rs`.
+//
+// Network input can be turned off while running, see `subsystems.rs`. While off, whatever arrives is discarded and
+// no heartbeats are sent, so the ground control station sees the link go away, and the vehicle stops as it would for
+// a lost link.
+pub struct MavlinkInputSource {
+    socket: UdpSocket,
+    channel_mapping: ChannelMapping,
+    ground_control_station_address: Option<SocketAddr>,
+    peer_address: Option<SocketAddr>,
+    last_heartbeat_received_at: Option<Instant>,
+    last_heartbeat_sent_at: Option<Instant>,
+    heartbeat_sequence: u8,
+    last_control_received_at: Option<Instant>,
+    connected: bool,
+    command: LocomotionCommand,
+    signing: Option<MessageSigning>,
+    require_signing: bool,
+    // Rejected frames are only warned about once per sender.
+    last_rejected_address: Option<SocketAddr>,
+    running: bool,
+}
+
+impl MavlinkInputSource {
+    pub fn new(
+        listen_address: SocketAddr,
+        ground_control_station_address: Option<SocketAddr>,
+        channel_mapping: ChannelMapping,
+        signing_passphrase: Option<&str>,
+        require_signing: bool,
+    ) -> Result<MavlinkInputSource, SetupError> {
+        if require_signing && signing_passphrase.is_none() {
+            return Err(SetupError::MissingSigningPassphrase);
+        }
+
+        let socket =
+            UdpSocket::bind(listen_address).map_err(|source| SetupError::CouldNotBind {
+                address: listen_address,
+                source,
+            })?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|source| SetupError::CouldNotSetNonBlocking { source })?;
+
+        log::info!(
+            "Listening for MAVLink messages on {}{}.",
+            listen_address,
+            match (signing_passphrase, require_signing) {
+                (None, _) => "",
+                (Some(_), false) => ", verifying signed ones",
+                (Some(_), true) => ", accepting only signed ones",
+            }
+        );
+
+        Ok(MavlinkInputSource {
+            socket,
+            channel_mapping,
+            ground_control_station_address,
+            peer_address: None,
+            last_heartbeat_received_at: None,
+            last_heartbeat_sent_at: None,
+            heartbeat_sequence: 0,
+            last_control_received_at: None,
+            connected: false,
+            command: LocomotionCommand::neutral(),
+            signing: signing_passphrase.map(MessageSigning::with_passphrase),
+            require_signing,
+            last_rejected_address: None,
+            running: true,
+        })
+    }
+
+    fn receive_messages(
+        &mut self,
+        now: Instant,
+        notify: &mut dyn FnMut(InputNotification),
+    ) -> Result<(), IoError> {
+        let mut buffer = [0u8; MAX_DATAGRAM_SIZE];
+
+        for _ in 0..MAX_DATAGRAMS_PER_ITERATION {
+            let (size, address) = match self.socket.recv_from(&mut buffer) {
+                Ok(result) => result,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                // Reported when an earlier heartbeat could not be delivered because nobody was listening.
+                Err(error) if error.kind() == ErrorKind::ConnectionRefused => continue,
+                Err(error) => return Err(error),
+            };
+
+            for frame in frame::decode_frames(&buffer[..size]) {
+                // Our own heartbeats might come back to us when broadcasting.
+                if frame.header == OUR_HEADER {
+                    continue;
+                }
+
+                if !self.is_authentic(&frame) {
+                    if self.last_rejected_address != Some(address) {
+                        log::warn!(
+                            "Ignoring MAVLink messages from {} that are not signed with our key.",
+                            address
+                        );
+                        self.last_rejected_address = Some(address);
+                    }
+                    continue;
+                }
+
+                match frame.message {
+                    Message::Heartbeat { vehicle_type } => {
+                        if vehicle_type != MAV_TYPE_GCS {
+                            continue;
+                        }
+
+                        if self.connected && self.peer_address != Some(address) {
+                            self.warn_about_other_sender(address);
+                            continue;
+                        }
+
+                        self.last_heartbeat_received_at = Some(now);
+                        if self.peer_address != Some(address) {
+                            log::info!("Receiving MAVLink heartbeats from {}.", address);
+                            self.peer_address = Some(address);
+                        }
+                        if !self.connected {
+                            log::info!("MAVLink link established.");
+                            self.connected = true;
+                            notify(InputNotification::Connected);
+                        }
+                    }
+                    Message::ManualControl {
+                        target_system,
+                        y,
+                        z,
+                        ..
+                    } => {
+                        if self.accepts_control(&frame, address) && is_for_us(target_system) {
+                            self.last_control_received_at = Some(now);
+                            self.command = LocomotionCommand::new(
+                                Throttle::new(manual_control_axis_value(z)),
+                                Steering::new(manual_control_axis_value(y)),
+                            );
+                        }
+                    }
+                    Message::RcChannelsOverride {
+                        target_system,
+                        channels,
+                    } => {
+                        if self.accepts_control(&frame, address) && is_for_us(target_system) {
+                            self.last_control_received_at = Some(now);
+                            self.command = self.channel_mapping.to_command(|channel| {
+                                pulse_width_value(channels[channel as usize - 1])
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn discard_messages(&mut self) -> Result<(), IoError> {
+        let mut buffer = [0u8; MAX_DATAGRAM_SIZE];
+
+        for _ in 0..MAX_DATAGRAMS_PER_ITERATION {
+            match self.socket.recv_from(&mut buffer) {
+                Ok(_) => (),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) if error.kind() == ErrorKind::ConnectionRefused => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_authentic(&mut self, frame: &Frame) -> bool {
+        match (&mut self.signing, frame.is_signed) {
+            (Some(signing), true) => signing.verify(
+                frame.bytes,
+                frame.header.system_id,
+                frame.header.component_id,
+            ),
+            (Some(_), false) => !self.require_signing,
+            (None, _) => true,
+        }
+    }
+
+    // Control messages are only taken from the ground control station the link is established with, and only signed
+    // ones when signing is set up (their signature was verified by `is_authentic`).
+    fn accepts_control(&mut self, frame: &Frame, address: SocketAddr) -> bool {
+        if !self.connected {
+            return false;
+        }
+        if self.peer_address != Some(address) {
+            self.warn_about_other_sender(address);
+            return false;
+        }
+
+        if self.signing.is_some() && !frame.is_signed {
+            if self.last_rejected_address != Some(address) {
+                log::warn!(
+                    "Ignoring unsigned MAVLink control messages from {}.",
+                    address
+                );
+                self.last_rejected_address = Some(address);
+            }
+            return false;
+        }
+
+        true
+    }
+
+    fn warn_about_other_sender(&mut self, address: SocketAddr) {
+        if self.last_rejected_address != Some(address) {
+            log::warn!(
+                "Ignoring MAVLink messages from {}, as the link is established with {}.",
+                address,
+                self.peer_address
+                    .map_or_else(|| "nobody".to_string(), |peer| peer.to_string())
+            );
+            self.last_rejected_address = Some(address);
+        }
+    }
+
+    fn send_heartbeat_if_due(&mut self, now: Instant) {
+        if self
+            .last_heartbeat_sent_at
+            .is_some_and(|sent_at| now - sent_at < HEARTBEAT_INTERVAL)
+        {
+            return;
+        }
+        self.last_heartbeat_sent_at = Some(now);
+
+        let heartbeat = frame::encode_heartbeat(
+            OUR_HEADER,
+            self.heartbeat_sequence,
+            MAV_TYPE_GROUND_ROVER,
+            MAV_MODE_FLAG_MANUAL_INPUT_ENABLED,
+            MAV_STATE_ACTIVE,
+            self.signing.as_mut(),
+        );
+        self.heartbeat_sequence = self.heartbeat_sequence.wrapping_add(1);
+
+        let mut addresses = vec![];
+        addresses.extend(self.ground_control_station_address);
+        addresses.extend(
+            self.peer_address
+                .filter(|address| Some(*address) != self.ground_control_station_address),
+        );
+
+        for address in addresses {
+            // Losing a heartbeat now and then is fine, the next one will follow shortly.
+            if let Err(error) = self.socket.send_to(&heartbeat, address) {
+                log::debug!(
+                    "Could not send MAVLink heartbeat to {}. - Cause: {}",
+                    address,
+                    error
+                );
+            }
+        }
+    }
+}
+
+impl InputSource for MavlinkInputSource {
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn process_input(
+        &mut self,
+        notify: &mut dyn FnMut(InputNotification),
+    ) -> Result<LocomotionCommand, Box<dyn Error>> {
+        let now = Instant::now();
+
+        if self.running {
+            self.receive_messages(now, notify)
+                .map_err(|source| ProcessingError::CouldNotReceive { source })?;
+            self.send_heartbeat_if_due(now);
+        } else {
+            self.discard_messages()
+                .map_err(|source| ProcessingError::CouldNotReceive { source })?;
+        }
+
+        let heartbeat_timed_out = self
+            .last_heartbeat_received_at
+            .is_none_or(|received_at| now - received_at >= HEARTBEAT_TIMEOUT);
+        if self.connected && heartbeat_timed_out {
+            log::warn!("No MAVLink heartbeats received, assuming the link is lost.");
+            self.connected = false;
+            notify(InputNotification::Disconnected);
+
+            if !self.command.is_neutral() {
+                notify(InputNotification::FailsafeEngaged);
+            }
+            self.command = LocomotionCommand::neutral();
+        }
+
+        let control_timed_out = self
+            .last_control_received_at
+            .is_none_or(|received_at| now - received_at >= CONTROL_TIMEOUT);
+        if control_timed_out {
+            self.command = LocomotionCommand::neutral();
+        }
+
+        Ok(self.command)
+    }
+
+    // Until heard from again, the ground control station is considered gone. It is told that we are still here right
+    // away.
+    fn expire_received_input(&mut self) {
+        self.last_heartbeat_received_at = None;
+        self.last_heartbeat_sent_at = None;
+        self.last_control_received_at = None;
+    }
+
+    fn network_input(&mut self) -> Option<&mut dyn Subsystem> {
+        Some(self)
+    }
+}
+
+impl Subsystem for MavlinkInputSource {
+    fn is_running(&self) -> bool {
+        self.running
+    }
+
+    // Like after being held up: the link is only there again once the ground control station is heard from.
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        self.expire_received_input();
+        self.running = true;
+        log::info!("Listening for MAVLink messages again.");
+
+        Ok(())
+    }
+
+    // The link is considered lost right away, with the next call to `process_input`.
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.expire_received_input();
+        self.running = false;
+        log::info!("Ignoring MAVLink messages until network input is enabled again.");
+
+        Ok(())
+    }
+}
+
+// A target system of 0 is a broadcast.
+fn is_for_us(target_system: u8) -> bool {
+    target_system == 0 || target_system == OUR_HEADER.system_id
+}
+
+fn manual_control_axis_value(value: i16) -> f64 {
+    (value as f64 / 1000.0).clamp(-1.0, 1.0)
+}
+
+fn pulse_width_value(pulse_width: u16) -> f64 {
+    // Channels that are not being overridden are treated as centered.
+    if pulse_width == 0 || pulse_width == u16::MAX {
+        return 0.0;
+    }
+
+    let pulse_width = pulse_width as f64;
+    let value = if pulse_width >= PULSE_WIDTH_CENTER {
+        (pulse_width - PULSE_WIDTH_CENTER) / (PULSE_WIDTH_MAXIMUM - PULSE_WIDTH_CENTER)
+    } else {
+        (pulse_width - PULSE_WIDTH_CENTER) / (PULSE_WIDTH_CENTER - PULSE_WIDTH_MINIMUM)
+    };
+
+    value.clamp(-1.0, 1.0)
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    CouldNotBind {
+        address: SocketAddr,
+        source: IoError,
+    },
+    CouldNotSetNonBlocking {
+        source: IoError,
+    },
+    MissingSigningPassphrase,
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SetupError::CouldNotBind { address: _, source } => Some(source),
+            SetupError::CouldNotSetNonBlocking { source } => Some(source),
+            SetupError::MissingSigningPassphrase => None,
+        }
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::CouldNotBind { address, source: _ } => {
+                format!("Could not bind MAVLink socket to {}.", address)
+            }
+            SetupError::CouldNotSetNonBlocking { source: _ } => {
+                "Could not make MAVLink socket non-blocking.".to_string()
+            }
+            SetupError::MissingSigningPassphrase => {
+                "MAVLink signing is required, but no signing passphrase is configured.".to_string()
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[derive(Debug)]
+pub enum ProcessingError {
+    CouldNotReceive { source: IoError },
+}
+
+impl Error for ProcessingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            ProcessingError::CouldNotReceive { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for ProcessingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            ProcessingError::CouldNotReceive { source: _ } => "Could not receive MAVLink messages.",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GCS_HEADER: Header = Header {
+        system_id: 255,
+        component_id: 190,
+    };
+
+    struct GroundControlStation {
+        socket: UdpSocket,
+        signing: Option<MessageSigning>,
+    }
+
+    impl GroundControlStation {
+        fn new(signing_passphrase: Option<&str>) -> Self {
+            Self {
+                socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+                signing: signing_passphrase.map(MessageSigning::with_passphrase),
+            }
+        }
+
+        fn send(&mut self, source: &MavlinkInputSource, frame: &[u8]) {
+            self.socket
+                .send_to(frame, source.socket.local_addr().unwrap())
+                .unwrap();
+        }
+
+        fn send_heartbeat(&mut self, source: &MavlinkInputSource) {
+            let heartbeat =
+                frame::encode_heartbeat(GCS_HEADER, 0, MAV_TYPE_GCS, 0, 0, self.signing.as_mut());
+            self.send(source, &heartbeat);
+        }
+
+        fn send_throttle(&mut self, source: &MavlinkInputSource, z: i16, signed: bool) {
+            let signing = self.signing.as_mut().filter(|_| signed);
+            let control = frame::encode_manual_control(GCS_HEADER, 0, 0, z, signing);
+            self.send(source, &control);
+        }
+    }
+
+    fn throttle(source: &mut MavlinkInputSource) -> f64 {
+        source
+            .process_input(&mut |_| ())
+            .unwrap()
+            .get_throttle()
+            .value()
+    }
+
+    fn mavlink_input_source(signing_passphrase: Option<&str>) -> MavlinkInputSource {
+        MavlinkInputSource::new(
+            "127.0.0.1:0".parse().unwrap(),
+            None,
+            ChannelMapping {
+                throttle_channel: 3,
+                steering_channel: 1,
+                throttle_reversed: false,
+                steering_reversed: false,
+            },
+            signing_passphrase,
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn only_the_ground_control_station_drives() {
+        let mut source = mavlink_input_source(None);
+        let mut ground_control_station = GroundControlStation::new(None);
+        let mut other = GroundControlStation::new(None);
+
+        ground_control_station.send_heartbeat(&source);
+        ground_control_station.send_throttle(&source, 500, false);
+        assert_eq!(throttle(&mut source), 0.5);
+
+        // Neither driving nor taking over the link works from elsewhere.
+        other.send_heartbeat(&source);
+        other.send_throttle(&source, 1000, false);
+        assert_eq!(throttle(&mut source), 0.5);
+        assert_eq!(
+            source.peer_address,
+            Some(ground_control_station.socket.local_addr().unwrap())
+        );
+    }
+
+    #[test]
+    fn control_has_to_be_signed_with_signing() {
+        let mut source = mavlink_input_source(Some("secret"));
+        let mut ground_control_station = GroundControlStation::new(Some("secret"));
+
+        ground_control_station.send_heartbeat(&source);
+        ground_control_station.send_throttle(&source, 500, false);
+        assert_eq!(throttle(&mut source), 0.0);
+
+        ground_control_station.send_throttle(&source, 500, true);
+        assert_eq!(throttle(&mut source), 0.5);
+    }
+}
@@ -0,0 +1,599 @@
+use crate::runloop;
+use std::error::Error;
+use std::ffi::CStr;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::Duration;
+
+// Advertises the network endpoints of the service via multicast DNS, as `<instance>._roestbak._udp.local`, so that a
+// ground station can find the vehicle on the network (e.g. with `avahi-browse _roestbak._udp`) without knowing its
+// address. Each endpoint is a service instance of its own, with a TXT record telling what it is for.
+//
+// This is just enough of a responder (RFC 6762) for a single host on a small network:
+// - it answers queries for our names with all of our records, without probing for name conflicts,
+// - it announces the records when starting, and withdraws them when stopping,
+// - it shares the port with any other responder (such as Avahi) on the host.
+//
+// Queries are handled on the network thread, see `network.rs`.
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+const SERVICE_TYPE: &str = "_roestbak._udp.local";
+const SERVICE_TYPE_ENUMERATION: &str = "_services._dns-sd._udp.local";
+
+// RFC 6762 recommends 120 s for records with a host name, and 75 minutes for the others.
+const HOST_RECORD_TTL: u32 = 120;
+const OTHER_RECORD_TTL: u32 = 4500;
+
+// Announcements are repeated once, after a second, in case the first one got lost.
+const ANNOUNCEMENT_INTERVAL: Duration = Duration::from_secs(1);
+const ANNOUNCEMENT_COUNT: u8 = 2;
+
+const MAX_DATAGRAMS_PER_ITERATION: usize = 16;
+const MAX_DATAGRAM_SIZE: usize = 9000;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+const CLASS_CACHE_FLUSH: u16 = 0x8000;
+const CLASS_UNICAST_RESPONSE: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+const FLAG_QUERY_RESPONSE: u16 = 0x8000;
+
+pub struct Endpoint {
+    // Tells apart the endpoints of one vehicle, e.g. "control".
+    pub role: &'static str,
+    pub protocol: &'static str,
+    pub port: u16,
+}
+
+pub struct MdnsAdvertiser {
+    socket: UdpSocket,
+    host_name: String,
+    instance_name: String,
+    endpoints: Vec<Endpoint>,
+    announcements_sent: u8,
+    last_announcement_at: Option<Duration>,
+}
+
+impl MdnsAdvertiser {
+    // `instance_name` defaults to the host name. The endpoints are advertised as "<instance name> <role>".
+    pub fn new(
+        instance_name: Option<&str>,
+        endpoints: Vec<Endpoint>,
+    ) -> Result<MdnsAdvertiser, SetupError> {
+        let host_name = host_name().map_err(|source| SetupError::CouldNotGetHostName { source })?;
+        let socket = bind_shared_socket().map_err(|source| SetupError::CouldNotBind { source })?;
+        socket
+            .join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)
+            .map_err(|source| SetupError::CouldNotJoinGroup { source })?;
+        socket
+            .set_multicast_ttl_v4(255)
+            .map_err(|source| SetupError::CouldNotJoinGroup { source })?;
+
+        let instance_name = instance_name.unwrap_or(&host_name).to_string();
+        for endpoint in &endpoints {
+            log::info!(
+                "Advertising {} on port {} as '{} {}' via mDNS.",
+                endpoint.role,
+                endpoint.port,
+                instance_name,
+                endpoint.role
+            );
+        }
+
+        Ok(MdnsAdvertiser {
+            socket,
+            host_name,
+            instance_name,
+            endpoints,
+            announcements_sent: 0,
+            last_announcement_at: None,
+        })
+    }
+
+    // Expected to be called every 10ms or so.
+    pub fn update(&mut self) {
+        let now = runloop::now();
+        let announcement_due = self.announcements_sent < ANNOUNCEMENT_COUNT
+            && self
+                .last_announcement_at
+                .is_none_or(|last_announcement_at| {
+                    now - last_announcement_at >= ANNOUNCEMENT_INTERVAL
+                });
+        if announcement_due {
+            self.announcements_sent += 1;
+            self.last_announcement_at = Some(now);
+            self.send(0, HOST_RECORD_TTL, OTHER_RECORD_TTL, multicast_address());
+        }
+
+        let mut buffer = [0u8; MAX_DATAGRAM_SIZE];
+        for _ in 0..MAX_DATAGRAMS_PER_ITERATION {
+            let (size, sender) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    log::debug!("Could not receive mDNS query. - Cause: {}", error);
+                    break;
+                }
+            };
+
+            let Some(query) = Query::parse(&buffer[..size]) else {
+                continue;
+            };
+            if !query
+                .questions
+                .iter()
+                .any(|question| self.is_ours(question))
+            {
+                continue;
+            }
+
+            // Queries that do not come from port 5353 are from simple resolvers that expect a plain DNS answer.
+            // Those, and questions asking for a unicast response, are answered directly.
+            let legacy = sender.port() != MDNS_PORT;
+            let unicast = legacy
+                || query
+                    .questions
+                    .iter()
+                    .all(|question| question.unicast_response);
+            if unicast {
+                let ttl = |ttl: u32| if legacy { ttl.min(10) } else { ttl };
+                self.send(
+                    if legacy { query.id } else { 0 },
+                    ttl(HOST_RECORD_TTL),
+                    ttl(OTHER_RECORD_TTL),
+                    sender,
+                );
+            } else {
+                self.send(0, HOST_RECORD_TTL, OTHER_RECORD_TTL, multicast_address());
+            }
+        }
+    }
+
+    // Tells the network that we are gone, so that ground stations do not keep listing the vehicle.
+    pub fn withdraw(&mut self) {
+        self.send(0, 0, 0, multicast_address());
+    }
+
+    fn is_ours(&self, question: &Question) -> bool {
+        let name_matches = |name: &str| question.name.eq_ignore_ascii_case(name);
+        let type_matches = |record_type: u16| {
+            question.record_type == record_type || question.record_type == TYPE_ANY
+        };
+
+        (name_matches(SERVICE_TYPE) && type_matches(TYPE_PTR))
+            || (name_matches(SERVICE_TYPE_ENUMERATION) && type_matches(TYPE_PTR))
+            || (name_matches(&self.host_domain_name()) && type_matches(TYPE_A))
+            || self.endpoints.iter().any(|endpoint| {
+                name_matches(&self.service_instance_name(endpoint))
+                    && (type_matches(TYPE_SRV) || type_matches(TYPE_TXT))
+            })
+    }
+
+    fn send(&self, id: u16, host_record_ttl: u32, other_record_ttl: u32, destination: SocketAddr) {
+        let addresses = match ipv4_addresses(&self.socket) {
+            Ok(addresses) => addresses,
+            Err(error) => {
+                log::debug!("Could not get network addresses. - Cause: {}", error);
+                Vec::new()
+            }
+        };
+
+        let response = self.response(id, host_record_ttl, other_record_ttl, &addresses);
+        if let Err(error) = self.socket.send_to(&response, destination) {
+            log::debug!(
+                "Could not send mDNS response to {}. - Cause: {}",
+                destination,
+                error
+            );
+        }
+    }
+
+    fn response(
+        &self,
+        id: u16,
+        host_record_ttl: u32,
+        other_record_ttl: u32,
+        addresses: &[Ipv4Addr],
+    ) -> Vec<u8> {
+        let host_domain_name = self.host_domain_name();
+        let mut records = Vec::new();
+
+        records.push(Record::pointer(
+            SERVICE_TYPE_ENUMERATION,
+            SERVICE_TYPE,
+            other_record_ttl,
+        ));
+        for endpoint in &self.endpoints {
+            let service_instance_name = self.service_instance_name(endpoint);
+            records.push(Record::pointer(
+                SERVICE_TYPE,
+                &service_instance_name,
+                other_record_ttl,
+            ));
+            records.push(Record::service(
+                &service_instance_name,
+                &host_domain_name,
+                endpoint.port,
+                host_record_ttl,
+            ));
+            records.push(Record::text(
+                &service_instance_name,
+                &[
+                    format!("role={}", endpoint.role),
+                    format!("protocol={}", endpoint.protocol),
+                ],
+                other_record_ttl,
+            ));
+        }
+        for address in addresses {
+            records.push(Record::address(
+                &host_domain_name,
+                *address,
+                host_record_ttl,
+            ));
+        }
+
+        let mut message = Vec::new();
+        message.extend_from_slice(&id.to_be_bytes());
+        message.extend_from_slice(&FLAGS_RESPONSE.to_be_bytes());
+        message.extend_from_slice(&0u16.to_be_bytes());
+        message.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        message.extend_from_slice(&0u16.to_be_bytes());
+        message.extend_from_slice(&0u16.to_be_bytes());
+        for record in records {
+            message.extend_from_slice(&record.0);
+        }
+
+        message
+    }
+
+    fn host_domain_name(&self) -> String {
+        format!("{}.local", self.host_name)
+    }
+
+    fn service_instance_name(&self, endpoint: &Endpoint) -> String {
+        format!("{} {}.{}", self.instance_name, endpoint.role, SERVICE_TYPE)
+    }
+}
+
+fn multicast_address() -> SocketAddr {
+    SocketAddr::V4(SocketAddrV4::new(MDNS_GROUP, MDNS_PORT))
+}
+
+// A resource record, encoded.
+struct Record(Vec<u8>);
+
+impl Record {
+    fn new(name: &str, record_type: u16, unique: bool, ttl: u32, data: &[u8]) -> Record {
+        let class = if unique {
+            CLASS_IN | CLASS_CACHE_FLUSH
+        } else {
+            CLASS_IN
+        };
+
+        let mut bytes = encode_name(name);
+        bytes.extend_from_slice(&record_type.to_be_bytes());
+        bytes.extend_from_slice(&class.to_be_bytes());
+        bytes.extend_from_slice(&ttl.to_be_bytes());
+        bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(data);
+
+        Record(bytes)
+    }
+
+    // Other hosts may point to instances of their own, so pointers are shared records.
+    fn pointer(name: &str, target: &str, ttl: u32) -> Record {
+        Record::new(name, TYPE_PTR, false, ttl, &encode_name(target))
+    }
+
+    fn service(name: &str, target: &str, port: u16, ttl: u32) -> Record {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0u16.to_be_bytes()); // Priority
+        data.extend_from_slice(&0u16.to_be_bytes()); // Weight
+        data.extend_from_slice(&port.to_be_bytes());
+        data.extend_from_slice(&encode_name(target));
+
+        Record::new(name, TYPE_SRV, true, ttl, &data)
+    }
+
+    fn text(name: &str, entries: &[String], ttl: u32) -> Record {
+        let mut data = Vec::new();
+        for entry in entries {
+            let entry = &entry.as_bytes()[..entry.len().min(255)];
+            data.push(entry.len() as u8);
+            data.extend_from_slice(entry);
+        }
+
+        Record::new(name, TYPE_TXT, true, ttl, &data)
+    }
+
+    fn address(name: &str, address: Ipv4Addr, ttl: u32) -> Record {
+        Record::new(name, TYPE_A, true, ttl, &address.octets())
+    }
+}
+
+// Labels longer than the 63 bytes DNS allows are cut short.
+fn encode_name(name: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        bytes.push(label.len() as u8);
+        bytes.extend_from_slice(label);
+    }
+    bytes.push(0);
+
+    bytes
+}
+
+struct Question {
+    name: String,
+    record_type: u16,
+    unicast_response: bool,
+}
+
+struct Query {
+    id: u16,
+    questions: Vec<Question>,
+}
+
+impl Query {
+    // Returns `None` for responses and anything that is not a well-formed query.
+    fn parse(message: &[u8]) -> Option<Query> {
+        let u16_at = |offset: usize| -> Option<u16> {
+            Some(u16::from_be_bytes([
+                *message.get(offset)?,
+                *message.get(offset + 1)?,
+            ]))
+        };
+
+        let id = u16_at(0)?;
+        if u16_at(2)? & FLAG_QUERY_RESPONSE != 0 {
+            return None;
+        }
+
+        let mut questions = Vec::new();
+        let mut offset = 12;
+        for _ in 0..u16_at(4)? {
+            let (name, name_end) = decode_name(message, offset)?;
+            let record_type = u16_at(name_end)?;
+            let class = u16_at(name_end + 2)?;
+            offset = name_end + 4;
+
+            questions.push(Question {
+                name,
+                record_type,
+                unicast_response: class & CLASS_UNICAST_RESPONSE != 0,
+            });
+        }
+
+        Some(Query { id, questions })
+    }
+}
+
+// Returns the name at `offset` and where it ends. Names may be compressed, i.e. end in a pointer to (the end of) a
+// name earlier in the message.
+fn decode_name(message: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut position = offset;
+    let mut end = None;
+
+    // Pointers must go backwards, which rules out loops.
+    let mut limit = offset;
+    loop {
+        let length = *message.get(position)? as usize;
+        match length {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(position + 1)));
+            }
+            length if length & 0xC0 == 0xC0 => {
+                let target = ((length & 0x3F) << 8) | *message.get(position + 1)? as usize;
+                if target >= limit {
+                    return None;
+                }
+                end.get_or_insert(position + 2);
+                limit = target;
+                position = target;
+            }
+            length if length <= 63 => {
+                let label = message.get(position + 1..position + 1 + length)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                position += 1 + length;
+            }
+            _ => return None,
+        }
+    }
+}
+
+fn bind_shared_socket() -> Result<UdpSocket, IoError> {
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_INET,
+            libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            0,
+        )
+    };
+    if fd == -1 {
+        return Err(IoError::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let socket = UdpSocket::from(fd);
+
+    // 💁‍♂️ Both are needed to share the port with Avahi, which sets both as well.
+    for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+        set_socket_option(&socket, option)?;
+    }
+
+    let address = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: MDNS_PORT.to_be(),
+        sin_addr: libc::in_addr {
+            s_addr: u32::from(Ipv4Addr::UNSPECIFIED).to_be(),
+        },
+        sin_zero: [0; 8],
+    };
+    let result = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            &address as *const libc::sockaddr_in as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+    if result == -1 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(socket)
+}
+
+fn set_socket_option(socket: &UdpSocket, option: libc::c_int) -> Result<(), IoError> {
+    let enabled: libc::c_int = 1;
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            &enabled as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if result == -1 {
+        Err(IoError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+// Without any domain, as in "roestbak" rather than "roestbak.example.com".
+fn host_name() -> Result<String, IoError> {
+    let mut buffer = [0u8; 256];
+    let result =
+        unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+    if result == -1 {
+        return Err(IoError::last_os_error());
+    }
+
+    let host_name = CStr::from_bytes_until_nul(&buffer)
+        .map_err(|_| IoError::from(ErrorKind::InvalidData))?
+        .to_string_lossy();
+
+    Ok(host_name.split('.').next().unwrap_or_default().to_string())
+}
+
+// The IPv4 addresses of all interfaces that are up, except for the loopback interface.
+//
+// 💁‍♂️ This asks the socket with ioctls rather than using `getifaddrs()`, as the latter opens a netlink socket of its
+// own, which the seccomp filter does not allow. Addresses are looked up for every response, as they may well have
+// changed since starting, e.g. when the WiFi connection only came up afterwards.
+fn ipv4_addresses(socket: &UdpSocket) -> Result<Vec<Ipv4Addr>, IoError> {
+    #[repr(C)]
+    struct InterfaceConfiguration {
+        length: libc::c_int,
+        requests: *mut libc::ifreq,
+    }
+
+    const MAX_INTERFACES: usize = 32;
+    let mut requests: [libc::ifreq; MAX_INTERFACES] = unsafe { std::mem::zeroed() };
+    let mut configuration = InterfaceConfiguration {
+        length: std::mem::size_of_val(&requests) as libc::c_int,
+        requests: requests.as_mut_ptr(),
+    };
+    let fd = socket.as_raw_fd();
+    if unsafe { libc::ioctl(fd, libc::SIOCGIFCONF as _, &mut configuration) } == -1 {
+        return Err(IoError::last_os_error());
+    }
+
+    let count = configuration.length as usize / std::mem::size_of::<libc::ifreq>();
+    let mut addresses = Vec::new();
+    for request in &requests[..count.min(MAX_INTERFACES)] {
+        let address = unsafe { request.ifr_ifru.ifru_addr };
+        if address.sa_family as libc::c_int != libc::AF_INET {
+            continue;
+        }
+        let address = unsafe { *(&address as *const libc::sockaddr as *const libc::sockaddr_in) };
+
+        let mut flags_request: libc::ifreq = unsafe { std::mem::zeroed() };
+        flags_request.ifr_name = request.ifr_name;
+        if unsafe { libc::ioctl(fd, libc::SIOCGIFFLAGS as _, &mut flags_request) } == -1 {
+            continue;
+        }
+        let flags = unsafe { flags_request.ifr_ifru.ifru_flags } as libc::c_int;
+        if flags & libc::IFF_UP != 0 && flags & libc::IFF_LOOPBACK == 0 {
+            addresses.push(Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)));
+        }
+    }
+
+    Ok(addresses)
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    CouldNotGetHostName { source: IoError },
+    CouldNotBind { source: IoError },
+    CouldNotJoinGroup { source: IoError },
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            SetupError::CouldNotGetHostName { source } => source,
+            SetupError::CouldNotBind { source } => source,
+            SetupError::CouldNotJoinGroup { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::CouldNotGetHostName { source: _ } => "Could not get host name.",
+            SetupError::CouldNotBind { source: _ } => "Could not bind to the mDNS port.",
+            SetupError::CouldNotJoinGroup { source: _ } => {
+                "Could not join the mDNS multicast group."
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_query() {
+        // A query for "_roestbak._udp.local" PTR, followed by one for "roestbak.local" A with a pointer to "local".
+        let mut message = vec![0x12, 0x34, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0];
+        message.extend_from_slice(&encode_name(SERVICE_TYPE));
+        message.extend_from_slice(&[0, 12, 0, 1]);
+        message.extend_from_slice(&[8]);
+        message.extend_from_slice(b"roestbak");
+        message.extend_from_slice(&[0xC0, 12 + 1 + 9 + 1 + 4, 0, 1, 0x80, 1]);
+
+        let query = Query::parse(&message).unwrap();
+        assert_eq!(query.id, 0x1234);
+        assert_eq!(query.questions.len(), 2);
+        assert_eq!(query.questions[0].name, SERVICE_TYPE);
+        assert_eq!(query.questions[0].record_type, TYPE_PTR);
+        assert!(!query.questions[0].unicast_response);
+        assert_eq!(query.questions[1].name, "roestbak.local");
+        assert_eq!(query.questions[1].record_type, TYPE_A);
+        assert!(query.questions[1].unicast_response);
+
+        // A pointer to itself is no name.
+        assert!(decode_name(&[0xC0, 0], 0).is_none());
+
+        // Responses are not queries.
+        message[2] = 0x84;
+        assert!(Query::parse(&message).is_none());
+    }
+}
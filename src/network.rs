@@ -0,0 +1,224 @@
+#[cfg(feature = "mdns")]
+use crate::mdns::MdnsAdvertiser;
+use crate::subsystems::Subsystem;
+use crate::telemetry::{Sample, UdpTelemetrySender};
+use std::error::Error;
+use std::io::Error as IoError;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Network services that nothing on the runloop depends on (telemetry, being found via mDNS) run on a thread of their
+// own, so that a congested network or a burst of queries never makes an iteration overrun. The split is:
+//
+// - The runloop only ever hands messages over through `NetworkRuntime`, which never blocks: the channel is bounded,
+//   and when the network thread falls behind, messages are dropped (and counted) rather than queued up. Handing over
+//   is a lock-free operation on the standard library's channel, without any allocation.
+// - The network thread gives no guarantees whatsoever. It handles messages as they come in and otherwise polls its
+//   services every `POLL_INTERVAL`, which is about the runloop's pace, so nothing gets noticeably slower.
+// - Nothing flows back. Services that affect driving (input sources, control requests) stay on the runloop, where
+//   they are bounded by doing non-blocking I/O only.
+//
+// The telemetry stream can be turned off while running, see `subsystems.rs`. That happens on the runloop's side, by
+// not handing over any telemetry, which leaves the thread and its socket as they are for when it is turned on again.
+//
+// ⚠️ Creating threads is not allowed once the seccomp filter has been installed, so the runtime has to be started
+// before that. The filter then applies to its thread as well.
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// About a second worth of telemetry at the default runloop interval, which a thread that is merely descheduled for a
+// while easily catches up with.
+const CHANNEL_CAPACITY: usize = 100;
+
+// What runs on the network thread. Any may be left out.
+pub struct NetworkServices {
+    pub telemetry_sender: Option<UdpTelemetrySender>,
+    #[cfg(feature = "mdns")]
+    pub mdns_advertiser: Option<MdnsAdvertiser>,
+}
+
+impl NetworkServices {
+    pub fn is_empty(&self) -> bool {
+        #[cfg(feature = "mdns")]
+        if self.mdns_advertiser.is_some() {
+            return false;
+        }
+
+        self.telemetry_sender.is_none()
+    }
+}
+
+enum Message {
+    Telemetry(Sample),
+    Stop,
+}
+
+pub struct NetworkRuntime {
+    sender: SyncSender<Message>,
+    thread: Option<JoinHandle<()>>,
+    dropped_message_count: u64,
+    // Whether messages are being dropped at the moment, so that only the first one of a series is logged.
+    dropping: bool,
+    // `None` without a telemetry sender.
+    telemetry_stream: Option<TelemetryStream>,
+}
+
+pub struct TelemetryStream {
+    running: bool,
+}
+
+impl Subsystem for TelemetryStream {
+    fn is_running(&self) -> bool {
+        self.running
+    }
+
+    fn start(&mut self) -> Result<(), Box<dyn Error>> {
+        self.running = true;
+        log::info!("Sending telemetry again.");
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        self.running = false;
+        log::info!("Stopped sending telemetry.");
+
+        Ok(())
+    }
+}
+
+impl NetworkRuntime {
+    pub fn start(services: NetworkServices) -> Result<NetworkRuntime, SetupError> {
+        let telemetry_stream = services
+            .telemetry_sender
+            .is_some()
+            .then_some(TelemetryStream { running: true });
+        let (sender, receiver) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        let (started_sender, started_receiver) = mpsc::sync_channel(1);
+        let thread = thread::Builder::new()
+            .name("network".to_string())
+            .spawn(move || {
+                let _ = started_sender.send(());
+                run(services, receiver)
+            })
+            .map_err(|source| SetupError::CouldNotStartThread { source })?;
+
+        // ⚠️ Starting a thread takes a few system calls on the thread itself (e.g. for naming it), which the seccomp
+        // filter does not allow. Returning only once the thread runs makes sure that those are done before the filter
+        // gets installed.
+        let _ = started_receiver.recv();
+
+        Ok(NetworkRuntime {
+            sender,
+            thread: Some(thread),
+            dropped_message_count: 0,
+            dropping: false,
+            telemetry_stream,
+        })
+    }
+
+    // Never blocks, see above.
+    pub fn send_telemetry(&mut self, sample: Sample) {
+        if !self
+            .telemetry_stream
+            .as_ref()
+            .is_some_and(TelemetryStream::is_running)
+        {
+            return;
+        }
+
+        match self.sender.try_send(Message::Telemetry(sample)) {
+            Ok(()) => {
+                if self.dropping {
+                    log::info!("Network thread has caught up again.");
+                    self.dropping = false;
+                }
+            }
+            Err(TrySendError::Full(_)) => {
+                self.dropped_message_count += 1;
+                if !self.dropping {
+                    log::warn!("Network thread is falling behind, dropping telemetry.");
+                    self.dropping = true;
+                }
+            }
+            // The thread only goes away when it panicked, which has been reported already.
+            Err(TrySendError::Disconnected(_)) => self.dropped_message_count += 1,
+        }
+    }
+
+    pub fn telemetry_stream(&mut self) -> Option<&mut TelemetryStream> {
+        self.telemetry_stream.as_mut()
+    }
+
+    pub fn dropped_message_count(&self) -> u64 {
+        self.dropped_message_count
+    }
+
+    // Lets the services finish (e.g. withdraw the mDNS records) and waits for the thread to exit. This does block,
+    // and is meant for when the runloop has concluded.
+    pub fn stop(mut self) {
+        if self.dropped_message_count > 0 {
+            log::info!(
+                "Dropped {} messages for the network thread.",
+                self.dropped_message_count
+            );
+        }
+
+        let _ = self.sender.send(Message::Stop);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Network thread panicked.");
+            }
+        }
+    }
+}
+
+fn run(mut services: NetworkServices, receiver: Receiver<Message>) {
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(Message::Telemetry(sample)) => {
+                if let Some(telemetry_sender) = &mut services.telemetry_sender {
+                    telemetry_sender.send(&sample);
+                }
+            }
+            Ok(Message::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+            Err(RecvTimeoutError::Timeout) => (),
+        }
+
+        #[cfg(feature = "mdns")]
+        if let Some(mdns_advertiser) = &mut services.mdns_advertiser {
+            mdns_advertiser.update();
+        }
+    }
+
+    #[cfg(feature = "mdns")]
+    if let Some(mdns_advertiser) = &mut services.mdns_advertiser {
+        mdns_advertiser.withdraw();
+    }
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    CouldNotStartThread { source: IoError },
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            SetupError::CouldNotStartThread { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::CouldNotStartThread { source: _ } => {
+                "Could not start the thread for network services."
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
@@ -0,0 +1,221 @@
+use crate::logging::format_duration;
+use std::fs::{self, File};
+use std::io::Error as IoError;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// 💁‍♂️ Without wheel encoders there is no way to know the distance travelled, so for now the odometer only keeps
+// track of how long the service has been running and how much of that time was spent driving (i.e. with a non-zero
+// throttle being commanded).
+
+// Losing power is the most common way for a session to end, so the counters are also saved periodically rather
+// than only at shutdown.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Counters {
+    total_runtime: Duration,
+    total_drive_time: Duration,
+}
+
+pub struct Odometer {
+    state_file_path: PathBuf,
+    previous_sessions: Counters,
+    session_start: Instant,
+    session_drive_time: Duration,
+    last_update: Instant,
+    last_save: Instant,
+    // Whether the state file may be written, which it may not while it holds totals that could not be read.
+    saving: bool,
+}
+
+impl Odometer {
+    // A state file that cannot be read is never overwritten, as that would lose the totals in it for good. A corrupt
+    // one is set aside as `<state file>.corrupt` for someone to look at. Should that not work either, or the file not
+    // be readable at all, the odometer starts from zero without saving anything, until the file has been dealt with.
+    pub fn load(state_file_path: &Path) -> Odometer {
+        let mut saving = true;
+        let previous_sessions = match read_counters(state_file_path) {
+            Ok(counters) => counters,
+            Err(error) if error.kind() == ErrorKind::InvalidData => {
+                let corrupt_file_path = corrupt_file_path(state_file_path);
+                match fs::rename(state_file_path, &corrupt_file_path) {
+                    Ok(()) => log::error!(
+                        "Odometer state in {} is corrupt, starting from zero. It was kept as {}. - Cause: {}",
+                        state_file_path.display(),
+                        corrupt_file_path.display(),
+                        error
+                    ),
+                    Err(rename_error) => {
+                        log::error!(
+                            "Odometer state in {} is corrupt, and could not be set aside either. Starting from zero, without saving. - Cause: {}, {}",
+                            state_file_path.display(),
+                            error,
+                            rename_error
+                        );
+                        saving = false;
+                    }
+                }
+                Counters::default()
+            }
+            Err(error) => {
+                log::error!(
+                    "Could not load odometer state from {}. Starting from zero, without saving. - Cause: {}",
+                    state_file_path.display(),
+                    error
+                );
+                saving = false;
+                Counters::default()
+            }
+        };
+
+        let now = Instant::now();
+
+        Odometer {
+            state_file_path: state_file_path.to_path_buf(),
+            previous_sessions,
+            session_start: now,
+            session_drive_time: Duration::ZERO,
+            last_update: now,
+            last_save: now,
+            saving,
+        }
+    }
+
+    pub fn update(&mut self, driving: bool) {
+        let now = Instant::now();
+
+        if driving {
+            self.session_drive_time += now - self.last_update;
+        }
+        self.last_update = now;
+
+        if now - self.last_save >= SAVE_INTERVAL {
+            self.last_save = now;
+            self.save();
+        }
+    }
+
+    pub fn save(&self) {
+        if !self.saving {
+            return;
+        }
+        if let Err(error) = write_counters(&self.state_file_path, &self.totals()) {
+            log::warn!(
+                "Could not save odometer state to {}. - Cause: {}",
+                self.state_file_path.display(),
+                error
+            );
+        }
+    }
+
+    pub fn log_totals(&self) {
+        let totals = self.totals();
+        log::info!(
+            "Odometer: total runtime {}, total drive time {}.",
+            format_duration(totals.total_runtime),
+            format_duration(totals.total_drive_time)
+        );
+    }
+
+    fn totals(&self) -> Counters {
+        Counters {
+            total_runtime: self.previous_sessions.total_runtime + self.session_start.elapsed(),
+            total_drive_time: self.previous_sessions.total_drive_time + self.session_drive_time,
+        }
+    }
+}
+
+fn read_counters(path: &Path) -> Result<Counters, IoError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Counters::default()),
+        Err(error) => return Err(error),
+    };
+
+    let mut counters = Counters::default();
+
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| invalid_data(line))?;
+
+        let value = value
+            .parse::<f64>()
+            .ok()
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| invalid_data(line))?;
+
+        match key {
+            "total_runtime_seconds" => counters.total_runtime = value,
+            "total_drive_time_seconds" => counters.total_drive_time = value,
+            _ => return Err(invalid_data(line)),
+        }
+    }
+
+    Ok(counters)
+}
+
+// The new state is written to a temporary file first, which then atomically replaces the previous state file. This
+// way a power loss halfway through writing can never leave a truncated state file behind.
+fn write_counters(path: &Path, counters: &Counters) -> Result<(), IoError> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let temporary_path = PathBuf::from(temporary_path);
+
+    let mut file = File::create(&temporary_path)?;
+    write!(
+        file,
+        "total_runtime_seconds = {:.3}\ntotal_drive_time_seconds = {:.3}\n",
+        counters.total_runtime.as_secs_f64(),
+        counters.total_drive_time.as_secs_f64()
+    )?;
+    file.sync_all()?;
+
+    fs::rename(&temporary_path, path)
+}
+
+fn corrupt_file_path(path: &Path) -> PathBuf {
+    let mut corrupt_file_path = path.as_os_str().to_owned();
+    corrupt_file_path.push(".corrupt");
+
+    PathBuf::from(corrupt_file_path)
+}
+
+fn invalid_data(line: &str) -> IoError {
+    IoError::new(
+        ErrorKind::InvalidData,
+        format!("Invalid line in odometer state file: '{}'.", line),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupt_state_is_not_overwritten() {
+        let folder = std::env::temp_dir().join(format!("roestbak-odometer-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let state_file_path = folder.join("odometer");
+        fs::write(&state_file_path, "total_runtime_seconds = 3600x\n").unwrap();
+
+        let odometer = Odometer::load(&state_file_path);
+        odometer.save();
+        assert_eq!(
+            fs::read_to_string(corrupt_file_path(&state_file_path)).unwrap(),
+            "total_runtime_seconds = 3600x\n"
+        );
+        assert!(read_counters(&state_file_path).is_ok());
+
+        // Unreadable state is not even attempted to be written over.
+        fs::remove_file(&state_file_path).unwrap();
+        fs::create_dir(&state_file_path).unwrap();
+        Odometer::load(&state_file_path).save();
+        assert!(!folder.join("odometer.tmp").exists());
+
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+use crate::configuration::Configuration;
+use crate::copilot::CoPilotMixer;
+use crate::gamepads::GamepadInputInterpreter;
+use crate::input_source::{InputSource, InputSourceKind};
+use crate::keyboard::KeyboardInputSource;
+#[cfg(feature = "mavlink")]
+use crate::mavlink::MavlinkInputSource;
+use crate::sbus::SbusInputSource;
+use std::error::Error;
+
+// Everything the vehicle can be driven with, by the name it is configured with (`input.source = <name>`), and how to
+// set it up from the configuration. The service only ever asks for the configured source by name, so adding a source
+// comes down to implementing `InputSource`, giving it a name in `InputSourceKind` and registering it below, without
+// touching the runloop.
+//
+// Sources that need a Cargo feature are only registered when it is enabled. The configuration already refuses to
+// name them otherwise, see `Entry::require_feature`.
+//
+// 💁‍♂️ Outputs are still driven by the PCA9685 only, which `LocomotionController` sets up by itself. Output sinks
+// (e.g. sysfs PWM) are to be registered here the same way once there is more than one kind of them.
+
+type CreateInputSource = fn(&Configuration) -> Result<Box<dyn InputSource>, Box<dyn Error>>;
+
+struct InputSourceRegistration {
+    kind: InputSourceKind,
+    create: CreateInputSource,
+}
+
+#[allow(unused_mut)]
+fn registered_input_sources() -> Vec<InputSourceRegistration> {
+    let mut registrations = vec![
+        InputSourceRegistration {
+            kind: InputSourceKind::Gamepad,
+            create: |configuration| {
+                Ok(Box::new(GamepadInputInterpreter::new(
+                    configuration.gamepad,
+                    &configuration.gamepad_devices,
+                    configuration.gamepad_mapping_file.as_deref(),
+                )?))
+            },
+        },
+        InputSourceRegistration {
+            kind: InputSourceKind::Sbus,
+            create: |configuration| {
+                Ok(Box::new(SbusInputSource::new(
+                    &configuration.sbus_device_file,
+                    configuration.sbus_channel_mapping,
+                )?))
+            },
+        },
+        InputSourceRegistration {
+            kind: InputSourceKind::Keyboard,
+            create: |configuration| {
+                Ok(Box::new(KeyboardInputSource::new(
+                    &configuration.keyboard_device_file,
+                )))
+            },
+        },
+    ];
+
+    #[cfg(feature = "mavlink")]
+    registrations.push(InputSourceRegistration {
+        kind: InputSourceKind::Mavlink,
+        create: |configuration| {
+            Ok(Box::new(MavlinkInputSource::new(
+                configuration.mavlink_listen_address,
+                configuration.mavlink_ground_control_station_address,
+                configuration.mavlink_channel_mapping,
+                configuration.mavlink_signing_passphrase.as_deref(),
+                configuration.mavlink_require_signing,
+            )?))
+        },
+    });
+
+    registrations
+}
+
+pub fn create_input_source(
+    kind: InputSourceKind,
+    configuration: &Configuration,
+) -> Result<Box<dyn InputSource>, Box<dyn Error>> {
+    let registration = registered_input_sources()
+        .into_iter()
+        .find(|registration| registration.kind == kind)
+        .ok_or(SetupError::NotAvailable { kind })?;
+
+    (registration.create)(configuration)
+}
+
+// The configured input source, mixed with the instructor's in training mode. See `copilot.rs`.
+pub fn create_driving_input(
+    configuration: &Configuration,
+) -> Result<Box<dyn InputSource>, Box<dyn Error>> {
+    let input_source = create_input_source(configuration.input_source, configuration)?;
+
+    match configuration.copilot_source {
+        Some(copilot_source) if copilot_source == configuration.input_source => {
+            log::error!("Training mode disabled, the instructor needs a different input source than the driver.");
+            Ok(input_source)
+        }
+        Some(copilot_source) => Ok(Box::new(CoPilotMixer::new(
+            input_source,
+            create_input_source(copilot_source, configuration)?,
+            configuration.copilot_authority_percent as f64 / 100.0,
+        ))),
+        None => Ok(input_source),
+    }
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    NotAvailable { kind: InputSourceKind },
+}
+
+impl Error for SetupError {}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::NotAvailable { kind } => format!(
+                "Input source {} is not available in this build.",
+                kind.name()
+            ),
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_input_source_is_registered() {
+        let registrations = registered_input_sources();
+
+        for kind in InputSourceKind::ALL {
+            let registered = registrations
+                .iter()
+                .filter(|registration| registration.kind == kind)
+                .count();
+            let expected = match kind {
+                InputSourceKind::Mavlink => usize::from(cfg!(feature = "mavlink")),
+                _ => 1,
+            };
+            assert_eq!(registered, expected, "{}", kind.name());
+        }
+    }
+}
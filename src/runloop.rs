@@ -1,8 +1,5 @@
-use libc;
+use crate::clock::{Clock, MonotonicClock};
 use std::error::Error;
-use std::io::Error as IoError;
-use std::mem::MaybeUninit;
-use std::ptr;
 use std::time::Duration;
 
 pub enum IterationOutcome {
@@ -10,95 +7,247 @@ pub enum IterationOutcome {
     KeepGoing,
 }
 
-pub fn start_runloop(
+// What an iteration gets to know about the schedule.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Iteration {
+    // Set when the runloop was held up for a long while since the previous iteration, e.g. because the system was
+    // suspended or the process stopped. Whatever happened in the meantime went unnoticed, and anything received before
+    // is stale.
+    pub held_up_for: Option<Duration>,
+}
+
+// When every iteration overruns, the runloop degenerates into a busy loop that keeps the CPU fully occupied. A
+// single overrun now and then is nothing to worry about, but this many in a row (half a second at the default
+// 10ms) is.
+const CONSECUTIVE_OVERRUNS_BEFORE_BUSY_LOOP: u64 = 50;
+
+// Being held up for longer than this is no overrun, but a gap in the schedule: rather than running late, the runloop
+// starts over from the present.
+const HOLD_UP_THRESHOLD: Duration = Duration::from_secs(1);
+
+pub struct Runloop<C: Clock = MonotonicClock> {
+    clock: C,
     interval: Duration,
-    mut block: impl FnMut() -> Result<IterationOutcome, Box<dyn Error>>,
-) -> Result<(), Box<dyn Error>> {
-    let mut start_of_upcoming_iteration = now();
-
-    loop {
-        match block()? {
-            IterationOutcome::Conclude => {
-                return Ok(());
-            }
+    overrun_count: u64,
+    consecutive_overrun_count: u64,
+}
 
-            IterationOutcome::KeepGoing => {
-                // The new deadline for starting the next iteration is `interval` added to the previous deadline. This should result
-                // in a regular, non-drifting schedule.
-                start_of_upcoming_iteration += interval;
-
-                // Should an iteration take longer than `interval`, the next iteration will start immediately.
-                //
-                // Note that such an overrun could take longer than just one interval. Maintaining the original schedule could therefore
-                // lead to a number of iterations running back-to-back until `start_of_upcoming_iteration` catches up to present time.
-                // This is not the desired behaviour, so `start_of_upcoming_iteration` is reset to present time in this case. A new regular
-                // schedule can then (hopefully) start from this point onward.
-                let end_of_current_iteration = now();
-                if end_of_current_iteration > start_of_upcoming_iteration {
-                    let overrun_duration = end_of_current_iteration - start_of_upcoming_iteration;
-                    log::warn!(
-                        "Runloop iteration overrun. Allotted time: {:?}, overran by: {:?}.",
-                        interval,
-                        overrun_duration
-                    );
-
-                    start_of_upcoming_iteration = end_of_current_iteration;
-                } else {
-                    sleep_until(start_of_upcoming_iteration);
+impl Runloop {
+    pub fn new(interval: Duration) -> Self {
+        Self::with_clock(interval, MonotonicClock)
+    }
+}
+
+impl<C: Clock> Runloop<C> {
+    pub fn with_clock(interval: Duration, clock: C) -> Self {
+        Self {
+            clock,
+            interval,
+            overrun_count: 0,
+            consecutive_overrun_count: 0,
+        }
+    }
+
+    // Every iteration has to do at least `essential_work_duration` worth of work. If that does not fit in the
+    // interval, every iteration would overrun.
+    pub fn check_interval(&self, essential_work_duration: Duration) -> Result<(), IntervalError> {
+        if essential_work_duration >= self.interval {
+            return Err(IntervalError::TooShort {
+                interval: self.interval,
+                essential_work_duration,
+            });
+        }
+
+        if essential_work_duration >= self.interval / 2 {
+            log::warn!(
+                "Runloop interval of {:?} leaves little room: {:?} is needed for driving the hardware alone.",
+                self.interval,
+                essential_work_duration
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn overrun_count(&self) -> u64 {
+        self.overrun_count
+    }
+
+    pub fn run(
+        &mut self,
+        mut block: impl FnMut(Iteration) -> Result<IterationOutcome, Box<dyn Error>>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut start_of_upcoming_iteration = self.clock.now();
+        let mut suspended = self.clock.suspended();
+        let mut iteration = Iteration::default();
+
+        loop {
+            match block(std::mem::take(&mut iteration))? {
+                IterationOutcome::Conclude => {
+                    return Ok(());
+                }
+
+                IterationOutcome::KeepGoing => {
+                    // The new deadline for starting the next iteration is `interval` added to the previous deadline. This should result
+                    // in a regular, non-drifting schedule.
+                    start_of_upcoming_iteration += self.interval;
+
+                    // Should an iteration take longer than `interval`, the next iteration will start immediately.
+                    //
+                    // Note that such an overrun could take longer than just one interval. Maintaining the original schedule could therefore
+                    // lead to a number of iterations running back-to-back until `start_of_upcoming_iteration` catches up to present time.
+                    // This is not the desired behaviour, so `start_of_upcoming_iteration` is reset to present time in this case. A new regular
+                    // schedule can then (hopefully) start from this point onward.
+                    let end_of_current_iteration = self.clock.now();
+
+                    // The monotonic clock stands still while the system is suspended, so that has to be accounted for
+                    // separately.
+                    let previously_suspended =
+                        std::mem::replace(&mut suspended, self.clock.suspended());
+                    let held_up_for = end_of_current_iteration
+                        .saturating_sub(start_of_upcoming_iteration)
+                        + suspended.saturating_sub(previously_suspended);
+                    if held_up_for >= HOLD_UP_THRESHOLD {
+                        log::warn!(
+                            "Runloop was held up for {:.1}s (was the system suspended?), resuming from now.",
+                            held_up_for.as_secs_f64()
+                        );
+
+                        iteration.held_up_for = Some(held_up_for);
+                        self.consecutive_overrun_count = 0;
+                        start_of_upcoming_iteration = end_of_current_iteration;
+                    } else if end_of_current_iteration > start_of_upcoming_iteration {
+                        let overrun_duration =
+                            end_of_current_iteration - start_of_upcoming_iteration;
+                        log::warn!(
+                            "Runloop iteration overrun. Allotted time: {:?}, overran by: {:?}.",
+                            self.interval,
+                            overrun_duration
+                        );
+
+                        self.overrun_count += 1;
+                        self.consecutive_overrun_count += 1;
+                        if self.consecutive_overrun_count == CONSECUTIVE_OVERRUNS_BEFORE_BUSY_LOOP {
+                            log::error!(
+                                "Runloop has overrun {} iterations in a row and is effectively busy looping. Consider configuring a longer interval.",
+                                self.consecutive_overrun_count
+                            );
+                        }
+
+                        start_of_upcoming_iteration = end_of_current_iteration;
+                    } else {
+                        if self.consecutive_overrun_count >= CONSECUTIVE_OVERRUNS_BEFORE_BUSY_LOOP {
+                            log::info!("Runloop is keeping up with its interval again.");
+                        }
+                        self.consecutive_overrun_count = 0;
+
+                        self.clock.sleep_until(start_of_upcoming_iteration);
+                    }
                 }
             }
         }
     }
 }
 
-// Rust internally represents `libc::timespec` values using a private `Timespec` type, which includes operations for arithmetic, comparing
-// and so on. As a point in time is—in present context—defined as a duration since some agreed upon past moment, the publicly available
-// `Duration` type is used(/abused?) for this purpose here. This avoids needlessly duplicating the logic for some needed operations.
-
-// ⚠️ Contrary to the `Duration` type, `libc::timespec`'s fields are signed. A negative value for `tv_sec` could be used to represent a
-// point in time before epoch. We therefore need to ensure that the clock we use won't emit negative values. This should not be a problem
-// for the monotonic clock. From clock_gettime(3):
-//
-// > A nonsettable system-wide clock that represents monotonic time since—as described by POSIX—"some unspecified point in the past".  On
-// > Linux, that point corresponds to the number of seconds that the system has been running since it was booted.
-const CLOCK: libc::clockid_t = libc::CLOCK_MONOTONIC;
-
-fn now() -> Duration {
-    let mut timespec: MaybeUninit<libc::timespec> = MaybeUninit::uninit();
-
-    let result = unsafe { libc::clock_gettime(CLOCK, timespec.as_mut_ptr()) };
-    if result != 0 {
-        let error = IoError::last_os_error();
-        panic!(
-            "Retrieving time from clock is expected to succeed. Error: {}",
-            error
-        );
-    }
+#[derive(Debug)]
+pub enum IntervalError {
+    TooShort {
+        interval: Duration,
+        essential_work_duration: Duration,
+    },
+}
+
+impl Error for IntervalError {}
 
-    let timespec = unsafe { timespec.assume_init() };
+impl std::fmt::Display for IntervalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            IntervalError::TooShort {
+                interval,
+                essential_work_duration,
+            } => format!(
+                "Runloop interval of {:?} cannot be met: driving the hardware alone takes {:?}. Configure a longer runloop.interval_ms.",
+                interval, essential_work_duration
+            ),
+        };
+
+        write!(f, "{}", description)
+    }
+}
 
-    Duration::new(
-        u64::try_from(timespec.tv_sec).expect("timespec.tv_sec out of bounds."),
-        u32::try_from(timespec.tv_nsec).expect("timespec.tv_nsec out of bounds."),
-    )
+// The time on the clock the runloop normally runs on. Code that needs to be stepped through in tests takes a `Clock`
+// instead.
+pub fn now() -> Duration {
+    MonotonicClock.now()
 }
 
-fn sleep_until(deadline: Duration) {
-    let deadline = libc::timespec {
-        tv_sec: libc::time_t::try_from(deadline.as_secs())
-            .expect("deadline.as_secs() out of bounds."),
-        tv_nsec: libc::c_long::try_from(deadline.subsec_nanos())
-            .expect("deadline.subsec_nanos() out of bounds."),
-    };
-
-    let result =
-        unsafe { libc::clock_nanosleep(CLOCK, libc::TIMER_ABSTIME, &deadline, ptr::null_mut()) };
-
-    // This implementation assumes that signals are blocked so that this call will never be interrupted.
-    if result != 0 {
-        panic!(
-            "Sleep is expected to succeed (are signals blocked?). Error code: {}",
-            result
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn overruns_restart_the_schedule() {
+        let clock = ManualClock::new(Duration::from_secs(1));
+        let mut runloop = Runloop::with_clock(Duration::from_millis(10), clock.clone());
+
+        // The third iteration takes longer than the interval.
+        let work = [1, 2, 25, 3, 4];
+        let mut started_at = Vec::new();
+        runloop
+            .run(|_| {
+                let iteration = started_at.len();
+                started_at.push(clock.now().as_millis());
+                clock.advance(Duration::from_millis(work[iteration]));
+
+                Ok(if iteration + 1 == work.len() {
+                    IterationOutcome::Conclude
+                } else {
+                    IterationOutcome::KeepGoing
+                })
+            })
+            .unwrap();
+
+        // Rather than catching up, iterations continue on a regular schedule from when the overrun ended.
+        assert_eq!(started_at, [1000, 1010, 1020, 1045, 1055]);
+        assert_eq!(runloop.overrun_count(), 1);
+    }
+
+    #[test]
+    fn being_held_up_is_no_overrun() {
+        let clock = ManualClock::new(Duration::from_secs(1));
+        let mut runloop = Runloop::with_clock(Duration::from_millis(10), clock.clone());
+
+        // The system is suspended during the second iteration, and the fourth one stalls.
+        let mut iterations = Vec::new();
+        runloop
+            .run(|iteration| {
+                iterations.push((clock.now().as_millis(), iteration.held_up_for));
+                match iterations.len() {
+                    2 => clock.suspend(Duration::from_secs(60)),
+                    4 => clock.advance(Duration::from_secs(2)),
+                    _ => clock.advance(Duration::from_millis(1)),
+                }
+
+                Ok(if iterations.len() == 6 {
+                    IterationOutcome::Conclude
+                } else {
+                    IterationOutcome::KeepGoing
+                })
+            })
+            .unwrap();
+
+        assert_eq!(
+            iterations,
+            [
+                (1000, None),
+                (1010, None),
+                (1010, Some(Duration::from_secs(60))),
+                (1020, None),
+                (3020, Some(Duration::from_millis(1990))),
+                (3030, None),
+            ]
         );
+        assert_eq!(runloop.overrun_count(), 0);
     }
 }
@@ -0,0 +1,242 @@
+use crate::input_source::{ChannelMapping, InputNotification, InputSource};
+use crate::locomotion::LocomotionCommand;
+use crate::serial::{self, Parity, SerialPort, SerialSettings};
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// SBUS is the serial protocol spoken by many hobby-grade RC receivers. Every 7 to 14 milliseconds the receiver sends
+// a 25 byte frame at 100000 baud, 8E2:
+// - a header byte (0x0F),
+// - 16 channels of 11 bits each, packed least significant bit first into 22 bytes,
+// - a flags byte with the digital channels 17 and 18, a "frame lost" bit and a "failsafe" bit,
+// - a footer byte (0x00, or one of a few other values for SBUS2).
+//
+// ⚠️ The SBUS signal is inverted with respect to a regular UART, so an inverter is needed between the receiver and
+// the Raspberry Pi's RX pin.
+
+const SBUS_SERIAL_SETTINGS: SerialSettings = SerialSettings {
+    baud_rate: 100_000,
+    parity: Parity::Even,
+    two_stop_bits: true,
+};
+
+const FRAME_SIZE: usize = 25;
+const FRAME_HEADER: u8 = 0x0F;
+const FRAME_FOOTERS: [u8; 5] = [0x00, 0x04, 0x14, 0x24, 0x34];
+const NUMBER_OF_CHANNELS: usize = 16;
+
+const FLAG_FRAME_LOST: u8 = 1 << 2;
+const FLAG_FAILSAFE: u8 = 1 << 3;
+
+// Raw channel values as sent by common receivers for the extreme and center stick positions.
+const CHANNEL_MINIMUM: f64 = 172.0;
+const CHANNEL_CENTER: f64 = 992.0;
+const CHANNEL_MAXIMUM: f64 = 1811.0;
+
+// Values close to the center are snapped to zero so that a stick at rest really means "stop", regardless of jitter
+// or imperfect trims.
+const DEADZONE_THRESHOLD: f64 = 0.03;
+
+// If no frames arrive for this long, the receiver is assumed to be gone (or unpowered) altogether.
+const FRAME_TIMEOUT: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Frame {
+    channels: [u16; NUMBER_OF_CHANNELS],
+    flags: u8,
+}
+
+impl Frame {
+    fn decode(bytes: &[u8; FRAME_SIZE]) -> Option<Frame> {
+        if bytes[0] != FRAME_HEADER || !FRAME_FOOTERS.contains(&bytes[FRAME_SIZE - 1]) {
+            return None;
+        }
+
+        let mut channels = [0u16; NUMBER_OF_CHANNELS];
+        let mut bit_offset = 0;
+
+        for channel in channels.iter_mut() {
+            let mut value = 0u16;
+            for bit in 0..11 {
+                let byte = bytes[1 + (bit_offset + bit) / 8];
+                if byte & (1 << ((bit_offset + bit) % 8)) != 0 {
+                    value |= 1 << bit;
+                }
+            }
+            *channel = value;
+            bit_offset += 11;
+        }
+
+        Some(Frame {
+            channels,
+            flags: bytes[FRAME_SIZE - 2],
+        })
+    }
+
+    fn is_failsafe(&self) -> bool {
+        self.flags & FLAG_FAILSAFE != 0
+    }
+
+    fn is_frame_lost(&self) -> bool {
+        self.flags & FLAG_FRAME_LOST != 0
+    }
+
+    // Returns the value of a 1-based channel from -1.0 to 1.0.
+    fn channel_value(&self, channel: u8) -> f64 {
+        let raw = self.channels[channel as usize - 1] as f64;
+
+        let value = if raw >= CHANNEL_CENTER {
+            (raw - CHANNEL_CENTER) / (CHANNEL_MAXIMUM - CHANNEL_CENTER)
+        } else {
+            (raw - CHANNEL_CENTER) / (CHANNEL_CENTER - CHANNEL_MINIMUM)
+        };
+        let value = value.clamp(-1.0, 1.0);
+
+        if value.abs() < DEADZONE_THRESHOLD {
+            0.0
+        } else {
+            value
+        }
+    }
+}
+
+pub struct SbusInputSource {
+    serial_port: SerialPort,
+    channel_mapping: ChannelMapping,
+    received: Vec<u8>,
+    last_frame_received_at: Option<Instant>,
+    connected: bool,
+    command: LocomotionCommand,
+}
+
+impl SbusInputSource {
+    pub fn new(
+        device_file_path: &Path,
+        channel_mapping: ChannelMapping,
+    ) -> Result<SbusInputSource, serial::SetupError> {
+        let serial_port = SerialPort::open(device_file_path, SBUS_SERIAL_SETTINGS)?;
+        log::info!(
+            "Listening for SBUS frames on {} (throttle on channel {}, steering on channel {}).",
+            device_file_path.display(),
+            channel_mapping.throttle_channel,
+            channel_mapping.steering_channel
+        );
+
+        Ok(SbusInputSource {
+            serial_port,
+            channel_mapping,
+            received: Vec::with_capacity(FRAME_SIZE * 4),
+            last_frame_received_at: None,
+            connected: false,
+            command: LocomotionCommand::neutral(),
+        })
+    }
+
+    fn read_latest_frame(&mut self) -> Result<Option<Frame>, Box<dyn Error>> {
+        let mut buffer = [0u8; FRAME_SIZE * 8];
+
+        loop {
+            let bytes_read = self.serial_port.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            self.received.extend_from_slice(&buffer[..bytes_read]);
+        }
+
+        // Only the most recent frame matters. Anything that does not look like a frame is skipped byte by byte
+        // until the stream is back in sync.
+        let mut latest_frame = None;
+        let mut position = 0;
+
+        while self.received.len() - position >= FRAME_SIZE {
+            let bytes: &[u8; FRAME_SIZE] = self.received[position..position + FRAME_SIZE]
+                .try_into()
+                .unwrap();
+
+            match Frame::decode(bytes) {
+                Some(frame) => {
+                    latest_frame = Some(frame);
+                    position += FRAME_SIZE;
+                }
+                None => position += 1,
+            }
+        }
+
+        self.received.drain(..position);
+
+        Ok(latest_frame)
+    }
+
+    fn lose_link(&mut self, notify: &mut dyn FnMut(InputNotification)) {
+        if self.connected {
+            self.connected = false;
+            notify(InputNotification::Disconnected);
+
+            if !self.command.is_neutral() {
+                notify(InputNotification::FailsafeEngaged);
+            }
+        }
+
+        self.command = LocomotionCommand::neutral();
+    }
+}
+
+impl InputSource for SbusInputSource {
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn process_input(
+        &mut self,
+        notify: &mut dyn FnMut(InputNotification),
+    ) -> Result<LocomotionCommand, Box<dyn Error>> {
+        let now = Instant::now();
+
+        match self.read_latest_frame()? {
+            // The receiver sets the failsafe flag once it has lost the radio link for a while. The channel values
+            // are then whatever the receiver was set up to output, which is not to be trusted.
+            Some(frame) if frame.is_failsafe() => {
+                if self.connected {
+                    log::warn!("SBUS receiver reports failsafe, radio link lost.");
+                }
+                self.last_frame_received_at = Some(now);
+                self.lose_link(notify);
+            }
+            // A single lost frame is repeated by the receiver, so the values are still fine.
+            Some(frame) => {
+                if frame.is_frame_lost() {
+                    log::debug!("SBUS receiver reports a lost frame.");
+                }
+                self.last_frame_received_at = Some(now);
+
+                if !self.connected {
+                    log::info!("SBUS radio link established.");
+                    self.connected = true;
+                    notify(InputNotification::Connected);
+                }
+
+                self.command = self
+                    .channel_mapping
+                    .to_command(|channel| frame.channel_value(channel));
+            }
+            None => {
+                let timed_out = self
+                    .last_frame_received_at
+                    .is_none_or(|received_at| now - received_at >= FRAME_TIMEOUT);
+
+                if timed_out && self.connected {
+                    log::warn!("No SBUS frames received, assuming the receiver is gone.");
+                    self.lose_link(notify);
+                }
+            }
+        }
+
+        Ok(self.command)
+    }
+
+    // The frames received before are no proof that the receiver is still there.
+    fn expire_received_input(&mut self) {
+        self.last_frame_received_at = None;
+    }
+}
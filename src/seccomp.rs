@@ -0,0 +1,391 @@
+use std::error::Error;
+use std::io::Error as IoError;
+
+// Once everything has been set up, the service only needs a small set of system calls: reading and writing file
+// descriptors (devices, sockets, inotify, signalfd), ioctls for I2C and input devices, time keeping, memory management
+// and the few file operations needed for persisting state and for the diagnostic dump. The seccomp filter installed
+// here kills the process as soon as it makes any other system call, limiting what an attacker could do after taking
+// over the process.
+//
+// ⚠️ The allowed system calls were determined for the targeted architectures (32-bit and 64-bit ARM) as well as
+// x86-64 for development. Should the process get killed by SIGSYS after an upgrade of the C library or the Rust
+// standard library, the list below is where to look (the audit log records the offending system call number).
+
+#[derive(Debug)]
+pub enum InstallError {
+    UnsupportedArchitecture,
+    CouldNotSetNoNewPrivileges { source: IoError },
+    CouldNotInstallFilter { source: IoError },
+    CouldNotApplyToAllThreads { thread_id: libc::c_long },
+}
+
+impl Error for InstallError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InstallError::UnsupportedArchitecture => None,
+            InstallError::CouldNotSetNoNewPrivileges { source } => Some(source),
+            InstallError::CouldNotInstallFilter { source } => Some(source),
+            InstallError::CouldNotApplyToAllThreads { thread_id: _ } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for InstallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            InstallError::UnsupportedArchitecture => {
+                "The seccomp filter is not available on this architecture.".to_string()
+            }
+            InstallError::CouldNotSetNoNewPrivileges { source: _ } => {
+                "Could not disable gaining new privileges, which is required for installing a seccomp filter.".to_string()
+            }
+            InstallError::CouldNotInstallFilter { source: _ } => {
+                "Could not install seccomp filter.".to_string()
+            }
+            InstallError::CouldNotApplyToAllThreads { thread_id } => {
+                format!(
+                    "Could not install seccomp filter, as thread {} has a conflicting filter.",
+                    thread_id
+                )
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+pub fn install_filter() -> Result<(), InstallError> {
+    let Some(architecture) = syscalls::AUDIT_ARCHITECTURE else {
+        return Err(InstallError::UnsupportedArchitecture);
+    };
+
+    let program = build_program(architecture, syscalls::ALLOWED);
+
+    let result = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    if result != 0 {
+        return Err(InstallError::CouldNotSetNoNewPrivileges {
+            source: IoError::last_os_error(),
+        });
+    }
+
+    let program = libc::sock_fprog {
+        len: program.len() as libc::c_ushort,
+        filter: program.as_ptr() as *mut libc::sock_filter,
+    };
+    // The filter applies to every thread of the process, not just the calling one (and the threads it creates later
+    // on). Should another thread be unable to take it, the ID of that thread is returned.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &program as *const libc::sock_fprog,
+        )
+    };
+    if result < 0 {
+        return Err(InstallError::CouldNotInstallFilter {
+            source: IoError::last_os_error(),
+        });
+    }
+    if result > 0 {
+        return Err(InstallError::CouldNotApplyToAllThreads { thread_id: result });
+    }
+
+    log::info!(
+        "Installed seccomp filter allowing {} system calls.",
+        syscalls::ALLOWED.len()
+    );
+
+    Ok(())
+}
+
+// Classic BPF, as far as needed here: BPF_LD | BPF_W | BPF_ABS, BPF_JMP | BPF_JEQ | BPF_K and BPF_RET | BPF_K.
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JMP_JEQ_K: u16 = 0x15;
+const BPF_RET_K: u16 = 0x06;
+
+// Not (yet) known to the libc crate.
+const SECCOMP_SET_MODE_FILTER: libc::c_uint = 1;
+
+// Offsets into `struct seccomp_data`.
+const SECCOMP_DATA_NR_OFFSET: u32 = 0;
+const SECCOMP_DATA_ARCH_OFFSET: u32 = 4;
+
+fn statement(code: u16, k: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+// The program checks that the system call is made using the expected calling convention (system call numbers differ
+// between them), and then compares the system call number against each allowed one in turn.
+fn build_program(architecture: u32, allowed: &[libc::c_long]) -> Vec<libc::sock_filter> {
+    let mut program = vec![
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_ARCH_OFFSET),
+        jump(BPF_JMP_JEQ_K, architecture, 1, 0),
+        statement(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS),
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_NR_OFFSET),
+    ];
+
+    for &syscall in allowed {
+        // On a match, skip the next instruction (which goes on to the next comparison) to get to the "allow".
+        program.push(jump(BPF_JMP_JEQ_K, syscall as u32, 0, 1));
+        program.push(statement(BPF_RET_K, libc::SECCOMP_RET_ALLOW));
+    }
+
+    program.push(statement(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS));
+
+    program
+}
+
+// Makes reading from `fd` fail with `errno` on the calling thread (and threads it creates), for exercising how read
+// errors are dealt with. Other threads are not affected, and the filter goes away with the thread.
+#[cfg(test)]
+pub fn fail_reads_on_this_thread(fd: std::os::fd::RawFd, errno: i32) -> Result<(), IoError> {
+    // Offset into `struct seccomp_data` of the lower half of the first argument, on little-endian architectures.
+    const SECCOMP_DATA_FIRST_ARGUMENT_OFFSET: u32 = 16;
+
+    let program = [
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_NR_OFFSET),
+        jump(BPF_JMP_JEQ_K, libc::SYS_read as u32, 0, 3),
+        statement(BPF_LD_W_ABS, SECCOMP_DATA_FIRST_ARGUMENT_OFFSET),
+        jump(BPF_JMP_JEQ_K, fd as u32, 0, 1),
+        statement(BPF_RET_K, libc::SECCOMP_RET_ERRNO | errno as u32),
+        statement(BPF_RET_K, libc::SECCOMP_RET_ALLOW),
+    ];
+    let program = libc::sock_fprog {
+        len: program.len() as libc::c_ushort,
+        filter: program.as_ptr() as *mut libc::sock_filter,
+    };
+
+    let result = unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(IoError::last_os_error());
+        }
+        libc::syscall(
+            libc::SYS_seccomp,
+            SECCOMP_SET_MODE_FILTER,
+            0,
+            &program as *const libc::sock_fprog,
+        )
+    };
+    if result != 0 {
+        return Err(IoError::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(target_arch = "arm")]
+mod syscalls {
+    pub const AUDIT_ARCHITECTURE: Option<u32> = Some(0x40000028);
+
+    // 64-bit time variants used by newer C libraries on 32-bit platforms, not (yet) known to the libc crate.
+    const SYS_CLOCK_GETTIME64: libc::c_long = 403;
+    const SYS_CLOCK_NANOSLEEP_TIME64: libc::c_long = 407;
+    const SYS_PPOLL_TIME64: libc::c_long = 414;
+    const SYS_FUTEX_TIME64: libc::c_long = 422;
+
+    pub const ALLOWED: &[libc::c_long] = &[
+        libc::SYS_read,
+        libc::SYS_readv,
+        libc::SYS_write,
+        libc::SYS_writev,
+        libc::SYS_ioctl,
+        libc::SYS_open,
+        libc::SYS_openat,
+        libc::SYS_close,
+        libc::SYS_fstat64,
+        libc::SYS_fstatat64,
+        libc::SYS_stat64,
+        libc::SYS_statx,
+        libc::SYS__llseek,
+        libc::SYS_fcntl64,
+        libc::SYS_fsync,
+        libc::SYS_rename,
+        libc::SYS_renameat,
+        libc::SYS_renameat2,
+        libc::SYS_unlink,
+        libc::SYS_unlinkat,
+        libc::SYS_getdents64,
+        // Symbolizing a backtrace (see `diagnostic_dump.rs`) looks up the executable and the working directory.
+        libc::SYS_readlink,
+        libc::SYS_readlinkat,
+        libc::SYS_getcwd,
+        libc::SYS_inotify_add_watch,
+        libc::SYS_inotify_rm_watch,
+        libc::SYS_recv,
+        libc::SYS_recvfrom,
+        libc::SYS_recvmsg,
+        libc::SYS_send,
+        libc::SYS_sendto,
+        libc::SYS_sendmsg,
+        libc::SYS_poll,
+        libc::SYS_ppoll,
+        SYS_PPOLL_TIME64,
+        libc::SYS_clock_gettime,
+        SYS_CLOCK_GETTIME64,
+        libc::SYS_clock_nanosleep,
+        SYS_CLOCK_NANOSLEEP_TIME64,
+        libc::SYS_nanosleep,
+        libc::SYS_futex,
+        SYS_FUTEX_TIME64,
+        libc::SYS_brk,
+        libc::SYS_mmap2,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_madvise,
+        libc::SYS_mprotect,
+        libc::SYS_getpid,
+        libc::SYS_gettid,
+        libc::SYS_geteuid32,
+        libc::SYS_getegid32,
+        libc::SYS_getrandom,
+        libc::SYS_sched_yield,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigreturn,
+        libc::SYS_sigaltstack,
+        libc::SYS_tgkill,
+        libc::SYS_restart_syscall,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+    ];
+}
+
+#[cfg(target_arch = "aarch64")]
+mod syscalls {
+    pub const AUDIT_ARCHITECTURE: Option<u32> = Some(0xC00000B7);
+
+    pub const ALLOWED: &[libc::c_long] = &[
+        libc::SYS_read,
+        libc::SYS_readv,
+        libc::SYS_write,
+        libc::SYS_writev,
+        libc::SYS_ioctl,
+        libc::SYS_openat,
+        libc::SYS_close,
+        libc::SYS_fstat,
+        libc::SYS_newfstatat,
+        libc::SYS_statx,
+        libc::SYS_lseek,
+        libc::SYS_fcntl,
+        libc::SYS_fsync,
+        libc::SYS_renameat2,
+        libc::SYS_unlinkat,
+        libc::SYS_getdents64,
+        // Symbolizing a backtrace (see `diagnostic_dump.rs`) looks up the executable and the working directory.
+        libc::SYS_readlinkat,
+        libc::SYS_getcwd,
+        libc::SYS_inotify_add_watch,
+        libc::SYS_inotify_rm_watch,
+        libc::SYS_recvfrom,
+        libc::SYS_recvmsg,
+        libc::SYS_sendto,
+        libc::SYS_sendmsg,
+        libc::SYS_ppoll,
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        libc::SYS_futex,
+        libc::SYS_brk,
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_madvise,
+        libc::SYS_mprotect,
+        libc::SYS_getpid,
+        libc::SYS_gettid,
+        libc::SYS_geteuid,
+        libc::SYS_getegid,
+        libc::SYS_getrandom,
+        libc::SYS_sched_yield,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack,
+        libc::SYS_tgkill,
+        libc::SYS_restart_syscall,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+    ];
+}
+
+#[cfg(target_arch = "x86_64")]
+mod syscalls {
+    pub const AUDIT_ARCHITECTURE: Option<u32> = Some(0xC000003E);
+
+    pub const ALLOWED: &[libc::c_long] = &[
+        libc::SYS_read,
+        libc::SYS_readv,
+        libc::SYS_write,
+        libc::SYS_writev,
+        libc::SYS_ioctl,
+        libc::SYS_open,
+        libc::SYS_openat,
+        libc::SYS_close,
+        libc::SYS_fstat,
+        libc::SYS_newfstatat,
+        libc::SYS_stat,
+        libc::SYS_statx,
+        libc::SYS_lseek,
+        libc::SYS_fcntl,
+        libc::SYS_fsync,
+        libc::SYS_rename,
+        libc::SYS_renameat,
+        libc::SYS_renameat2,
+        libc::SYS_unlink,
+        libc::SYS_unlinkat,
+        libc::SYS_getdents64,
+        // Symbolizing a backtrace (see `diagnostic_dump.rs`) looks up the executable and the working directory.
+        libc::SYS_readlink,
+        libc::SYS_readlinkat,
+        libc::SYS_getcwd,
+        libc::SYS_inotify_add_watch,
+        libc::SYS_inotify_rm_watch,
+        libc::SYS_recvfrom,
+        libc::SYS_recvmsg,
+        libc::SYS_sendto,
+        libc::SYS_sendmsg,
+        libc::SYS_poll,
+        libc::SYS_ppoll,
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        libc::SYS_futex,
+        libc::SYS_brk,
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_madvise,
+        libc::SYS_mprotect,
+        libc::SYS_getpid,
+        libc::SYS_gettid,
+        libc::SYS_geteuid,
+        libc::SYS_getegid,
+        libc::SYS_getrandom,
+        libc::SYS_sched_yield,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack,
+        libc::SYS_tgkill,
+        libc::SYS_restart_syscall,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+    ];
+}
+
+#[cfg(not(any(target_arch = "arm", target_arch = "aarch64", target_arch = "x86_64")))]
+mod syscalls {
+    pub const AUDIT_ARCHITECTURE: Option<u32> = None;
+    pub const ALLOWED: &[libc::c_long] = &[];
+}
@@ -0,0 +1,123 @@
+use crate::control_values::{Steering, Throttle};
+use crate::locomotion::{self, LocomotionCommand, LocomotionController};
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+// An opt-in exercise of the motors at startup, so that wiring faults show up before anyone picks up the controller:
+// the steering servo sweeps through a small range, after which the throttle is blipped forward and backward.
+//
+// ⚠️ This moves the wheels. It is only meant to be enabled with the vehicle on a stand, or with plenty of room.
+
+// ESCs typically require a few seconds of neutral signal after power-up before they respond to throttle.
+const ESC_ARMING_DELAY: Duration = Duration::from_secs(2);
+
+const STEERING_SWEEP_RANGE: f64 = 0.3;
+const STEERING_SWEEP_STEPS: usize = 6;
+const STEERING_SWEEP_STEP_DURATION: Duration = Duration::from_millis(100);
+
+const THROTTLE_BLIP: f64 = 0.15;
+const THROTTLE_BLIP_DURATION: Duration = Duration::from_millis(150);
+const PAUSE_DURATION: Duration = Duration::from_millis(300);
+
+pub fn run(locomotion_controller: &mut LocomotionController) -> Result<(), SelfTestError> {
+    log::info!("Running motor self-test.");
+
+    let result = run_steps(locomotion_controller);
+
+    // Whatever happened, try to leave the vehicle standing still.
+    let _ = locomotion_controller.execute_command(LocomotionCommand::neutral());
+
+    match &result {
+        Ok(_) => log::info!("Motor self-test passed."),
+        Err(error) => log::error!("Motor self-test failed: {}", error),
+    }
+
+    result
+}
+
+fn run_steps(locomotion_controller: &mut LocomotionController) -> Result<(), SelfTestError> {
+    let mut execute = |step: &'static str, command: LocomotionCommand, duration: Duration| {
+        locomotion_controller
+            .execute_command(command)
+            .map_err(|source| SelfTestError::CouldNotExecuteStep { step, source })?;
+        thread::sleep(duration);
+        Ok(())
+    };
+
+    execute(
+        "waiting for the ESC to arm",
+        LocomotionCommand::neutral(),
+        ESC_ARMING_DELAY,
+    )?;
+
+    // Center, full left, full right (within range) and back to center.
+    for index in 0..=STEERING_SWEEP_STEPS {
+        let fraction = index as f64 / STEERING_SWEEP_STEPS as f64;
+        let direction = -STEERING_SWEEP_RANGE + 2.0 * STEERING_SWEEP_RANGE * fraction;
+        execute(
+            "sweeping steering",
+            LocomotionCommand::new(Throttle::NEUTRAL, Steering::new(direction)),
+            STEERING_SWEEP_STEP_DURATION,
+        )?;
+    }
+    execute(
+        "centering steering",
+        LocomotionCommand::neutral(),
+        PAUSE_DURATION,
+    )?;
+
+    execute(
+        "blipping throttle forward",
+        LocomotionCommand::new(Throttle::new(THROTTLE_BLIP), Steering::CENTER),
+        THROTTLE_BLIP_DURATION,
+    )?;
+    execute(
+        "returning throttle to neutral",
+        LocomotionCommand::neutral(),
+        PAUSE_DURATION,
+    )?;
+    execute(
+        "blipping throttle backward",
+        LocomotionCommand::new(Throttle::new(-THROTTLE_BLIP), Steering::CENTER),
+        THROTTLE_BLIP_DURATION,
+    )?;
+    execute(
+        "returning throttle to neutral",
+        LocomotionCommand::neutral(),
+        PAUSE_DURATION,
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum SelfTestError {
+    CouldNotExecuteStep {
+        step: &'static str,
+        source: locomotion::ExecuteCommandError,
+    },
+}
+
+impl Error for SelfTestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            SelfTestError::CouldNotExecuteStep { step: _, source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SelfTestError::CouldNotExecuteStep { step, source: _ } => {
+                format!(
+                    "Motor self-test could not complete step '{}'. Check the PCA9685 wiring.",
+                    step
+                )
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
@@ -0,0 +1,186 @@
+use std::error::Error;
+use std::io::Error as IoError;
+use std::os::fd::{AsFd, OwnedFd};
+use std::path::{Path, PathBuf};
+
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    pub parity: Parity,
+    pub two_stop_bits: bool,
+}
+
+// A UART in raw, non-blocking mode with 8 data bits. Arbitrary baud rates are supported, which is needed for
+// protocols such as SBUS that do not use one of the standard rates.
+pub struct SerialPort {
+    device_fd: OwnedFd,
+}
+
+impl SerialPort {
+    pub fn open(
+        device_file_path: &Path,
+        settings: SerialSettings,
+    ) -> Result<SerialPort, SetupError> {
+        let device_fd = ffi::open_serial_device(device_file_path).map_err(|source| {
+            SetupError::CouldNotOpenSerialDevice {
+                path: device_file_path.to_path_buf(),
+                source,
+            }
+        })?;
+        ffi::configure(device_fd.as_fd(), settings).map_err(|source| {
+            SetupError::CouldNotConfigure {
+                path: device_file_path.to_path_buf(),
+                source,
+            }
+        })?;
+
+        Ok(SerialPort { device_fd })
+    }
+
+    // Reads whatever is available without blocking and returns the number of bytes read, which is 0 if nothing was
+    // available.
+    pub fn read(&self, buffer: &mut [u8]) -> Result<usize, IoError> {
+        ffi::read_available(self.device_fd.as_fd(), buffer)
+    }
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    CouldNotOpenSerialDevice { path: PathBuf, source: IoError },
+    CouldNotConfigure { path: PathBuf, source: IoError },
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            SetupError::CouldNotOpenSerialDevice { path: _, source } => source,
+            SetupError::CouldNotConfigure { path: _, source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::CouldNotOpenSerialDevice { path, source: _ } => {
+                format!("Could not open serial device at {}.", path.display())
+            }
+            SetupError::CouldNotConfigure { path, source: _ } => {
+                format!("Could not configure serial device at {}.", path.display())
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+mod ffi {
+    use super::{Parity, SerialSettings};
+    use std::ffi::CString;
+    use std::io::Error as IoError;
+    use std::mem::MaybeUninit;
+    use std::os::fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+    use std::os::unix::prelude::OsStrExt;
+    use std::path::Path;
+
+    pub fn open_serial_device(device_file_path: &Path) -> Result<OwnedFd, IoError> {
+        let device_file_path = CString::new(device_file_path.as_os_str().as_bytes()).unwrap();
+
+        let fd = unsafe {
+            libc::open(
+                device_file_path.as_ptr(),
+                libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK | libc::O_CLOEXEC,
+            )
+        };
+
+        if fd == -1 {
+            Err(IoError::last_os_error())
+        } else {
+            Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+        }
+    }
+
+    // The classic termios interface only supports a fixed set of baud rates. `termios2` together with `BOTHER`
+    // allows setting any rate the UART is capable of.
+    pub fn configure(device_fd: BorrowedFd<'_>, settings: SerialSettings) -> Result<(), IoError> {
+        let mut options = MaybeUninit::<libc::termios2>::uninit();
+
+        let result =
+            unsafe { libc::ioctl(device_fd.as_raw_fd(), libc::TCGETS2, options.as_mut_ptr()) };
+        if result < 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        let mut options = unsafe { options.assume_init() };
+
+        // Raw mode, equivalent to `cfmakeraw`.
+        options.c_iflag &= !(libc::IGNBRK
+            | libc::BRKINT
+            | libc::PARMRK
+            | libc::ISTRIP
+            | libc::INLCR
+            | libc::IGNCR
+            | libc::ICRNL
+            | libc::IXON);
+        options.c_oflag &= !libc::OPOST;
+        options.c_lflag &= !(libc::ECHO | libc::ECHONL | libc::ICANON | libc::ISIG | libc::IEXTEN);
+
+        options.c_cflag &=
+            !(libc::CSIZE | libc::PARENB | libc::PARODD | libc::CSTOPB | libc::CBAUD);
+        options.c_cflag |= libc::CS8 | libc::CREAD | libc::CLOCAL | libc::BOTHER;
+        match settings.parity {
+            Parity::None => (),
+            Parity::Even => options.c_cflag |= libc::PARENB,
+            Parity::Odd => options.c_cflag |= libc::PARENB | libc::PARODD,
+        }
+        if settings.two_stop_bits {
+            options.c_cflag |= libc::CSTOPB;
+        }
+        options.c_ispeed = settings.baud_rate;
+        options.c_ospeed = settings.baud_rate;
+
+        // Reads never wait: whatever has been received so far is returned immediately.
+        options.c_cc[libc::VMIN] = 0;
+        options.c_cc[libc::VTIME] = 0;
+
+        let result = unsafe { libc::ioctl(device_fd.as_raw_fd(), libc::TCSETS2, &options) };
+        if result < 0 {
+            return Err(IoError::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn read_available(device_fd: BorrowedFd<'_>, buffer: &mut [u8]) -> Result<usize, IoError> {
+        let bytes_read = unsafe {
+            libc::read(
+                device_fd.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+
+        if bytes_read < 0 {
+            let error = IoError::last_os_error();
+
+            if error
+                .raw_os_error()
+                .is_some_and(|code| code == libc::EAGAIN)
+            {
+                return Ok(0);
+            }
+
+            return Err(error);
+        }
+
+        Ok(bytes_read as usize)
+    }
+}
@@ -0,0 +1,205 @@
+use crate::application_state::ApplicationState;
+use crate::control_values::Throttle;
+use crate::event_bus::{Event, EventSubscriber};
+use crate::gps::{Fix, Position};
+use crate::locomotion::LatencyPercentiles;
+use std::fs;
+use std::io::Error as IoError;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// Below this speed, a GPS module's position wanders about by itself, which is not to count as distance covered.
+const GPS_MOVING_SPEED: f64 = 0.5;
+
+pub struct SessionStatistics {
+    start: Instant,
+    gamepad_connects: u64,
+    gamepad_disconnects: u64,
+    max_commanded_throttle: f64,
+    failsafe_activations: u64,
+    i2c_errors: u64,
+    runloop_overruns: u64,
+    input_latency: Option<LatencyPercentiles>,
+    driving_duration: Duration,
+    driving_since: Option<Instant>,
+    // In m/s and m. The speed stays `None` without any GPS fix.
+    max_gps_speed: Option<f64>,
+    gps_distance: f64,
+    last_gps_position: Option<Position>,
+}
+
+impl SessionStatistics {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            gamepad_connects: 0,
+            gamepad_disconnects: 0,
+            max_commanded_throttle: 0.0,
+            failsafe_activations: 0,
+            i2c_errors: 0,
+            runloop_overruns: 0,
+            input_latency: None,
+            driving_duration: Duration::ZERO,
+            driving_since: None,
+            max_gps_speed: None,
+            gps_distance: 0.0,
+            last_gps_position: None,
+        }
+    }
+
+    pub fn record_gamepad_connected(&mut self) {
+        self.gamepad_connects += 1;
+    }
+
+    pub fn record_gamepad_disconnected(&mut self) {
+        self.gamepad_disconnects += 1;
+    }
+
+    // Both directions count: the maximum is taken over the absolute throttle value.
+    pub fn record_commanded_throttle(&mut self, throttle: Throttle) {
+        self.max_commanded_throttle = self.max_commanded_throttle.max(throttle.value().abs());
+    }
+
+    pub fn record_gps_fix(&mut self, fix: Fix) {
+        self.max_gps_speed = Some(
+            self.max_gps_speed
+                .map_or(fix.speed, |speed| speed.max(fix.speed)),
+        );
+
+        if fix.speed >= GPS_MOVING_SPEED {
+            if let Some(last_position) = &self.last_gps_position {
+                self.gps_distance += last_position.distance_to(&fix.position);
+            }
+        }
+        self.last_gps_position = Some(fix.position);
+    }
+
+    pub fn record_failsafe_activation(&mut self) {
+        self.failsafe_activations += 1;
+    }
+
+    pub fn record_i2c_error(&mut self) {
+        self.i2c_errors += 1;
+    }
+
+    pub fn i2c_error_count(&self) -> u64 {
+        self.i2c_errors
+    }
+
+    pub fn record_runloop_overruns(&mut self, count: u64) {
+        self.runloop_overruns += count;
+    }
+
+    pub fn record_input_latency(&mut self, percentiles: Option<LatencyPercentiles>) {
+        self.input_latency = percentiles;
+    }
+
+    pub fn run_duration(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // The time spent actually driving, as opposed to standing still or waiting.
+    pub fn driving_duration(&self) -> Duration {
+        self.driving_duration
+            + self
+                .driving_since
+                .map_or(Duration::ZERO, |driving_since| driving_since.elapsed())
+    }
+
+    pub fn log_summary(&self) {
+        log::info!(
+            "Session summary: ran for {:?} (driving for {:?}), {} gamepad connect(s), {} gamepad disconnect(s), max commanded throttle {:.2}, {} failsafe activation(s), {} I2C error(s), {} runloop overrun(s).",
+            self.run_duration(),
+            self.driving_duration(),
+            self.gamepad_connects,
+            self.gamepad_disconnects,
+            self.max_commanded_throttle,
+            self.failsafe_activations,
+            self.i2c_errors,
+            self.runloop_overruns
+        );
+
+        if let Some(max_gps_speed) = self.max_gps_speed {
+            log::info!(
+                "GPS: max speed {:.1} km/h, covered {:.0} m.",
+                max_gps_speed * 3.6,
+                self.gps_distance
+            );
+        }
+
+        if let Some(latency) = &self.input_latency {
+            log::info!(
+                "Input to PWM latency: p50 {:?}, p95 {:?}, p99 {:?}, max {:?}.",
+                latency.p50,
+                latency.p95,
+                latency.p99,
+                latency.max
+            );
+        }
+    }
+
+    pub fn write_json(&self, path: &Path) -> Result<(), IoError> {
+        fs::write(path, self.to_json())
+    }
+
+    fn to_json(&self) -> String {
+        let input_latency = match &self.input_latency {
+            Some(latency) => format!(
+                "{{ \"p50_ms\": {:.3}, \"p95_ms\": {:.3}, \"p99_ms\": {:.3}, \"max_ms\": {:.3} }}",
+                latency.p50.as_secs_f64() * 1000.0,
+                latency.p95.as_secs_f64() * 1000.0,
+                latency.p99.as_secs_f64() * 1000.0,
+                latency.max.as_secs_f64() * 1000.0
+            ),
+            None => "null".to_string(),
+        };
+
+        format!(
+            concat!(
+                "{{\n",
+                "  \"run_duration_seconds\": {:.3},\n",
+                "  \"driving_duration_seconds\": {:.3},\n",
+                "  \"gamepad_connects\": {},\n",
+                "  \"gamepad_disconnects\": {},\n",
+                "  \"max_commanded_throttle\": {:.3},\n",
+                "  \"failsafe_activations\": {},\n",
+                "  \"i2c_errors\": {},\n",
+                "  \"runloop_overruns\": {},\n",
+                "  \"input_latency\": {},\n",
+                "  \"max_gps_speed_m_s\": {},\n",
+                "  \"gps_distance_m\": {:.1}\n",
+                "}}\n"
+            ),
+            self.run_duration().as_secs_f64(),
+            self.driving_duration().as_secs_f64(),
+            self.gamepad_connects,
+            self.gamepad_disconnects,
+            self.max_commanded_throttle,
+            self.failsafe_activations,
+            self.i2c_errors,
+            self.runloop_overruns,
+            input_latency,
+            self.max_gps_speed
+                .map_or("null".to_string(), |speed| format!("{:.2}", speed)),
+            self.gps_distance
+        )
+    }
+}
+
+impl EventSubscriber for SessionStatistics {
+    fn handle_event(&mut self, event: Event) {
+        let Event::StateChanged { state, .. } = event else {
+            return;
+        };
+        let driving = state == ApplicationState::Driving;
+
+        match (self.driving_since, driving) {
+            (None, true) => self.driving_since = Some(Instant::now()),
+            (Some(driving_since), false) => {
+                self.driving_duration += driving_since.elapsed();
+                self.driving_since = None;
+            }
+            _ => (),
+        }
+    }
+}
@@ -1,19 +1,26 @@
-use libc;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::error::Error;
 use std::io::Error as IoError;
 use std::mem;
 use std::mem::MaybeUninit;
-use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
 use std::ptr;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum SignalIntention {
     Terminate,
+    // Log a diagnostic dump, then terminate as usual. See `diagnostic_dump.rs`.
+    DumpAndTerminate,
     ReloadConfiguration,
 }
 
 pub struct SignalManager {
     signal_fd: OwnedFd,
+    // How many times termination was asked for. See `is_urgent`.
+    termination_requests: Cell<u32>,
+    // Signals read in the last batch that were not handed out yet. See `try_next_signal`.
+    received: RefCell<VecDeque<SignalIntention>>,
 }
 
 impl SignalManager {
@@ -30,35 +37,78 @@ impl SignalManager {
         let signal_fd = create_signal_fd(mask)
             .map_err(|source| InstallError::CouldNotCreateFileDescriptor { source })?;
 
-        Ok(SignalManager { signal_fd })
+        Ok(SignalManager {
+            signal_fd,
+            termination_requests: Cell::new(0),
+            received: RefCell::new(VecDeque::new()),
+        })
     }
 
-    pub fn next_signal(&self) -> Result<Option<SignalIntention>, ReceiveError> {
-        let next_signal = self.read_from_signal_fd()?.map(|signal_info| {
-            let received_signal = i32::try_from(signal_info.ssi_signo).expect(
-                "Signals are defined as i32, but the field for them in signalfd_siginfo is a u32.",
-            );
+    /// Reads every signal that arrived since the last call, without ever blocking, and returns what they ask for in
+    /// the order they arrived. The same intention is only returned once, however often its signal arrived.
+    pub fn pending_signals(&self) -> Result<Vec<SignalIntention>, ReceiveError> {
+        let mut intentions = Vec::new();
 
-            MANAGED_SIGNALS
-                .iter()
-                .find(|mapping| mapping.0 == received_signal)
-                .map(|mapping| mapping.1)
-                .unwrap()
-        });
+        while let Some(intention) = self.try_next_signal()? {
+            if !intentions.contains(&intention) {
+                intentions.push(intention);
+            }
+        }
+
+        Ok(intentions)
+    }
+
+    /// Returns what the next signal asks for, or `None` if no signal is pending. Never blocks, so it is meant to be
+    /// called once the file descriptor (see `AsFd`) is readable, e.g. from a poll or epoll based loop.
+    pub fn try_next_signal(&self) -> Result<Option<SignalIntention>, ReceiveError> {
+        let mut received = self.received.borrow_mut();
+
+        if received.is_empty() {
+            for signal_info in self.read_from_signal_fd()? {
+                let received_signal = i32::try_from(signal_info.ssi_signo).expect(
+                    "Signals are defined as i32, but the field for them in signalfd_siginfo is a u32.",
+                );
+                let intention = MANAGED_SIGNALS
+                    .iter()
+                    .find(|mapping| mapping.0 == received_signal)
+                    .map(|mapping| mapping.1)
+                    .unwrap();
+
+                if let SignalIntention::Terminate | SignalIntention::DumpAndTerminate = intention {
+                    self.termination_requests
+                        .set(self.termination_requests.get().saturating_add(1));
+                }
+                received.push_back(intention);
+            }
+        }
+
+        Ok(received.pop_front())
+    }
+
+    /// Whether termination was asked for again after the first time, e.g. by pressing Ctrl-C a second time while the
+    /// vehicle is still being brought to a stop, meaning that shutting down is to be cut as short as safely possible.
+    ///
+    /// Any signals that arrived since they were last read are read first, so this can be checked over and over while
+    /// shutting down, when the runloop no longer reads them. Other intentions are dropped at that point.
+    pub fn is_urgent(&self) -> bool {
+        let _ = self.pending_signals();
 
-        Ok(next_signal)
+        self.termination_requests.get() >= 2
     }
 
-    fn read_from_signal_fd(&self) -> Result<Option<libc::signalfd_siginfo>, ReceiveError> {
+    // Reads up to `BATCH_SIZE` signals at once. None being pending makes for an empty batch.
+    fn read_from_signal_fd(&self) -> Result<Vec<libc::signalfd_siginfo>, ReceiveError> {
         const SIGNALFD_SIGINFO_SIZE: usize = mem::size_of::<libc::signalfd_siginfo>();
+        const BATCH_SIZE: usize = 8;
 
         unsafe {
-            let mut signal_info: MaybeUninit<libc::signalfd_siginfo> = MaybeUninit::uninit();
+            let mut signal_infos: [MaybeUninit<libc::signalfd_siginfo>; BATCH_SIZE] =
+                [MaybeUninit::uninit(); BATCH_SIZE];
 
             let bytes_read = libc::read(
                 self.signal_fd.as_raw_fd(),
-                signal_info.as_mut_ptr() as *mut libc::c_void,
-                SIGNALFD_SIGINFO_SIZE,
+                signal_infos.as_mut_ptr() as *mut libc::c_void,
+                SIGNALFD_SIGINFO_SIZE * BATCH_SIZE,
             );
 
             if bytes_read < 0 {
@@ -68,24 +118,35 @@ impl SignalManager {
                     .raw_os_error()
                     .is_some_and(|code| code == libc::EAGAIN)
                 {
-                    return Ok(None);
+                    return Ok(Vec::new());
                 } else {
                     return Err(ReceiveError::CouldNotReadFromFileDescriptor { source: error });
                 }
             }
 
-            if bytes_read as usize != SIGNALFD_SIGINFO_SIZE {
+            // The kernel only ever hands out whole structures.
+            if !(bytes_read as usize).is_multiple_of(SIGNALFD_SIGINFO_SIZE) {
                 return Err(ReceiveError::InvalidReadFromFileDescriptor);
             }
 
-            Ok(Some(signal_info.assume_init()))
+            Ok(signal_infos[..bytes_read as usize / SIGNALFD_SIGINFO_SIZE]
+                .iter()
+                .map(|signal_info| signal_info.assume_init())
+                .collect())
         }
     }
 }
 
-const MANAGED_SIGNALS: [(i32, SignalIntention); 3] = [
+impl AsFd for SignalManager {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.signal_fd.as_fd()
+    }
+}
+
+const MANAGED_SIGNALS: [(i32, SignalIntention); 4] = [
     (libc::SIGTERM, SignalIntention::Terminate),
     (libc::SIGINT, SignalIntention::Terminate),
+    (libc::SIGQUIT, SignalIntention::DumpAndTerminate),
     (libc::SIGHUP, SignalIntention::ReloadConfiguration),
 ];
 
@@ -159,7 +220,7 @@ where
         for signal in signals {
             libc::sigaddset(mask.as_mut_ptr(), signal);
         }
-        return mask.assume_init();
+        mask.assume_init()
     }
 }
 
@@ -180,3 +241,45 @@ fn create_signal_fd(signal_set: libc::sigset_t) -> Result<OwnedFd, IoError> {
         Ok(unsafe { OwnedFd::from_raw_fd(fd) })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The signals are raised on the test's own thread, which is the only one `install` blocks them on.
+    #[test]
+    fn pending_signals_are_read_at_once() {
+        let signal_manager = SignalManager::install().unwrap();
+        assert!(signal_manager.pending_signals().unwrap().is_empty());
+
+        unsafe {
+            libc::raise(libc::SIGHUP);
+            libc::raise(libc::SIGTERM);
+            libc::raise(libc::SIGINT);
+        }
+        let intentions = signal_manager.pending_signals().unwrap();
+        assert_eq!(intentions.len(), 2);
+        assert!(intentions.contains(&SignalIntention::ReloadConfiguration));
+        assert!(intentions.contains(&SignalIntention::Terminate));
+        assert!(signal_manager.pending_signals().unwrap().is_empty());
+
+        // Both of them asked for termination.
+        assert!(signal_manager.is_urgent());
+
+        // One at a time, once the file descriptor says there is something to read.
+        unsafe {
+            libc::raise(libc::SIGHUP);
+        }
+        let mut poll_fd = libc::pollfd {
+            fd: signal_manager.as_fd().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        assert_eq!(unsafe { libc::poll(&mut poll_fd, 1, 0) }, 1);
+        assert!(matches!(
+            signal_manager.try_next_signal(),
+            Ok(Some(SignalIntention::ReloadConfiguration))
+        ));
+        assert!(matches!(signal_manager.try_next_signal(), Ok(None)));
+    }
+}
@@ -0,0 +1,414 @@
+// Runs the control pipeline without any hardware, for testing timing-dependent behaviour end to end: scripted gamepad
+// events go into the input interpreter, the resulting commands go through the locomotion controller, and what comes
+// out are the pulse widths written to a mock PCA9685. Everything runs on a manual clock, which only moves by one
+// runloop interval per iteration, so runs are deterministic.
+//
+// 💁‍♂️ An iteration does what the runloop in `main.rs` does with input and locomotion, leaving out everything that
+// has nothing to do with driving (statistics, telemetry, control requests and such).
+
+use crate::clock::{Clock, ManualClock};
+use crate::failsafe_policy::FailsafeResponse;
+use crate::gamepads::mock::ScriptedGamepad;
+use crate::gamepads::{AnyGamepadEvent, GamepadInputInterpreter, GamepadSettings};
+use crate::i2c::mock::MockI2CTransport;
+use crate::idle::InactivityLock;
+use crate::input_source::{InputNotification, InputSource};
+use crate::locomotion::{
+    ArmingStep, LocomotionCommand, LocomotionController, OutputChannels, PCA9685Driver,
+    PCA9685Settings, PulseWidths, PWM_FREQUENCY,
+};
+use std::time::Duration;
+
+const RUNLOOP_INTERVAL: Duration = Duration::from_millis(10);
+const REFRESH_INTERVAL: Duration = Duration::from_millis(100);
+const FAILSAFE_BRAKE_RAMP: Duration = Duration::from_millis(500);
+
+const THROTTLE_CHANNEL: u8 = 0;
+const STEERING_CHANNEL: u8 = 1;
+
+// The registers of channel 0, those of the other channels follow in steps of 4.
+const REGISTER_LED0_ON_L: u8 = 0x06;
+const REGISTER_LED0_OFF_H: u8 = 0x09;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct PwmWrite {
+    // Since the simulation started.
+    time: Duration,
+    channel: u8,
+    pulse_width_us: f64,
+}
+
+struct Simulation {
+    clock: ManualClock,
+    interpreter: GamepadInputInterpreter<ScriptedGamepad, ManualClock>,
+    gamepad: ScriptedGamepad,
+    locomotion_controller: LocomotionController<MockI2CTransport, ManualClock>,
+    i2c: MockI2CTransport,
+    // What happens when the gamepad disconnects, coasting down over `FAILSAFE_BRAKE_RAMP` unless changed.
+    failsafe_response: FailsafeResponse,
+    // Commands are only executed while armed, which only the inactivity lock changes, if there is one.
+    inactivity_lock: Option<InactivityLock<ManualClock>>,
+    armed: bool,
+    started_at: Duration,
+    // What has been written to the PCA9685 so far, by register.
+    registers: [u8; 256],
+    pwm_writes: Vec<PwmWrite>,
+}
+
+impl Simulation {
+    fn new(settings: GamepadSettings) -> Self {
+        let mut simulation = Self::with_arming_sequence(settings, &[]);
+        // Setting up the PCA9685 is of no interest.
+        simulation.pwm_writes.clear();
+
+        simulation
+    }
+
+    // The writes made while setting up are recorded, all at the time setting up was done.
+    fn with_arming_sequence(settings: GamepadSettings, arming_sequence: &[ArmingStep]) -> Self {
+        let started_at = Duration::from_secs(1000);
+        let clock = ManualClock::new(started_at);
+
+        let gamepad = ScriptedGamepad::new(clock.clone());
+        let i2c = MockI2CTransport::new();
+        let pca9685_driver =
+            PCA9685Driver::with_transport(i2c.clone(), PWM_FREQUENCY, &PCA9685Settings::default())
+                .unwrap();
+        let locomotion_controller = LocomotionController::with_driver(
+            pca9685_driver,
+            clock.clone(),
+            &OutputChannels::default(),
+            REFRESH_INTERVAL,
+            PulseWidths::default(),
+            arming_sequence,
+        )
+        .unwrap();
+
+        let mut simulation = Self {
+            interpreter: GamepadInputInterpreter::with_event_source(
+                gamepad.clone(),
+                clock.clone(),
+                settings,
+            ),
+            clock,
+            gamepad,
+            locomotion_controller,
+            i2c,
+            failsafe_response: FailsafeResponse::coast(FAILSAFE_BRAKE_RAMP),
+            inactivity_lock: None,
+            armed: true,
+            started_at,
+            registers: [0; 256],
+            pwm_writes: Vec::new(),
+        };
+        simulation.record_pwm_writes();
+
+        simulation
+    }
+
+    // Runs one iteration, with `events` having arrived since the previous one.
+    fn step(&mut self, events: &[AnyGamepadEvent]) {
+        self.clock.advance(RUNLOOP_INTERVAL);
+
+        self.gamepad.push(events);
+        let locomotion_controller = &mut self.locomotion_controller;
+        let failsafe_response = self.failsafe_response;
+        let command = self
+            .interpreter
+            .process_input(&mut |notification| {
+                if notification == InputNotification::Disconnected {
+                    failsafe_response.stop(locomotion_controller);
+                }
+            })
+            .unwrap();
+        if let Some(inactivity_lock) = &mut self.inactivity_lock {
+            if inactivity_lock.update(self.armed, self.interpreter.last_activity(), &command) {
+                self.armed = false;
+                self.interpreter.release_latched_input();
+            }
+        }
+        let command = if self.armed {
+            command
+        } else {
+            LocomotionCommand::neutral()
+        };
+        self.locomotion_controller.execute_command(command).unwrap();
+
+        self.record_pwm_writes();
+    }
+
+    fn run_for(&mut self, duration: Duration) {
+        for _ in 0..duration.as_millis() / RUNLOOP_INTERVAL.as_millis() {
+            self.step(&[]);
+        }
+    }
+
+    // Returns the pulse widths written since the previous call, in order.
+    fn take_pwm_writes(&mut self, channel: u8) -> Vec<PwmWrite> {
+        let (taken, others) = self
+            .pwm_writes
+            .drain(..)
+            .partition(|pwm_write| pwm_write.channel == channel);
+        self.pwm_writes = others;
+
+        taken
+    }
+
+    // A channel counts as written once its last register is, which is how the PCA9685 updates its outputs too.
+    fn record_pwm_writes(&mut self) {
+        let time = self.clock.now() - self.started_at;
+        let period_us = 1_000_000.0 / self.locomotion_controller.pca9685_driver().pwm_frequency();
+
+        for (register, value) in self.i2c.writes() {
+            self.registers[register as usize] = value;
+
+            if !(REGISTER_LED0_OFF_H..REGISTER_LED0_OFF_H + 4 * 16).contains(&register)
+                || !(register - REGISTER_LED0_OFF_H).is_multiple_of(4)
+            {
+                continue;
+            }
+            let channel = (register - REGISTER_LED0_OFF_H) / 4;
+            let first_register = (REGISTER_LED0_ON_L + 4 * channel) as usize;
+            let on = u16::from_le_bytes([
+                self.registers[first_register],
+                self.registers[first_register + 1] & 0x0F,
+            ]);
+            let off = u16::from_le_bytes([
+                self.registers[first_register + 2],
+                self.registers[first_register + 3] & 0x0F,
+            ]);
+            // The pulse starts at count `on` and wraps around the end of the period if needed.
+            let counts = (off + 4096 - on) % 4096;
+            self.pwm_writes.push(PwmWrite {
+                time,
+                channel,
+                pulse_width_us: counts as f64 / 4096.0 * period_us,
+            });
+        }
+        self.i2c.clear_writes();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control_values::NormalizedAxis;
+    use crate::gamepads::{Stick, StickAxis, Trigger};
+
+    // A count is about 5μs at 50 Hz.
+    fn assert_pulse_width(pwm_write: &PwmWrite, expected_us: u32) {
+        assert!(
+            (pwm_write.pulse_width_us - expected_us as f64).abs() < 5.0,
+            "Expected a pulse width of {}μs, got {:?}.",
+            expected_us,
+            pwm_write
+        );
+    }
+
+    fn full_throttle() -> AnyGamepadEvent {
+        AnyGamepadEvent::TriggerAdjusted(Trigger::Right, NormalizedAxis::new(1.0))
+    }
+
+    #[test]
+    fn failsafe_brings_throttle_down_gradually() {
+        let pulse_widths = PulseWidths::default();
+        let mut simulation = Simulation::new(GamepadSettings::default());
+
+        simulation.step(&[AnyGamepadEvent::Connected, full_throttle()]);
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        assert_eq!(throttle.len(), 1);
+        assert_pulse_width(&throttle[0], pulse_widths.positive_us);
+
+        simulation.step(&[AnyGamepadEvent::Disconnected]);
+        let disconnected_at = simulation.clock.now() - simulation.started_at;
+        simulation.run_for(FAILSAFE_BRAKE_RAMP * 2);
+
+        // Rather than cutting to neutral, the throttle comes down a little every iteration until it gets there.
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        let neutral_index = throttle
+            .iter()
+            .position(|pwm_write| {
+                (pwm_write.pulse_width_us - pulse_widths.center_us as f64).abs() < 5.0
+            })
+            .unwrap();
+        let ramp = &throttle[..=neutral_index];
+        assert!(ramp.len() > 40);
+        assert!(ramp[0].pulse_width_us > pulse_widths.positive_us as f64);
+        assert!(ramp
+            .windows(2)
+            .all(|pair| pair[1].pulse_width_us > pair[0].pulse_width_us));
+        let ramp_duration = ramp.last().unwrap().time - disconnected_at;
+        assert!(ramp_duration.abs_diff(FAILSAFE_BRAKE_RAMP) <= RUNLOOP_INTERVAL);
+
+        // Once there, it stays there.
+        for pwm_write in &throttle[neutral_index..] {
+            assert_pulse_width(pwm_write, pulse_widths.center_us);
+        }
+
+        // Input coming back takes over right away.
+        simulation.step(&[AnyGamepadEvent::Connected, full_throttle()]);
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        assert_pulse_width(throttle.last().unwrap(), pulse_widths.positive_us);
+    }
+
+    #[test]
+    fn failsafe_can_brake_instead() {
+        let pulse_widths = PulseWidths::default();
+        let mut simulation = Simulation::new(GamepadSettings::default());
+        simulation.failsafe_response = "brake:300".parse().unwrap();
+
+        simulation.step(&[AnyGamepadEvent::Connected, full_throttle()]);
+        simulation.take_pwm_writes(THROTTLE_CHANNEL);
+
+        simulation.step(&[AnyGamepadEvent::Disconnected]);
+        let disconnected_at = simulation.clock.now() - simulation.started_at;
+        simulation.run_for(Duration::from_millis(1000));
+
+        // Half reverse throttle for the duration, which the ESC takes as braking, then neutral.
+        let braking_us = (pulse_widths.center_us + pulse_widths.negative_us) / 2;
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        let (braking, after): (Vec<PwmWrite>, Vec<PwmWrite>) = throttle
+            .into_iter()
+            .partition(|pwm_write| pwm_write.time - disconnected_at < Duration::from_millis(300));
+        assert_eq!(braking[0].time, disconnected_at);
+        for pwm_write in &braking {
+            assert_pulse_width(pwm_write, braking_us);
+        }
+        assert_eq!(after[0].time - disconnected_at, Duration::from_millis(300));
+        for pwm_write in &after {
+            assert_pulse_width(pwm_write, pulse_widths.center_us);
+        }
+    }
+
+    #[test]
+    fn inactivity_lock_leaves_a_moving_vehicle_alone() {
+        let pulse_widths = PulseWidths::default();
+        let mut simulation = Simulation::new(GamepadSettings::default());
+        let timeout = Duration::from_secs(5);
+        simulation.inactivity_lock = Some(InactivityLock::with_clock(
+            timeout,
+            simulation.clock.clone(),
+        ));
+
+        // A trigger held steady sends nothing after the first event, but the vehicle is being driven all along.
+        simulation.step(&[AnyGamepadEvent::Connected, full_throttle()]);
+        simulation.run_for(timeout * 2);
+        assert!(simulation.armed);
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        assert_pulse_width(throttle.last().unwrap(), pulse_widths.positive_us);
+
+        // Once the trigger is let go, the timeout starts.
+        simulation.step(&[AnyGamepadEvent::TriggerAdjusted(
+            Trigger::Right,
+            NormalizedAxis::new(0.0),
+        )]);
+        simulation.run_for(timeout - RUNLOOP_INTERVAL);
+        assert!(simulation.armed);
+        simulation.run_for(RUNLOOP_INTERVAL * 2);
+        assert!(!simulation.armed);
+
+        // Locked, the trigger does nothing.
+        simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        simulation.step(&[full_throttle()]);
+        simulation.run_for(REFRESH_INTERVAL);
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        assert_pulse_width(throttle.last().unwrap(), pulse_widths.center_us);
+    }
+
+    #[test]
+    fn held_values_are_refreshed() {
+        let mut simulation = Simulation::new(GamepadSettings::default());
+        simulation.step(&[
+            AnyGamepadEvent::Connected,
+            AnyGamepadEvent::StickAdjusted(
+                Stick::Left,
+                StickAxis::Horizontal,
+                NormalizedAxis::new(0.5),
+            ),
+        ]);
+        let steering = simulation.take_pwm_writes(STEERING_CHANNEL);
+        assert_eq!(steering.len(), 1);
+        simulation.take_pwm_writes(THROTTLE_CHANNEL);
+
+        // Nothing changes, so nothing is written until the values are due for a refresh.
+        simulation.run_for(Duration::from_millis(1000));
+        let refreshed_steering = simulation.take_pwm_writes(STEERING_CHANNEL);
+        let refreshed_throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        assert_eq!(refreshed_steering.len(), 10);
+        assert_eq!(refreshed_throttle.len(), 10);
+        for (pwm_write, refreshed) in refreshed_throttle.iter().zip(&refreshed_steering) {
+            assert_eq!(pwm_write.time, refreshed.time);
+            assert_eq!(refreshed.pulse_width_us, steering[0].pulse_width_us);
+            assert_eq!(refreshed.time.as_millis() % REFRESH_INTERVAL.as_millis(), 0);
+        }
+    }
+
+    #[test]
+    fn shutting_down_comes_to_a_stop() {
+        let pulse_widths = PulseWidths::default();
+        let mut simulation = Simulation::new(GamepadSettings::default());
+        simulation.step(&[AnyGamepadEvent::Connected, full_throttle()]);
+        simulation.take_pwm_writes(THROTTLE_CHANNEL);
+
+        let stopping_at = simulation.clock.now();
+        simulation
+            .locomotion_controller
+            .come_to_stop(FAILSAFE_BRAKE_RAMP, RUNLOOP_INTERVAL, || false)
+            .unwrap();
+        assert_eq!(simulation.clock.now() - stopping_at, FAILSAFE_BRAKE_RAMP);
+
+        simulation.record_pwm_writes();
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        assert!(throttle.len() > 40);
+        assert!(throttle
+            .windows(2)
+            .all(|pair| pair[1].pulse_width_us > pair[0].pulse_width_us));
+        assert_pulse_width(throttle.last().unwrap(), pulse_widths.center_us);
+
+        // Termination being asked for again partway through cuts the ramp short.
+        simulation.step(&[full_throttle()]);
+        simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        let stopping_at = simulation.clock.now();
+        let checks = std::cell::Cell::new(0);
+        simulation
+            .locomotion_controller
+            .come_to_stop(FAILSAFE_BRAKE_RAMP, RUNLOOP_INTERVAL, || {
+                checks.set(checks.get() + 1);
+                checks.get() > 5
+            })
+            .unwrap();
+        assert_eq!(simulation.clock.now() - stopping_at, RUNLOOP_INTERVAL * 5);
+
+        simulation.record_pwm_writes();
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        assert_pulse_width(throttle.last().unwrap(), pulse_widths.center_us);
+    }
+
+    #[test]
+    fn esc_is_armed_before_driving() {
+        let pulse_widths = PulseWidths::default();
+        let arming_sequence = [
+            ArmingStep {
+                pulse_width_us: 1000,
+                duration: Duration::from_millis(2000),
+            },
+            ArmingStep {
+                pulse_width_us: 2000,
+                duration: Duration::from_millis(500),
+            },
+        ];
+        let mut simulation =
+            Simulation::with_arming_sequence(GamepadSettings::default(), &arming_sequence);
+
+        // Every step is held for its duration, and the ESC is left at neutral.
+        assert_eq!(
+            simulation.clock.now() - simulation.started_at,
+            Duration::from_millis(2500)
+        );
+        let throttle = simulation.take_pwm_writes(THROTTLE_CHANNEL);
+        assert_eq!(throttle.len(), 3);
+        assert_pulse_width(&throttle[0], 1000);
+        assert_pulse_width(&throttle[1], 2000);
+        assert_pulse_width(&throttle[2], pulse_widths.center_us);
+    }
+}
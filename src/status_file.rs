@@ -0,0 +1,172 @@
+use crate::application_state::ApplicationState;
+use crate::logging::LoggedError;
+use crate::runloop;
+use std::fs::{self, File};
+use std::io::Error as IoError;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+
+// A small JSON file describing how the service is doing, for monitoring scripts and other services on the same
+// machine that would rather read a file than talk to the control socket. It is rewritten every `WRITE_INTERVAL` while
+// running, and once more when the service stops, with `running` set to `false`. How recent the file is can be told
+// from its modification time.
+//
+// 💁‍♂️ The file lives in `/run` by default, which is a RAM disk, so writing it this often wears nothing out and does
+// not have to survive a power loss. Readers can still never see half a file, as it is replaced atomically.
+const WRITE_INTERVAL: Duration = Duration::from_secs(1);
+
+pub struct Status {
+    pub running: bool,
+    pub state: ApplicationState,
+    pub input_connected: bool,
+    pub input_device: Option<String>,
+    pub uptime: Duration,
+    pub last_error: Option<LoggedError>,
+}
+
+pub struct StatusFile {
+    path: PathBuf,
+    last_write: Option<Duration>,
+    // Whether the previous write failed, so that only the first failure of a series is logged.
+    failing: bool,
+}
+
+impl StatusFile {
+    pub fn new(path: &Path) -> StatusFile {
+        StatusFile {
+            path: path.to_path_buf(),
+            last_write: None,
+            failing: false,
+        }
+    }
+
+    // Whether it is time to write the status again, so that it is only gathered when needed.
+    pub fn is_due(&self) -> bool {
+        self.last_write
+            .is_none_or(|last_write| runloop::now() - last_write >= WRITE_INTERVAL)
+    }
+
+    pub fn write(&mut self, status: &Status) {
+        let now = runloop::now();
+        self.last_write = Some(now);
+
+        match write_atomically(&self.path, &to_json(status, process::id(), now)) {
+            Ok(()) => self.failing = false,
+            Err(error) => {
+                if !self.failing {
+                    log::warn!(
+                        "Could not write status file {}. - Cause: {}",
+                        self.path.display(),
+                        error
+                    );
+                    self.failing = true;
+                }
+            }
+        }
+    }
+}
+
+fn write_atomically(path: &Path, contents: &str) -> Result<(), IoError> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let temporary_path = PathBuf::from(temporary_path);
+
+    let mut file = File::create(&temporary_path)?;
+    file.write_all(contents.as_bytes())?;
+
+    fs::rename(&temporary_path, path)
+}
+
+fn to_json(status: &Status, pid: u32, now: Duration) -> String {
+    let input_device = match &status.input_device {
+        Some(description) => json_string(description),
+        None => "null".to_string(),
+    };
+    let last_error = match &status.last_error {
+        Some(error) => format!(
+            "{{ \"message\": {}, \"seconds_ago\": {} }}",
+            json_string(&error.message),
+            now.saturating_sub(error.timestamp).as_secs()
+        ),
+        None => "null".to_string(),
+    };
+
+    format!(
+        concat!(
+            "{{\n",
+            "  \"pid\": {},\n",
+            "  \"running\": {},\n",
+            "  \"state\": \"{}\",\n",
+            "  \"input_connected\": {},\n",
+            "  \"input_device\": {},\n",
+            "  \"uptime_seconds\": {},\n",
+            "  \"last_error\": {}\n",
+            "}}\n"
+        ),
+        pid,
+        status.running,
+        status.state,
+        status.input_connected,
+        input_device,
+        status.uptime.as_secs(),
+        last_error
+    )
+}
+
+// Log messages and device names are free-form text.
+fn json_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            character if character.is_control() => {
+                escaped.push_str(&format!("\\u{:04x}", character as u32))
+            }
+            character => escaped.push(character),
+        }
+    }
+    escaped.push('"');
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_as_json() {
+        let status = Status {
+            running: true,
+            state: ApplicationState::Driving,
+            input_connected: true,
+            input_device: Some("Xbox \"Wireless\" Controller".to_string()),
+            uptime: Duration::from_millis(61_500),
+            last_error: Some(LoggedError {
+                message: "Could not update buzzer.\n\tRemote I/O error".to_string(),
+                timestamp: Duration::from_secs(90),
+            }),
+        };
+
+        assert_eq!(
+            to_json(&status, 1234, Duration::from_secs(100)),
+            concat!(
+                "{\n",
+                "  \"pid\": 1234,\n",
+                "  \"running\": true,\n",
+                "  \"state\": \"driving\",\n",
+                "  \"input_connected\": true,\n",
+                "  \"input_device\": \"Xbox \\\"Wireless\\\" Controller\",\n",
+                "  \"uptime_seconds\": 61,\n",
+                "  \"last_error\": { \"message\": \"Could not update buzzer.\\n\\u0009Remote I/O error\", ",
+                "\"seconds_ago\": 10 }\n",
+                "}\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use crate::application_state::ApplicationState;
+use crate::event_bus::{Event, EventSubscriber};
+use crate::locomotion::{PCA9685Driver, SetPWMError};
+use crate::runloop;
+use std::rc::Rc;
+use std::time::Duration;
+
+// A status LED on a spare PCA9685 channel, showing at a glance what the vehicle is up to:
+// - slow blink: waiting for an input source (e.g. the gamepad) to connect,
+// - solid: armed and ready to drive,
+// - off: connected, but disarmed (or asleep),
+// - fast blink: the failsafe engaged, until input is restored, or the service ran into an error.
+
+const SLOW_BLINK_HALF_PERIOD: Duration = Duration::from_millis(500);
+const FAST_BLINK_HALF_PERIOD: Duration = Duration::from_millis(100);
+
+pub struct StatusLed {
+    pca9685_driver: Rc<PCA9685Driver>,
+    channel: u8,
+    state: ApplicationState,
+    lit: Option<bool>,
+}
+
+impl StatusLed {
+    pub fn new(pca9685_driver: Rc<PCA9685Driver>, channel: u8) -> StatusLed {
+        StatusLed {
+            pca9685_driver,
+            channel,
+            state: ApplicationState::WaitingForInput,
+            lit: None,
+        }
+    }
+
+    // Expected to be called every runloop iteration, for blinking.
+    pub fn update(&mut self) -> Result<(), SetPWMError> {
+        let blink = |half_period: Duration| {
+            (runloop::now().as_millis() / half_period.as_millis()).is_multiple_of(2)
+        };
+
+        let lit = match self.state {
+            ApplicationState::WaitingForInput => blink(SLOW_BLINK_HALF_PERIOD),
+            ApplicationState::Armed | ApplicationState::Driving => true,
+            ApplicationState::Disarmed | ApplicationState::Asleep => false,
+            ApplicationState::Failsafe | ApplicationState::Fault => blink(FAST_BLINK_HALF_PERIOD),
+        };
+
+        if self.lit != Some(lit) {
+            self.lit = None;
+            if lit {
+                self.pca9685_driver.set_full_on(self.channel)?;
+            } else {
+                self.pca9685_driver.set_full_off(self.channel)?;
+            }
+            self.lit = Some(lit);
+        }
+
+        Ok(())
+    }
+}
+
+impl EventSubscriber for StatusLed {
+    fn handle_event(&mut self, event: Event) {
+        if let Event::StateChanged { state, .. } = event {
+            self.state = state;
+        }
+    }
+}
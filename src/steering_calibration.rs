@@ -0,0 +1,221 @@
+use crate::gamepads::{AnyGamepad, AnyGamepadEvent, Button, DpadAxis, GamepadDeviceRules};
+use crate::locomotion::{
+    LocomotionController, PulseWidths, MAXIMUM_PULSE_WIDTH_US, MINIMUM_PULSE_WIDTH_US,
+};
+use crate::runloop::{IterationOutcome, Runloop};
+use crate::signals::{SignalIntention, SignalManager};
+use std::error::Error;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Servos differ in where their center is and how far they can travel before the steering linkage binds up. Driving
+// a servo into its mechanical limit makes it stall, which drains the battery and wears out the servo. Calibration
+// finds the pulse widths for full left, center and full right by moving the servo step by step with the dpad:
+// - dpad left and right move the servo,
+// - A accepts the current position and goes on to the next one,
+// - B quits without saving.
+//
+// 💁‍♂️ The throttle is held at neutral throughout, so the vehicle does not need to be put on a stand.
+
+const RUNLOOP_INTERVAL: Duration = Duration::from_millis(10);
+const STEP_US: u32 = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Position {
+    Center,
+    Left,
+    Right,
+}
+
+impl Position {
+    fn instructions(&self) -> &'static str {
+        match self {
+            Position::Center => "Move the wheels to point straight ahead, then press A.",
+            Position::Left => {
+                "Move the wheels as far left as they go without binding, then press A."
+            }
+            Position::Right => {
+                "Move the wheels as far right as they go without binding, then press A."
+            }
+        }
+    }
+}
+
+// Loads the steering calibration for driving, falling back to standard pulse widths.
+pub fn load(path: &Path) -> PulseWidths {
+    match PulseWidths::load(path) {
+        Ok(Some(pulse_widths)) => {
+            log::info!(
+                "Loaded steering calibration: left {} µs, center {} µs, right {} µs.",
+                pulse_widths.negative_us,
+                pulse_widths.center_us,
+                pulse_widths.positive_us
+            );
+            pulse_widths
+        }
+        Ok(None) => PulseWidths::default(),
+        Err(error) => {
+            log::warn!(
+                "Could not load steering calibration from {}, using standard pulse widths. - Cause: {}",
+                path.display(),
+                error
+            );
+            PulseWidths::default()
+        }
+    }
+}
+
+// Returns whether a calibration was saved, as opposed to it being cancelled.
+pub fn run(
+    locomotion_controller: &mut LocomotionController,
+    calibration_file: &Path,
+    gamepad_device_rules: &GamepadDeviceRules,
+    gamepad_mapping_file: Option<&Path>,
+) -> Result<bool, Box<dyn Error>> {
+    let signal_manager = SignalManager::install()?;
+    let mut gamepad = AnyGamepad::new(gamepad_device_rules, gamepad_mapping_file)?;
+    let mut runloop = Runloop::new(RUNLOOP_INTERVAL);
+
+    let mut pulse_widths = load(calibration_file);
+    let mut position = Position::Center;
+    let mut pulse_width_us = pulse_widths.center_us;
+    let mut completed = false;
+
+    // Pulses get longer towards whichever side is currently calibrated to have the longer pulse.
+    let left_direction: i64 = if pulse_widths.negative_us >= pulse_widths.positive_us {
+        1
+    } else {
+        -1
+    };
+
+    println!("Waiting for a gamepad. Press B to quit without saving.");
+    locomotion_controller.set_steering_pulse_width(pulse_width_us)?;
+
+    runloop.run(|_| {
+        let terminating = signal_manager.pending_signals()?.iter().any(|intention| {
+            matches!(
+                intention,
+                SignalIntention::Terminate | SignalIntention::DumpAndTerminate
+            )
+        });
+        if terminating {
+            return Ok(IterationOutcome::Conclude);
+        }
+
+        let mut step: i64 = 0;
+        let mut accepted = false;
+        let mut cancelled = false;
+
+        gamepad.read_events(|event, _| match event {
+            AnyGamepadEvent::Connected => println!("{}", position.instructions()),
+            AnyGamepadEvent::DpadAdjusted(DpadAxis::Horizontal, value) if value.value() < 0.0 => {
+                step += left_direction;
+            }
+            AnyGamepadEvent::DpadAdjusted(DpadAxis::Horizontal, value) if value.value() > 0.0 => {
+                step -= left_direction;
+            }
+            AnyGamepadEvent::ButtonPressed(Button::A) => accepted = true,
+            AnyGamepadEvent::ButtonPressed(Button::B) => cancelled = true,
+            _ => (),
+        })?;
+
+        if cancelled {
+            return Ok(IterationOutcome::Conclude);
+        }
+
+        if step != 0 {
+            pulse_width_us = (pulse_width_us as i64 + step * STEP_US as i64)
+                .clamp(MINIMUM_PULSE_WIDTH_US as i64, MAXIMUM_PULSE_WIDTH_US as i64)
+                as u32;
+            println!("{} µs", pulse_width_us);
+        }
+
+        if accepted {
+            position = match position {
+                Position::Center => {
+                    pulse_widths.center_us = pulse_width_us;
+                    pulse_width_us = pulse_widths.negative_us;
+                    Position::Left
+                }
+                Position::Left => {
+                    pulse_widths.negative_us = pulse_width_us;
+                    pulse_width_us = pulse_widths.positive_us;
+                    Position::Right
+                }
+                Position::Right => {
+                    pulse_widths.positive_us = pulse_width_us;
+                    completed = true;
+                    return Ok(IterationOutcome::Conclude);
+                }
+            };
+            println!("{}", position.instructions());
+        }
+
+        locomotion_controller.set_steering_pulse_width(pulse_width_us)?;
+
+        Ok(IterationOutcome::KeepGoing)
+    })?;
+
+    // Leave the wheels pointing straight ahead rather than at whichever position was being calibrated.
+    locomotion_controller.set_steering_pulse_width(pulse_widths.center_us)?;
+
+    if !completed {
+        println!("Calibration cancelled, nothing was saved.");
+        return Ok(false);
+    }
+
+    if !pulse_widths.is_consistent() {
+        return Err(CalibrationError::InconsistentPositions { pulse_widths }.into());
+    }
+
+    pulse_widths
+        .save(calibration_file)
+        .map_err(|source| CalibrationError::CouldNotSave {
+            path: calibration_file.to_path_buf(),
+            source,
+        })?;
+
+    println!(
+        "Saved steering calibration to {}: left {} µs, center {} µs, right {} µs.",
+        calibration_file.display(),
+        pulse_widths.negative_us,
+        pulse_widths.center_us,
+        pulse_widths.positive_us
+    );
+
+    Ok(true)
+}
+
+#[derive(Debug)]
+pub enum CalibrationError {
+    InconsistentPositions { pulse_widths: PulseWidths },
+    CouldNotSave { path: PathBuf, source: IoError },
+}
+
+impl Error for CalibrationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CalibrationError::InconsistentPositions { pulse_widths: _ } => None,
+            CalibrationError::CouldNotSave { path: _, source } => Some(source),
+        }
+    }
+}
+
+impl std::fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            CalibrationError::InconsistentPositions { pulse_widths } => {
+                format!(
+                    "The center ({} µs) does not lie between left ({} µs) and right ({} µs), nothing was saved.",
+                    pulse_widths.center_us, pulse_widths.negative_us, pulse_widths.positive_us
+                )
+            }
+            CalibrationError::CouldNotSave { path, source: _ } => {
+                format!("Could not save steering calibration to {}.", path.display())
+            }
+        };
+
+        write!(f, "{}", description)
+    }
+}
@@ -0,0 +1,140 @@
+use std::error::Error;
+use std::str::FromStr;
+
+// Optional parts of the service that can be turned off and on again while it runs, through the control socket (e.g.
+// `roestbak ctl disable lighting`), without a restart. Turning one off only lasts until the service restarts; what
+// is configured is what runs after that.
+//
+// Only subsystems that were configured and could be set up can be toggled. Turning one on does not set it up anew:
+// e.g. the sockets are all created before the seccomp filter is installed, so they are kept open while a subsystem is
+// off, and merely not used.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SubsystemKind {
+    // The UDP telemetry stream, see `telemetry/udp_stream.rs`.
+    Telemetry,
+    // Driving over the network, i.e. by MAVLink.
+    NetworkInput,
+    // Brake lights and turn signals, see `lighting.rs`.
+    Lighting,
+}
+
+impl SubsystemKind {
+    pub const ALL: [SubsystemKind; 3] = [
+        SubsystemKind::Telemetry,
+        SubsystemKind::NetworkInput,
+        SubsystemKind::Lighting,
+    ];
+
+    // As in `enable <name>`.
+    pub fn name(self) -> &'static str {
+        match self {
+            SubsystemKind::Telemetry => "telemetry",
+            SubsystemKind::NetworkInput => "network-input",
+            SubsystemKind::Lighting => "lighting",
+        }
+    }
+
+    // For the status API, where the subsystems that can be toggled report whether they are enabled.
+    pub fn status_key(self) -> &'static str {
+        match self {
+            SubsystemKind::Telemetry => "telemetry_enabled",
+            SubsystemKind::NetworkInput => "network_input_enabled",
+            SubsystemKind::Lighting => "lighting_enabled",
+        }
+    }
+}
+
+impl std::fmt::Display for SubsystemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SubsystemKind::Telemetry => "telemetry stream",
+            SubsystemKind::NetworkInput => "network input",
+            SubsystemKind::Lighting => "lighting",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+impl FromStr for SubsystemKind {
+    type Err = ();
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        SubsystemKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == text)
+            .ok_or(())
+    }
+}
+
+/// Something that can be stopped and started again while the service runs. Subsystems are running once set up.
+pub trait Subsystem {
+    fn is_running(&self) -> bool;
+
+    // Only called while stopped. Picks up where the subsystem left off, as if it had been running all along.
+    fn start(&mut self) -> Result<(), Box<dyn Error>>;
+
+    // Only called while running. Leaves whatever the subsystem drives in a safe, inactive state (e.g. lights off),
+    // rather than as it was last set.
+    fn stop(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+// Starts or stops the subsystem, unless it is already doing what is asked for. Returns whether it did anything.
+pub fn set_running(subsystem: &mut dyn Subsystem, running: bool) -> Result<bool, Box<dyn Error>> {
+    if subsystem.is_running() == running {
+        return Ok(false);
+    }
+
+    if running {
+        subsystem.start()?;
+    } else {
+        subsystem.stop()?;
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Toggle {
+        running: bool,
+        transitions: u32,
+    }
+
+    impl Subsystem for Toggle {
+        fn is_running(&self) -> bool {
+            self.running
+        }
+
+        fn start(&mut self) -> Result<(), Box<dyn Error>> {
+            self.running = true;
+            self.transitions += 1;
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+            self.running = false;
+            self.transitions += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn toggling_by_name() {
+        assert_eq!("network-input".parse(), Ok(SubsystemKind::NetworkInput));
+        assert_eq!("gimbal".parse::<SubsystemKind>(), Err(()));
+
+        let mut toggle = Toggle {
+            running: true,
+            transitions: 0,
+        };
+        assert!(!set_running(&mut toggle, true).unwrap());
+        assert!(set_running(&mut toggle, false).unwrap());
+        assert!(!set_running(&mut toggle, false).unwrap());
+        assert!(set_running(&mut toggle, true).unwrap());
+        assert_eq!(toggle.transitions, 2);
+    }
+}
@@ -0,0 +1,32 @@
+mod csv_log;
+mod udp_stream;
+
+pub use csv_log::CsvTelemetryLog;
+pub use udp_stream::{listen, UdpTelemetrySender, DEFAULT_PORT};
+
+use crate::application_state::ApplicationState;
+use crate::control_values::{Steering, Throttle};
+use crate::gps::Fix;
+use std::time::Duration;
+
+// What the service was up to during one runloop iteration, for analysing a run afterwards.
+#[derive(Debug, Copy, Clone)]
+pub struct Sample {
+    // When the iteration ran, on the clock of `runloop::now()`.
+    pub timestamp: Duration,
+    pub state: ApplicationState,
+    pub input_connected: bool,
+    pub armed: bool,
+    pub throttle: Throttle,
+    pub steering: Steering,
+    // The pulse widths last written to the ESC and steering servo, if any.
+    pub throttle_pulse_width_us: Option<f64>,
+    pub steering_pulse_width_us: Option<f64>,
+    // The I2C errors since the service started.
+    pub i2c_errors: u64,
+    // The last temperature read by the thermal sensor, in °C, and whether the fan is running, if there are any.
+    pub temperature_celsius: Option<f64>,
+    pub fan_running: Option<bool>,
+    // The current GPS fix, if any.
+    pub gps_fix: Option<Fix>,
+}
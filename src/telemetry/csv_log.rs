@@ -0,0 +1,175 @@
+use super::Sample;
+use crate::runloop;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Error as IoError, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Appends a row per runloop iteration to a CSV file, for looking into a run in a spreadsheet afterwards. To keep the
+// log from filling up the SD card, the file is moved aside to `<file>.1` (replacing the previous one) once it grows
+// beyond `max_size`, so that at most twice that is kept.
+//
+// Rows are buffered and written out every `FLUSH_INTERVAL`, as a write per iteration would keep the SD card busy.
+// Should writing fail, the log is given up on with a warning rather than warning 100 times per second.
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const HEADER: &str = concat!(
+    "time_s,state,input_connected,armed,throttle,steering,",
+    "throttle_pulse_us,steering_pulse_us,iteration_gap_ms,temperature_c,fan_running,",
+    "latitude,longitude,gps_speed_m_s\n"
+);
+
+pub struct CsvTelemetryLog {
+    path: PathBuf,
+    max_size: u64,
+    writer: Option<BufWriter<File>>,
+    size: u64,
+    started_at: Duration,
+    last_timestamp: Option<Duration>,
+    last_flush: Duration,
+}
+
+impl CsvTelemetryLog {
+    pub fn open(path: &Path, max_size: u64) -> Result<CsvTelemetryLog, IoError> {
+        let (writer, size) = open_file(path)?;
+        log::info!("Logging telemetry to {}.", path.display());
+
+        let now = runloop::now();
+        Ok(CsvTelemetryLog {
+            path: path.to_path_buf(),
+            max_size,
+            writer: Some(writer),
+            size,
+            started_at: now,
+            last_timestamp: None,
+            last_flush: now,
+        })
+    }
+
+    pub fn record(&mut self, sample: &Sample) {
+        if self.writer.is_none() {
+            return;
+        }
+
+        if let Err(error) = self.write_row(sample) {
+            log::warn!(
+                "Could not write telemetry to {}, giving up on it. - Cause: {}",
+                self.path.display(),
+                error
+            );
+            self.writer = None;
+        }
+    }
+
+    // Writes out what is still buffered, for when the service exits.
+    pub fn flush(&mut self) {
+        if let Some(Err(error)) = self.writer.as_mut().map(|writer| writer.flush()) {
+            log::warn!(
+                "Could not write telemetry to {}. - Cause: {}",
+                self.path.display(),
+                error
+            );
+        }
+    }
+
+    fn write_row(&mut self, sample: &Sample) -> Result<(), IoError> {
+        if self.size >= self.max_size {
+            self.rotate()?;
+        }
+
+        // How long after the previous iteration this one started, which shows any hiccups of the runloop.
+        let iteration_gap = self
+            .last_timestamp
+            .map(|last_timestamp| {
+                format_milliseconds(sample.timestamp.saturating_sub(last_timestamp))
+            })
+            .unwrap_or_default();
+        self.last_timestamp = Some(sample.timestamp);
+
+        let row = format!(
+            "{:.3},{},{},{},{:.3},{:.3},{},{},{},{},{},{}\n",
+            sample
+                .timestamp
+                .saturating_sub(self.started_at)
+                .as_secs_f64(),
+            sample.state,
+            sample.input_connected,
+            sample.armed,
+            sample.throttle.value(),
+            sample.steering.value(),
+            format_pulse_width(sample.throttle_pulse_width_us),
+            format_pulse_width(sample.steering_pulse_width_us),
+            iteration_gap,
+            sample
+                .temperature_celsius
+                .map(|temperature| format!("{:.2}", temperature))
+                .unwrap_or_default(),
+            sample
+                .fan_running
+                .map(|running| running.to_string())
+                .unwrap_or_default(),
+            sample
+                .gps_fix
+                .map(|fix| format!(
+                    "{:.7},{:.7},{:.2}",
+                    fix.position.latitude, fix.position.longitude, fix.speed
+                ))
+                .unwrap_or(",,".to_string())
+        );
+
+        let Some(writer) = &mut self.writer else {
+            return Ok(());
+        };
+        writer.write_all(row.as_bytes())?;
+        self.size += row.len() as u64;
+
+        if sample.timestamp.saturating_sub(self.last_flush) >= FLUSH_INTERVAL {
+            self.last_flush = sample.timestamp;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), IoError> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+
+        let mut rotated_path = self.path.as_os_str().to_owned();
+        rotated_path.push(".1");
+        fs::rename(&self.path, rotated_path)?;
+
+        let (writer, size) = open_file(&self.path)?;
+        self.writer = Some(writer);
+        self.size = size;
+
+        Ok(())
+    }
+}
+
+// Appends to an existing file, so that a restart does not wipe the log of the run that led to it. A new file starts
+// with a header.
+fn open_file(path: &Path) -> Result<(BufWriter<File>, u64), IoError> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut size = file.metadata()?.len();
+
+    let mut writer = BufWriter::new(file);
+    if size == 0 {
+        writer.write_all(HEADER.as_bytes())?;
+        size = HEADER.len() as u64;
+    }
+
+    Ok((writer, size))
+}
+
+fn format_milliseconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+fn format_pulse_width(pulse_width_us: Option<f64>) -> String {
+    pulse_width_us
+        .map(|pulse_width_us| format!("{:.1}", pulse_width_us))
+        .unwrap_or_default()
+}
@@ -0,0 +1,408 @@
+use super::Sample;
+use crate::application_state::ApplicationState;
+use crate::control_values::{Steering, Throttle};
+use crate::gps::{Fix, Position};
+use crate::runloop;
+use std::error::Error;
+use std::io::Error as IoError;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+// Sends a compact binary packet to a ground station (e.g. a laptop charting live data) at a fixed rate. The
+// destination may be a broadcast address, so that whoever is on the network can listen in. Packets that cannot be
+// sent (e.g. while out of WiFi range) are simply dropped.
+//
+// A packet is `PACKET_SIZE` bytes, with multi-byte values in little endian:
+//
+// | Offset | Size | Contents                                                                  |
+// | ------ | ---- | ------------------------------------------------------------------------- |
+// | 0      | 2    | Magic, "RB"                                                               |
+// | 2      | 1    | Version, 3                                                                |
+// | 3      | 1    | State, see `state_code`                                                   |
+// | 4      | 1    | Flags: bit 0 = input connected, bit 1 = armed, bit 2 = fan running        |
+// | 5      | 1    | Reserved, 0                                                               |
+// | 6      | 2    | Sequence number, wrapping around                                          |
+// | 8      | 4    | Milliseconds since the service started                                    |
+// | 12     | 2    | Throttle, signed, -10000 to 10000                                         |
+// | 14     | 2    | Steering, signed, -10000 to 10000                                         |
+// | 16     | 2    | Throttle pulse width in μs, 0 if none                                     |
+// | 18     | 2    | Steering pulse width in μs, 0 if none                                     |
+// | 20     | 2    | Battery voltage in mV, 0xFFFF if unknown                                  |
+// | 22     | 4    | I2C errors so far                                                         |
+// | 26     | 2    | Temperature in 0.01 °C, signed, 0x8000 if unknown                         |
+// | 28     | 4    | GPS latitude in 10⁻⁷°, signed, 0x80000000 if there is no fix              |
+// | 32     | 4    | GPS longitude in 10⁻⁷°, signed                                            |
+// | 36     | 2    | GPS speed in cm/s                                                         |
+
+pub const PACKET_SIZE: usize = 38;
+
+// The port to listen on when none is given.
+pub const DEFAULT_PORT: u16 = 14600;
+
+const MAGIC: [u8; 2] = *b"RB";
+const VERSION: u8 = 3;
+const FLAG_INPUT_CONNECTED: u8 = 0x01;
+const FLAG_ARMED: u8 = 0x02;
+const FLAG_FAN_RUNNING: u8 = 0x04;
+const UNKNOWN_BATTERY_VOLTAGE: u16 = 0xFFFF;
+const UNKNOWN_TEMPERATURE: i16 = i16::MIN;
+const NO_GPS_FIX: i32 = i32::MIN;
+const COORDINATE_SCALE: f64 = 10_000_000.0;
+const VALUE_SCALE: f64 = 10000.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Packet {
+    pub sequence: u16,
+    pub time_ms: u32,
+    pub state: ApplicationState,
+    pub input_connected: bool,
+    pub armed: bool,
+    pub throttle: Throttle,
+    pub steering: Steering,
+    pub throttle_pulse_width_us: Option<u16>,
+    pub steering_pulse_width_us: Option<u16>,
+    pub battery_millivolts: Option<u16>,
+    pub i2c_errors: u32,
+    pub temperature_celsius: Option<f64>,
+    pub fan_running: bool,
+    pub gps_fix: Option<Fix>,
+}
+
+impl Packet {
+    pub fn encode(&self) -> [u8; PACKET_SIZE] {
+        let mut bytes = [0u8; PACKET_SIZE];
+
+        bytes[0..2].copy_from_slice(&MAGIC);
+        bytes[2] = VERSION;
+        bytes[3] = state_code(self.state);
+        if self.input_connected {
+            bytes[4] |= FLAG_INPUT_CONNECTED;
+        }
+        if self.armed {
+            bytes[4] |= FLAG_ARMED;
+        }
+        if self.fan_running {
+            bytes[4] |= FLAG_FAN_RUNNING;
+        }
+        bytes[6..8].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.time_ms.to_le_bytes());
+        bytes[12..14].copy_from_slice(&encode_value(self.throttle.value()).to_le_bytes());
+        bytes[14..16].copy_from_slice(&encode_value(self.steering.value()).to_le_bytes());
+        bytes[16..18].copy_from_slice(&self.throttle_pulse_width_us.unwrap_or(0).to_le_bytes());
+        bytes[18..20].copy_from_slice(&self.steering_pulse_width_us.unwrap_or(0).to_le_bytes());
+        bytes[20..22].copy_from_slice(
+            &self
+                .battery_millivolts
+                .unwrap_or(UNKNOWN_BATTERY_VOLTAGE)
+                .to_le_bytes(),
+        );
+        bytes[22..26].copy_from_slice(&self.i2c_errors.to_le_bytes());
+        bytes[26..28].copy_from_slice(
+            &self
+                .temperature_celsius
+                .map_or(UNKNOWN_TEMPERATURE, |temperature| {
+                    (temperature * 100.0).round() as i16
+                })
+                .to_le_bytes(),
+        );
+        let (latitude, longitude, speed) = match self.gps_fix {
+            Some(fix) => (
+                (fix.position.latitude * COORDINATE_SCALE).round() as i32,
+                (fix.position.longitude * COORDINATE_SCALE).round() as i32,
+                (fix.speed * 100.0).round() as u16,
+            ),
+            None => (NO_GPS_FIX, 0, 0),
+        };
+        bytes[28..32].copy_from_slice(&latitude.to_le_bytes());
+        bytes[32..36].copy_from_slice(&longitude.to_le_bytes());
+        bytes[36..38].copy_from_slice(&speed.to_le_bytes());
+
+        bytes
+    }
+
+    // Returns `None` for anything that is not a packet of the version we know.
+    pub fn decode(bytes: &[u8]) -> Option<Packet> {
+        if bytes.len() != PACKET_SIZE || bytes[0..2] != MAGIC || bytes[2] != VERSION {
+            return None;
+        }
+
+        let u16_at = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let i16_at = |offset: usize| i16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ])
+        };
+        let i32_at = |offset: usize| u32_at(offset) as i32;
+        let optional = |value: u16, none: u16| Some(value).filter(|value| *value != none);
+
+        Some(Packet {
+            sequence: u16_at(6),
+            time_ms: u32_at(8),
+            state: state_from_code(bytes[3])?,
+            input_connected: bytes[4] & FLAG_INPUT_CONNECTED != 0,
+            armed: bytes[4] & FLAG_ARMED != 0,
+            throttle: Throttle::new(i16_at(12) as f64 / VALUE_SCALE),
+            steering: Steering::new(i16_at(14) as f64 / VALUE_SCALE),
+            throttle_pulse_width_us: optional(u16_at(16), 0),
+            steering_pulse_width_us: optional(u16_at(18), 0),
+            battery_millivolts: optional(u16_at(20), UNKNOWN_BATTERY_VOLTAGE),
+            i2c_errors: u32_at(22),
+            temperature_celsius: Some(i16_at(26))
+                .filter(|temperature| *temperature != UNKNOWN_TEMPERATURE)
+                .map(|temperature| temperature as f64 / 100.0),
+            fan_running: bytes[4] & FLAG_FAN_RUNNING != 0,
+            gps_fix: (i32_at(28) != NO_GPS_FIX).then(|| Fix {
+                position: Position {
+                    latitude: i32_at(28) as f64 / COORDINATE_SCALE,
+                    longitude: i32_at(32) as f64 / COORDINATE_SCALE,
+                },
+                speed: u16_at(36) as f64 / 100.0,
+            }),
+        })
+    }
+}
+
+impl std::fmt::Display for Packet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let optional =
+            |value: Option<u16>| value.map(|value| value.to_string()).unwrap_or_default();
+
+        write!(
+            f,
+            "{},{:.3},{},{},{},{:.4},{:.4},{},{},{},{},{},{},{}",
+            self.sequence,
+            self.time_ms as f64 / 1000.0,
+            self.state,
+            self.input_connected,
+            self.armed,
+            self.throttle.value(),
+            self.steering.value(),
+            optional(self.throttle_pulse_width_us),
+            optional(self.steering_pulse_width_us),
+            optional(self.battery_millivolts),
+            self.i2c_errors,
+            self.temperature_celsius
+                .map(|temperature| format!("{:.2}", temperature))
+                .unwrap_or_default(),
+            self.fan_running,
+            self.gps_fix
+                .map(|fix| format!(
+                    "{:.7},{:.7},{:.2}",
+                    fix.position.latitude, fix.position.longitude, fix.speed
+                ))
+                .unwrap_or(",,".to_string())
+        )
+    }
+}
+
+// The columns of a packet's `Display` output.
+pub const PACKET_COLUMNS: &str =
+    "sequence,time_s,state,input_connected,armed,throttle,steering,throttle_pulse_us,\
+                                  steering_pulse_us,battery_mv,i2c_errors,temperature_c,fan_running,latitude,longitude,gps_speed_m_s";
+
+fn encode_value(value: f64) -> i16 {
+    (value * VALUE_SCALE).round() as i16
+}
+
+fn state_code(state: ApplicationState) -> u8 {
+    match state {
+        ApplicationState::WaitingForInput => 0,
+        ApplicationState::Disarmed => 1,
+        ApplicationState::Asleep => 2,
+        ApplicationState::Armed => 3,
+        ApplicationState::Driving => 4,
+        ApplicationState::Failsafe => 5,
+        ApplicationState::Fault => 6,
+    }
+}
+
+fn state_from_code(code: u8) -> Option<ApplicationState> {
+    Some(match code {
+        0 => ApplicationState::WaitingForInput,
+        1 => ApplicationState::Disarmed,
+        2 => ApplicationState::Asleep,
+        3 => ApplicationState::Armed,
+        4 => ApplicationState::Driving,
+        5 => ApplicationState::Failsafe,
+        6 => ApplicationState::Fault,
+        _ => return None,
+    })
+}
+
+pub struct UdpTelemetrySender {
+    socket: UdpSocket,
+    destination: SocketAddr,
+    interval: Duration,
+    started_at: Duration,
+    last_sent_at: Option<Duration>,
+    sequence: u16,
+    failing: bool,
+}
+
+impl UdpTelemetrySender {
+    pub fn new(destination: SocketAddr, rate_hz: u32) -> Result<UdpTelemetrySender, SetupError> {
+        let bind_address = match destination {
+            SocketAddr::V4(_) => SocketAddr::from(([0, 0, 0, 0], 0)),
+            SocketAddr::V6(_) => SocketAddr::from(([0u16; 8], 0)),
+        };
+        let socket = UdpSocket::bind(bind_address)
+            .map_err(|source| SetupError::CouldNotCreateSocket { source })?;
+        socket
+            .set_nonblocking(true)
+            .map_err(|source| SetupError::CouldNotCreateSocket { source })?;
+        if destination.is_ipv4() {
+            socket
+                .set_broadcast(true)
+                .map_err(|source| SetupError::CouldNotCreateSocket { source })?;
+        }
+
+        log::info!("Sending telemetry to {} at {} Hz.", destination, rate_hz);
+
+        Ok(UdpTelemetrySender {
+            socket,
+            destination,
+            interval: Duration::from_secs(1) / rate_hz,
+            started_at: runloop::now(),
+            last_sent_at: None,
+            sequence: 0,
+            failing: false,
+        })
+    }
+
+    // Expected to be called every runloop iteration. Only every so many samples are actually sent.
+    pub fn send(&mut self, sample: &Sample) {
+        if self.last_sent_at.is_some_and(|last_sent_at| {
+            sample.timestamp.saturating_sub(last_sent_at) < self.interval
+        }) {
+            return;
+        }
+        self.last_sent_at = Some(sample.timestamp);
+
+        let packet = Packet {
+            sequence: self.sequence,
+            time_ms: sample.timestamp.saturating_sub(self.started_at).as_millis() as u32,
+            state: sample.state,
+            input_connected: sample.input_connected,
+            armed: sample.armed,
+            throttle: sample.throttle,
+            steering: sample.steering,
+            throttle_pulse_width_us: sample
+                .throttle_pulse_width_us
+                .map(|pulse_width_us| pulse_width_us.round() as u16),
+            steering_pulse_width_us: sample
+                .steering_pulse_width_us
+                .map(|pulse_width_us| pulse_width_us.round() as u16),
+            // 💁‍♂️ There is no battery monitoring yet.
+            battery_millivolts: None,
+            i2c_errors: sample.i2c_errors.min(u32::MAX as u64) as u32,
+            temperature_celsius: sample.temperature_celsius,
+            fan_running: sample.fan_running == Some(true),
+            gps_fix: sample.gps_fix,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+
+        // Only the first failure in a row is worth a warning, as the network may well be gone for a while.
+        match self.socket.send_to(&packet.encode(), self.destination) {
+            Ok(_) => {
+                if self.failing {
+                    log::info!("Sending telemetry to {} again.", self.destination);
+                    self.failing = false;
+                }
+            }
+            Err(error) => {
+                if !self.failing {
+                    log::warn!(
+                        "Could not send telemetry to {}. - Cause: {}",
+                        self.destination,
+                        error
+                    );
+                    self.failing = true;
+                }
+            }
+        }
+    }
+}
+
+// Prints every telemetry packet received on `listen_address` as a line of comma-separated values, for charting on
+// the ground station. Runs until interrupted.
+pub fn listen(listen_address: SocketAddr) -> Result<(), IoError> {
+    let socket = UdpSocket::bind(listen_address)?;
+    eprintln!("Listening for telemetry on {}.", listen_address);
+    println!("{}", PACKET_COLUMNS);
+
+    let mut buffer = [0u8; 2048];
+    loop {
+        let (size, _) = socket.recv_from(&mut buffer)?;
+        if let Some(packet) = Packet::decode(&buffer[..size]) {
+            println!("{}", packet);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SetupError {
+    CouldNotCreateSocket { source: IoError },
+}
+
+impl Error for SetupError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(match self {
+            SetupError::CouldNotCreateSocket { source } => source,
+        })
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            SetupError::CouldNotCreateSocket { source: _ } => "Could not create telemetry socket.",
+        };
+
+        write!(f, "{}", description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_round_trip() {
+        let packet = Packet {
+            sequence: 0xFFFF,
+            time_ms: 123_456,
+            state: ApplicationState::Driving,
+            input_connected: true,
+            armed: true,
+            throttle: Throttle::new(-0.25),
+            steering: Steering::new(1.0),
+            throttle_pulse_width_us: Some(1625),
+            steering_pulse_width_us: None,
+            battery_millivolts: None,
+            i2c_errors: 3,
+            temperature_celsius: Some(-12.5),
+            fan_running: false,
+            gps_fix: Some(Fix {
+                position: Position {
+                    latitude: 52.25,
+                    longitude: -4.5,
+                },
+                speed: 3.75,
+            }),
+        };
+
+        let bytes = packet.encode();
+        assert_eq!(&bytes[0..6], &[b'R', b'B', 3, 4, 0x03, 0]);
+        assert_eq!(&bytes[12..14], &(-2500i16).to_le_bytes());
+        assert_eq!(Packet::decode(&bytes), Some(packet));
+
+        assert_eq!(Packet::decode(&bytes[..PACKET_SIZE - 1]), None);
+        let mut unknown_version = bytes;
+        unknown_version[2] = 2;
+        assert_eq!(Packet::decode(&unknown_version), None);
+    }
+}
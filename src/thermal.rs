@@ -0,0 +1,167 @@
+mod tmp102;
+
+pub use tmp102::Tmp102;
+
+use crate::i2c::I2CTransport;
+use crate::runloop;
+use std::time::Duration;
+
+// Keeps a hot ESC or motor from burning out by lowering the throttle cap as it heats up: from full throttle at
+// `derate_from` down to `max_speed_at_limit` at `limit`, in a straight line, and no further beyond it. The cap only
+// comes back up once the temperature has dropped `COOLDOWN_HYSTERESIS` below where it would have to be for that, so
+// that a temperature hovering around a point does not make the throttle cap follow its every twitch.
+//
+// ⚠️ A sensor that cannot be read leaves the cap where it was, as there is no telling whether things are cooling
+// down. It is retried every `READ_INTERVAL`.
+
+const READ_INTERVAL: Duration = Duration::from_secs(1);
+const COOLDOWN_HYSTERESIS: f64 = 5.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DeratingSettings {
+    // In °C.
+    pub derate_from: f64,
+    pub limit: f64,
+    // The throttle cap from `limit` upwards, from 0.0 to 1.0.
+    pub max_speed_at_limit: f64,
+}
+
+impl DeratingSettings {
+    // The throttle cap for a temperature, going by the temperature alone.
+    fn cap(&self, temperature: f64) -> f64 {
+        // Checking the limit first keeps a `limit` that is not above `derate_from` from dividing by zero.
+        if temperature >= self.limit {
+            self.max_speed_at_limit
+        } else if temperature <= self.derate_from {
+            1.0
+        } else {
+            let fraction = (temperature - self.derate_from) / (self.limit - self.derate_from);
+
+            1.0 - fraction * (1.0 - self.max_speed_at_limit)
+        }
+    }
+}
+
+pub struct ThermalDerating<T: I2CTransport> {
+    sensor: Tmp102<T>,
+    settings: DeratingSettings,
+    cap: f64,
+    temperature: Option<f64>,
+    last_read: Option<Duration>,
+    // Whether the previous read failed, so that only the first failure of a series is logged.
+    failing: bool,
+}
+
+impl<T: I2CTransport> ThermalDerating<T> {
+    pub fn new(sensor: Tmp102<T>, settings: DeratingSettings) -> Self {
+        Self {
+            sensor,
+            settings,
+            cap: 1.0,
+            temperature: None,
+            last_read: None,
+            failing: false,
+        }
+    }
+
+    // Expected to be called every runloop iteration. The sensor is only read every `READ_INTERVAL`.
+    pub fn update(&mut self) {
+        let now = runloop::now();
+        if self
+            .last_read
+            .is_some_and(|last_read| now - last_read < READ_INTERVAL)
+        {
+            return;
+        }
+        self.last_read = Some(now);
+
+        match self.sensor.read_temperature() {
+            Ok(temperature) => {
+                self.failing = false;
+                self.handle_temperature(temperature);
+            }
+            Err(error) => {
+                if !self.failing {
+                    log::warn!(
+                        "Could not read the temperature, keeping the throttle cap at {:.0}%. - Cause: {}",
+                        self.cap * 100.0,
+                        error
+                    );
+                    self.failing = true;
+                }
+            }
+        }
+    }
+
+    fn handle_temperature(&mut self, temperature: f64) {
+        self.temperature = Some(temperature);
+
+        let cap = self.settings.cap(temperature).min(
+            self.settings
+                .cap(temperature + COOLDOWN_HYSTERESIS)
+                .max(self.cap),
+        );
+        if cap == self.cap {
+            return;
+        }
+
+        if self.cap == 1.0 {
+            log::warn!(
+                "Derating the throttle, as the temperature rose to {:.1} °C.",
+                temperature
+            );
+        } else if cap == 1.0 {
+            log::info!(
+                "No longer derating the throttle, as the temperature dropped to {:.1} °C.",
+                temperature
+            );
+        }
+        self.cap = cap;
+    }
+
+    // From 0.0 to 1.0, to scale the throttle by.
+    pub fn throttle_cap(&self) -> f64 {
+        self.cap
+    }
+
+    // The last temperature read, in °C.
+    pub fn temperature(&self) -> Option<f64> {
+        self.temperature
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::mock::MockI2CTransport;
+
+    #[test]
+    fn derating_and_cooling_down() {
+        let settings = DeratingSettings {
+            derate_from: 60.0,
+            limit: 80.0,
+            max_speed_at_limit: 0.2,
+        };
+        let mut derating =
+            ThermalDerating::new(Tmp102::with_transport(MockI2CTransport::new()), settings);
+        let mut cap_at = |temperature: f64| {
+            derating.handle_temperature(temperature);
+            (derating.throttle_cap() * 100.0).round()
+        };
+
+        assert_eq!(cap_at(25.0), 100.0);
+        assert_eq!(cap_at(60.0), 100.0);
+        assert_eq!(cap_at(70.0), 60.0);
+        assert_eq!(cap_at(90.0), 20.0);
+
+        // Cooling down only raises the cap once the temperature is well below where it was reached.
+        assert_eq!(cap_at(79.0), 20.0);
+        assert_eq!(cap_at(75.0), 20.0);
+        assert_eq!(cap_at(70.0), 40.0);
+        assert_eq!(cap_at(72.0), 40.0);
+        assert_eq!(cap_at(55.0), 100.0);
+
+        // Heating up again lowers it right away.
+        assert_eq!(cap_at(65.0), 80.0);
+    }
+}
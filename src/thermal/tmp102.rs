@@ -0,0 +1,81 @@
+use crate::i2c::{self, AddressingOptions, I2CDevice, I2CTransport};
+use std::path::Path;
+
+// The datasheet is available at: https://www.ti.com/lit/ds/symlink/tmp102.pdf.
+//
+// The sensor measures continuously (4 times a second by default) from the moment it is powered up, so reading the
+// latest measurement is all there is to it.
+
+pub struct Tmp102<T: I2CTransport = I2CDevice> {
+    i2c_device: T,
+}
+
+impl Tmp102<I2CDevice> {
+    // The address depends on what the ADD0 pin is connected to: 0x48 to 0x4B.
+    pub fn new(i2c_device_file: &Path, address: u8) -> Result<Self, i2c::SetupError> {
+        let i2c_device = I2CDevice::new(
+            i2c_device_file,
+            address as i32,
+            AddressingOptions::default(),
+        )?;
+
+        Ok(Self::with_transport(i2c_device))
+    }
+}
+
+impl<T: I2CTransport> Tmp102<T> {
+    pub fn with_transport(i2c_device: T) -> Self {
+        Self { i2c_device }
+    }
+
+    // In °C, with a resolution of 0.0625 °C.
+    pub fn read_temperature(&self) -> Result<f64, i2c::ReadError> {
+        // The high byte comes first.
+        let register = self
+            .i2c_device
+            .read_word_data(REGISTER_TEMPERATURE)?
+            .swap_bytes();
+
+        // A 12-bit two's complement value, left aligned. In extended mode (for temperatures over 128 °C), which is
+        // flagged by the lowest bit, it is 13 bits wide.
+        let counts = if register & EXTENDED_MODE_FLAG != 0 {
+            (register as i16) >> 3
+        } else {
+            (register as i16) >> 4
+        };
+
+        Ok(counts as f64 * 0.0625)
+    }
+}
+
+const REGISTER_TEMPERATURE: u8 = 0x00;
+
+const EXTENDED_MODE_FLAG: u16 = 0x0001;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::i2c::mock::MockI2CTransport;
+
+    #[test]
+    fn temperatures() {
+        let i2c = MockI2CTransport::new();
+        let sensor = Tmp102::with_transport(i2c.clone());
+        let read = |high: u8, low: u8| {
+            // The mock hands out the register after the one read as the second byte.
+            i2c.write_byte_data(0x00, high).unwrap();
+            i2c.write_byte_data(0x01, low).unwrap();
+            sensor.read_temperature().unwrap()
+        };
+
+        // The examples from the datasheet.
+        assert_eq!(read(0x7F, 0xF0), 127.9375);
+        assert_eq!(read(0x19, 0x00), 25.0);
+        assert_eq!(read(0x00, 0x40), 0.25);
+        assert_eq!(read(0x00, 0x00), 0.0);
+        assert_eq!(read(0xFF, 0xC0), -0.25);
+        assert_eq!(read(0xE7, 0x00), -25.0);
+        // Extended mode.
+        assert_eq!(read(0x4B, 0x01), 150.0);
+    }
+}
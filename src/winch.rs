@@ -0,0 +1,95 @@
+use crate::locomotion::{self, PCA9685Driver, PulseWidths, SetPWMError};
+use std::rc::Rc;
+
+// A winch on a spare PCA9685 channel, as crawlers have for pulling themselves out of trouble, driven through its own
+// ESC or winch controller. It only runs while the driver holds the winch modifier and presses the dpad up (pulling
+// in) or down (letting out), and goes back to neutral as soon as either is let go of.
+//
+// ⚠️ The winch and the throttle are interlocked: while the vehicle is disarmed or the throttle is applied, the winch
+// stays at neutral, so that the vehicle does not drive against the winch line (or off while the line is still being
+// let out).
+
+pub struct Winch {
+    pca9685_driver: Rc<PCA9685Driver>,
+    channel: u8,
+    pulse_widths: PulseWidths,
+    // From 0.0 to 1.0.
+    speed: f64,
+    // -1.0, 0.0 or 1.0.
+    direction: f64,
+    // `None` while it is not known what the output was last set to.
+    written_count: Option<u16>,
+    // Whether the winch was asked to run but held back by the interlock, so that this is only logged once.
+    interlocked: bool,
+}
+
+impl Winch {
+    pub fn new(
+        pca9685_driver: Rc<PCA9685Driver>,
+        channel: u8,
+        pulse_widths: PulseWidths,
+        speed: f64,
+    ) -> Winch {
+        Winch {
+            pca9685_driver,
+            channel,
+            pulse_widths,
+            speed,
+            direction: 0.0,
+            written_count: None,
+            interlocked: false,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.direction != 0.0
+    }
+
+    // Brings the winch to neutral, such as before the service exits.
+    pub fn stop(&mut self) -> Result<(), SetPWMError> {
+        self.update(0.0, false)
+    }
+
+    // Expected to be called every runloop iteration with the direction the driver asks for, 1.0 for pulling in, and
+    // whether the vehicle is armed without any throttle applied.
+    pub fn update(&mut self, requested_direction: f64, may_run: bool) -> Result<(), SetPWMError> {
+        let direction = if requested_direction == 0.0 {
+            self.interlocked = false;
+            0.0
+        } else if !may_run {
+            if !self.interlocked {
+                log::info!("Not running the winch while disarmed or driving.");
+                self.interlocked = true;
+            }
+            0.0
+        } else {
+            self.interlocked = false;
+            requested_direction.signum()
+        };
+
+        if direction != self.direction {
+            if direction > 0.0 {
+                log::info!("Winch pulling in.");
+            } else if direction < 0.0 {
+                log::info!("Winch letting out.");
+            } else {
+                log::info!("Winch stopped.");
+            }
+            self.direction = direction;
+        }
+
+        let count =
+            locomotion::duty_cycle_count(locomotion::locomotion_value_to_pwm_on_percentage(
+                direction * self.speed,
+                &self.pulse_widths,
+                self.pca9685_driver.pwm_frequency(),
+            ))?;
+        if self.written_count != Some(count) {
+            self.written_count = None;
+            self.pca9685_driver.set_pwm_on_count(self.channel, count)?;
+            self.written_count = Some(count);
+        }
+
+        Ok(())
+    }
+}